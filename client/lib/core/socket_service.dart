@@ -29,6 +29,10 @@ class SocketService {
   final _eventController = StreamController<WsEvent>.broadcast();
 
   String? _token;
+  /// Gateway session to resume after a dropped connection, and the last
+  /// event sequence number seen in it.
+  String? _sessionId;
+  int _lastSeq = 0;
   Timer? _reconnectTimer;
   int _reconnectAttempts = 0;
   bool _shouldReconnect = false;
@@ -52,6 +56,7 @@ class SocketService {
 
     _shouldReconnect = true;
     _token = token;
+    _sessionId = null;
     _reconnectAttempts = 0;
     // Clean up any existing connection first
     _cleanup();
@@ -74,7 +79,11 @@ class SocketService {
       // Wait for the channel to be ready before sending identify
       channel.ready.then((_) {
         if (_channel == channel && _token != null) {
-          _sendIdentify(_token!);
+          if (_sessionId != null) {
+            _sendResume(_token!, _sessionId!, _lastSeq);
+          } else {
+            _sendIdentify(_token!);
+          }
         }
       }).catchError((error) {
         debugPrint('WebSocket ready error: $error');
@@ -88,6 +97,12 @@ class SocketService {
           try {
             final json = jsonDecode(message as String);
             final event = WsEvent.fromJson(json);
+            final seq = json['seq'];
+            if (seq is int) _lastSeq = seq;
+            if (event.type == 'Ready') {
+              _sessionId = event.data?['session_id'] as String?;
+              _lastSeq = 0;
+            }
             _eventController.add(event);
             debugPrint('WS Event: ${event.type}');
           } catch (e) {
@@ -153,6 +168,16 @@ class SocketService {
     send(payload);
   }
 
+  /// Pick up a dropped session; the server replays missed events, or
+  /// answers with a fresh Ready if the session is gone.
+  void _sendResume(String token, String sessionId, int lastSeq) {
+    final payload = {
+      'type': 'Resume',
+      'data': {'token': token, 'session_id': sessionId, 'last_seq': lastSeq}
+    };
+    send(payload);
+  }
+
   void send(Map<String, dynamic> payload) {
     if (_channel != null) {
       try {
@@ -167,6 +192,7 @@ class SocketService {
   void disconnect() {
     _shouldReconnect = false;
     _token = null;
+    _sessionId = null;
     _reconnectAttempts = 0;
     _cleanup();
   }
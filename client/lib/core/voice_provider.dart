@@ -237,9 +237,6 @@ class VoiceNotifier extends StateNotifier<VoiceState> {
         payload == null) {
       return;
     }
-    if (channelId != state.currentChannelId) {
-      return; // ignore signals for other channels
-    }
 
     // Only handle signals from the server (serverId or nil UUID)
     if (fromUserId != serverId &&
@@ -247,6 +244,17 @@ class VoiceNotifier extends StateNotifier<VoiceState> {
       return;
     }
 
+    // A moderator moved us: the signal names the channel we're now in
+    if (signalType == 'reconnect') {
+      if (state.currentChannelId != null) {
+        await _handleMoved(channelId);
+      }
+      return;
+    }
+    if (channelId != state.currentChannelId) {
+      return; // ignore signals for other channels
+    }
+
     debugPrint('[Voice] Signal from server: $signalType');
 
     switch (signalType) {
@@ -264,6 +272,24 @@ class VoiceNotifier extends StateNotifier<VoiceState> {
     }
   }
 
+  /// Reconnect to the SFU after being moved to [channelId]. The server has
+  /// already dropped our old peer connection, so negotiate a fresh one.
+  Future<void> _handleMoved(String channelId) async {
+    debugPrint('[Voice] Moved to channel $channelId, reconnecting');
+    // Switch channels before awaiting so the leave event for the old
+    // channel isn't mistaken for us leaving voice
+    state = state.copyWith(currentChannelId: channelId, isConnecting: true);
+
+    try {
+      await _cleanupWebRTC();
+      await _startLocalAudio();
+      await _initiateSfuCall(channelId);
+    } catch (e) {
+      debugPrint('[Voice] Failed to reconnect after move: $e');
+      await _cleanupWebRTC();
+    }
+  }
+
   /// Handle an answer from the server (response to our initial offer).
   Future<void> _handleAnswer(dynamic payload) async {
     if (_serverConnection == null) return;
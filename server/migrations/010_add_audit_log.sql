@@ -0,0 +1,25 @@
+CREATE TYPE audit_action AS ENUM (
+    'member_kick',
+    'member_ban',
+    'member_unban',
+    'channel_delete',
+    'role_create',
+    'role_delete',
+    'member_role_add',
+    'member_role_remove'
+);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    server_id UUID NOT NULL REFERENCES servers(id) ON DELETE CASCADE,
+    -- Kept (as NULL) when the acting account is deleted
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    action audit_action NOT NULL,
+    -- The user, channel or role acted on (no FK: targets may be gone)
+    target_id UUID,
+    -- Role involved in role changes
+    role_id UUID,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS idx_audit_log_server ON audit_log (server_id, id DESC);
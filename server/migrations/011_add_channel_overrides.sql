@@ -0,0 +1,22 @@
+CREATE TYPE override_target AS ENUM ('role', 'member');
+
+CREATE TABLE IF NOT EXISTS channel_overrides (
+    channel_id UUID NOT NULL REFERENCES channels(id) ON DELETE CASCADE,
+    -- A role id or a user id, depending on target_type
+    target_id UUID NOT NULL,
+    target_type override_target NOT NULL,
+    allow BIGINT NOT NULL DEFAULT 0,
+    deny BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (channel_id, target_id)
+);
+CREATE INDEX IF NOT EXISTS idx_channel_overrides_target ON channel_overrides (target_id);
+
+-- Every server needs an @everyone role: member permissions beyond
+-- VIEW_CHANNEL come from roles, and the seeded default server never got one.
+-- Backfill it with SEND_MESSAGES (16), matching newly created servers.
+INSERT INTO roles (id, server_id, name, permissions, color, position)
+SELECT gen_random_uuid(), s.id, '@everyone', 16, 0, 0
+FROM servers s
+WHERE NOT EXISTS (
+    SELECT 1 FROM roles r WHERE r.server_id = s.id AND r.name = '@everyone'
+);
@@ -0,0 +1,8 @@
+-- Categories are channels of their own type that group other channels
+ALTER TYPE channel_type ADD VALUE IF NOT EXISTS 'category';
+
+-- Deleting a category moves its channels out of it instead of failing
+ALTER TABLE channels DROP CONSTRAINT IF EXISTS channels_category_id_fkey;
+ALTER TABLE channels
+    ADD CONSTRAINT channels_category_id_fkey
+    FOREIGN KEY (category_id) REFERENCES channels(id) ON DELETE SET NULL;
@@ -0,0 +1,14 @@
+-- DM channels are channel rows without a server, so messages, attachments,
+-- reactions and pins work unchanged
+ALTER TABLE channels ALTER COLUMN server_id DROP NOT NULL;
+
+CREATE TABLE IF NOT EXISTS dm_channels (
+    channel_id UUID PRIMARY KEY REFERENCES channels(id) ON DELETE CASCADE,
+    -- The participant pair, stored sorted so each pair has one channel
+    user_a UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_b UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_a, user_b),
+    CHECK (user_a < user_b)
+);
+CREATE INDEX IF NOT EXISTS idx_dm_channels_user_b ON dm_channels (user_b);
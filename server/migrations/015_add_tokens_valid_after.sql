@@ -0,0 +1,2 @@
+-- Tokens issued before this moment are rejected (set on password change)
+ALTER TABLE users ADD COLUMN IF NOT EXISTS tokens_valid_after TIMESTAMPTZ;
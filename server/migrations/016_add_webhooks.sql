@@ -0,0 +1,20 @@
+-- Incoming webhooks: post into a channel with a secret token instead of a JWT.
+-- Each webhook authors its messages as its own synthetic user row, so
+-- messages.author_id keeps pointing at users.
+CREATE TABLE IF NOT EXISTS webhooks (
+    id UUID PRIMARY KEY,
+    channel_id UUID NOT NULL REFERENCES channels(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id),
+    name VARCHAR(80) NOT NULL,
+    avatar_url TEXT,
+    -- SHA-256 of the secret token; the token itself is only shown once
+    token_hash VARCHAR(64) NOT NULL,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS idx_webhooks_channel ON webhooks (channel_id);
+
+-- Per-message overrides sent with a webhook execution
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS webhook_id UUID REFERENCES webhooks(id) ON DELETE SET NULL;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS webhook_username VARCHAR(80);
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS webhook_avatar_url TEXT;
@@ -0,0 +1 @@
+ALTER TABLE channels ADD COLUMN IF NOT EXISTS slow_mode_secs INTEGER NOT NULL DEFAULT 0;
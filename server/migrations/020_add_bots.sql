@@ -0,0 +1,10 @@
+-- Bot accounts: user rows that authenticate with a long-lived token instead
+-- of a password, so they can join servers and post like any member.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_bot BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS bots (
+    id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS idx_bots_owner ON bots (owner_id);
@@ -0,0 +1,11 @@
+-- Link previews unfurled from the first URL in a message
+CREATE TABLE IF NOT EXISTS message_embeds (
+    message_id BIGINT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    title TEXT,
+    description TEXT,
+    image_url TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS embeds_enabled BOOLEAN NOT NULL DEFAULT TRUE;
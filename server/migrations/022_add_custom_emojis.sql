@@ -0,0 +1,17 @@
+-- Per-server custom emojis, referenced in messages and reactions as <:name:id>.
+-- The image itself lives in storage under emojis/{server_id}/{id}.
+CREATE TABLE IF NOT EXISTS custom_emojis (
+    id UUID PRIMARY KEY,
+    server_id UUID NOT NULL REFERENCES servers(id) ON DELETE CASCADE,
+    name VARCHAR(32) NOT NULL,
+    animated BOOLEAN NOT NULL DEFAULT FALSE,
+    creator_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (server_id, name)
+);
+
+-- Room for a <:name:id> reference as well as unicode emojis
+ALTER TABLE reactions ALTER COLUMN emoji TYPE VARCHAR(80);
+
+ALTER TYPE audit_action ADD VALUE IF NOT EXISTS 'emoji_create';
+ALTER TYPE audit_action ADD VALUE IF NOT EXISTS 'emoji_delete';
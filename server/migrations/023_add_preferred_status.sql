@@ -0,0 +1,5 @@
+-- A status the user picked themselves (idle, dnd or offline/invisible),
+-- restored when they connect. NULL means no preference: they show as online.
+CREATE TYPE presence_status AS ENUM ('online', 'idle', 'dnd', 'offline');
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS preferred_status presence_status;
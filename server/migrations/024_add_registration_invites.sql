@@ -0,0 +1,10 @@
+-- One-time codes that let someone register while auth.allow_local_registration
+-- is off (invite-only instances). Minted by instance admins.
+CREATE TABLE IF NOT EXISTS registration_invites (
+    code VARCHAR(32) PRIMARY KEY,
+    creator_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ,
+    used_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
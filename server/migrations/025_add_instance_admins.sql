@@ -0,0 +1,6 @@
+-- Instance administrators, who moderate the whole deployment rather than a
+-- single server. Accounts listed in auth.admins are flagged at startup.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- A disabled server keeps its data but grants nobody any permissions
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS disabled BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Per-account lockout after repeated failed logins, whatever IP they come from
+ALTER TABLE users ADD COLUMN IF NOT EXISTS failed_logins INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ;
@@ -0,0 +1,11 @@
+-- Long-lived refresh tokens, exchanged for new access tokens at
+-- POST /api/auth/refresh. Only a SHA-256 of each token is stored; a token is
+-- replaced by a new one every time it's used.
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user ON refresh_tokens (user_id);
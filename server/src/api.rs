@@ -1,32 +1,46 @@
 #[allow(unused_imports)]
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
-use axum::body::Body;
+use axum::body::{Body, Bytes};
 use axum::extract::ws::{Message as WsMessage, WebSocket};
-use axum::extract::{FromRequestParts, Path, Query, State, WebSocketUpgrade};
+use axum::extract::{
+    ConnectInfo, DefaultBodyLimit, FromRequestParts, MatchedPath, Path, Query, State,
+    WebSocketUpgrade,
+};
 use axum::http::request::Parts;
-use axum::http::{header, StatusCode};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::IntoResponse;
 use axum::routing::{delete, get, post, put};
 use axum::{Json, Router};
 use axum_extra::extract::Multipart;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use jsonwebtoken::jwk::JwkSet;
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 use tower_http::cors::CorsLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
 use uuid::Uuid;
 
-use crate::auth;
-use crate::config::{AppConfig, ServerMode};
+use crate::auth::{self, TokenType};
+use crate::chat;
+use crate::config::{AppConfig, JwtAlgorithm, ServerMode};
+use crate::crypto::{self, PreKeyBundle};
 use crate::db::{self, DbPool};
+use crate::embeds;
 use crate::error::{AppError, AppResult};
+use crate::images;
 use crate::models::*;
 use crate::presence::PresenceManager;
+use crate::ratelimit::RateLimiter;
+use crate::session::WsSession;
+use crate::storage::Storage;
+use crate::voice_state::VoiceStateStore;
 
 // ─── Helpers ───────────────────────────────────────────────────────────────
 
@@ -47,6 +61,214 @@ async fn check_permission(
     Ok(())
 }
 
+/// Like `check_permission`, but for a channel: resolves the member's server
+/// permissions, then applies the channel's role and member overrides. A
+/// channel the user can't view is Forbidden for every permission.
+async fn check_channel_permission(
+    state: &AppState,
+    user_id: Uuid,
+    channel_id: Uuid,
+    permission: i64,
+) -> AppResult<()> {
+    let channel_server_id = find_channel(state, channel_id).await?.server_id;
+
+    // DM channels have no server: only the two participants may use them,
+    // and only to read and send
+    let Some(channel_server_id) = channel_server_id else {
+        let (a, b) = db::dms::participants(&state.db, channel_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Channel not found".to_string()))?;
+        let dm_perms = Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES;
+        if (user_id != a && user_id != b) || permission & !dm_perms != 0 {
+            return Err(AppError::Forbidden);
+        }
+        return Ok(());
+    };
+
+    let base = db::members::get_permissions(&state.db, user_id, channel_server_id).await?;
+    let overrides =
+        db::overrides::list_for_member(&state.db, channel_id, channel_server_id, user_id).await?;
+
+    if !channel_permits(base, &overrides, permission) {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(())
+}
+
+/// Whether `user_id` may see `channel_id` at all. Gateway subscriptions follow
+/// this so channel events don't reach members that REST would refuse.
+async fn can_view_channel(state: &AppState, user_id: Uuid, channel_id: Uuid) -> bool {
+    check_channel_permission(state, user_id, channel_id, Permissions::VIEW_CHANNEL)
+        .await
+        .is_ok()
+}
+
+/// The server `channels` that `user_id` can view. Resolves each server's
+/// permissions once and every override in one query, instead of a full
+/// permission check per channel.
+async fn viewable_channels(
+    state: &AppState,
+    user_id: Uuid,
+    channels: &[Channel],
+) -> AppResult<HashSet<Uuid>> {
+    let mut base = HashMap::new();
+    for server_id in channels.iter().filter_map(|c| c.server_id) {
+        if let std::collections::hash_map::Entry::Vacant(entry) = base.entry(server_id) {
+            entry.insert(db::members::get_permissions(&state.db, user_id, server_id).await?);
+        }
+    }
+
+    let channel_ids: Vec<Uuid> = channels.iter().map(|c| c.id).collect();
+    let mut overrides: HashMap<Uuid, Vec<ChannelOverride>> = HashMap::new();
+    for o in db::overrides::list_for_member_in_channels(&state.db, &channel_ids, user_id).await? {
+        overrides.entry(o.channel_id).or_default().push(o);
+    }
+
+    Ok(channels
+        .iter()
+        .filter(|c| {
+            c.server_id
+                .and_then(|id| base.get(&id))
+                .is_some_and(|base| {
+                    let overrides = overrides.get(&c.id).map(Vec::as_slice).unwrap_or_default();
+                    channel_permits(*base, overrides, Permissions::VIEW_CHANNEL)
+                })
+        })
+        .map(|c| c.id)
+        .collect())
+}
+
+/// Rebuild a server channel's subscriber list from the connected members who
+/// can currently view it, e.g. after its overrides changed. Sessions waiting
+/// to be resumed stay subscribed so their events are there to replay.
+async fn resync_channel_subs(state: &AppState, server_id: Uuid, channel_id: Uuid) -> AppResult<()> {
+    let mut subs = Vec::new();
+    for user_id in state.server_member_ids(&server_id).await? {
+        if state.ws_sessions.contains_key(&user_id)
+            && can_view_channel(state, user_id, channel_id).await
+        {
+            subs.push(user_id);
+        }
+    }
+    state.channel_subs.insert(channel_id, subs);
+    Ok(())
+}
+
+/// The server seeded on first start. It's the only one that can be joined
+/// without an invite.
+pub const DEFAULT_SERVER_ID: Uuid = Uuid::from_u128(0x00000000_0000_7000_8000_000000000001);
+
+/// Owns the default server until the first user claims it.
+pub const SYSTEM_USER_ID: Uuid = Uuid::from_u128(0x00000000_0000_7000_8000_000000000000);
+
+/// Whether server permissions `base`, after a channel's `overrides`, allow
+/// `permission` in that channel.
+fn channel_permits(base: Permissions, overrides: &[ChannelOverride], permission: i64) -> bool {
+    let perms = base.with_overrides(overrides);
+    perms.has(Permissions::VIEW_CHANNEL) && perms.has(permission)
+}
+
+async fn find_channel(state: &AppState, channel_id: Uuid) -> AppResult<Channel> {
+    db::channels::find_by_id(&state.db, channel_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Channel not found".to_string()))
+}
+
+/// Fetch a voice channel together with its server ID; any other kind of
+/// channel is a bad request.
+async fn find_voice_channel(state: &AppState, channel_id: Uuid) -> AppResult<(Channel, Uuid)> {
+    let channel = find_channel(state, channel_id).await?;
+    match channel.server_id {
+        Some(server_id) if channel.channel_type == ChannelType::Voice => Ok((channel, server_id)),
+        _ => Err(AppError::BadRequest("Not a voice channel".to_string())),
+    }
+}
+
+/// The permission needed to post in a channel of this type. Voice channels
+/// and categories don't hold messages at all.
+fn post_permission(channel_type: &ChannelType) -> AppResult<i64> {
+    match channel_type {
+        ChannelType::Text => Ok(Permissions::SEND_MESSAGES),
+        // Only moderators post announcements
+        ChannelType::Announcement => Ok(Permissions::MANAGE_MESSAGES),
+        ChannelType::Voice | ChannelType::Category => Err(AppError::BadRequest(
+            "Messages can't be sent to this channel".to_string(),
+        )),
+    }
+}
+
+/// Refuse to create one more of something when `count` already reached
+/// its configured `max`, explaining the limit with `message`.
+fn check_limit(count: i64, max: i64, message: impl FnOnce() -> String) -> AppResult<()> {
+    if count >= max {
+        return Err(AppError::BadRequest(message()));
+    }
+    Ok(())
+}
+
+/// A new message's text with control characters stripped. It may only be
+/// empty when attachments make up the message.
+fn message_content(content: &str, has_attachments: bool) -> AppResult<String> {
+    let content = chat::sanitize_content(content);
+    if !content.is_empty() || !has_attachments {
+        chat::validate_message(&content)?;
+    }
+    Ok(content)
+}
+
+/// Append to a server's audit log. Failures are logged, not returned: the
+/// action being recorded has already happened.
+async fn record_audit(
+    state: &AppState,
+    server_id: Uuid,
+    actor_id: Uuid,
+    action: AuditAction,
+    target_id: Option<Uuid>,
+    role_id: Option<Uuid>,
+    reason: Option<&str>,
+) {
+    if let Err(e) = db::audit::insert(
+        &state.db, server_id, actor_id, action, target_id, role_id, reason,
+    )
+    .await
+    {
+        tracing::warn!("Failed to write audit log entry {:?}: {}", action, e);
+    }
+}
+
+/// Add a user to a server and announce it with `MemberJoin`.
+async fn add_member_and_broadcast(
+    state: &AppState,
+    user_id: Uuid,
+    server_id: Uuid,
+) -> AppResult<()> {
+    let server = db::servers::find_by_id(&state.db, server_id).await?;
+    if server.is_some_and(|s| s.disabled) {
+        return Err(AppError::Forbidden);
+    }
+    if db::bans::find(&state.db, server_id, user_id)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Forbidden);
+    }
+
+    db::members::add(&state.db, user_id, server_id).await?;
+    state.cache_member_add(server_id, user_id);
+
+    // Broadcast MemberJoin to all connected server members
+    if let Ok(Some(user)) = db::users::find_by_id(&state.db, user_id).await {
+        let event = WsEvent::MemberJoin {
+            server_id,
+            user: UserPublic::from(user),
+        };
+        state.broadcast_to_server(&server_id, &event).await;
+    }
+
+    Ok(())
+}
+
 // ─── Application State ─────────────────────────────────────────────────────
 
 /// Shared application state available to all handlers.
@@ -57,32 +279,65 @@ pub struct AppState {
     pub redis: Option<redis::Client>,
     pub config: AppConfig,
     pub snowflake: Arc<SnowflakeGenerator>,
-    /// Connected WebSocket sessions: user_id → sender
-    pub ws_sessions: Arc<DashMap<Uuid, broadcast::Sender<String>>>,
+    /// WebSocket sessions, including dropped ones that can still be resumed: user_id → session
+    pub ws_sessions: Arc<DashMap<Uuid, Arc<WsSession>>>,
     /// Channel subscribers: channel_id → set of user_ids
     pub channel_subs: Arc<DashMap<Uuid, Vec<Uuid>>>,
+    /// Server members: server_id → member user_ids (lazily populated broadcast cache)
+    pub server_members: Arc<DashMap<Uuid, Vec<Uuid>>>,
     pub presence: Arc<PresenceManager>,
     /// HTTP client for calling the auth hub (community mode).
     pub http_client: reqwest::Client,
-    /// Cached validated tokens: token → (user_id, username, validated_at)
-    pub token_cache: Arc<DashMap<String, (Uuid, String, Instant)>>,
-    /// Cached public key PEM from the auth hub (Community mode).
-    pub hub_public_key: Arc<RwLock<Option<Vec<u8>>>>,
+    /// HTTP client for link previews, restricted to public addresses
+    pub embed_client: reqwest::Client,
+    /// Cached validated tokens: token → (user_id, username, token_type, validated_at)
+    pub token_cache: Arc<DashMap<String, (Uuid, String, TokenType, Instant)>>,
+    /// Cached JWKS from the auth hub and when it was fetched (Community mode).
+    pub hub_jwks: Arc<RwLock<Option<(JwkSet, Instant)>>>,
+    /// Profiles fetched from the auth hub: user_id → (profile, fetched_at)
+    pub hub_profiles: Arc<DashMap<Uuid, (UserPublic, Instant)>>,
     /// Voice channel participants: channel_id → list of VoiceParticipant
     pub voice_states: Arc<DashMap<Uuid, Vec<VoiceParticipant>>>,
+    /// Moderator-enforced voice mutes: server_id → muted user ids
+    pub voice_mutes: Arc<DashMap<Uuid, HashSet<Uuid>>>,
+    /// Redis mirror of `voice_states` (only when Redis is configured)
+    pub voice_store: Option<Arc<VoiceStateStore>>,
     /// SFU server for WebRTC relay
     pub sfu: Arc<crate::voice::SfuServer>,
+    /// Per-IP limiter for login/register attempts
+    pub auth_limiter: Arc<RateLimiter<IpAddr>>,
+    /// Per-user limit on sending messages, across all channels
+    pub message_limiter: Arc<RateLimiter<Uuid>>,
+    /// Link previews fetched per author (user or webhook), at the same rate
+    /// as messages: edits and webhooks can trigger them too
+    pub unfurl_limiter: Arc<RateLimiter<Uuid>>,
+    /// Backend holding avatars and attachments (local disk or S3)
+    pub storage: Arc<dyn Storage>,
+    /// Slow mode: (channel_id, user_id) → when the user last posted there
+    pub last_posts: Arc<DashMap<(Uuid, Uuid), Instant>>,
+    /// Thumbnail keys of legacy avatars that couldn't be rendered, so they
+    /// aren't decoded again on every request
+    pub failed_avatar_thumbnails: Arc<DashSet<String>>,
 }
 
 /// Duration to cache validated tokens (60 seconds).
 const TOKEN_CACHE_TTL_SECS: u64 = 60;
 
+/// How long a profile fetched from the auth hub is served from cache.
+const HUB_PROFILE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Minimum time between JWKS refetches triggered by an unknown `kid`, so
+/// forged tokens can't hammer the auth hub.
+const JWKS_REFRESH_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Size of `last_posts` above which expired slow-mode entries are pruned.
+const SLOW_MODE_PRUNE_THRESHOLD: usize = 10_000;
+
 impl AppState {
     pub fn new(db: DbPool, redis: Option<redis::Client>, config: AppConfig) -> Self {
-        let voice_public_ip = config.voice.public_ip.clone();
-        let ws_sessions: Arc<DashMap<Uuid, broadcast::Sender<String>>> = Arc::new(DashMap::new());
+        let ws_sessions: Arc<DashMap<Uuid, Arc<WsSession>>> = Arc::new(DashMap::new());
         let sfu = Arc::new(
-            crate::voice::SfuServer::new(voice_public_ip).expect("Failed to initialize SFU"),
+            crate::voice::SfuServer::new(&config.voice).expect("Failed to initialize SFU"),
         );
 
         // Wire up the SFU's ws_sender so it can push signaling messages to clients.
@@ -92,31 +347,215 @@ impl AppState {
             tokio::spawn(async move {
                 let sender: crate::voice::WsSenderFn =
                     Arc::new(move |target_user_id: Uuid, event: serde_json::Value| {
-                        if let Some(tx) = ws_sessions_c.get(&target_user_id) {
+                        if let Some(session) = ws_sessions_c.get(&target_user_id) {
                             let json = serde_json::to_string(&event).unwrap_or_default();
-                            let _ = tx.send(json);
+                            session.send(&json);
                         }
                     });
                 sfu_c.set_ws_sender(sender).await;
             });
         }
 
-        Self {
+        let auth_limiter = Arc::new(RateLimiter::new(
+            config.auth.rate_limit.attempts,
+            std::time::Duration::from_secs(config.auth.rate_limit.window_secs),
+        ));
+        let message_limiter = Arc::new(RateLimiter::new(
+            config.messages.rate_limit,
+            Duration::from_secs(config.messages.rate_limit_window_secs),
+        ));
+        let unfurl_limiter = Arc::new(RateLimiter::new(
+            config.messages.rate_limit,
+            Duration::from_secs(config.messages.rate_limit_window_secs),
+        ));
+
+        let voice_store = redis
+            .clone()
+            .map(|client| Arc::new(VoiceStateStore::new(client)));
+
+        let snowflake = Arc::new(SnowflakeGenerator::new(config.server.worker_id));
+        let presence = Arc::new(PresenceManager::new(Duration::from_secs(
+            config.presence.idle_timeout_secs,
+        )));
+        let storage =
+            crate::storage::from_config(&config.storage).expect("Failed to initialize storage");
+
+        let state = Self {
             db,
             redis,
             config,
-            snowflake: Arc::new(SnowflakeGenerator::new(1)),
+            snowflake,
             ws_sessions,
             channel_subs: Arc::new(DashMap::new()),
-            presence: Arc::new(PresenceManager::new()),
+            server_members: Arc::new(DashMap::new()),
+            presence,
             http_client: reqwest::Client::builder()
                 .timeout(std::time::Duration::from_secs(10))
                 .build()
                 .unwrap_or_default(),
+            embed_client: embeds::client(),
             token_cache: Arc::new(DashMap::new()),
-            hub_public_key: Arc::new(RwLock::new(None)),
+            hub_jwks: Arc::new(RwLock::new(None)),
+            hub_profiles: Arc::new(DashMap::new()),
             voice_states: Arc::new(DashMap::new()),
+            voice_mutes: Arc::new(DashMap::new()),
             sfu,
+            auth_limiter,
+            message_limiter,
+            unfurl_limiter,
+            voice_store,
+            storage,
+            last_posts: Arc::new(DashMap::new()),
+            failed_avatar_thumbnails: Arc::new(DashSet::new()),
+        };
+
+        // Drop voice participants whose WebRTC connection died without a leave
+        {
+            let state_c = state.clone();
+            tokio::spawn(async move {
+                let sfu = state_c.sfu.clone();
+                let handler: crate::voice::PeerLostFn =
+                    Arc::new(move |channel_id: Uuid, user_id: Uuid| {
+                        let in_channel = state_c
+                            .voice_states
+                            .get(&channel_id)
+                            .is_some_and(|p| p.iter().any(|p| p.user_id == user_id));
+                        if !in_channel {
+                            return;
+                        }
+                        tracing::info!(
+                            "Voice peer for user {} in channel {} dropped, cleaning up",
+                            user_id,
+                            channel_id
+                        );
+                        let state_c = state_c.clone();
+                        tokio::spawn(async move {
+                            leave_voice_channel(&state_c, channel_id, user_id).await;
+                        });
+                    });
+                sfu.set_peer_lost_handler(handler).await;
+            });
+        }
+
+        // Announce users the presence manager turns Idle after inactivity
+        {
+            let state_c = state.clone();
+            tokio::spawn(state.presence.clone().idle_loop(move |user_id| {
+                let state_c = state_c.clone();
+                tokio::spawn(async move {
+                    state_c
+                        .broadcast_presence(user_id, PresenceStatus::Idle)
+                        .await;
+                });
+            }));
+        }
+
+        // Route SFU voice activity to the voice channel's subscribers
+        {
+            let state_c = state.clone();
+            tokio::spawn(async move {
+                let sfu = state_c.sfu.clone();
+                let handler: crate::voice::SpeakingFn =
+                    Arc::new(move |channel_id: Uuid, user_id: Uuid, speaking: bool| {
+                        state_c.broadcast_to_channel(
+                            &channel_id,
+                            &WsEvent::VoiceSpeaking {
+                                channel_id,
+                                user_id,
+                                speaking,
+                            },
+                        );
+                    });
+                sfu.set_speaking_handler(handler).await;
+            });
+        }
+
+        // Keep our participants' Redis entries from expiring while they're connected
+        if let Some(store) = state.voice_store.clone() {
+            let state_c = state.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(crate::voice_state::PARTICIPANT_TTL / 3);
+                loop {
+                    ticker.tick().await;
+                    // `voice_states` also mirrors other instances' participants;
+                    // theirs must be left to expire if that instance dies
+                    let participants: Vec<VoiceParticipant> = state_c
+                        .voice_states
+                        .iter()
+                        .flat_map(|entry| entry.value().clone())
+                        .filter(|p| state_c.is_connected(&p.user_id))
+                        .collect();
+                    for participant in &participants {
+                        if let Err(e) = store.save(participant).await {
+                            tracing::warn!("Failed to refresh voice state in Redis: {}", e);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
+        // Rebroadcast voice events published by other instances to our own subscribers
+        if let Some(store) = state.voice_store.clone() {
+            let state_c = state.clone();
+            tokio::spawn(async move {
+                let result = store
+                    .subscribe(|channel_id, event| {
+                        state_c.apply_remote_voice_event(&event);
+                        state_c.broadcast_to_channel(&channel_id, &event);
+                    })
+                    .await;
+                if let Err(e) = result {
+                    tracing::error!("Voice state subscription ended: {}", e);
+                }
+            });
+        }
+
+        state
+    }
+
+    /// Mirror a voice join, leave or state change published by another
+    /// instance into `voice_states`, so Ready and voice checks here see it.
+    fn apply_remote_voice_event(&self, event: &WsEvent) {
+        let WsEvent::VoiceStateUpdate {
+            channel_id,
+            user_id,
+            joined,
+            muted,
+            deafened,
+            user,
+        } = event
+        else {
+            return;
+        };
+
+        if !joined {
+            if let Some(mut participants) = self.voice_states.get_mut(channel_id) {
+                participants.retain(|p| p.user_id != *user_id);
+                if participants.is_empty() {
+                    drop(participants);
+                    self.voice_states.remove(channel_id);
+                }
+            }
+            return;
+        }
+
+        let mut participants = self.voice_states.entry(*channel_id).or_default();
+        match participants.iter_mut().find(|p| p.user_id == *user_id) {
+            Some(p) => {
+                p.muted = *muted;
+                p.deafened = *deafened;
+                if user.is_some() {
+                    p.user = user.clone();
+                }
+            }
+            None => participants.push(VoiceParticipant {
+                user_id: *user_id,
+                channel_id: *channel_id,
+                muted: *muted,
+                deafened: *deafened,
+                user: user.clone(),
+            }),
         }
     }
 
@@ -125,168 +564,553 @@ impl AppState {
         if let Some(user_ids) = self.channel_subs.get(channel_id) {
             let json = serde_json::to_string(event).unwrap_or_default();
             for user_id in user_ids.iter() {
-                if let Some(sender) = self.ws_sessions.get(user_id) {
-                    let _ = sender.send(json.clone());
+                if let Some(session) = self.ws_sessions.get(user_id) {
+                    session.send(&json);
+                }
+            }
+        }
+    }
+
+    /// Broadcast an event to all users subscribed to a channel except one
+    /// (typically the user who triggered it).
+    pub fn broadcast_to_channel_except(&self, channel_id: &Uuid, event: &WsEvent, exclude: &Uuid) {
+        if let Some(user_ids) = self.channel_subs.get(channel_id) {
+            let json = serde_json::to_string(event).unwrap_or_default();
+            for user_id in user_ids.iter().filter(|id| *id != exclude) {
+                if let Some(session) = self.ws_sessions.get(user_id) {
+                    session.send(&json);
                 }
             }
         }
     }
 
+    /// Whether the user has a live gateway connection. A session waiting out
+    /// its resume window still buffers events, but doesn't count.
+    pub fn is_connected(&self, user_id: &Uuid) -> bool {
+        self.ws_sessions
+            .get(user_id)
+            .is_some_and(|session| session.is_attached())
+    }
+
     /// Broadcast an event specifically to a single user's WebSocket sessions.
     pub fn broadcast_to_user(&self, user_id: &Uuid, event: &WsEvent) {
-        if let Some(sender) = self.ws_sessions.get(user_id) {
+        if let Some(session) = self.ws_sessions.get(user_id) {
             let json = serde_json::to_string(event).unwrap_or_default();
-            let _ = sender.send(json);
+            session.send(&json);
         }
     }
 
     /// Broadcast an event to all connected members of a server.
-    /// This directly queries all members of the server rather than just active channel listeners.
+    /// This reaches all members of the server rather than just active channel listeners.
+    /// The member list is loaded from the database once and then served from `server_members`.
     pub async fn broadcast_to_server(&self, server_id: &Uuid, event: &WsEvent) {
-        if let Ok(members) = db::servers::list_members(&self.db, *server_id).await {
-            let json = serde_json::to_string(event).unwrap_or_default();
-            for member in members {
-                // Check if they are currently online by inspecting our active ws_sessions hash map
-                if let Some(sender) = self.ws_sessions.get(&member.user_id) {
-                    let _ = sender.send(json.clone());
-                }
+        let Ok(member_ids) = self.server_member_ids(server_id).await else {
+            return;
+        };
+
+        let json = serde_json::to_string(event).unwrap_or_default();
+        for user_id in member_ids {
+            // Check if they are currently online by inspecting our active ws_sessions hash map
+            if let Some(session) = self.ws_sessions.get(&user_id) {
+                session.send(&json);
             }
         }
     }
 
-    /// Validate a token, either locally (auth hub / standalone) or via the
-    /// auth hub's public key (community — fetched once and cached).
-    pub async fn validate_token_federated(&self, token: &str) -> AppResult<(Uuid, String)> {
-        // Check cache first
-        if let Some(entry) = self.token_cache.get(token) {
-            let (user_id, username, cached_at) = entry.value().clone();
-            if cached_at.elapsed().as_secs() < TOKEN_CACHE_TTL_SECS {
-                return Ok((user_id, username));
-            } else {
-                drop(entry);
-                self.token_cache.remove(token);
+    /// Broadcast an event exactly once to every user who shares at least one
+    /// server with `user_id` (excluding the user themselves).
+    pub async fn broadcast_to_mutuals(&self, user_id: &Uuid, event: &WsEvent) {
+        let Ok(servers) = db::servers::list_for_user(&self.db, *user_id).await else {
+            return;
+        };
+
+        let mut recipients = HashSet::new();
+        for server in servers {
+            if let Ok(member_ids) = self.server_member_ids(&server.id).await {
+                recipients.extend(member_ids);
             }
         }
+        recipients.remove(user_id);
 
-        let (user_id, username) = match self.config.mode {
-            ServerMode::Community => {
-                // Fetch the auth hub's public key if we haven't yet
-                let pub_key = {
-                    let cached = self.hub_public_key.read().await;
-                    cached.clone()
-                };
+        let json = serde_json::to_string(event).unwrap_or_default();
+        for recipient in recipients {
+            if let Some(session) = self.ws_sessions.get(&recipient) {
+                session.send(&json);
+            }
+        }
+    }
 
-                let pub_key_pem = match pub_key {
-                    Some(key) => key,
-                    None => {
-                        let hub_url = &self.config.identity.auth_hub_url;
-                        if hub_url.is_empty() {
-                            return Err(AppError::Internal(anyhow::anyhow!(
-                                "auth_hub_url not configured for community mode"
-                            )));
-                        }
+    /// Tell a user's mutuals and their own sessions about a status change
+    /// that wasn't requested through `update_presence`.
+    pub async fn broadcast_presence(&self, user_id: Uuid, status: PresenceStatus) {
+        let event = WsEvent::PresenceUpdate {
+            user_id,
+            status,
+            custom_text: self.presence.get_custom_text(user_id),
+        };
+        self.broadcast_to_mutuals(&user_id, &event).await;
+        self.broadcast_to_user(&user_id, &event);
+    }
 
-                        tracing::info!("Fetching auth hub public key from {}", hub_url);
-                        let resp = self
-                            .http_client
-                            .get(format!("{}/api/auth/public-key", hub_url))
-                            .send()
-                            .await
-                            .map_err(|e| {
-                                AppError::Internal(anyhow::anyhow!(
-                                    "Failed to fetch public key from auth hub: {}",
-                                    e
-                                ))
-                            })?;
-
-                        if !resp.status().is_success() {
-                            return Err(AppError::Internal(anyhow::anyhow!(
-                                "Auth hub returned {} for public key request",
-                                resp.status()
-                            )));
-                        }
+    /// Member user ids of a server, served from `server_members` once loaded.
+    async fn server_member_ids(&self, server_id: &Uuid) -> AppResult<Vec<Uuid>> {
+        if let Some(ids) = self.server_members.get(server_id) {
+            return Ok(ids.value().clone());
+        }
 
-                        let body: PublicKeyResponse = resp.json().await.map_err(|e| {
-                            AppError::Internal(anyhow::anyhow!(
-                                "Invalid public key response: {}",
-                                e
-                            ))
-                        })?;
-
-                        let key_bytes = body.public_key_pem.into_bytes();
-                        // Cache it
-                        let mut cached = self.hub_public_key.write().await;
-                        *cached = Some(key_bytes.clone());
-                        key_bytes
-                    }
-                };
+        let members = db::servers::list_members(&self.db, *server_id).await?;
+        let ids: Vec<Uuid> = members.into_iter().map(|m| m.user_id).collect();
+        self.server_members.insert(*server_id, ids.clone());
+        Ok(ids)
+    }
+
+    /// Record a new server member in the broadcast cache (if the server is cached).
+    pub fn cache_member_add(&self, server_id: Uuid, user_id: Uuid) {
+        if let Some(mut ids) = self.server_members.get_mut(&server_id) {
+            if !ids.contains(&user_id) {
+                ids.push(user_id);
+            }
+        }
+    }
+
+    /// Drop a departed server member from the broadcast cache.
+    pub fn cache_member_remove(&self, server_id: Uuid, user_id: Uuid) {
+        if let Some(mut ids) = self.server_members.get_mut(&server_id) {
+            ids.retain(|id| *id != user_id);
+        }
+    }
 
-                // Validate the token locally using the hub's public key
-                let claims = auth::validate_token_with_public_key(&pub_key_pem, token)?;
-                let uid = auth::user_id_from_claims(&claims)?;
-                let uname = claims.username;
+    /// Enforce a channel's slow mode for `user_id`, recording this post if
+    /// it's allowed. Errors with the seconds left until they may post again.
+    pub fn check_slow_mode(
+        &self,
+        channel_id: Uuid,
+        user_id: Uuid,
+        cooldown: Duration,
+    ) -> AppResult<()> {
+        if self.last_posts.len() > SLOW_MODE_PRUNE_THRESHOLD {
+            let max = Duration::from_secs(chat::MAX_SLOW_MODE_SECS as u64);
+            self.last_posts.retain(|_, at| at.elapsed() < max);
+        }
 
-                (uid, uname)
+        use dashmap::mapref::entry::Entry;
+
+        let now = Instant::now();
+        match self.last_posts.entry((channel_id, user_id)) {
+            Entry::Occupied(mut last) => {
+                let elapsed = now.duration_since(*last.get());
+                if elapsed < cooldown {
+                    let remaining = cooldown - elapsed;
+                    return Err(AppError::RateLimited(Some(
+                        remaining.as_secs_f64().ceil() as u64
+                    )));
+                }
+                last.insert(now);
             }
-            _ => {
-                // Local validation (auth hub or standalone)
-                let claims = auth::validate_token(&self.config.auth, token)?;
-                let uid = auth::user_id_from_claims(&claims)?;
-                (uid, claims.username)
+            Entry::Vacant(slot) => {
+                slot.insert(now);
             }
-        };
+        }
+        Ok(())
+    }
 
-        // Cache the result
-        self.token_cache.insert(
-            token.to_string(),
-            (user_id, username.clone(), Instant::now()),
-        );
+    /// Drop validated-token cache entries older than `TOKEN_CACHE_TTL_SECS`.
+    /// Returns how many were removed.
+    pub fn sweep_token_cache(&self) -> usize {
+        let before = self.token_cache.len();
+        self.token_cache
+            .retain(|_, (_, _, _, cached_at)| cached_at.elapsed().as_secs() < TOKEN_CACHE_TTL_SECS);
+        before.saturating_sub(self.token_cache.len())
+    }
 
-        Ok((user_id, username))
+    /// Periodically sweep the token cache so tokens that are never
+    /// re-validated (e.g. from disconnected clients) don't pile up.
+    pub async fn token_cache_sweep_loop(self, every: Duration) {
+        let mut ticker = tokio::time::interval(every);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let removed = self.sweep_token_cache();
+            if removed > 0 {
+                tracing::debug!("Evicted {} expired token cache entries", removed);
+            }
+            self.hub_profiles
+                .retain(|_, (_, fetched_at)| fetched_at.elapsed() < HUB_PROFILE_TTL);
+        }
     }
-}
 
-// ─── JWT Auth Extractor ─────────────────────────────────────────────────────
+    /// The auth hub's key set, fetched on first use and again when a token
+    /// names a `kid` we haven't seen (the hub rotated its signing key).
+    async fn hub_jwks(&self, token: &str) -> AppResult<JwkSet> {
+        let kid = auth::token_key_id(token);
+        if let Some((jwks, fetched_at)) = self.hub_jwks.read().await.as_ref() {
+            let known = kid.as_deref().is_none_or(|kid| jwks.find(kid).is_some());
+            if known || fetched_at.elapsed() < JWKS_REFRESH_COOLDOWN {
+                return Ok(jwks.clone());
+            }
+        }
 
-/// Authenticated user extracted from the `Authorization: Bearer <token>` header.
-/// Supports both local validation (auth hub / standalone) and federated
-/// validation (community mode → calls auth hub with caching).
-pub struct AuthUser {
-    pub user_id: Uuid,
-}
+        let hub_url = &self.config.identity.auth_hub_url;
+        if hub_url.is_empty() {
+            return Err(AppError::Internal(anyhow::anyhow!(
+                "auth_hub_url not configured for community mode"
+            )));
+        }
 
-#[axum::async_trait]
-impl FromRequestParts<AppState> for AuthUser {
-    type Rejection = AppError;
+        tracing::info!("Fetching auth hub JWKS from {}", hub_url);
+        let resp = self
+            .http_client
+            .get(format!("{}/api/auth/.well-known/jwks.json", hub_url))
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::Internal(anyhow::anyhow!("Failed to fetch JWKS from auth hub: {}", e))
+            })?;
+
+        // Hubs from before key rotation only publish their one key as PEM
+        let jwks = if resp.status() == StatusCode::NOT_FOUND {
+            self.hub_legacy_public_key(hub_url).await?
+        } else if !resp.status().is_success() {
+            return Err(AppError::Internal(anyhow::anyhow!(
+                "Auth hub returned {} for JWKS request",
+                resp.status()
+            )));
+        } else {
+            resp.json()
+                .await
+                .map_err(|e| AppError::Internal(anyhow::anyhow!("Invalid JWKS response: {}", e)))?
+        };
 
-    async fn from_request_parts(
-        parts: &mut Parts,
-        state: &AppState,
-    ) -> Result<Self, Self::Rejection> {
-        let header = parts
-            .headers
-            .get("Authorization")
-            .and_then(|v| v.to_str().ok())
-            .ok_or(AppError::Unauthorized)?;
+        *self.hub_jwks.write().await = Some((jwks.clone(), Instant::now()));
+        Ok(jwks)
+    }
 
-        let token = header
-            .strip_prefix("Bearer ")
-            .ok_or(AppError::Unauthorized)?;
+    /// The key set of a hub that only serves `/api/auth/public-key`.
+    async fn hub_legacy_public_key(&self, hub_url: &str) -> AppResult<JwkSet> {
+        tracing::info!("Auth hub has no JWKS, fetching its public key instead");
+        let resp = self
+            .http_client
+            .get(format!("{}/api/auth/public-key", hub_url))
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::Internal(anyhow::anyhow!(
+                    "Failed to fetch public key from auth hub: {}",
+                    e
+                ))
+            })?;
+
+        if !resp.status().is_success() {
+            return Err(AppError::Internal(anyhow::anyhow!(
+                "Auth hub returned {} for public key request",
+                resp.status()
+            )));
+        }
 
-        let (user_id, _username) = state.validate_token_federated(token).await?;
+        let key: PublicKeyResponse = resp.json().await.map_err(|e| {
+            AppError::Internal(anyhow::anyhow!("Invalid public key response: {}", e))
+        })?;
+        Ok(JwkSet {
+            keys: vec![auth::rsa_public_jwk(&key.public_key_pem)?],
+        })
+    }
 
-        Ok(AuthUser { user_id })
+    /// Ask the auth hub whether it still accepts `token`. Logouts and password
+    /// changes are recorded at the hub, so the signature check alone can't see
+    /// them. If the hub can't be reached, the signature check decides: an
+    /// outage shouldn't lock everyone out.
+    async fn hub_accepts_token(&self, token: &str) -> bool {
+        let hub_url = &self.config.identity.auth_hub_url;
+        let resp = self
+            .http_client
+            .post(format!("{}/api/auth/validate", hub_url))
+            .json(&ValidateTokenRequest {
+                token: token.to_string(),
+            })
+            .send()
+            .await;
+        let body = match resp {
+            Ok(resp) if resp.status().is_success() => resp.json::<ValidateTokenResponse>().await,
+            Ok(resp) => {
+                tracing::warn!("Auth hub returned {} for token validation", resp.status());
+                return true;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to reach auth hub for token validation: {}", e);
+                return true;
+            }
+        };
+        match body {
+            Ok(body) => body.valid,
+            Err(e) => {
+                tracing::warn!("Invalid token validation response: {}", e);
+                true
+            }
+        }
     }
-}
 
-// ─── Auth Hub Validation Types ──────────────────────────────────────────────
+    /// A user's current profile from the auth hub, cached for `HUB_PROFILE_TTL`.
+    /// The hub's `GET /api/users/:user_id` needs a token, so the caller's own is
+    /// passed along. The local user row is refreshed from the result.
+    async fn hub_profile(&self, user_id: Uuid, token: &str) -> AppResult<UserPublic> {
+        if let Some(entry) = self.hub_profiles.get(&user_id) {
+            let (profile, fetched_at) = entry.value();
+            if fetched_at.elapsed() < HUB_PROFILE_TTL {
+                return Ok(profile.clone());
+            }
+        }
 
-#[derive(Debug, Deserialize)]
-struct ValidateTokenRequest {
-    token: String,
-}
+        let hub_url = &self.config.identity.auth_hub_url;
+        if hub_url.is_empty() {
+            return Err(AppError::Internal(anyhow::anyhow!(
+                "auth_hub_url not configured for community mode"
+            )));
+        }
 
-#[derive(Debug, Serialize, Deserialize)]
+        let resp = self
+            .http_client
+            .get(format!("{}/api/users/{}", hub_url, user_id))
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::Internal(anyhow::anyhow!(
+                    "Failed to fetch profile from auth hub: {}",
+                    e
+                ))
+            })?;
+
+        match resp.status() {
+            StatusCode::NOT_FOUND => return Err(AppError::NotFound("User not found".to_string())),
+            StatusCode::UNAUTHORIZED => return Err(AppError::Unauthorized),
+            status if !status.is_success() => {
+                return Err(AppError::Internal(anyhow::anyhow!(
+                    "Auth hub returned {} for profile request",
+                    status
+                )));
+            }
+            _ => {}
+        }
+
+        let profile: UserPublic = resp
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(anyhow::anyhow!("Invalid profile response: {}", e)))?;
+
+        if let Err(e) = db::users::upsert_federated(
+            &self.db,
+            profile.id,
+            &profile.username,
+            &profile.display_name,
+            profile.avatar_hash.as_deref(),
+        )
+        .await
+        {
+            tracing::warn!("Failed to store hub profile of {}: {:?}", profile.id, e);
+        }
+
+        self.hub_profiles
+            .insert(user_id, (profile.clone(), Instant::now()));
+        Ok(profile)
+    }
+
+    /// Validate a token, either locally (auth hub / standalone) or against the
+    /// auth hub's published keys (community — cached, refetched on rotation).
+    pub async fn validate_token_federated(
+        &self,
+        token: &str,
+    ) -> AppResult<(Uuid, String, TokenType)> {
+        // Check cache first
+        if let Some(entry) = self.token_cache.get(token) {
+            let (user_id, username, token_type, cached_at) = entry.value().clone();
+            if cached_at.elapsed().as_secs() < TOKEN_CACHE_TTL_SECS {
+                return Ok((user_id, username, token_type));
+            } else {
+                drop(entry);
+                self.token_cache.remove(token);
+            }
+        }
+
+        let claims = match self.config.mode {
+            // With a shared secret there is no public key to fetch
+            ServerMode::Community if self.config.auth.algorithm == JwtAlgorithm::Hs256 => {
+                auth::validate_token(&self.config.auth, token)?
+            }
+            ServerMode::Community => {
+                // Validate locally against the auth hub's published keys
+                let jwks = self.hub_jwks(token).await?;
+                auth::validate_token_with_jwks(&jwks, token)?
+            }
+            _ => {
+                // Local validation (auth hub or standalone)
+                auth::validate_token(&self.config.auth, token)?
+            }
+        };
+
+        // Reject tokens that were revoked via logout
+        if !claims.jti.is_empty() && db::revoked_tokens::is_revoked(&self.db, &claims.jti).await? {
+            return Err(AppError::Unauthorized);
+        }
+
+        let user_id = auth::user_id_from_claims(&claims)?;
+
+        // Reject tokens issued before the user last changed their password
+        if let Some(cutoff) = db::users::tokens_valid_after(&self.db, user_id).await? {
+            if claims.iat < cutoff.timestamp() {
+                return Err(AppError::Unauthorized);
+            }
+        }
+
+        // Hub tokens are revoked at the hub. Asking once per cache miss means a
+        // revoked token stops working here within `TOKEN_CACHE_TTL_SECS`.
+        if self.config.mode == ServerMode::Community && !self.hub_accepts_token(token).await {
+            return Err(AppError::Unauthorized);
+        }
+
+        let username = claims.username;
+        let token_type = claims.token_type;
+
+        // Cache the result
+        self.token_cache.insert(
+            token.to_string(),
+            (user_id, username.clone(), token_type, Instant::now()),
+        );
+
+        Ok((user_id, username, token_type))
+    }
+}
+
+// ─── JWT Auth Extractor ─────────────────────────────────────────────────────
+
+/// Authenticated user extracted from the `Authorization: Bearer <token>` header.
+/// Supports both local validation (auth hub / standalone) and federated
+/// validation (community mode → calls auth hub with caching).
+pub struct AuthUser {
+    pub user_id: Uuid,
+    /// Authenticated with a bot token
+    pub is_bot: bool,
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .ok_or(AppError::Unauthorized)?;
+
+        let token = header
+            .strip_prefix("Bearer ")
+            .ok_or(AppError::Unauthorized)?;
+
+        let (user_id, _username, token_type) = state.validate_token_federated(token).await?;
+
+        Ok(AuthUser {
+            user_id,
+            is_bot: token_type.is_bot(),
+        })
+    }
+}
+
+/// An authenticated instance admin (`users.is_admin`). Distinct from server
+/// permissions: it grants nothing inside a server, only the `/api/admin` routes.
+pub struct AdminUser {
+    pub user_id: Uuid,
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let auth = AuthUser::from_request_parts(parts, state).await?;
+        if auth.is_bot {
+            return Err(AppError::Forbidden);
+        }
+
+        let is_admin = db::users::find_by_id(&state.db, auth.user_id)
+            .await?
+            .is_some_and(|user| user.is_admin);
+        if !is_admin {
+            return Err(AppError::Forbidden);
+        }
+
+        Ok(AdminUser {
+            user_id: auth.user_id,
+        })
+    }
+}
+
+/// The client's IP address, for per-client rate limits. Behind a reverse
+/// proxy every connection comes from the proxy, so `X-Forwarded-For` is used
+/// instead, but only when the peer is one of `auth.rate_limit.trusted_proxies`:
+/// anyone else could set the header to get a fresh bucket per request.
+pub struct ClientIp(pub IpAddr);
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(peer) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .copied()
+            .ok_or_else(|| AppError::Internal(anyhow::anyhow!("Missing peer address")))?;
+        let trusted = &state.config.auth.rate_limit.trusted_proxies;
+        Ok(ClientIp(client_ip(peer.ip(), &parts.headers, trusted)))
+    }
+}
+
+/// Resolve the client behind `peer`. `X-Forwarded-For` is read right to left,
+/// since each proxy appends the address it got the request from: the first
+/// hop that isn't a trusted proxy is the client. A malformed entry stops the
+/// walk at the last hop known to be genuine.
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    if !trusted_proxies.contains(&client) {
+        return client;
+    }
+    let hops = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .collect::<Vec<_>>();
+    for hop in hops.into_iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+                if !trusted_proxies.contains(&ip) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+// ─── Auth Hub Validation Types ──────────────────────────────────────────────
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ValidateTokenRequest {
+    token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 struct ValidateTokenResponse {
     valid: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -314,15 +1138,41 @@ pub fn build_router(state: AppState) -> Router {
     // Always available
     router = router
         .route("/health", get(health_check))
-        .route("/api/instance/info", get(instance_info));
+        .route("/api/instance/info", get(instance_info))
+        .route(
+            "/api/users/@me",
+            get(get_current_user).patch(update_current_user),
+        )
+        .route("/api/users/:user_id", get(get_user))
+        .route("/api/users/:user_id/federated", get(get_federated_user))
+        .route("/api/openapi.json", get(crate::openapi::openapi_json));
 
     // Auth endpoints (auth hub + standalone)
     if state.config.is_auth_hub() {
         router = router
             .route("/api/auth/register", post(register))
             .route("/api/auth/login", post(login))
+            .route("/api/auth/logout", post(logout))
+            .route("/api/auth/refresh", post(refresh_session))
+            .route("/api/auth/change-password", post(change_password))
+            .route("/api/users/@me", delete(delete_account))
+            // Bots are accounts like any other, so they live on the hub too;
+            // community servers accept their tokens through it
+            .route("/api/bots", post(create_bot))
+            .route("/api/bots/:bot_id/token", post(reset_bot_token))
             .route("/api/auth/validate", post(validate_token_endpoint))
-            .route("/api/auth/public-key", get(public_key_endpoint));
+            .route("/api/auth/public-key", get(public_key_endpoint))
+            .route("/api/auth/.well-known/jwks.json", get(jwks_endpoint))
+            .route(
+                "/api/admin/registration-invites",
+                post(create_registration_invite).get(list_registration_invites),
+            )
+            .route(
+                "/api/admin/registration-invites/:code",
+                delete(delete_registration_invite),
+            )
+            .route("/api/admin/users", get(admin_list_users))
+            .route("/api/admin/users/:user_id", delete(admin_delete_user));
     }
 
     // Community endpoints (community + standalone)
@@ -331,15 +1181,40 @@ pub fn build_router(state: AppState) -> Router {
             // Servers
             .route("/api/servers", post(create_server))
             .route("/api/servers", get(list_servers))
-            .route("/api/servers/:server_id", get(get_server))
+            .route(
+                "/api/servers/:server_id",
+                get(get_server).patch(update_server).delete(delete_server),
+            )
             .route("/api/servers/:server_id/join", post(join_server))
             .route("/api/servers/:server_id/leave", post(leave_server))
+            .route("/api/servers/:server_id/transfer", post(transfer_server))
+            .route(
+                "/api/admin/servers/:server_id/disable",
+                post(admin_disable_server),
+            )
+            .route(
+                "/api/admin/servers/:server_id/enable",
+                post(admin_enable_server),
+            )
+            // Invites
+            .route(
+                "/api/servers/:server_id/invites",
+                post(create_invite).get(list_invites),
+            )
+            .route(
+                "/api/invites/:code",
+                post(redeem_invite).delete(delete_invite),
+            )
             // Channels
             .route("/api/servers/:server_id/channels", post(create_channel))
             .route("/api/servers/:server_id/channels", get(list_channels))
+            .route(
+                "/api/servers/:server_id/channels/reorder",
+                axum::routing::patch(reorder_channels),
+            )
             .route(
                 "/api/servers/:server_id/channels/:channel_id",
-                delete(delete_channel),
+                delete(delete_channel).patch(update_channel),
             )
             // Roles
             .route("/api/servers/:server_id/roles", get(list_roles))
@@ -359,48 +1234,152 @@ pub fn build_router(state: AppState) -> Router {
             .route("/api/servers/:server_id/members", get(list_members))
             .route(
                 "/api/servers/:server_id/members/:user_id",
-                get(get_member).delete(kick_member),
+                get(get_member).patch(update_member).delete(kick_member),
             )
+            // Audit log
+            .route("/api/servers/:server_id/audit-logs", get(list_audit_logs))
             // Bans
             .route("/api/servers/:server_id/bans", get(list_bans))
             .route(
                 "/api/servers/:server_id/bans/:user_id",
                 post(ban_member).delete(unban_member),
             )
+            // Custom emojis
+            .route(
+                "/api/servers/:server_id/emojis",
+                get(list_emojis).post(upload_emoji),
+            )
+            .route(
+                "/api/servers/:server_id/emojis/:emoji_id",
+                delete(delete_emoji),
+            )
+            .route(
+                "/api/channels/:channel_id/permissions/:target_id",
+                put(set_channel_override).delete(delete_channel_override),
+            )
             // Messages
             .route("/api/channels/:channel_id/messages", post(send_message))
             .route("/api/channels/:channel_id/messages", get(get_messages))
             .route(
                 "/api/channels/:channel_id/messages/:message_id",
-                delete(delete_message),
+                get(get_message).delete(delete_message).patch(edit_message),
+            )
+            .route(
+                "/api/channels/:channel_id/messages/:message_id/history",
+                get(get_message_history),
+            )
+            .route(
+                "/api/channels/:channel_id/messages/search",
+                get(search_messages),
+            )
+            .route(
+                "/api/channels/:channel_id/messages/bulk-delete",
+                post(bulk_delete_messages),
+            )
+            .route("/api/channels/:channel_id/ack", post(ack_channel))
+            .route("/api/channels/:channel_id/typing", post(trigger_typing))
+            // Webhooks
+            .route(
+                "/api/channels/:channel_id/webhooks",
+                post(create_webhook).get(list_webhooks),
+            )
+            .route(
+                "/api/channels/:channel_id/webhooks/:webhook_id",
+                delete(delete_webhook),
+            )
+            .route("/api/webhooks/:webhook_id/:token", post(execute_webhook))
+            .route("/api/channels/:channel_id/pins", get(list_pins))
+            .route(
+                "/api/channels/:channel_id/pins/:message_id",
+                put(pin_message).delete(unpin_message),
+            )
+            .route(
+                "/api/channels/:channel_id/messages/:message_id/reactions/:emoji",
+                put(add_reaction).delete(remove_reaction),
             )
             // WebSocket gateway
             .route("/ws", get(ws_upgrade))
             // Avatars
             .route("/api/users/@me/avatar", put(upload_avatar))
+            .route(
+                "/api/users/@me/presence",
+                axum::routing::patch(update_presence),
+            )
             .route("/api/avatars/:user_id/:hash", get(get_avatar))
+            .route("/api/emojis/:server_id/:emoji_id", get(get_emoji))
+            // Attachments (the default 2 MB body limit is too small for them)
+            .route(
+                "/api/channels/:channel_id/attachments",
+                post(upload_attachment).layer(DefaultBodyLimit::max(
+                    state.config.uploads.max_attachment_size + ATTACHMENT_MULTIPART_OVERHEAD,
+                )),
+            )
+            .route("/api/attachments/:channel_id/:hash", get(get_attachment))
+            // Direct messages
+            .route("/api/users/@me/dms", post(create_dm).get(list_dms))
+            // E2EE pre-keys
+            .route(
+                "/api/users/@me/keys",
+                put(upload_keys).get(get_own_key_count),
+            )
+            .route("/api/users/@me/identity-key", put(set_identity_key))
+            .route("/api/users/:user_id/keys", get(get_key_bundle))
             // Voice signaling
+            .route("/api/voice/config", get(voice_config))
             .route("/api/voice/:channel_id/join", post(voice_join))
             .route("/api/voice/:channel_id/leave", post(voice_leave))
             .route(
                 "/api/voice/:channel_id/state",
                 axum::routing::patch(voice_update_state),
             )
+            .route(
+                "/api/voice/:channel_id/members/:user_id/move",
+                post(voice_move_member),
+            )
+            .route(
+                "/api/voice/:channel_id/members/:user_id/mute",
+                post(voice_mute_member),
+            )
             .route(
                 "/api/voice/:channel_id/participants",
                 get(voice_participants),
             );
     }
 
+    // Layers run bottom-up on requests: assign the id first so the trace span
+    // and the response both see it
     router
         .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
         .with_state(state)
 }
 
+/// Span wrapping each request, so every log line it produces (including
+/// internal errors) carries the `X-Request-Id` — either the client's or one
+/// generated for it. Logs the route template rather than the URI, which can
+/// hold secrets such as webhook tokens.
+fn request_span(request: &axum::http::Request<Body>) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or_default();
+    tracing::info_span!("request", method = %request.method(), route, request_id)
+}
+
 // ─── Avatar Handlers ────────────────────────────────────────────────────────
 
 const MAX_AVATAR_SIZE: usize = 2 * 1024 * 1024; // 2 MB
+const MAX_AVATAR_DIMENSION: u32 = 4096;
+/// Square thumbnails kept for every avatar, served with `?size=`.
+const AVATAR_THUMBNAIL_SIZES: &[u32] = &[64, 128];
 const ALLOWED_CONTENT_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
 
 async fn upload_avatar(
@@ -425,14 +1404,6 @@ async fn upload_avatar(
             )));
         }
 
-        let ext = match content_type.as_str() {
-            "image/png" => "png",
-            "image/jpeg" => "jpg",
-            "image/gif" => "gif",
-            "image/webp" => "webp",
-            _ => "bin",
-        };
-
         let data = field
             .bytes()
             .await
@@ -445,6 +1416,7 @@ async fn upload_avatar(
                 MAX_AVATAR_SIZE
             )));
         }
+        validate_image(&data, &content_type, MAX_AVATAR_DIMENSION)?;
 
         // Compute SHA-256 hash
         use sha2::{Digest, Sha256};
@@ -452,42 +1424,39 @@ async fn upload_avatar(
         hasher.update(&data);
         let hash = format!("{:x}", hasher.finalize());
 
-        // Save to disk: ./data/avatars/{user_id}/{hash}.{ext}
-        let dir = PathBuf::from("./data/avatars").join(auth.user_id.to_string());
-        tokio::fs::create_dir_all(&dir).await.map_err(|e| {
-            AppError::Internal(anyhow::anyhow!("Failed to create avatar directory: {}", e))
-        })?;
-
-        // Remove old avatars for this user
-        if let Ok(mut entries) = tokio::fs::read_dir(&dir).await {
-            while let Ok(Some(entry)) = entries.next_entry().await {
-                let _ = tokio::fs::remove_file(entry.path()).await;
-            }
-        }
+        let previous_hash = db::users::find_by_id(&state.db, auth.user_id)
+            .await?
+            .and_then(|u| u.avatar_hash);
 
-        let file_path = dir.join(format!("{}.{}", hash, ext));
-        tokio::fs::write(&file_path, &data).await.map_err(|e| {
-            AppError::Internal(anyhow::anyhow!("Failed to write avatar file: {}", e))
-        })?;
+        state
+            .storage
+            .put(
+                &avatar_key(auth.user_id, &hash),
+                data.clone(),
+                &content_type,
+            )
+            .await
+            .map_err(|e| AppError::Internal(e.context("Failed to store avatar")))?;
+        store_avatar_thumbnails(
+            &state,
+            auth.user_id,
+            &hash,
+            data,
+            AVATAR_THUMBNAIL_SIZES.to_vec(),
+        )
+        .await;
 
         // Update DB
         db::users::update_avatar_hash(&state.db, auth.user_id, &hash).await?;
 
-        // Broadcast UserUpdate to all channels the user is in so clients update their avatars live
-        if let Ok(Some(updated_user)) = db::users::find_by_id(&state.db, auth.user_id).await {
-            let event = WsEvent::UserUpdate {
-                user: updated_user.into(),
-            };
-
-            // Broadcast to all servers the user is a member of so other users see the update
-            if let Ok(servers) = db::servers::list_for_user(&state.db, auth.user_id).await {
-                for server in servers {
-                    state.broadcast_to_server(&server.id, &event).await;
-                }
-            }
+        // Remove the user's old avatar
+        if let Some(previous) = previous_hash.filter(|h| *h != hash) {
+            delete_avatar(&state, auth.user_id, &previous).await;
+        }
 
-            // Also broadcast directly to the user (their own sessions)
-            state.broadcast_to_user(&auth.user_id, &event);
+        // Broadcast UserUpdate so clients update their avatars live
+        if let Ok(Some(updated_user)) = db::users::find_by_id(&state.db, auth.user_id).await {
+            broadcast_user_update(&state, updated_user.into()).await;
         }
 
         return Ok(Json(serde_json::json!({ "avatar_hash": hash })));
@@ -496,113 +1465,463 @@ async fn upload_avatar(
     Err(AppError::BadRequest("No file provided".to_string()))
 }
 
-async fn get_avatar(
-    Path((user_id, hash)): Path<(Uuid, String)>,
-) -> Result<impl IntoResponse, AppError> {
-    let dir = PathBuf::from("./data/avatars").join(user_id.to_string());
-
-    // Look for file matching the hash with any extension
-    let mut found: Option<(PathBuf, String)> = None;
-    if let Ok(mut entries) = tokio::fs::read_dir(&dir).await {
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            let name = entry.file_name().to_string_lossy().to_string();
-            if name.starts_with(&hash) {
-                let ext = name.rsplit('.').next().unwrap_or("bin").to_string();
-                let content_type = match ext.as_str() {
-                    "png" => "image/png",
-                    "jpg" | "jpeg" => "image/jpeg",
-                    "gif" => "image/gif",
-                    "webp" => "image/webp",
-                    _ => "application/octet-stream",
-                };
-                found = Some((entry.path(), content_type.to_string()));
-                break;
-            }
+/// Send a `UserUpdate` to every server the user is in and to their own sessions.
+async fn broadcast_user_update(state: &AppState, user: UserPublic) {
+    let user_id = user.id;
+    let event = WsEvent::UserUpdate { user };
+
+    // Broadcast to all servers the user is a member of so other users see the update
+    if let Ok(servers) = db::servers::list_for_user(&state.db, user_id).await {
+        for server in servers {
+            state.broadcast_to_server(&server.id, &event).await;
         }
     }
 
-    let (path, content_type) =
-        found.ok_or_else(|| AppError::NotFound("Avatar not found".to_string()))?;
+    // Also broadcast directly to the user (their own sessions)
+    state.broadcast_to_user(&user_id, &event);
+}
 
-    let data = tokio::fs::read(&path)
-        .await
-        .map_err(|e| AppError::Internal(anyhow::anyhow!("Failed to read avatar: {}", e)))?;
+/// Avatars uploaded before the storage backend was introduced were saved
+/// as `{hash}.{ext}`; they are still served and cleaned up.
+const LEGACY_AVATAR_EXTENSIONS: &[&str] = &["png", "jpg", "gif", "webp"];
 
-    Ok((
-        [
-            (header::CONTENT_TYPE, content_type),
-            (
-                header::CACHE_CONTROL,
-                "public, max-age=31536000, immutable".to_string(),
-            ),
-        ],
-        Body::from(data),
-    ))
+fn avatar_key(user_id: Uuid, hash: &str) -> String {
+    format!("avatars/{}/{}", user_id, hash)
 }
 
-// ─── Auth Handlers ──────────────────────────────────────────────────────────
+/// The current key of an avatar followed by its legacy variants.
+fn avatar_keys(user_id: Uuid, hash: &str) -> Vec<String> {
+    let key = avatar_key(user_id, hash);
+    std::iter::once(key.clone())
+        .chain(
+            LEGACY_AVATAR_EXTENSIONS
+                .iter()
+                .map(|ext| format!("{}.{}", key, ext)),
+        )
+        .collect()
+}
 
-async fn register(
-    State(state): State<AppState>,
-    Json(req): Json<CreateUserRequest>,
-) -> AppResult<Json<AuthResponse>> {
-    // Validate input
-    if req.username.len() < 3 || req.username.len() > 32 {
-        return Err(AppError::BadRequest(
-            "Username must be 3-32 characters".to_string(),
-        ));
-    }
-    if req.password.len() < 8 {
-        return Err(AppError::BadRequest(
-            "Password must be at least 8 characters".to_string(),
-        ));
+fn avatar_thumbnail_key(user_id: Uuid, hash: &str, size: u32) -> String {
+    format!("{}-{}", avatar_key(user_id, hash), size)
+}
+
+/// Render and store thumbnails of an avatar, returning the ones that were
+/// made. Failures are only logged: the full-size avatar still works, and a
+/// missing thumbnail is rendered again when it's first requested.
+async fn store_avatar_thumbnails(
+    state: &AppState,
+    user_id: Uuid,
+    hash: &str,
+    data: Bytes,
+    sizes: Vec<u32>,
+) -> Vec<(u32, Vec<u8>)> {
+    let rendered = tokio::task::spawn_blocking(move || images::thumbnails(&data, &sizes)).await;
+    let thumbnails = match rendered {
+        Ok(Ok(thumbnails)) => thumbnails,
+        Ok(Err(e)) => {
+            tracing::warn!("Failed to render thumbnails of avatar {}: {:#}", hash, e);
+            return Vec::new();
+        }
+        Err(e) => {
+            tracing::warn!("Thumbnail task for avatar {} failed: {}", hash, e);
+            return Vec::new();
+        }
+    };
+
+    for (size, png) in &thumbnails {
+        let key = avatar_thumbnail_key(user_id, hash, *size);
+        if let Err(e) = state
+            .storage
+            .put(&key, png.clone().into(), "image/png")
+            .await
+        {
+            tracing::warn!("Failed to store avatar thumbnail {}: {:#}", key, e);
+        }
     }
+    thumbnails
+}
 
-    // Check if username is taken
-    if db::users::find_by_username(&state.db, &req.username)
-        .await?
-        .is_some()
-    {
-        return Err(AppError::Conflict("Username already taken".to_string()));
+/// Delete a stored avatar and its thumbnails, logging rather than failing
+/// on storage errors.
+async fn delete_avatar(state: &AppState, user_id: Uuid, hash: &str) {
+    let thumbnail_keys = AVATAR_THUMBNAIL_SIZES
+        .iter()
+        .map(|&size| avatar_thumbnail_key(user_id, hash, size));
+    for key in avatar_keys(user_id, hash).into_iter().chain(thumbnail_keys) {
+        if let Err(e) = state.storage.delete(&key).await {
+            tracing::warn!("Failed to delete avatar {}: {:#}", key, e);
+        }
     }
+}
 
-    // Hash password (CPU-intensive Argon2 — run on blocking threadpool)
-    let password = req.password.clone();
-    let password_hash = tokio::task::spawn_blocking(move || auth::hash_password(&password))
-        .await
-        .map_err(|e| {
-            AppError::Internal(anyhow::anyhow!("Password hashing task failed: {}", e))
-        })??;
+/// Content type of an allowed avatar image, from its magic bytes.
+fn avatar_content_type(data: &[u8]) -> &'static str {
+    images::content_type(data).unwrap_or("application/octet-stream")
+}
 
-    // Create user
-    let display_name = req.display_name.unwrap_or_else(|| req.username.clone());
-    let user_id = Uuid::now_v7();
-    let user = db::users::create(
+/// Check that an uploaded image really is of its declared type and that its
+/// header doesn't claim a canvas bigger than `max_dimension` on either side
+/// (a small file can still decode to gigabytes of pixels).
+fn validate_image(data: &[u8], declared_type: &str, max_dimension: u32) -> AppResult<()> {
+    if images::content_type(data) != Some(declared_type) {
+        return Err(AppError::BadRequest(format!(
+            "File content is not a valid {}",
+            declared_type
+        )));
+    }
+    let (width, height) = images::dimensions(data)
+        .ok_or_else(|| AppError::BadRequest("Unreadable image".to_string()))?;
+    if width == 0 || height == 0 || width > max_dimension || height > max_dimension {
+        return Err(AppError::BadRequest(format!(
+            "Image is {}x{} pixels. Maximum is {}x{}",
+            width, height, max_dimension, max_dimension
+        )));
+    }
+    Ok(())
+}
+
+/// The full-size avatar, trying its legacy keys too.
+async fn load_avatar(state: &AppState, user_id: Uuid, hash: &str) -> AppResult<Vec<u8>> {
+    for key in avatar_keys(user_id, hash) {
+        if let Some(data) = state.storage.get(&key).await? {
+            return Ok(data);
+        }
+    }
+    Err(AppError::NotFound("Avatar not found".to_string()))
+}
+
+/// An avatar thumbnail, rendered and stored on first request if the avatar
+/// predates thumbnails. Such avatars were never validated, so they're checked
+/// like an upload before being decoded.
+async fn load_avatar_thumbnail(
+    state: &AppState,
+    user_id: Uuid,
+    hash: &str,
+    size: u32,
+) -> AppResult<Vec<u8>> {
+    let key = avatar_thumbnail_key(user_id, hash, size);
+    if let Some(data) = state.storage.get(&key).await? {
+        return Ok(data);
+    }
+    let unavailable = || AppError::NotFound("Avatar thumbnail not available".to_string());
+    if state.failed_avatar_thumbnails.contains(&key) {
+        return Err(unavailable());
+    }
+
+    let original = load_avatar(state, user_id, hash).await?;
+    let valid = images::content_type(&original)
+        .is_some_and(|ct| validate_image(&original, ct, MAX_AVATAR_DIMENSION).is_ok());
+    let rendered = if valid {
+        store_avatar_thumbnails(state, user_id, hash, original.into(), vec![size])
+            .await
+            .pop()
+    } else {
+        None
+    };
+    match rendered {
+        Some((_, png)) => Ok(png),
+        None => {
+            state.failed_avatar_thumbnails.insert(key);
+            Err(unavailable())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AvatarQuery {
+    /// Serve the square thumbnail of this size instead of the full image
+    size: Option<u32>,
+}
+
+/// GET /api/avatars/:user_id/:hash
+/// Redirects to the storage backend when it can hand out direct URLs.
+/// `?size=64` (or 128) returns a thumbnail, always served directly.
+async fn get_avatar(
+    State(state): State<AppState>,
+    Path((user_id, hash)): Path<(Uuid, String)>,
+    Query(params): Query<AvatarQuery>,
+) -> Result<axum::response::Response, AppError> {
+    // Hashes are hex SHA-256; anything else can't name a stored file
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::NotFound("Avatar not found".to_string()));
+    }
+
+    let data = match params.size {
+        Some(size) => {
+            if !AVATAR_THUMBNAIL_SIZES.contains(&size) {
+                return Err(AppError::BadRequest(format!(
+                    "Unsupported avatar size. Available: {:?}",
+                    AVATAR_THUMBNAIL_SIZES
+                )));
+            }
+            load_avatar_thumbnail(&state, user_id, &hash, size).await?
+        }
+        None => {
+            if let Some(url) = state.storage.presigned_url(&avatar_key(user_id, &hash)) {
+                return Ok(axum::response::Redirect::temporary(&url).into_response());
+            }
+            load_avatar(&state, user_id, &hash).await?
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, avatar_content_type(&data).to_string()),
+            (
+                header::CACHE_CONTROL,
+                "public, max-age=31536000, immutable".to_string(),
+            ),
+        ],
+        Body::from(data),
+    )
+        .into_response())
+}
+
+// ─── Attachment Handlers ────────────────────────────────────────────────────
+
+/// Keep only the final path component and cap the length of an uploaded filename.
+fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    let base: String = base.chars().filter(|c| !c.is_control()).take(255).collect();
+    if base.is_empty() {
+        "file".to_string()
+    } else {
+        base
+    }
+}
+
+/// Room for multipart boundaries and headers on top of the file itself.
+const ATTACHMENT_MULTIPART_OVERHEAD: usize = 64 * 1024;
+
+fn attachment_key(channel_id: Uuid, hash: &str) -> String {
+    format!("attachments/{}/{}", channel_id, hash)
+}
+
+/// POST /api/channels/:channel_id/attachments
+/// Stores an upload under `attachments/{channel_id}/{hash}`. The returned
+/// id is then passed in `attachments` when sending the message.
+async fn upload_attachment(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> AppResult<Json<Attachment>> {
+    // Attachments only ever end up in messages
+    let channel = find_channel(&state, channel_id).await?;
+    let required = post_permission(&channel.channel_type)?;
+    // Checked before the body is read: only those who may post here can upload
+    check_channel_permission(&state, auth.user_id, channel_id, required).await?;
+
+    let max_size = state.config.uploads.max_attachment_size;
+
+    let field = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Invalid multipart data: {}", e)))?
+        .ok_or_else(|| AppError::BadRequest("No file provided".to_string()))?;
+
+    let filename = sanitize_filename(field.file_name().unwrap_or("file"));
+    let content_type = field
+        .content_type()
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    let data = field
+        .bytes()
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Failed to read file: {}", e)))?;
+
+    if data.len() > max_size {
+        return Err(AppError::BadRequest(format!(
+            "File too large ({} bytes). Maximum is {} bytes",
+            data.len(),
+            max_size
+        )));
+    }
+
+    // Compute SHA-256 hash
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(&data);
+    let hash = format!("{:x}", hasher.finalize());
+
+    let size = data.len() as i64;
+    state
+        .storage
+        .put(&attachment_key(channel_id, &hash), data, &content_type)
+        .await
+        .map_err(|e| AppError::Internal(e.context("Failed to store attachment")))?;
+
+    let attachment = db::attachments::create(
         &state.db,
-        user_id,
-        &req.username,
-        &display_name,
-        &password_hash,
+        Uuid::now_v7(),
+        channel_id,
+        auth.user_id,
+        &filename,
+        &content_type,
+        size,
+        &hash,
     )
     .await?;
 
-    // Auto-join user to all existing servers (i.e. the default server)
-    let all_servers = db::servers::list_all(&state.db).await?;
+    Ok(Json(attachment))
+}
+
+/// GET /api/attachments/:channel_id/:hash
+/// Only for those who can view the channel the file was uploaded to.
+async fn get_attachment(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, hash)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    // Hashes are hex SHA-256; anything else can't name a stored file
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::NotFound("Attachment not found".to_string()));
+    }
+
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+
+    let attachment = db::attachments::find_by_hash(&state.db, channel_id, &hash)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Attachment not found".to_string()))?;
+
+    let data = state
+        .storage
+        .get(&attachment_key(channel_id, &hash))
+        .await?
+        .ok_or_else(|| AppError::NotFound("Attachment not found".to_string()))?;
+
+    // Only plain media renders inline; everything else (HTML, SVG, ...) is
+    // forced to download so uploads can't run script on our origin
+    let is_media = ["image/", "video/", "audio/"]
+        .iter()
+        .any(|prefix| attachment.content_type.starts_with(prefix))
+        && attachment.content_type != "image/svg+xml";
+    let disposition = format!(
+        "{}; filename=\"{}\"",
+        if is_media { "inline" } else { "attachment" },
+        attachment.filename.replace(['"', '\\'], "_")
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            // Never changes, but shared caches must not hand it to others
+            (
+                header::CACHE_CONTROL,
+                "private, max-age=31536000, immutable".to_string(),
+            ),
+        ],
+        Body::from(data),
+    ))
+}
+
+// ─── Auth Handlers ──────────────────────────────────────────────────────────
+
+/// Reject the request if this client has exhausted its login/register attempts.
+fn check_auth_rate_limit(state: &AppState, ip: IpAddr) -> AppResult<()> {
+    if !state.auth_limiter.check(&ip) {
+        tracing::warn!("Auth rate limit exceeded for {}", ip);
+        return Err(AppError::RateLimited(None));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/register",
+    tag = "auth",
+    request_body = CreateUserRequest,
+    responses(
+        (status = 200, description = "Account created", body = AuthResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Registration is invite-only and no valid invite was given", body = ErrorResponse),
+        (status = 409, description = "Username taken", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+    ),
+    security(()),
+)]
+async fn register(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(req): Json<CreateUserRequest>,
+) -> AppResult<Json<AuthResponse>> {
+    check_auth_rate_limit(&state, ip)?;
+
+    // Validate input
+    let username = auth::normalize_username(&req.username)?;
+    auth::check_password_policy(&state.config.auth.password_policy, &req.password)?;
+
+    // Check if username is taken
+    if db::users::find_by_username(&state.db, &username)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict("Username already taken".to_string()));
+    }
+
+    // A closed instance only takes people with an unused invite
+    let invite_code = if state.config.auth.allow_local_registration {
+        None
+    } else {
+        Some(req.invite_code.as_deref().ok_or(AppError::Forbidden)?)
+    };
+
+    // Hash password (CPU-intensive Argon2 — run on blocking threadpool)
+    let password = req.password.clone();
+    let password_hash = tokio::task::spawn_blocking(move || auth::hash_password(&password))
+        .await
+        .map_err(|e| {
+            AppError::Internal(anyhow::anyhow!("Password hashing task failed: {}", e))
+        })??;
+
+    // Create user
+    let display_name = req.display_name.unwrap_or_else(|| username.clone());
+    let user_id = Uuid::now_v7();
+    let is_admin = state.config.auth.is_admin(&username);
+    let user = match invite_code {
+        Some(code) => db::registration_invites::redeem(
+            &state.db,
+            code,
+            user_id,
+            &username,
+            &display_name,
+            &password_hash,
+            is_admin,
+        )
+        .await?
+        .ok_or(AppError::Forbidden)?,
+        None => {
+            db::users::create(
+                &state.db,
+                user_id,
+                &username,
+                &display_name,
+                &password_hash,
+                is_admin,
+            )
+            .await?
+        }
+    };
+
+    // Auto-join the user to the default server; others need an invite
+    let default_server = db::servers::find_by_id(&state.db, DEFAULT_SERVER_ID).await?;
     let user_public = UserPublic::from(user.clone());
-    let system_owner_id = Uuid::parse_str("00000000-0000-7000-8000-000000000000").unwrap();
 
-    for server in &all_servers {
+    if let Some(server) = &default_server {
         // Claim the server if it's currently owned by the system user
-        if server.owner_id == system_owner_id {
+        if server.owner_id == SYSTEM_USER_ID {
             tracing::info!(
                 "User {} is claiming the default server {} on registration",
                 user.id,
                 server.id
             );
-            let _ = db::servers::transfer_ownership(&state.db, server.id, user.id).await;
-
             // Broadcast the server update so any connected clients get it (unlikely on register, but good for completeness)
-            if let Ok(Some(updated_server)) = db::servers::find_by_id(&state.db, server.id).await {
+            if let Ok(Some(updated_server)) =
+                db::servers::transfer_ownership(&state.db, server.id, SYSTEM_USER_ID, user.id).await
+            {
                 let event = WsEvent::ServerUpdate {
                     server: ServerPublic::from(updated_server),
                 };
@@ -610,7 +1929,12 @@ async fn register(
             }
         }
 
-        let _ = db::members::add(&state.db, user.id, server.id).await;
+        if db::members::add(&state.db, user.id, server.id)
+            .await
+            .is_ok()
+        {
+            state.cache_member_add(server.id, user.id);
+        }
         // Broadcast MemberJoin so connected clients update their member lists
         let event = WsEvent::MemberJoin {
             server_id: server.id,
@@ -619,680 +1943,3814 @@ async fn register(
         state.broadcast_to_server(&server.id, &event).await;
     }
 
-    // Generate token
+    issue_session(&state, user).await
+}
+
+/// A new access token and refresh token for `user`.
+async fn issue_session(state: &AppState, user: User) -> AppResult<Json<AuthResponse>> {
     let token = auth::create_token(&state.config.auth, user.id, &user.username)?;
 
+    let refresh_token = auth::generate_refresh_token();
+    db::refresh_tokens::create(
+        &state.db,
+        &auth::hash_refresh_token(&refresh_token),
+        user.id,
+        refresh_token_expiry(state),
+    )
+    .await?;
+
     Ok(Json(AuthResponse {
         token,
+        refresh_token: Some(refresh_token),
         user: user.into(),
     }))
 }
 
+fn refresh_token_expiry(state: &AppState) -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc::now() + chrono::Duration::seconds(state.config.auth.refresh_token_expiry as i64)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Logged in", body = AuthResponse),
+        (status = 401, description = "Wrong credentials, or the account is locked", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+    ),
+    security(()),
+)]
 async fn login(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     Json(req): Json<LoginRequest>,
 ) -> AppResult<Json<AuthResponse>> {
-    let user = db::users::find_by_username(&state.db, &req.username)
-        .await?
-        .ok_or(AppError::Unauthorized)?;
+    check_auth_rate_limit(&state, ip)?;
+
+    // Unknown and locked accounts are checked against a hash no password
+    // matches, so neither the response nor its timing tells them apart from
+    // a wrong password
+    let now = chrono::Utc::now();
+    let user = db::users::find_by_username(&state.db, &req.username).await?;
+    let locked = user
+        .as_ref()
+        .is_some_and(|user| user.locked_until.is_some_and(|until| until > now));
+    let hash = match &user {
+        Some(user) if !locked => user.password_hash.clone(),
+        _ => db::NO_PASSWORD_HASH.to_string(),
+    };
 
     // Verify password (CPU-intensive Argon2 — run on blocking threadpool)
     let password = req.password.clone();
-    let hash = user.password_hash.clone();
     let valid = tokio::task::spawn_blocking(move || auth::verify_password(&password, &hash))
         .await
         .map_err(|e| {
             AppError::Internal(anyhow::anyhow!("Password verification task failed: {}", e))
         })??;
-    if !valid {
-        return Err(AppError::Unauthorized);
+    let user = match user {
+        Some(user) if valid => user,
+        Some(user) if !locked => {
+            record_failed_login(&state, user.id).await?;
+            return Err(AppError::Unauthorized);
+        }
+        _ => return Err(AppError::Unauthorized),
+    };
+    if user.failed_logins > 0 || user.locked_until.is_some() {
+        db::users::reset_failed_logins(&state.db, user.id).await?;
     }
 
     // Update last seen
     db::users::update_last_seen(&state.db, user.id).await?;
 
-    // Generate token
+    issue_session(&state, user).await
+}
+
+/// Count a wrong password against an account, locking it once the failures
+/// reach the configured limit.
+async fn record_failed_login(state: &AppState, user_id: Uuid) -> AppResult<()> {
+    let lockout = &state.config.auth.lockout;
+    if lockout.max_failures == 0 {
+        return Ok(());
+    }
+    let failures = db::users::record_failed_login(&state.db, user_id).await?;
+    if let Some(duration) = auth::lockout_duration(lockout, failures) {
+        db::users::lock(&state.db, user_id, chrono::Utc::now() + duration).await?;
+        tracing::warn!("Locked account {} after failed logins", user_id);
+    }
+    Ok(())
+}
+
+/// POST /api/auth/refresh — trade a refresh token for a new access token.
+/// The refresh token is used up and a replacement returned alongside.
+#[utoipa::path(
+    post,
+    path = "/api/auth/refresh",
+    tag = "auth",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "New tokens", body = AuthResponse),
+        (status = 401, description = "Unknown, used or expired refresh token", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+    ),
+    security(()),
+)]
+async fn refresh_session(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(req): Json<RefreshTokenRequest>,
+) -> AppResult<Json<AuthResponse>> {
+    check_auth_rate_limit(&state, ip)?;
+
+    let refresh_token = auth::generate_refresh_token();
+    let user_id = db::refresh_tokens::rotate(
+        &state.db,
+        &auth::hash_refresh_token(&req.refresh_token),
+        &auth::hash_refresh_token(&refresh_token),
+        refresh_token_expiry(&state),
+    )
+    .await?
+    .ok_or(AppError::Unauthorized)?;
+
+    let user = db::users::find_by_id(&state.db, user_id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
     let token = auth::create_token(&state.config.auth, user.id, &user.username)?;
 
     Ok(Json(AuthResponse {
         token,
+        refresh_token: Some(refresh_token),
         user: user.into(),
     }))
 }
 
-// ─── Auth Validation & Instance Info ────────────────────────────────────────
-
-/// POST /api/auth/validate — auth hub only.
-/// Validates a JWT and returns user info. Used by community servers.
-async fn validate_token_endpoint(
+/// POST /api/auth/logout — revoke the bearer token used for this request,
+/// and the session's refresh token if one is given in the body.
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    tag = "auth",
+    request_body(content = Option<RefreshTokenRequest>),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn logout(
     State(state): State<AppState>,
-    Json(req): Json<ValidateTokenRequest>,
-) -> Json<ValidateTokenResponse> {
-    match auth::validate_token(&state.config.auth, &req.token) {
-        Ok(claims) => {
-            match auth::user_id_from_claims(&claims) {
-                Ok(uid) => {
-                    // Look up full user data for display_name and avatar
-                    let (display_name, avatar_hash) =
-                        if let Ok(Some(user)) = db::users::find_by_id(&state.db, uid).await {
-                            (Some(user.display_name), user.avatar_hash)
-                        } else {
-                            (Some(claims.username.clone()), None)
-                        };
-
-                    Json(ValidateTokenResponse {
-                        valid: true,
-                        user_id: Some(uid.to_string()),
-                        username: Some(claims.username),
-                        display_name,
-                        avatar_hash,
-                    })
-                }
-                Err(_) => Json(ValidateTokenResponse {
-                    valid: false,
-                    user_id: None,
-                    username: None,
-                    display_name: None,
-                    avatar_hash: None,
-                }),
-            }
-        }
-        Err(_) => Json(ValidateTokenResponse {
-            valid: false,
-            user_id: None,
-            username: None,
-            display_name: None,
-            avatar_hash: None,
-        }),
-    }
-}
+    headers: HeaderMap,
+    body: Option<Json<RefreshTokenRequest>>,
+) -> AppResult<StatusCode> {
+    let token = headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or(AppError::Unauthorized)?;
 
-/// GET /api/auth/public-key — auth hub only.
-/// Returns the RSA public key PEM so community servers can verify tokens locally.
-async fn public_key_endpoint(State(state): State<AppState>) -> AppResult<Json<PublicKeyResponse>> {
-    let pem = auth::read_public_key_pem(&state.config.auth)?;
-    Ok(Json(PublicKeyResponse {
-        public_key_pem: pem,
-        algorithm: "RS256".to_string(),
-    }))
-}
+    // Make sure the token is valid (and not already revoked) before blacklisting it
+    let (user_id, _username, _) = state.validate_token_federated(token).await?;
+    let claims = auth::validate_token(&state.config.auth, token)?;
 
-/// GET /api/instance/info — always available.
-/// Returns server mode and metadata for client discovery.
-async fn instance_info(State(state): State<AppState>) -> impl IntoResponse {
-    let mode_str = match state.config.mode {
-        ServerMode::AuthHub => "auth_hub",
-        ServerMode::Community => "community",
-        ServerMode::Standalone => "standalone",
-    };
+    if claims.jti.is_empty() {
+        return Err(AppError::BadRequest(
+            "Token cannot be revoked; please log in again".to_string(),
+        ));
+    }
 
-    let mut default_server_id = None;
-    if state.config.is_community() {
-        if let Ok(servers) = db::servers::list_all(&state.db).await {
-            if let Some(first) = servers.first() {
-                default_server_id = Some(first.id);
-            }
-        }
+    let expires_at = chrono::DateTime::from_timestamp(claims.exp, 0)
+        .ok_or_else(|| AppError::Internal(anyhow::anyhow!("Invalid token expiry")))?;
+    db::revoked_tokens::revoke(&state.db, &claims.jti, user_id, expires_at).await?;
+    if let Some(Json(req)) = body {
+        let hash = auth::hash_refresh_token(&req.refresh_token);
+        db::refresh_tokens::delete(&state.db, &hash, user_id).await?;
     }
 
-    Json(serde_json::json!({
-        "mode": mode_str,
-        "name": state.config.server.public_url,
-        "version": env!("CARGO_PKG_VERSION"),
-        "default_server_id": default_server_id,
-    }))
-}
+    // Don't keep serving the token from the validation cache
+    state.token_cache.remove(token);
 
-// ─── Server Handlers ────────────────────────────────────────────────────────
+    Ok(StatusCode::NO_CONTENT)
+}
 
-async fn create_server(
+/// POST /api/auth/change-password — logs out every other session and
+/// returns a fresh token for this one.
+#[utoipa::path(
+    post,
+    path = "/api/auth/change-password",
+    tag = "auth",
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed", body = AuthResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn change_password(
     State(state): State<AppState>,
     auth: AuthUser,
-    Json(req): Json<CreateServerRequest>,
-) -> AppResult<Json<Server>> {
-    let user_id = auth.user_id;
-
-    let server_id = Uuid::now_v7();
-    let server = db::servers::create(
-        &state.db,
-        server_id,
-        &req.name,
-        user_id,
-        req.e2ee_enabled.unwrap_or(false),
-    )
-    .await?;
+    Json(req): Json<ChangePasswordRequest>,
+) -> AppResult<Json<AuthResponse>> {
+    auth::check_password_policy(&state.config.auth.password_policy, &req.new_password)?;
 
-    // Add owner as member
-    db::members::add(&state.db, user_id, server_id).await?;
+    let user = db::users::find_by_id(&state.db, auth.user_id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
 
-    // Create default channels
-    let general_id = Uuid::now_v7();
-    db::channels::create(
-        &state.db,
-        general_id,
-        server_id,
-        "general",
-        &ChannelType::Text,
-        0,
-        None,
-    )
-    .await?;
-    let voice_id = Uuid::now_v7();
-    db::channels::create(
-        &state.db,
-        voice_id,
-        server_id,
-        "Voice",
-        &ChannelType::Voice,
-        1,
-        None,
-    )
-    .await?;
-
-    // Create @everyone role (default permissions: SEND_MESSAGES)
-    db::roles::create(
-        &state.db,
-        server_id,
-        "@everyone",
-        Permissions::SEND_MESSAGES,
-        0,
-        0,
-    )
-    .await?;
+    // Verify and hash (CPU-intensive Argon2 — run on blocking threadpool)
+    let old_password = req.old_password;
+    let hash = user.password_hash.clone();
+    let valid = tokio::task::spawn_blocking(move || auth::verify_password(&old_password, &hash))
+        .await
+        .map_err(|e| {
+            AppError::Internal(anyhow::anyhow!("Password verification task failed: {}", e))
+        })??;
+    if !valid {
+        return Err(AppError::Unauthorized);
+    }
 
-    Ok(Json(server))
-}
+    let new_password = req.new_password;
+    let password_hash = tokio::task::spawn_blocking(move || auth::hash_password(&new_password))
+        .await
+        .map_err(|e| {
+            AppError::Internal(anyhow::anyhow!("Password hashing task failed: {}", e))
+        })??;
+    db::users::update_password_hash(&state.db, user.id, &password_hash).await?;
+    db::refresh_tokens::delete_for_user(&state.db, user.id).await?;
 
-async fn list_servers(
-    State(state): State<AppState>,
-    auth: AuthUser,
-) -> AppResult<Json<Vec<Server>>> {
-    let servers = db::servers::list_for_user(&state.db, auth.user_id).await?;
-    Ok(Json(servers))
-}
+    // Cached validations would otherwise keep old tokens alive for a while
+    state.token_cache.retain(|_, (id, _, _, _)| *id != user.id);
 
-async fn get_server(
-    State(state): State<AppState>,
-    Path(server_id): Path<Uuid>,
-) -> AppResult<Json<Server>> {
-    let server = db::servers::find_by_id(&state.db, server_id)
-        .await?
-        .ok_or(AppError::NotFound("Server not found".to_string()))?;
-    Ok(Json(server))
+    // `iat` has whole-second precision and the cut-off is compared rounded
+    // down, so this token is issued at or after it
+    issue_session(&state, user).await
 }
 
-async fn join_server(
+/// DELETE /api/users/@me — permanently delete the caller's account.
+/// Owned servers pass to their longest-standing other member.
+#[utoipa::path(
+    delete,
+    path = "/api/users/@me",
+    tag = "users",
+    request_body = DeleteAccountRequest,
+    responses(
+        (status = 204, description = "Account deleted"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn delete_account(
     State(state): State<AppState>,
     auth: AuthUser,
-    Path(server_id): Path<Uuid>,
+    Json(req): Json<DeleteAccountRequest>,
 ) -> AppResult<StatusCode> {
-    // 1. Check if the server is currently "unclaimed" (owned by the dummy system user)
-    let system_owner_id = Uuid::parse_str("00000000-0000-7000-8000-000000000000").unwrap();
-    if let Ok(Some(server)) = db::servers::find_by_id(&state.db, server_id).await {
-        if server.owner_id == system_owner_id {
-            // First user to join the default server claims it
-            tracing::info!(
-                "User {} is claiming the default server {}",
-                auth.user_id,
-                server_id
-            );
-            db::servers::transfer_ownership(&state.db, server_id, auth.user_id).await?;
+    let user = db::users::find_by_id(&state.db, auth.user_id)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
 
-            // Broadcast the server update so the client gets owner permissions immediately
-            if let Ok(Some(updated_server)) = db::servers::find_by_id(&state.db, server_id).await {
-                let event = WsEvent::ServerUpdate {
-                    server: ServerPublic::from(updated_server),
-                };
-                state.broadcast_to_server(&server_id, &event).await;
+    // Verify password (CPU-intensive Argon2 — run on blocking threadpool)
+    let password = req.password;
+    let hash = user.password_hash.clone();
+    let valid = tokio::task::spawn_blocking(move || auth::verify_password(&password, &hash))
+        .await
+        .map_err(|e| {
+            AppError::Internal(anyhow::anyhow!("Password verification task failed: {}", e))
+        })??;
+    if !valid {
+        return Err(AppError::Unauthorized);
+    }
+
+    remove_account(&state, &user, false).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Delete an account and the bots it owns, handing each server they own to
+/// the longest-standing human member. A server with no other human members is
+/// a Conflict, unless `force` is set, in which case it's deleted along with
+/// the account.
+async fn remove_account(state: &AppState, user: &User, force: bool) -> AppResult<()> {
+    let mut accounts = db::bots::list_owned(&state.db, user.id).await?;
+    accounts.push(user.clone());
+    let leaving: Vec<Uuid> = accounts.iter().map(|a| a.id).collect();
+
+    // Work out who inherits each owned server before touching anything
+    let mut memberships = Vec::new();
+    let mut transfers = Vec::new();
+    let mut deleted_servers = Vec::new();
+    for account in &accounts {
+        for server in db::servers::list_for_user(&state.db, account.id).await? {
+            memberships.push((server.id, account.id));
+            if server.owner_id != account.id {
+                continue;
+            }
+            match db::members::find_heir(&state.db, server.id, &leaving).await? {
+                Some(heir) => transfers.push((server.id, heir)),
+                None if force => deleted_servers.push(server.id),
+                None => {
+                    return Err(AppError::Conflict(format!(
+                        "Server '{}' has no other human members to take it over; delete it first",
+                        server.name
+                    )))
+                }
             }
         }
     }
+    memberships.retain(|(server_id, _)| !deleted_servers.contains(server_id));
+    let mut remnants = Vec::new();
+    for server_id in &deleted_servers {
+        remnants.push(ServerRemnants::collect(state, *server_id).await?);
+    }
 
-    // 2. Add the user as a member
-    db::members::add(&state.db, auth.user_id, server_id).await?;
+    db::users::delete_account(&state.db, user.id, &transfers, &deleted_servers).await?;
 
-    // 3. Broadcast MemberJoin to all connected server members
-    if let Ok(Some(user)) = db::users::find_by_id(&state.db, auth.user_id).await {
-        let event = WsEvent::MemberJoin {
-            server_id,
-            user: UserPublic::from(user),
-        };
+    for account in &accounts {
+        if let Some(hash) = &account.avatar_hash {
+            delete_avatar(state, account.id, hash).await;
+        }
+        // Drop any live session and cached tokens; the tokens themselves stay
+        // valid until expiry, but every lookup of the account now fails
+        if let Some((_, session)) = state.ws_sessions.remove(&account.id) {
+            session.close();
+        }
+    }
+    state
+        .token_cache
+        .retain(|_, (id, _, _, _)| !leaving.contains(id));
+
+    for (server_id, user_id) in memberships {
+        state.cache_member_remove(server_id, user_id);
+        let event = WsEvent::MemberLeave { server_id, user_id };
         state.broadcast_to_server(&server_id, &event).await;
     }
+    for (server_id, _) in &transfers {
+        if let Some(server) = db::servers::find_by_id(&state.db, *server_id).await? {
+            let event = WsEvent::ServerUpdate {
+                server: ServerPublic::from(server),
+            };
+            state.broadcast_to_server(server_id, &event).await;
+        }
+    }
+    for server in remnants {
+        server.clean_up(state).await;
+    }
 
-    Ok(StatusCode::OK)
+    Ok(())
 }
 
-async fn leave_server(
+// ─── Auth Validation & Instance Info ────────────────────────────────────────
+
+/// POST /api/auth/validate — auth hub only.
+/// Validates a JWT and returns user info. Used by community servers, which
+/// rely on it to learn about revoked tokens.
+async fn validate_token_endpoint(
     State(state): State<AppState>,
-    auth: AuthUser,
-    Path(server_id): Path<Uuid>,
-) -> AppResult<StatusCode> {
-    if let Some(server) = db::servers::find_by_id(&state.db, server_id).await? {
-        if server.owner_id == auth.user_id {
-            return Err(AppError::BadRequest(
-                "Server owners cannot leave their own server".into(),
-            ));
+    Json(req): Json<ValidateTokenRequest>,
+) -> Json<ValidateTokenResponse> {
+    match state.validate_token_federated(&req.token).await {
+        Ok((uid, username, _)) => {
+            // Look up full user data for display_name and avatar
+            let (display_name, avatar_hash) =
+                if let Ok(Some(user)) = db::users::find_by_id(&state.db, uid).await {
+                    (Some(user.display_name), user.avatar_hash)
+                } else {
+                    (Some(username.clone()), None)
+                };
+
+            Json(ValidateTokenResponse {
+                valid: true,
+                user_id: Some(uid.to_string()),
+                username: Some(username),
+                display_name,
+                avatar_hash,
+            })
         }
-    } else {
-        return Err(AppError::NotFound("Server not found".into()));
+        Err(_) => Json(ValidateTokenResponse {
+            valid: false,
+            user_id: None,
+            username: None,
+            display_name: None,
+            avatar_hash: None,
+        }),
     }
+}
 
-    db::members::remove(&state.db, auth.user_id, server_id).await?;
-
-    // Broadcast MemberLeave to all connected server members
-    let event = WsEvent::MemberLeave {
-        server_id,
-        user_id: auth.user_id,
-    };
-    state.broadcast_to_server(&server_id, &event).await;
+/// GET /api/auth/public-key — auth hub only.
+/// Returns the signing key's PEM so community servers can verify tokens locally.
+/// Kept for older community servers; current ones use the JWKS, which also
+/// carries rotated keys. HS256 deployments have no public key, so this returns 404.
+async fn public_key_endpoint(State(state): State<AppState>) -> AppResult<Json<PublicKeyResponse>> {
+    if state.config.auth.algorithm == JwtAlgorithm::Hs256 {
+        return Err(AppError::NotFound(
+            "No public key — this instance uses HS256 tokens".to_string(),
+        ));
+    }
 
-    Ok(StatusCode::OK)
+    let pem = auth::read_public_key_pem(&state.config.auth)?;
+    Ok(Json(PublicKeyResponse {
+        public_key_pem: pem,
+        algorithm: "RS256".to_string(),
+    }))
 }
 
-// ─── Role Handlers ──────────────────────────────────────────────────────────
+/// GET /api/auth/.well-known/jwks.json — auth hub only.
+/// Publishes every key tokens may be signed with, identified by `kid`, so
+/// community servers can pick up a new key before the old one is retired.
+async fn jwks_endpoint(State(state): State<AppState>) -> AppResult<Json<JwkSet>> {
+    if state.config.auth.algorithm == JwtAlgorithm::Hs256 {
+        return Err(AppError::NotFound(
+            "No public key — this instance uses HS256 tokens".to_string(),
+        ));
+    }
 
-#[derive(Deserialize)]
-pub struct CreateRoleRequest {
-    name: String,
-    permissions: i64,
-    color: i32,
-    position: i32,
+    Ok(Json(auth::published_keys(&state.config.auth)?))
 }
 
-async fn list_roles(
-    State(state): State<AppState>,
-    Path(server_id): Path<Uuid>,
-) -> AppResult<Json<Vec<Role>>> {
-    let roles = db::roles::list_for_server(&state.db, server_id).await?;
-    Ok(Json(roles))
-}
+// ─── Admin Handlers ─────────────────────────────────────────────────────────
 
-async fn create_role(
+/// POST /api/admin/registration-invites
+/// Mint a one-time code for registering while registration is closed.
+async fn create_registration_invite(
     State(state): State<AppState>,
-    auth: AuthUser,
-    Path(server_id): Path<Uuid>,
-    Json(req): Json<CreateRoleRequest>,
-) -> AppResult<Json<Role>> {
-    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
+    admin: AdminUser,
+    Json(req): Json<CreateRegistrationInviteRequest>,
+) -> AppResult<Json<RegistrationInvite>> {
+    if matches!(req.expires_at, Some(at) if at <= chrono::Utc::now()) {
+        return Err(AppError::BadRequest(
+            "expires_at must be in the future".to_string(),
+        ));
+    }
 
-    let role = db::roles::create(
+    let invite = db::registration_invites::create(
         &state.db,
-        server_id,
-        &req.name,
-        req.permissions,
-        req.color,
-        req.position,
+        &generate_invite_code(REGISTRATION_INVITE_CODE_LENGTH),
+        admin.user_id,
+        req.expires_at,
     )
     .await?;
-
-    Ok(Json(role))
+    Ok(Json(invite))
 }
 
-async fn update_role(
+/// GET /api/admin/registration-invites
+async fn list_registration_invites(
     State(state): State<AppState>,
-    auth: AuthUser,
-    Path((server_id, role_id)): Path<(Uuid, Uuid)>,
-    Json(req): Json<CreateRoleRequest>,
-) -> AppResult<Json<Role>> {
-    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
-
-    let role = db::roles::update(
-        &state.db,
-        role_id,
-        server_id,
-        &req.name,
-        req.permissions,
-        req.color,
-        req.position,
-    )
-    .await?
-    .ok_or(AppError::NotFound("Role not found".to_string()))?;
-
-    Ok(Json(role))
+    _admin: AdminUser,
+) -> AppResult<Json<Vec<RegistrationInvite>>> {
+    let invites = db::registration_invites::list(&state.db).await?;
+    Ok(Json(invites))
 }
 
-async fn delete_role(
+/// DELETE /api/admin/registration-invites/:code
+async fn delete_registration_invite(
     State(state): State<AppState>,
-    auth: AuthUser,
-    Path((server_id, role_id)): Path<(Uuid, Uuid)>,
+    _admin: AdminUser,
+    Path(code): Path<String>,
 ) -> AppResult<StatusCode> {
-    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
-    // TODO: Prevent deleting @everyone or integration roles
-    db::roles::delete(&state.db, role_id).await?;
+    if !db::registration_invites::delete(&state.db, &code).await? {
+        return Err(AppError::NotFound("Invite not found".to_string()));
+    }
     Ok(StatusCode::NO_CONTENT)
 }
 
-async fn assign_role(
-    State(state): State<AppState>,
-    auth: AuthUser,
-    Path((server_id, user_id, role_id)): Path<(Uuid, Uuid, Uuid)>,
-) -> AppResult<StatusCode> {
-    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
-    db::members::add_role(&state.db, user_id, server_id, role_id).await?;
+#[derive(Deserialize)]
+struct AdminUserQuery {
+    after: Option<Uuid>,
+    limit: Option<i64>,
+    query: Option<String>,
+}
 
-    if let Ok(Some(member)) = db::members::find(&state.db, user_id, server_id).await {
-        state
-            .broadcast_to_server(&server_id, &WsEvent::MemberUpdate { server_id, member })
-            .await;
+/// GET /api/admin/users?after=&limit=&query=
+async fn admin_list_users(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(params): Query<AdminUserQuery>,
+) -> AppResult<Json<Vec<InstanceUser>>> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 100);
+    let query = params
+        .query
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty());
+    if query.is_some_and(|q| q.chars().count() > chat::MAX_DISPLAY_NAME_LENGTH) {
+        return Err(AppError::BadRequest(format!(
+            "User query must be at most {} characters",
+            chat::MAX_DISPLAY_NAME_LENGTH
+        )));
     }
 
-    Ok(StatusCode::OK)
+    let users = db::users::list_paginated(&state.db, params.after, query, limit).await?;
+    Ok(Json(users.into_iter().map(InstanceUser::from).collect()))
 }
 
-async fn remove_role(
+/// DELETE /api/admin/users/:user_id
+/// Force-delete an account. Servers it owns pass to another member, or are
+/// deleted if it was their only member.
+async fn admin_delete_user(
     State(state): State<AppState>,
-    auth: AuthUser,
-    Path((server_id, user_id, role_id)): Path<(Uuid, Uuid, Uuid)>,
+    admin: AdminUser,
+    Path(user_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
-    db::members::remove_role(&state.db, user_id, server_id, role_id).await?;
-
-    if let Ok(Some(member)) = db::members::find(&state.db, user_id, server_id).await {
-        state
-            .broadcast_to_server(&server_id, &WsEvent::MemberUpdate { server_id, member })
-            .await;
+    if user_id == admin.user_id {
+        return Err(AppError::BadRequest(
+            "Delete your own account with DELETE /api/users/@me".to_string(),
+        ));
     }
+    let user = db::users::find_by_id(&state.db, user_id)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
 
-    Ok(StatusCode::OK)
+    remove_account(&state, &user, true).await?;
+    tracing::info!("Admin {} deleted user {}", admin.user_id, user_id);
+    Ok(StatusCode::NO_CONTENT)
 }
 
-async fn get_member(
+/// POST /api/admin/servers/:server_id/disable
+async fn admin_disable_server(
     State(state): State<AppState>,
-    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
-) -> AppResult<Json<Member>> {
-    let member = db::members::find(&state.db, user_id, server_id)
-        .await?
-        .ok_or(AppError::NotFound("Member not found".to_string()))?;
-    Ok(Json(member))
+    admin: AdminUser,
+    Path(server_id): Path<Uuid>,
+) -> AppResult<Json<Server>> {
+    let server = set_server_disabled(&state, server_id, true).await?;
+    tracing::info!("Admin {} disabled server {}", admin.user_id, server_id);
+    Ok(Json(server))
 }
 
-async fn list_members(
+/// POST /api/admin/servers/:server_id/enable
+async fn admin_enable_server(
     State(state): State<AppState>,
+    admin: AdminUser,
     Path(server_id): Path<Uuid>,
-) -> AppResult<Json<Vec<Member>>> {
-    let mut members = db::members::list_for_server(&state.db, server_id).await?;
+) -> AppResult<Json<Server>> {
+    let server = set_server_disabled(&state, server_id, false).await?;
+    tracing::info!("Admin {} re-enabled server {}", admin.user_id, server_id);
+    Ok(Json(server))
+}
 
-    // Populate presence status
-    let user_ids: Vec<Uuid> = members.iter().map(|m| m.user_id).collect();
-    let statuses = state.presence.get_bulk_status(&user_ids);
+async fn set_server_disabled(
+    state: &AppState,
+    server_id: Uuid,
+    disabled: bool,
+) -> AppResult<Server> {
+    let server = db::servers::set_disabled(&state.db, server_id, disabled)
+        .await?
+        .ok_or(AppError::NotFound("Server not found".to_string()))?;
 
-    for member in &mut members {
-        member.status = Some(
-            statuses
-                .get(&member.user_id)
-                .cloned()
-                .unwrap_or(PresenceStatus::Offline),
-        );
+    let event = WsEvent::ServerUpdate {
+        server: ServerPublic::from(server.clone()),
+    };
+    state.broadcast_to_server(&server_id, &event).await;
+    Ok(server)
+}
+
+/// GET /api/instance/info — always available.
+/// Returns server mode and metadata for client discovery.
+async fn instance_info(State(state): State<AppState>) -> impl IntoResponse {
+    let mode_str = match state.config.mode {
+        ServerMode::AuthHub => "auth_hub",
+        ServerMode::Community => "community",
+        ServerMode::Standalone => "standalone",
+    };
+
+    let mut default_server_id = None;
+    if state.config.is_community() {
+        if let Ok(Some(server)) = db::servers::find_by_id(&state.db, DEFAULT_SERVER_ID).await {
+            default_server_id = Some(server.id);
+        }
     }
 
-    Ok(Json(members))
+    Json(serde_json::json!({
+        "mode": mode_str,
+        "name": state.config.server.public_url,
+        "version": env!("CARGO_PKG_VERSION"),
+        "default_server_id": default_server_id,
+        "registration_open": state.config.is_auth_hub() && state.config.auth.allow_local_registration,
+    }))
 }
 
-async fn kick_member(
+// ─── User Handlers ──────────────────────────────────────────────────────────
+
+/// GET /api/users/@me
+#[utoipa::path(
+    get,
+    path = "/api/users/@me",
+    tag = "users",
+    responses(
+        (status = 200, description = "The caller's account", body = CurrentUser),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn get_current_user(
     State(state): State<AppState>,
     auth: AuthUser,
-    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
-) -> AppResult<StatusCode> {
-    check_permission(&state, auth.user_id, server_id, Permissions::KICK_MEMBERS).await?;
+) -> AppResult<Json<CurrentUser>> {
+    let user = db::users::find_by_id(&state.db, auth.user_id)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
+    Ok(Json(user.into()))
+}
 
-    // Cannot kick the server owner
-    if let Some(server) = db::servers::find_by_id(&state.db, server_id).await? {
-        if server.owner_id == user_id {
-            return Err(AppError::Forbidden);
-        }
-    }
+/// PATCH /api/users/@me
+#[utoipa::path(
+    patch,
+    path = "/api/users/@me",
+    tag = "users",
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "Account updated", body = CurrentUser),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn update_current_user(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<UpdateUserRequest>,
+) -> AppResult<Json<CurrentUser>> {
+    let Some(display_name) = req.display_name else {
+        return get_current_user(State(state), auth).await;
+    };
 
-    db::members::remove(&state.db, user_id, server_id).await?;
+    let display_name = clean_display_name(&display_name)?;
+    let user = db::users::update_display_name(&state.db, auth.user_id, &display_name)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
+    broadcast_user_update(&state, user.clone().into()).await;
 
-    // Broadcast MemberLeave
-    let event = WsEvent::MemberLeave { server_id, user_id };
-    state.broadcast_to_server(&server_id, &event).await;
+    Ok(Json(user.into()))
+}
 
-    Ok(StatusCode::NO_CONTENT)
+/// GET /api/users/:user_id
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 200, description = "The user", body = UserPublic),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+    ),
+)]
+async fn get_user(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<Json<UserPublic>> {
+    let user = db::users::find_by_id(&state.db, user_id)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
+    Ok(Json(user.into()))
 }
 
-// ─── Ban Handlers ───────────────────────────────────────────────────────────
+/// GET /api/users/:user_id/federated
+/// In community mode, the user's current profile from the auth hub (cached
+/// briefly), so renames and new avatars show up without a fresh token. Other
+/// modes own their users, so this is the same as `GET /api/users/:user_id`.
+async fn get_federated_user(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+    headers: HeaderMap,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<Json<UserPublic>> {
+    if state.config.mode != ServerMode::Community {
+        let user = db::users::find_by_id(&state.db, user_id)
+            .await?
+            .ok_or(AppError::NotFound("User not found".to_string()))?;
+        return Ok(Json(user.into()));
+    }
 
-#[derive(Deserialize)]
-pub struct CreateBanRequest {
-    reason: Option<String>,
+    let token = headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or(AppError::Unauthorized)?;
+
+    Ok(Json(state.hub_profile(user_id, token).await?))
 }
 
-async fn ban_member(
+/// Sanitize a display name to a single line and check its length.
+fn clean_display_name(display_name: &str) -> AppResult<String> {
+    let display_name = chat::sanitize_content(display_name).replace(['\n', '\t'], " ");
+    let display_name = display_name.trim();
+    let len = display_name.chars().count();
+    if len == 0 || len > chat::MAX_DISPLAY_NAME_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "Display name must be 1-{} characters",
+            chat::MAX_DISPLAY_NAME_LENGTH
+        )));
+    }
+    Ok(display_name.to_string())
+}
+
+// ─── Bot Handlers ───────────────────────────────────────────────────────────
+
+/// POST /api/bots — auth hub only.
+/// Creates a bot account owned by the caller. The returned token is shown only once.
+#[utoipa::path(
+    post,
+    path = "/api/bots",
+    tag = "users",
+    request_body = CreateBotRequest,
+    responses(
+        (status = 200, description = "Bot created with its token", body = AuthResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Bots can't create bots", body = ErrorResponse),
+        (status = 409, description = "Username taken", body = ErrorResponse),
+    ),
+)]
+async fn create_bot(
     State(state): State<AppState>,
     auth: AuthUser,
-    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
-    Json(req): Json<CreateBanRequest>,
-) -> AppResult<StatusCode> {
-    check_permission(&state, auth.user_id, server_id, Permissions::BAN_MEMBERS).await?;
-
-    // Cannot ban the server owner
-    if let Some(server) = db::servers::find_by_id(&state.db, server_id).await? {
-        if server.owner_id == user_id {
-            return Err(AppError::Forbidden);
-        }
+    Json(req): Json<CreateBotRequest>,
+) -> AppResult<Json<AuthResponse>> {
+    if auth.is_bot {
+        return Err(AppError::Forbidden);
     }
+    let username = auth::normalize_username(&req.username)?;
+    if db::users::find_by_username(&state.db, &username)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict("Username already taken".to_string()));
+    }
+    let display_name = match &req.display_name {
+        Some(name) => clean_display_name(name)?,
+        None => username.clone(),
+    };
 
-    // Add to bans table
-    db::bans::create(&state.db, server_id, user_id, req.reason.as_deref()).await?;
-
-    // Remove from server (kick)
-    db::members::remove(&state.db, user_id, server_id).await?;
-
-    // Broadcast MemberLeave
-    let event = WsEvent::MemberLeave { server_id, user_id };
-    state.broadcast_to_server(&server_id, &event).await;
+    // Each bot could own servers of its own, so this also caps those
+    let max_bots = state.config.limits.max_bots_per_user;
+    let bot = db::bots::create(
+        &state.db,
+        Uuid::now_v7(),
+        auth.user_id,
+        &username,
+        &display_name,
+        max_bots,
+    )
+    .await?
+    .ok_or_else(|| AppError::BadRequest(format!("You can own at most {} bots", max_bots)))?;
+    let token = auth::create_bot_token(&state.config.auth, bot.id, &bot.username)?;
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(Json(AuthResponse {
+        token,
+        refresh_token: None,
+        user: bot.into(),
+    }))
 }
 
-async fn unban_member(
+/// POST /api/bots/:bot_id/token — auth hub only.
+/// Owner only. Revokes every token of the bot and returns a new one, shown only once.
+#[utoipa::path(
+    post,
+    path = "/api/bots/{bot_id}/token",
+    tag = "users",
+    params(
+        ("bot_id" = Uuid, Path, description = "Bot user ID"),
+    ),
+    responses(
+        (status = 200, description = "New bot token", body = AuthResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such bot owned by the caller", body = ErrorResponse),
+    ),
+)]
+async fn reset_bot_token(
     State(state): State<AppState>,
     auth: AuthUser,
-    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
-) -> AppResult<StatusCode> {
-    check_permission(&state, auth.user_id, server_id, Permissions::BAN_MEMBERS).await?;
+    Path(bot_id): Path<Uuid>,
+) -> AppResult<Json<AuthResponse>> {
+    let bot = db::bots::revoke_tokens(&state.db, bot_id, auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Bot not found".to_string()))?;
 
-    let deleted = db::bans::delete(&state.db, server_id, user_id).await?;
-    if deleted {
-        Ok(StatusCode::NO_CONTENT)
-    } else {
-        Err(AppError::NotFound("Ban not found".to_string()))
+    // Drop whatever is still connected or cached with the old token
+    if let Some((_, session)) = state.ws_sessions.remove(&bot.id) {
+        session.close();
     }
+    state.token_cache.retain(|_, (id, _, _, _)| *id != bot.id);
+
+    // `iat` has whole-second precision and the cut-off is compared rounded
+    // down, so this token is issued at or after it
+    let token = auth::create_bot_token(&state.config.auth, bot.id, &bot.username)?;
+
+    Ok(Json(AuthResponse {
+        token,
+        refresh_token: None,
+        user: bot.into(),
+    }))
 }
 
-async fn list_bans(
+// ─── Server Handlers ────────────────────────────────────────────────────────
+
+#[utoipa::path(
+    post,
+    path = "/api/servers",
+    tag = "servers",
+    request_body = CreateServerRequest,
+    responses(
+        (status = 200, description = "Server created", body = Server),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn create_server(
     State(state): State<AppState>,
     auth: AuthUser,
-    Path(server_id): Path<Uuid>,
-) -> AppResult<Json<Vec<crate::models::Ban>>> {
-    check_permission(&state, auth.user_id, server_id, Permissions::BAN_MEMBERS).await?;
+    Json(req): Json<CreateServerRequest>,
+) -> AppResult<Json<Server>> {
+    let user_id = auth.user_id;
+
+    let max_servers = state.config.limits.max_servers_per_user;
+    let owned = db::servers::count_owned(&state.db, user_id).await?;
+    check_limit(owned, max_servers, || {
+        format!("You can own at most {} servers", max_servers)
+    })?;
 
-    // We don't have a list_for_server yet in db::bans, let's just make it return an empty list or implement it right after.
-    // For now, let's implement the DB view query directly here since we missed it in db.rs
-    let bans = sqlx::query_as::<_, crate::models::Ban>(
-        "SELECT * FROM bans WHERE server_id = $1 ORDER BY banned_at DESC",
+    let server_id = Uuid::now_v7();
+    let server = db::servers::create(
+        &state.db,
+        server_id,
+        &req.name,
+        user_id,
+        req.e2ee_enabled.unwrap_or(false),
     )
-    .bind(server_id)
-    .fetch_all(&state.db)
     .await?;
 
-    Ok(Json(bans))
-}
-
-// ─── Channel Handlers ───────────────────────────────────────────────────────
+    // Add owner as member
+    db::members::add(&state.db, user_id, server_id).await?;
 
-async fn create_channel(
-    State(state): State<AppState>,
-    auth: AuthUser,
-    Path(server_id): Path<Uuid>,
-    Json(req): Json<CreateChannelRequest>,
-) -> AppResult<Json<Channel>> {
-    check_permission(
-        &state,
-        auth.user_id,
+    // Create default channels
+    let general_id = Uuid::now_v7();
+    db::channels::create(
+        &state.db,
+        general_id,
         server_id,
-        Permissions::MANAGE_CHANNELS,
+        "general",
+        &ChannelType::Text,
+        0,
+        None,
+        None,
+    )
+    .await?;
+    let voice_id = Uuid::now_v7();
+    db::channels::create(
+        &state.db,
+        voice_id,
+        server_id,
+        "Voice",
+        &ChannelType::Voice,
+        1,
+        None,
+        None,
     )
     .await?;
 
-    let channel_id = Uuid::now_v7();
-    let channel = db::channels::create(
+    // Create @everyone role (default permissions: SEND_MESSAGES)
+    db::roles::create(
         &state.db,
-        channel_id,
         server_id,
-        &req.name,
-        &req.channel_type,
+        "@everyone",
+        Permissions::SEND_MESSAGES,
+        0,
         0,
-        req.category_id,
     )
     .await?;
 
-    // Broadcast to server members
-    state.broadcast_to_channel(&server_id, &WsEvent::ChannelCreate(channel.clone()));
+    Ok(Json(server))
+}
 
-    Ok(Json(channel))
+#[utoipa::path(
+    get,
+    path = "/api/servers",
+    tag = "servers",
+    responses(
+        (status = 200, description = "Servers the caller is a member of", body = [Server]),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn list_servers(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<Server>>> {
+    let servers = db::servers::list_for_user(&state.db, auth.user_id).await?;
+    Ok(Json(servers))
 }
 
-async fn list_channels(
+#[utoipa::path(
+    get,
+    path = "/api/servers/{server_id}",
+    tag = "servers",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    responses(
+        (status = 200, description = "The server", body = Server),
+        (status = 404, description = "Server not found", body = ErrorResponse),
+    ),
+    security(()),
+)]
+async fn get_server(
     State(state): State<AppState>,
     Path(server_id): Path<Uuid>,
-) -> AppResult<Json<Vec<Channel>>> {
-    let mut channels = db::channels::list_for_server(&state.db, server_id).await?;
+) -> AppResult<Json<Server>> {
+    let server = db::servers::find_by_id(&state.db, server_id)
+        .await?
+        .ok_or(AppError::NotFound("Server not found".to_string()))?;
+    Ok(Json(server))
+}
 
-    // Embed active voice participants into voice channels
-    for channel in channels.iter_mut() {
-        if channel.channel_type == ChannelType::Voice {
-            if let Some(participants) = state.voice_states.get(&channel.id) {
-                channel.voice_participants = Some(participants.value().clone());
-            } else {
-                channel.voice_participants = Some(Vec::new());
-            }
+/// PATCH /api/servers/:server_id
+#[utoipa::path(
+    patch,
+    path = "/api/servers/{server_id}",
+    tag = "servers",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    request_body = UpdateServerRequest,
+    responses(
+        (status = 200, description = "Server updated", body = Server),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Server not found", body = ErrorResponse),
+    ),
+)]
+async fn update_server(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+    Json(req): Json<UpdateServerRequest>,
+) -> AppResult<Json<Server>> {
+    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
+
+    let name = req.name.as_deref().map(str::trim);
+    if let Some(name) = name {
+        if name.is_empty() || name.chars().count() > 100 {
+            return Err(AppError::BadRequest(
+                "Server name must be 1-100 characters".to_string(),
+            ));
         }
     }
 
-    Ok(Json(channels))
+    let server = db::servers::update(&state.db, server_id, name, req.embeds_enabled)
+        .await?
+        .ok_or(AppError::NotFound("Server not found".to_string()))?;
+
+    let event = WsEvent::ServerUpdate {
+        server: ServerPublic::from(server.clone()),
+    };
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(Json(server))
 }
 
-async fn delete_channel(
+/// DELETE /api/servers/:server_id — owner only. Removes the server with all
+/// its channels, messages, members, roles and emojis.
+#[utoipa::path(
+    delete,
+    path = "/api/servers/{server_id}",
+    tag = "servers",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    responses(
+        (status = 204, description = "Server deleted"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the owner", body = ErrorResponse),
+        (status = 404, description = "Server not found", body = ErrorResponse),
+    ),
+)]
+async fn delete_server(
     State(state): State<AppState>,
     auth: AuthUser,
-    Path((server_id, channel_id)): Path<(Uuid, Uuid)>,
+    Path(server_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    check_permission(
-        &state,
-        auth.user_id,
-        server_id,
-        Permissions::MANAGE_CHANNELS,
-    )
-    .await?;
-
-    // Delete the channel from the database
-    let deleted = db::channels::delete(&state.db, channel_id).await?;
-
-    if deleted {
-        // Broadcast channel deletion (you might want to add a ChannelDelete event to WsEvent instead of raw ID, but we can reuse MessageDelete-like logic or just rely on state refetch for now. Since we don't have ChannelDelete in WsEvent, we do nothing for now and rely on standard app reload or we should add ChannelDelete event).
-        // For now, return OK.
-        Ok(StatusCode::NO_CONTENT)
-    } else {
-        Err(AppError::NotFound("Channel not found".to_string()))
+    // The owner check is part of the delete, so ownership can't change
+    // between checking and deleting; a failed delete only picks the error
+    let remnants = ServerRemnants::collect(&state, server_id).await?;
+    if !db::servers::delete(&state.db, server_id, auth.user_id).await? {
+        return Err(match db::servers::find_by_id(&state.db, server_id).await? {
+            Some(_) => AppError::Forbidden,
+            None => AppError::NotFound("Server not found".to_string()),
+        });
     }
+    remnants.clean_up(&state).await;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
-// ─── Message Handlers ───────────────────────────────────────────────────────
+/// What a server leaves behind outside its own rows. Collected before the
+/// server is deleted, since everything needed to find it cascades away.
+struct ServerRemnants {
+    server_id: Uuid,
+    channels: Vec<Channel>,
+    emojis: Vec<CustomEmoji>,
+    member_ids: Vec<Uuid>,
+}
 
+impl ServerRemnants {
+    async fn collect(state: &AppState, server_id: Uuid) -> AppResult<Self> {
+        Ok(Self {
+            server_id,
+            channels: db::channels::list_for_server(&state.db, server_id).await?,
+            emojis: db::emojis::list_for_server(&state.db, server_id).await?,
+            member_ids: state.server_member_ids(&server_id).await?,
+        })
+    }
+
+    /// Once the server is deleted: hang up its voice channels, drop its
+    /// caches and emoji files, and tell its members it's gone.
+    async fn clean_up(self, state: &AppState) {
+        let server_id = self.server_id;
+        for channel in &self.channels {
+            let participants = state
+                .voice_states
+                .get(&channel.id)
+                .map(|p| p.iter().map(|p| p.user_id).collect::<Vec<_>>())
+                .unwrap_or_default();
+            for user_id in participants {
+                leave_voice_channel(state, channel.id, user_id).await;
+            }
+            state.channel_subs.remove(&channel.id);
+            state.voice_states.remove(&channel.id);
+        }
+        state.server_members.remove(&server_id);
+        state.voice_mutes.remove(&server_id);
+
+        for emoji in &self.emojis {
+            let key = emoji_key(server_id, emoji.id);
+            if let Err(e) = state.storage.delete(&key).await {
+                tracing::warn!("Failed to delete emoji {}: {:#}", key, e);
+            }
+        }
+
+        let event = WsEvent::ServerDelete { server_id };
+        for user_id in &self.member_ids {
+            state.broadcast_to_user(user_id, &event);
+        }
+    }
+}
+
+/// POST /api/servers/:server_id/transfer — owner only. The old owner stays
+/// on as a regular member.
+#[utoipa::path(
+    post,
+    path = "/api/servers/{server_id}/transfer",
+    tag = "servers",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    request_body = TransferOwnershipRequest,
+    responses(
+        (status = 200, description = "Ownership transferred", body = Server),
+        (status = 400, description = "The new owner isn't a member", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the owner", body = ErrorResponse),
+        (status = 404, description = "Server not found", body = ErrorResponse),
+    ),
+)]
+async fn transfer_server(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+    Json(req): Json<TransferOwnershipRequest>,
+) -> AppResult<Json<Server>> {
+    if req.new_owner_id == auth.user_id {
+        return Err(AppError::BadRequest(
+            "You can't transfer a server to yourself".to_string(),
+        ));
+    }
+    let server = db::servers::find_by_id(&state.db, server_id)
+        .await?
+        .ok_or(AppError::NotFound("Server not found".to_string()))?;
+    if server.owner_id != auth.user_id {
+        return Err(AppError::Forbidden);
+    }
+    if db::members::find(&state.db, req.new_owner_id, server_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::BadRequest(
+            "The new owner must be a member of the server".to_string(),
+        ));
+    }
+
+    // Only changes hands if the caller still owns it, so two concurrent
+    // transfers can't both succeed
+    let server =
+        db::servers::transfer_ownership(&state.db, server_id, auth.user_id, req.new_owner_id)
+            .await?
+            .ok_or(AppError::Forbidden)?;
+
+    let event = WsEvent::ServerUpdate {
+        server: ServerPublic::from(server.clone()),
+    };
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(Json(server))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/servers/{server_id}/join",
+    tag = "servers",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    responses(
+        (status = 200, description = "Joined"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Not the default server, or banned from it", body = ErrorResponse),
+    ),
+)]
+async fn join_server(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    // Every other server is joined through an invite
+    if server_id != DEFAULT_SERVER_ID {
+        return Err(AppError::Forbidden);
+    }
+
+    // 1. Check if the server is currently "unclaimed" (owned by the dummy system user)
+    if let Ok(Some(server)) = db::servers::find_by_id(&state.db, server_id).await {
+        if server.owner_id == SYSTEM_USER_ID {
+            // First user to join the default server claims it
+            tracing::info!(
+                "User {} is claiming the default server {}",
+                auth.user_id,
+                server_id
+            );
+            // Broadcast the server update so the client gets owner permissions immediately.
+            // Nothing changes if someone else claimed it first.
+            if let Some(updated_server) =
+                db::servers::transfer_ownership(&state.db, server_id, SYSTEM_USER_ID, auth.user_id)
+                    .await?
+            {
+                let event = WsEvent::ServerUpdate {
+                    server: ServerPublic::from(updated_server),
+                };
+                state.broadcast_to_server(&server_id, &event).await;
+            }
+        }
+    }
+
+    // 2. Add the user as a member and broadcast MemberJoin
+    add_member_and_broadcast(&state, auth.user_id, server_id).await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/servers/{server_id}/leave",
+    tag = "servers",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    responses(
+        (status = 200, description = "Left"),
+        (status = 400, description = "The owner can't leave", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "Server not found", body = ErrorResponse),
+    ),
+)]
+async fn leave_server(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    if let Some(server) = db::servers::find_by_id(&state.db, server_id).await? {
+        if server.owner_id == auth.user_id {
+            return Err(AppError::BadRequest(
+                "Server owners cannot leave their own server".into(),
+            ));
+        }
+    } else {
+        return Err(AppError::NotFound("Server not found".into()));
+    }
+
+    db::members::remove(&state.db, auth.user_id, server_id).await?;
+    state.cache_member_remove(server_id, auth.user_id);
+
+    // Broadcast MemberLeave to all connected server members
+    let event = WsEvent::MemberLeave {
+        server_id,
+        user_id: auth.user_id,
+    };
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(StatusCode::OK)
+}
+
+// ─── Invite Handlers ────────────────────────────────────────────────────────
+
+/// Length of generated invite codes (the `invites.code` column allows 16).
+const INVITE_CODE_LENGTH: usize = 8;
+/// Registration invites are guessable into an account, so they're longer.
+const REGISTRATION_INVITE_CODE_LENGTH: usize = 24;
+
+fn generate_invite_code(length: usize) -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::Rng;
+
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// POST /api/servers/:server_id/invites
+async fn create_invite(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+    Json(req): Json<CreateInviteRequest>,
+) -> AppResult<Json<Invite>> {
+    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
+
+    if matches!(req.max_uses, Some(n) if n < 1) {
+        return Err(AppError::BadRequest(
+            "max_uses must be at least 1".to_string(),
+        ));
+    }
+    if matches!(req.expires_at, Some(at) if at <= chrono::Utc::now()) {
+        return Err(AppError::BadRequest(
+            "expires_at must be in the future".to_string(),
+        ));
+    }
+
+    let invite = db::invites::create(
+        &state.db,
+        &generate_invite_code(INVITE_CODE_LENGTH),
+        server_id,
+        auth.user_id,
+        req.max_uses,
+        req.expires_at,
+    )
+    .await?;
+
+    Ok(Json(invite))
+}
+
+/// GET /api/servers/:server_id/invites
+async fn list_invites(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+) -> AppResult<Json<Vec<Invite>>> {
+    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
+
+    let invites = db::invites::list_for_server(&state.db, server_id).await?;
+    Ok(Json(invites))
+}
+
+/// POST /api/invites/:code — redeem an invite and join its server.
+async fn redeem_invite(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(code): Path<String>,
+) -> AppResult<Json<Server>> {
+    let invite = db::invites::find_by_code(&state.db, &code)
+        .await?
+        .ok_or(AppError::NotFound("Invite not found".to_string()))?;
+
+    let server = db::servers::find_by_id(&state.db, invite.server_id)
+        .await?
+        .ok_or(AppError::NotFound("Server not found".to_string()))?;
+
+    // Already a member — nothing to consume
+    if db::members::find(&state.db, auth.user_id, server.id)
+        .await?
+        .is_some()
+    {
+        return Ok(Json(server));
+    }
+
+    if db::bans::find(&state.db, server.id, auth.user_id)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Forbidden);
+    }
+
+    if matches!(invite.expires_at, Some(at) if at <= chrono::Utc::now()) {
+        return Err(AppError::Gone("Invite has expired".to_string()));
+    }
+
+    if db::invites::increment_uses(&state.db, &code)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::BadRequest(
+            "Invite has reached its maximum uses".to_string(),
+        ));
+    }
+
+    add_member_and_broadcast(&state, auth.user_id, server.id).await?;
+
+    Ok(Json(server))
+}
+
+/// DELETE /api/invites/:code
+async fn delete_invite(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(code): Path<String>,
+) -> AppResult<StatusCode> {
+    let invite = db::invites::find_by_code(&state.db, &code)
+        .await?
+        .ok_or(AppError::NotFound("Invite not found".to_string()))?;
+
+    check_permission(
+        &state,
+        auth.user_id,
+        invite.server_id,
+        Permissions::MANAGE_SERVER,
+    )
+    .await?;
+
+    db::invites::delete(&state.db, &code).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ─── Role Handlers ──────────────────────────────────────────────────────────
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct CreateRoleRequest {
+    name: String,
+    permissions: i64,
+    color: i32,
+    position: i32,
+}
+
+/// Name of the role every member implicitly has (see `get_permissions`).
+const EVERYONE_ROLE_NAME: &str = "@everyone";
+
+/// The implicit @everyone role: it can't be deleted, renamed or moved.
+fn is_everyone_role(role: &Role) -> bool {
+    role.name == EVERYONE_ROLE_NAME && role.position == 0
+}
+
+/// Refuse to hand out permission bits the caller doesn't hold themselves.
+fn check_grantable(caller: Permissions, requested: i64) -> AppResult<()> {
+    if caller.bits() & Permissions::ADMINISTRATOR == 0 && requested & !caller.bits() != 0 {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Whether a caller whose highest role sits at `caller_top` may manage a
+/// role at `position`: only roles strictly below their own.
+fn can_manage_role(caller_top: Option<i32>, position: i32) -> bool {
+    caller_top.is_some_and(|top| position < top)
+}
+
+/// Refuse to rename or move `role` to `name` at `position` unless the
+/// caller ranked at `caller_top` manages it both before and after.
+fn check_role_update(
+    role: &Role,
+    caller_top: Option<i32>,
+    name: &str,
+    position: i32,
+) -> AppResult<()> {
+    if is_everyone_role(role) {
+        // Permissions resolve @everyone by name, so it must stay as it is
+        if name != EVERYONE_ROLE_NAME || position != 0 {
+            return Err(AppError::BadRequest(
+                "The @everyone role can't be renamed or moved".to_string(),
+            ));
+        }
+    } else if name == EVERYONE_ROLE_NAME
+        || !can_manage_role(caller_top, role.position)
+        || !can_manage_role(caller_top, position)
+    {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Refuse to delete @everyone, or a role the caller doesn't manage.
+fn check_role_delete(role: &Role, caller_top: Option<i32>) -> AppResult<()> {
+    if is_everyone_role(role) {
+        return Err(AppError::BadRequest(
+            "The @everyone role can't be deleted".to_string(),
+        ));
+    }
+    if !can_manage_role(caller_top, role.position) {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+/// A member's rank for hierarchy checks: their highest role position, with
+/// the owner above every role and members without roles below everyone.
+async fn member_rank(state: &AppState, server: &Server, user_id: Uuid) -> AppResult<Option<i32>> {
+    if server.owner_id == user_id {
+        return Ok(Some(i32::MAX));
+    }
+    db::members::highest_role_position(&state.db, user_id, server.id).await
+}
+
+/// Moderators may only act on members ranked strictly below them.
+fn outranks(actor: Option<i32>, target: Option<i32>) -> bool {
+    actor > target
+}
+
+/// Refuse moderation of `target_id` unless `actor_id` outranks them. Also
+/// covers the owner, who nobody outranks.
+async fn check_hierarchy(
+    state: &AppState,
+    server_id: Uuid,
+    actor_id: Uuid,
+    target_id: Uuid,
+) -> AppResult<()> {
+    let server = db::servers::find_by_id(&state.db, server_id)
+        .await?
+        .ok_or(AppError::NotFound("Server not found".to_string()))?;
+    let actor = member_rank(state, &server, actor_id).await?;
+    let target = member_rank(state, &server, target_id).await?;
+    if !outranks(actor, target) {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+/// The caller's permissions and rank in a server, for role management.
+async fn role_manager(
+    state: &AppState,
+    user_id: Uuid,
+    server_id: Uuid,
+) -> AppResult<(Permissions, Option<i32>)> {
+    let perms = db::members::get_permissions(&state.db, user_id, server_id).await?;
+    if !perms.has(Permissions::MANAGE_SERVER) {
+        return Err(AppError::Forbidden);
+    }
+
+    let server = db::servers::find_by_id(&state.db, server_id)
+        .await?
+        .ok_or(AppError::NotFound("Server not found".to_string()))?;
+    let top = member_rank(state, &server, user_id).await?;
+    Ok((perms, top))
+}
+
+/// Ensure the caller may give `role_id` to, or take it from, `user_id`.
+async fn check_role_assignment(
+    state: &AppState,
+    server_id: Uuid,
+    actor_id: Uuid,
+    user_id: Uuid,
+    role_id: Uuid,
+) -> AppResult<()> {
+    let (_, top) = role_manager(state, actor_id, server_id).await?;
+
+    let role = db::roles::list_for_server(&state.db, server_id)
+        .await?
+        .into_iter()
+        .find(|r| r.id == role_id)
+        .ok_or(AppError::NotFound("Role not found".to_string()))?;
+    if !can_manage_role(top, role.position) {
+        return Err(AppError::Forbidden);
+    }
+    // Members may shuffle roles below their own on themselves
+    if user_id != actor_id {
+        check_hierarchy(state, server_id, actor_id, user_id).await?;
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/servers/{server_id}/roles",
+    tag = "roles",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    responses(
+        (status = 200, description = "The server's roles", body = [Role]),
+    ),
+    security(()),
+)]
+async fn list_roles(
+    State(state): State<AppState>,
+    Path(server_id): Path<Uuid>,
+) -> AppResult<Json<Vec<Role>>> {
+    let roles = db::roles::list_for_server(&state.db, server_id).await?;
+    Ok(Json(roles))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/servers/{server_id}/roles",
+    tag = "roles",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    request_body = CreateRoleRequest,
+    responses(
+        (status = 200, description = "Role created", body = Role),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn create_role(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+    Json(req): Json<CreateRoleRequest>,
+) -> AppResult<Json<Role>> {
+    let (perms, top) = role_manager(&state, auth.user_id, server_id).await?;
+    check_grantable(perms, req.permissions)?;
+    // A server missing its @everyone role may create it, at the bottom
+    if req.name == EVERYONE_ROLE_NAME {
+        let roles = db::roles::list_for_server(&state.db, server_id).await?;
+        if roles.iter().any(|r| r.name == EVERYONE_ROLE_NAME) {
+            return Err(AppError::BadRequest(
+                "A server can only have one @everyone role".to_string(),
+            ));
+        }
+        if req.position != 0 {
+            return Err(AppError::BadRequest(
+                "The @everyone role must be at position 0".to_string(),
+            ));
+        }
+    }
+    if !can_manage_role(top, req.position) {
+        return Err(AppError::Forbidden);
+    }
+
+    let role = db::roles::create(
+        &state.db,
+        server_id,
+        &req.name,
+        req.permissions,
+        req.color,
+        req.position,
+    )
+    .await?;
+
+    record_audit(
+        &state,
+        server_id,
+        auth.user_id,
+        AuditAction::RoleCreate,
+        Some(role.id),
+        Some(role.id),
+        None,
+    )
+    .await;
+
+    let event = WsEvent::RoleCreate(role.clone());
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(Json(role))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/servers/{server_id}/roles/{role_id}",
+    tag = "roles",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    request_body = CreateRoleRequest,
+    responses(
+        (status = 200, description = "Role updated", body = Role),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Role not found", body = ErrorResponse),
+    ),
+)]
+async fn update_role(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, role_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<CreateRoleRequest>,
+) -> AppResult<Json<Role>> {
+    let (perms, top) = role_manager(&state, auth.user_id, server_id).await?;
+    check_grantable(perms, req.permissions)?;
+
+    let existing = db::roles::list_for_server(&state.db, server_id)
+        .await?
+        .into_iter()
+        .find(|r| r.id == role_id)
+        .ok_or(AppError::NotFound("Role not found".to_string()))?;
+    check_role_update(&existing, top, &req.name, req.position)?;
+
+    let role = db::roles::update(
+        &state.db,
+        role_id,
+        server_id,
+        &req.name,
+        req.permissions,
+        req.color,
+        req.position,
+    )
+    .await?
+    .ok_or(AppError::NotFound("Role not found".to_string()))?;
+
+    let event = WsEvent::RoleUpdate(role.clone());
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(Json(role))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/servers/{server_id}/roles/{role_id}",
+    tag = "roles",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    responses(
+        (status = 204, description = "Role deleted"),
+        (status = 400, description = "The @everyone role can't be deleted", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Role not found", body = ErrorResponse),
+    ),
+)]
+async fn delete_role(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, role_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    let (_, top) = role_manager(&state, auth.user_id, server_id).await?;
+
+    let role = db::roles::list_for_server(&state.db, server_id)
+        .await?
+        .into_iter()
+        .find(|r| r.id == role_id)
+        .ok_or(AppError::NotFound("Role not found".to_string()))?;
+    check_role_delete(&role, top)?;
+
+    db::roles::delete(&state.db, role_id).await?;
+    db::overrides::delete_for_target(&state.db, role_id).await?;
+    record_audit(
+        &state,
+        server_id,
+        auth.user_id,
+        AuditAction::RoleDelete,
+        Some(role_id),
+        Some(role_id),
+        None,
+    )
+    .await;
+
+    let event = WsEvent::RoleDelete { server_id, role_id };
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/servers/{server_id}/members/{user_id}/roles/{role_id}",
+    tag = "roles",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    responses(
+        (status = 200, description = "Role assigned"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Role not found", body = ErrorResponse),
+    ),
+)]
+async fn assign_role(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, user_id, role_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    check_role_assignment(&state, server_id, auth.user_id, user_id, role_id).await?;
+    db::members::add_role(&state.db, user_id, server_id, role_id).await?;
+    record_audit(
+        &state,
+        server_id,
+        auth.user_id,
+        AuditAction::MemberRoleAdd,
+        Some(user_id),
+        Some(role_id),
+        None,
+    )
+    .await;
+
+    if let Ok(Some(member)) = db::members::find(&state.db, user_id, server_id).await {
+        state
+            .broadcast_to_server(&server_id, &WsEvent::MemberUpdate { server_id, member })
+            .await;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/servers/{server_id}/members/{user_id}/roles/{role_id}",
+    tag = "roles",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    responses(
+        (status = 200, description = "Role removed"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn remove_role(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, user_id, role_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    check_role_assignment(&state, server_id, auth.user_id, user_id, role_id).await?;
+    db::members::remove_role(&state.db, user_id, server_id, role_id).await?;
+    record_audit(
+        &state,
+        server_id,
+        auth.user_id,
+        AuditAction::MemberRoleRemove,
+        Some(user_id),
+        Some(role_id),
+        None,
+    )
+    .await;
+
+    if let Ok(Some(member)) = db::members::find(&state.db, user_id, server_id).await {
+        state
+            .broadcast_to_server(&server_id, &WsEvent::MemberUpdate { server_id, member })
+            .await;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/servers/{server_id}/members/{user_id}",
+    tag = "members",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 200, description = "The member", body = Member),
+        (status = 404, description = "Member not found", body = ErrorResponse),
+    ),
+    security(()),
+)]
+async fn get_member(
+    State(state): State<AppState>,
+    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<Json<Member>> {
+    let member = db::members::find(&state.db, user_id, server_id)
+        .await?
+        .ok_or(AppError::NotFound("Member not found".to_string()))?;
+    Ok(Json(member))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MemberQuery {
+    after: Option<Uuid>,
+    limit: Option<i64>,
+    query: Option<String>,
+}
+
+/// GET /api/servers/:server_id/members?after=&limit=&query=
+#[utoipa::path(
+    get,
+    path = "/api/servers/{server_id}/members",
+    tag = "members",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        MemberQuery,
+    ),
+    responses(
+        (status = 200, description = "Members ordered by user ID", body = [Member]),
+    ),
+    security(()),
+)]
+async fn list_members(
+    State(state): State<AppState>,
+    Path(server_id): Path<Uuid>,
+    Query(params): Query<MemberQuery>,
+) -> AppResult<Json<Vec<Member>>> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 100);
+    let query = params
+        .query
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty());
+    if query.is_some_and(|q| q.chars().count() > chat::MAX_DISPLAY_NAME_LENGTH) {
+        return Err(AppError::BadRequest(format!(
+            "Member query must be at most {} characters",
+            chat::MAX_DISPLAY_NAME_LENGTH
+        )));
+    }
+
+    let mut members =
+        db::members::list_paginated(&state.db, server_id, params.after, query, limit).await?;
+
+    // Populate presence status for this page only
+    let user_ids: Vec<Uuid> = members.iter().map(|m| m.user_id).collect();
+    let statuses = state.presence.get_bulk_status(&user_ids);
+
+    for member in &mut members {
+        member.status = Some(
+            statuses
+                .get(&member.user_id)
+                .cloned()
+                .unwrap_or(PresenceStatus::Offline),
+        );
+    }
+
+    Ok(Json(members))
+}
+
+/// PATCH /api/servers/:server_id/members/:user_id
+/// Members may change their own nickname; KICK_MEMBERS is needed for others'.
+#[utoipa::path(
+    patch,
+    path = "/api/servers/{server_id}/members/{user_id}",
+    tag = "members",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    request_body = UpdateMemberRequest,
+    responses(
+        (status = 200, description = "Member updated", body = Member),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Member not found", body = ErrorResponse),
+    ),
+)]
+async fn update_member(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<UpdateMemberRequest>,
+) -> AppResult<Json<Member>> {
+    // Renaming others is moderation: it needs KICK_MEMBERS and a higher rank
+    if user_id != auth.user_id {
+        check_permission(&state, auth.user_id, server_id, Permissions::KICK_MEMBERS).await?;
+        check_hierarchy(&state, server_id, auth.user_id, user_id).await?;
+    }
+
+    let nickname = match req.nickname {
+        Some(nickname) => {
+            let nickname = chat::sanitize_content(&nickname).replace(['\n', '\t'], " ");
+            let len = nickname.chars().count();
+            if len == 0 || len > chat::MAX_NICKNAME_LENGTH {
+                return Err(AppError::BadRequest(format!(
+                    "Nickname must be 1-{} characters",
+                    chat::MAX_NICKNAME_LENGTH
+                )));
+            }
+            Some(nickname)
+        }
+        None => None,
+    };
+
+    let member = db::members::update_nickname(&state.db, user_id, server_id, nickname.as_deref())
+        .await?
+        .ok_or_else(|| AppError::NotFound("Member not found".to_string()))?;
+
+    let event = WsEvent::MemberUpdate {
+        server_id,
+        member: member.clone(),
+    };
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(Json(member))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/servers/{server_id}/members/{user_id}",
+    tag = "members",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 204, description = "Member kicked"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Member not found", body = ErrorResponse),
+    ),
+)]
+async fn kick_member(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    check_permission(&state, auth.user_id, server_id, Permissions::KICK_MEMBERS).await?;
+    check_hierarchy(&state, server_id, auth.user_id, user_id).await?;
+
+    db::members::remove(&state.db, user_id, server_id).await?;
+    state.cache_member_remove(server_id, user_id);
+    record_audit(
+        &state,
+        server_id,
+        auth.user_id,
+        AuditAction::MemberKick,
+        Some(user_id),
+        None,
+        None,
+    )
+    .await;
+
+    // Broadcast MemberLeave
+    let event = WsEvent::MemberLeave { server_id, user_id };
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ─── Ban Handlers ───────────────────────────────────────────────────────────
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct CreateBanRequest {
+    reason: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/servers/{server_id}/bans/{user_id}",
+    tag = "members",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    request_body = CreateBanRequest,
+    responses(
+        (status = 204, description = "User banned"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn ban_member(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<CreateBanRequest>,
+) -> AppResult<StatusCode> {
+    check_permission(&state, auth.user_id, server_id, Permissions::BAN_MEMBERS).await?;
+    check_hierarchy(&state, server_id, auth.user_id, user_id).await?;
+
+    // Add to bans table
+    db::bans::create(&state.db, server_id, user_id, req.reason.as_deref()).await?;
+
+    // Remove from server (kick)
+    db::members::remove(&state.db, user_id, server_id).await?;
+    state.cache_member_remove(server_id, user_id);
+    record_audit(
+        &state,
+        server_id,
+        auth.user_id,
+        AuditAction::MemberBan,
+        Some(user_id),
+        None,
+        req.reason.as_deref(),
+    )
+    .await;
+
+    // Broadcast MemberLeave, then the ban itself
+    let event = WsEvent::MemberLeave { server_id, user_id };
+    state.broadcast_to_server(&server_id, &event).await;
+    let event = WsEvent::BanAdd { server_id, user_id };
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/servers/{server_id}/bans/{user_id}",
+    tag = "members",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 204, description = "User unbanned"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Ban not found", body = ErrorResponse),
+    ),
+)]
+async fn unban_member(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, user_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    check_permission(&state, auth.user_id, server_id, Permissions::BAN_MEMBERS).await?;
+
+    let deleted = db::bans::delete(&state.db, server_id, user_id).await?;
+    if deleted {
+        record_audit(
+            &state,
+            server_id,
+            auth.user_id,
+            AuditAction::MemberUnban,
+            Some(user_id),
+            None,
+            None,
+        )
+        .await;
+
+        let event = WsEvent::BanRemove { server_id, user_id };
+        state.broadcast_to_server(&server_id, &event).await;
+
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Ban not found".to_string()))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/servers/{server_id}/bans",
+    tag = "members",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    responses(
+        (status = 200, description = "The server's bans", body = [Ban]),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn list_bans(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+) -> AppResult<Json<Vec<crate::models::Ban>>> {
+    check_permission(&state, auth.user_id, server_id, Permissions::BAN_MEMBERS).await?;
+
+    let bans = db::bans::list_for_server(&state.db, server_id).await?;
+    Ok(Json(bans))
+}
+
+// ─── Audit Log Handlers ─────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct AuditLogQuery {
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// GET /api/servers/:server_id/audit-logs
+async fn list_audit_logs(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+    Query(params): Query<AuditLogQuery>,
+) -> AppResult<Json<Vec<AuditLogEntry>>> {
+    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
+
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    let entries = db::audit::list_for_server(&state.db, server_id, params.before, limit).await?;
+    Ok(Json(entries))
+}
+
+// ─── Custom Emoji Handlers ──────────────────────────────────────────────────
+
+const MAX_EMOJI_SIZE: usize = 256 * 1024; // 256 KB
+const MAX_EMOJI_DIMENSION: u32 = 512;
+
+fn emoji_key(server_id: Uuid, emoji_id: Uuid) -> String {
+    format!("emojis/{}/{}", server_id, emoji_id)
+}
+
+/// Send the server's current emoji list to its members.
+async fn broadcast_emojis_update(state: &AppState, server_id: Uuid) -> AppResult<()> {
+    let emojis = db::emojis::list_for_server(&state.db, server_id).await?;
+    state
+        .broadcast_to_server(&server_id, &WsEvent::EmojisUpdate { server_id, emojis })
+        .await;
+    Ok(())
+}
+
+/// GET /api/servers/:server_id/emojis
+async fn list_emojis(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+) -> AppResult<Json<Vec<CustomEmoji>>> {
+    if db::members::find(&state.db, auth.user_id, server_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::Forbidden);
+    }
+
+    let emojis = db::emojis::list_for_server(&state.db, server_id).await?;
+    Ok(Json(emojis))
+}
+
+/// POST /api/servers/:server_id/emojis
+/// Multipart with a `name` field and an `image` file.
+async fn upload_emoji(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> AppResult<Json<CustomEmoji>> {
+    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
+
+    let mut name = None;
+    let mut image = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Invalid multipart data: {}", e)))?
+    {
+        match field.name() {
+            Some("name") => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|e| AppError::BadRequest(format!("Failed to read name: {}", e)))?;
+                name = Some(text);
+            }
+            Some("image") => {
+                let content_type = field
+                    .content_type()
+                    .unwrap_or("application/octet-stream")
+                    .to_string();
+                if !ALLOWED_CONTENT_TYPES.contains(&content_type.as_str()) {
+                    return Err(AppError::BadRequest(format!(
+                        "Invalid file type: {}. Allowed: PNG, JPEG, GIF, WebP",
+                        content_type
+                    )));
+                }
+
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| AppError::BadRequest(format!("Failed to read file: {}", e)))?;
+                if data.len() > MAX_EMOJI_SIZE {
+                    return Err(AppError::BadRequest(format!(
+                        "File too large ({} bytes). Maximum is {} bytes",
+                        data.len(),
+                        MAX_EMOJI_SIZE
+                    )));
+                }
+                validate_image(&data, &content_type, MAX_EMOJI_DIMENSION)?;
+                image = Some((data, content_type));
+            }
+            _ => {}
+        }
+    }
+
+    let name = name.ok_or_else(|| AppError::BadRequest("No name provided".to_string()))?;
+    let (data, content_type) =
+        image.ok_or_else(|| AppError::BadRequest("No file provided".to_string()))?;
+    chat::validate_emoji_name(&name)?;
+
+    let existing = db::emojis::list_for_server(&state.db, server_id).await?;
+    if existing.len() >= chat::MAX_EMOJIS_PER_SERVER {
+        return Err(AppError::BadRequest(format!(
+            "Server already has the maximum of {} emojis",
+            chat::MAX_EMOJIS_PER_SERVER
+        )));
+    }
+    if existing.iter().any(|e| e.name == name) {
+        return Err(AppError::Conflict(format!(
+            "An emoji named '{}' already exists",
+            name
+        )));
+    }
+
+    let emoji_id = Uuid::now_v7();
+    let animated = content_type == "image/gif";
+    state
+        .storage
+        .put(&emoji_key(server_id, emoji_id), data, &content_type)
+        .await
+        .map_err(|e| AppError::Internal(e.context("Failed to store emoji")))?;
+
+    let emoji = db::emojis::create(
+        &state.db,
+        emoji_id,
+        server_id,
+        &name,
+        animated,
+        auth.user_id,
+    )
+    .await?;
+
+    record_audit(
+        &state,
+        server_id,
+        auth.user_id,
+        AuditAction::EmojiCreate,
+        Some(emoji_id),
+        None,
+        None,
+    )
+    .await;
+    broadcast_emojis_update(&state, server_id).await?;
+
+    Ok(Json(emoji))
+}
+
+/// DELETE /api/servers/:server_id/emojis/:emoji_id
+async fn delete_emoji(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, emoji_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    check_permission(&state, auth.user_id, server_id, Permissions::MANAGE_SERVER).await?;
+
+    if !db::emojis::delete(&state.db, server_id, emoji_id).await? {
+        return Err(AppError::NotFound("Emoji not found".to_string()));
+    }
+
+    let key = emoji_key(server_id, emoji_id);
+    if let Err(e) = state.storage.delete(&key).await {
+        tracing::warn!("Failed to delete emoji {}: {:#}", key, e);
+    }
+
+    record_audit(
+        &state,
+        server_id,
+        auth.user_id,
+        AuditAction::EmojiDelete,
+        Some(emoji_id),
+        None,
+        None,
+    )
+    .await;
+    broadcast_emojis_update(&state, server_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/emojis/:server_id/:emoji_id
+/// Served like avatars: emoji ids are never reused, so images cache forever.
+async fn get_emoji(
+    State(state): State<AppState>,
+    Path((server_id, emoji_id)): Path<(Uuid, Uuid)>,
+) -> Result<axum::response::Response, AppError> {
+    let key = emoji_key(server_id, emoji_id);
+    if let Some(url) = state.storage.presigned_url(&key) {
+        return Ok(axum::response::Redirect::temporary(&url).into_response());
+    }
+
+    let data = state
+        .storage
+        .get(&key)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Emoji not found".to_string()))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, avatar_content_type(&data).to_string()),
+            (
+                header::CACHE_CONTROL,
+                "public, max-age=31536000, immutable".to_string(),
+            ),
+        ],
+        Body::from(data),
+    )
+        .into_response())
+}
+
+// ─── Channel Handlers ───────────────────────────────────────────────────────
+
+#[utoipa::path(
+    post,
+    path = "/api/servers/{server_id}/channels",
+    tag = "channels",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    request_body = CreateChannelRequest,
+    responses(
+        (status = 200, description = "Channel created", body = Channel),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn create_channel(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+    Json(req): Json<CreateChannelRequest>,
+) -> AppResult<Json<Channel>> {
+    check_permission(
+        &state,
+        auth.user_id,
+        server_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    let user_limit = voice_user_limit(&req.channel_type, req.user_limit)?;
+
+    if let Some(category_id) = req.category_id {
+        if req.channel_type == ChannelType::Category {
+            return Err(AppError::BadRequest(
+                "Categories can't be nested".to_string(),
+            ));
+        }
+        let is_category = db::channels::list_for_server(&state.db, server_id)
+            .await?
+            .iter()
+            .any(|c| c.id == category_id && c.channel_type == ChannelType::Category);
+        if !is_category {
+            return Err(AppError::BadRequest(
+                "category_id must refer to a category in this server".to_string(),
+            ));
+        }
+    }
+
+    let max_channels = state.config.limits.max_channels_per_server;
+    let channels = db::channels::count_for_server(&state.db, server_id).await?;
+    check_limit(channels, max_channels, || {
+        format!("A server can have at most {} channels", max_channels)
+    })?;
+
+    let channel_id = Uuid::now_v7();
+    let channel = db::channels::create(
+        &state.db,
+        channel_id,
+        server_id,
+        &req.name,
+        &req.channel_type,
+        0,
+        req.category_id,
+        user_limit,
+    )
+    .await?;
+
+    // Broadcast to server members
+    state.broadcast_to_channel(&server_id, &WsEvent::ChannelCreate(channel.clone()));
+
+    Ok(Json(channel))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/servers/{server_id}/channels",
+    tag = "channels",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    responses(
+        (status = 200, description = "The server's channels", body = [Channel]),
+    ),
+    security(()),
+)]
+async fn list_channels(
+    State(state): State<AppState>,
+    Path(server_id): Path<Uuid>,
+) -> AppResult<Json<Vec<Channel>>> {
+    let mut channels = db::channels::list_for_server(&state.db, server_id).await?;
+
+    // Embed active voice participants into voice channels
+    for channel in channels.iter_mut() {
+        if channel.channel_type == ChannelType::Voice {
+            if let Some(participants) = state.voice_states.get(&channel.id) {
+                channel.voice_participants = Some(participants.value().clone());
+            } else {
+                channel.voice_participants = Some(Vec::new());
+            }
+        }
+    }
+
+    Ok(Json(channels))
+}
+
+/// PATCH /api/servers/:server_id/channels/:channel_id
+#[utoipa::path(
+    patch,
+    path = "/api/servers/{server_id}/channels/{channel_id}",
+    tag = "channels",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+    ),
+    request_body = UpdateChannelRequest,
+    responses(
+        (status = 200, description = "Channel updated", body = Channel),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Channel not found", body = ErrorResponse),
+    ),
+)]
+async fn update_channel(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, channel_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<UpdateChannelRequest>,
+) -> AppResult<Json<Channel>> {
+    check_permission(
+        &state,
+        auth.user_id,
+        server_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    let name = match req.name {
+        Some(name) => {
+            let name = chat::sanitize_content(&name).replace(['\n', '\t'], " ");
+            let len = name.chars().count();
+            if len == 0 || len > chat::MAX_CHANNEL_NAME_LENGTH {
+                return Err(AppError::BadRequest(format!(
+                    "Channel name must be 1-{} characters",
+                    chat::MAX_CHANNEL_NAME_LENGTH
+                )));
+            }
+            Some(name)
+        }
+        None => None,
+    };
+    let topic = req.topic.map(|t| chat::sanitize_content(&t));
+    if matches!(&topic, Some(t) if t.chars().count() > chat::MAX_CHANNEL_TOPIC_LENGTH) {
+        return Err(AppError::BadRequest(format!(
+            "Channel topic must be at most {} characters",
+            chat::MAX_CHANNEL_TOPIC_LENGTH
+        )));
+    }
+    if matches!(req.slow_mode_secs, Some(s) if !(0..=chat::MAX_SLOW_MODE_SECS).contains(&s)) {
+        return Err(AppError::BadRequest(format!(
+            "slow_mode_secs must be between 0 and {}",
+            chat::MAX_SLOW_MODE_SECS
+        )));
+    }
+
+    let channel = db::channels::update(
+        &state.db,
+        server_id,
+        channel_id,
+        name.as_deref(),
+        topic.as_deref(),
+        req.slow_mode_secs,
+    )
+    .await?
+    .ok_or(AppError::NotFound("Channel not found".to_string()))?;
+
+    let event = WsEvent::ChannelUpdate(channel.clone());
+    state.broadcast_to_server(&server_id, &event).await;
+
+    Ok(Json(channel))
+}
+
+/// PATCH /api/servers/:server_id/channels/reorder
+#[utoipa::path(
+    patch,
+    path = "/api/servers/{server_id}/channels/reorder",
+    tag = "channels",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+    ),
+    request_body = ReorderChannelsRequest,
+    responses(
+        (status = 200, description = "Channels in their new order", body = [Channel]),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn reorder_channels(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(server_id): Path<Uuid>,
+    Json(req): Json<ReorderChannelsRequest>,
+) -> AppResult<Json<Vec<Channel>>> {
+    check_permission(
+        &state,
+        auth.user_id,
+        server_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    let existing = db::channels::list_for_server(&state.db, server_id).await?;
+    let mut seen = HashSet::new();
+    for id in &req.channel_ids {
+        if !seen.insert(*id) {
+            return Err(AppError::BadRequest("Duplicate channel id".to_string()));
+        }
+        if !existing.iter().any(|c| c.id == *id) {
+            return Err(AppError::BadRequest(format!(
+                "Channel {} does not belong to this server",
+                id
+            )));
+        }
+    }
+
+    let updated = db::channels::update_positions(&state.db, server_id, &req.channel_ids).await?;
+
+    // Only announce channels whose position actually changed
+    for channel in &updated {
+        let moved = existing
+            .iter()
+            .any(|c| c.id == channel.id && c.position != channel.position);
+        if moved {
+            let event = WsEvent::ChannelUpdate(channel.clone());
+            state.broadcast_to_server(&server_id, &event).await;
+        }
+    }
+
+    Ok(Json(updated))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/servers/{server_id}/channels/{channel_id}",
+    tag = "channels",
+    params(
+        ("server_id" = Uuid, Path, description = "Server ID"),
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+    ),
+    responses(
+        (status = 204, description = "Channel deleted"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Channel not found", body = ErrorResponse),
+    ),
+)]
+async fn delete_channel(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((server_id, channel_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    check_permission(
+        &state,
+        auth.user_id,
+        server_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    // Channels in a deleted category are moved out of it by the database
+    let children: Vec<Channel> = db::channels::list_for_server(&state.db, server_id)
+        .await?
+        .into_iter()
+        .filter(|c| c.category_id == Some(channel_id))
+        .collect();
+
+    // Delete the channel from the database
+    let deleted = db::channels::delete(&state.db, channel_id).await?;
+
+    if deleted {
+        for mut child in children {
+            child.category_id = None;
+            let event = WsEvent::ChannelUpdate(child);
+            state.broadcast_to_server(&server_id, &event).await;
+        }
+
+        // Nobody can be subscribed to a channel that no longer exists
+        state.channel_subs.remove(&channel_id);
+        record_audit(
+            &state,
+            server_id,
+            auth.user_id,
+            AuditAction::ChannelDelete,
+            Some(channel_id),
+            None,
+            None,
+        )
+        .await;
+
+        // Broadcast to the whole server, not just channel subscribers, so members
+        // viewing other channels also drop it from their sidebar
+        let event = WsEvent::ChannelDelete {
+            server_id,
+            channel_id,
+        };
+        state.broadcast_to_server(&server_id, &event).await;
+
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Channel not found".to_string()))
+    }
+}
+
+// ─── Channel Permission Handlers ────────────────────────────────────────────
+
+/// PUT /api/channels/:channel_id/permissions/:target_id
+async fn set_channel_override(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, target_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<SetChannelOverrideRequest>,
+) -> AppResult<Json<ChannelOverride>> {
+    check_channel_permission(
+        &state,
+        auth.user_id,
+        channel_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    // DM channels were already refused by the permission check
+    let server_id = find_channel(&state, channel_id)
+        .await?
+        .server_id
+        .ok_or(AppError::Forbidden)?;
+
+    // The target has to belong to the channel's server
+    let target_exists = match req.target_type {
+        OverrideTarget::Role => db::roles::list_for_server(&state.db, server_id)
+            .await?
+            .iter()
+            .any(|r| r.id == target_id),
+        OverrideTarget::Member => db::members::find(&state.db, target_id, server_id)
+            .await?
+            .is_some(),
+    };
+    if !target_exists {
+        return Err(AppError::NotFound("Override target not found".to_string()));
+    }
+    if req.allow & req.deny != 0 {
+        return Err(AppError::BadRequest(
+            "A permission can't be both allowed and denied".to_string(),
+        ));
+    }
+
+    let o = db::overrides::upsert(
+        &state.db,
+        channel_id,
+        target_id,
+        req.target_type,
+        req.allow,
+        req.deny,
+    )
+    .await?;
+    resync_channel_subs(&state, server_id, channel_id).await?;
+    Ok(Json(o))
+}
+
+/// DELETE /api/channels/:channel_id/permissions/:target_id
+async fn delete_channel_override(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, target_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    check_channel_permission(
+        &state,
+        auth.user_id,
+        channel_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    let server_id: Uuid = sqlx::query_scalar("SELECT server_id FROM channels WHERE id = $1")
+        .bind(channel_id)
+        .fetch_one(&state.db)
+        .await?;
+
+    if db::overrides::delete(&state.db, channel_id, target_id).await? {
+        resync_channel_subs(&state, server_id, channel_id).await?;
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Override not found".to_string()))
+    }
+}
+
+// ─── DM Handlers ────────────────────────────────────────────────────────────
+
+/// POST /api/users/@me/dms
+async fn create_dm(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<CreateDmRequest>,
+) -> AppResult<Json<DmChannel>> {
+    if req.recipient_id == auth.user_id {
+        return Err(AppError::BadRequest(
+            "Can't open a DM with yourself".to_string(),
+        ));
+    }
+    if db::users::find_by_id(&state.db, req.recipient_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    let dm = db::dms::find_or_create(&state.db, auth.user_id, req.recipient_id).await?;
+
+    // Connected participants get edits/deletes/reactions like any other channel
+    for user_id in [auth.user_id, req.recipient_id] {
+        if state.ws_sessions.contains_key(&user_id) {
+            let mut subs = state.channel_subs.entry(dm.id).or_default();
+            if !subs.contains(&user_id) {
+                subs.push(user_id);
+            }
+        }
+    }
+
+    Ok(Json(dm))
+}
+
+/// GET /api/users/@me/dms
+async fn list_dms(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<DmChannel>>> {
+    let dms = db::dms::list_for_user(&state.db, auth.user_id).await?;
+    Ok(Json(dms))
+}
+
+// ─── Message Handlers ───────────────────────────────────────────────────────
+
+/// Mentions in a new message, resolved against who can actually be pinged.
+#[derive(Debug, Default)]
+struct ResolvedMentions {
+    /// Explicitly mentioned users who belong to the channel
+    users: Vec<Uuid>,
+    /// An allowed @everyone / @here
+    everyone: bool,
+    /// Everyone to send `Mentioned` to (never the author, and in server
+    /// channels only connected members who can view the channel)
+    notify: HashSet<Uuid>,
+}
+
+/// Resolve `chat::parse_mentions` output for a message `author_id` sends in
+/// `channel_id`. @everyone / @here only count with `MENTION_EVERYONE`.
+async fn resolve_mentions(
+    state: &AppState,
+    channel_id: Uuid,
+    author_id: Uuid,
+    content: &str,
+) -> AppResult<ResolvedMentions> {
+    let parsed = chat::parse_mentions(content);
+    if parsed.is_empty() {
+        return Ok(ResolvedMentions::default());
+    }
+
+    let channel = find_channel(state, channel_id).await?;
+    let members: HashSet<Uuid> = match channel.server_id {
+        Some(server_id) => state
+            .server_member_ids(&server_id)
+            .await?
+            .into_iter()
+            .collect(),
+        None => db::dms::participants(&state.db, channel_id)
+            .await?
+            .map(|(a, b)| HashSet::from([a, b]))
+            .unwrap_or_default(),
+    };
+
+    let mut resolved = ResolvedMentions::default();
+    for mention in &parsed {
+        if let chat::MentionType::User(user_id) = mention {
+            if members.contains(user_id) && !resolved.users.contains(user_id) {
+                resolved.users.push(*user_id);
+            }
+        }
+    }
+    resolved.notify.extend(&resolved.users);
+
+    let everyone = parsed.contains(&chat::MentionType::Everyone);
+    let here = parsed.contains(&chat::MentionType::Here);
+    if channel.server_id.is_some()
+        && (everyone || here)
+        && check_channel_permission(state, author_id, channel_id, Permissions::MENTION_EVERYONE)
+            .await
+            .is_ok()
+    {
+        resolved.everyone = true;
+        resolved.notify.extend(
+            members.iter().filter(|id| {
+                everyone || state.presence.get_status(**id) != PresenceStatus::Offline
+            }),
+        );
+    }
+
+    resolved.notify.remove(&author_id);
+
+    // Nobody is pinged about a channel they can't open. Only connected users
+    // get `Mentioned` at all, so there's no need to check anyone else.
+    if channel.server_id.is_some() {
+        let mut notify = HashSet::new();
+        for user_id in std::mem::take(&mut resolved.notify) {
+            if state.is_connected(&user_id) && can_view_channel(state, user_id, channel_id).await {
+                notify.insert(user_id);
+            }
+        }
+        resolved.notify = notify;
+    }
+    Ok(resolved)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/channels/{channel_id}/messages",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+    ),
+    request_body = SendMessageRequest,
+    responses(
+        (status = 200, description = "Message sent", body = Message),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Channel not found", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+    ),
+)]
 async fn send_message(
     State(state): State<AppState>,
     auth: AuthUser,
     Path(channel_id): Path<Uuid>,
-    Json(req): Json<SendMessageRequest>,
-) -> AppResult<Json<Message>> {
-    let message_id = state.snowflake.next_id();
-    let message = db::messages::create(
+    Json(mut req): Json<SendMessageRequest>,
+) -> AppResult<Json<Message>> {
+    if let Err(wait) = state.message_limiter.try_acquire(&auth.user_id) {
+        return Err(AppError::RateLimited(
+            Some(wait.as_secs_f64().ceil() as u64),
+        ));
+    }
+
+    let channel = find_channel(&state, channel_id).await?;
+    let required = post_permission(&channel.channel_type)?;
+    check_channel_permission(&state, auth.user_id, channel_id, required).await?;
+
+    req.content = message_content(&req.content, !req.attachments.is_empty())?;
+    check_custom_emojis(&state, channel_id, &req.content).await?;
+
+    req.attachments.sort_unstable();
+    req.attachments.dedup();
+    if req.attachments.len() > chat::MAX_ATTACHMENTS_PER_MESSAGE {
+        return Err(AppError::BadRequest(format!(
+            "At most {} attachments per message",
+            chat::MAX_ATTACHMENTS_PER_MESSAGE
+        )));
+    }
+    if !req.attachments.is_empty() {
+        let pending =
+            db::attachments::count_pending(&state.db, channel_id, auth.user_id, &req.attachments)
+                .await?;
+        if pending != req.attachments.len() as i64 {
+            return Err(AppError::BadRequest("Unknown attachment".to_string()));
+        }
+    }
+
+    // Replies must point at a live message in the same channel
+    let referenced = match req.reply_to_id {
+        Some(reply_to_id) => {
+            let mut referenced = db::messages::find_by_id(&state.db, channel_id, reply_to_id)
+                .await?
+                .filter(|m| !m.is_deleted)
+                .ok_or_else(|| AppError::BadRequest("Unknown message to reply to".to_string()))?;
+            referenced.referenced_message = None;
+            referenced.attachments.clear();
+            Some(Box::new(referenced))
+        }
+        None => None,
+    };
+
+    let mentions = resolve_mentions(&state, channel_id, auth.user_id, &req.content).await?;
+
+    // Slow mode, last: it records the post. Moderators who can manage
+    // messages are exempt
+    let secs = channel.slow_mode_secs;
+    if secs > 0 {
+        let exempt = check_channel_permission(
+            &state,
+            auth.user_id,
+            channel_id,
+            Permissions::MANAGE_MESSAGES,
+        )
+        .await
+        .is_ok();
+        if !exempt {
+            state.check_slow_mode(channel_id, auth.user_id, Duration::from_secs(secs as u64))?;
+        }
+    }
+
+    let message_id = state.snowflake.next_id();
+    let mut message = db::messages::create(
+        &state.db,
+        message_id,
+        channel_id,
+        auth.user_id,
+        &req.content,
+        req.reply_to_id,
+        &mentions.users,
+        mentions.everyone,
+    )
+    .await?;
+    message.referenced_message = referenced;
+
+    if !req.attachments.is_empty() {
+        message.attachments = db::attachments::attach_to_message(
+            &state.db,
+            message_id,
+            channel_id,
+            auth.user_id,
+            &req.attachments,
+        )
+        .await?;
+    }
+
+    // Your own message never counts as unread
+    db::read_states::ack(&state.db, auth.user_id, channel_id, message_id).await?;
+
+    broadcast_message_event(&state, channel_id, &WsEvent::MessageCreate(message.clone())).await?;
+    spawn_unfurl(&state, &message, auth.user_id);
+
+    ping_mentioned(&state, channel_id, message_id, &mentions.notify);
+
+    Ok(Json(message))
+}
+
+/// Ping mentioned users directly, even if they aren't looking at the channel.
+/// Do Not Disturb holds the ping back; the message and its stored mention
+/// still arrive, so it shows up as unread.
+fn ping_mentioned(state: &AppState, channel_id: Uuid, message_id: i64, users: &HashSet<Uuid>) {
+    let mentioned = WsEvent::Mentioned {
+        channel_id,
+        message_id,
+    };
+    for user_id in users {
+        if state.presence.get_status(*user_id) != PresenceStatus::Dnd {
+            state.broadcast_to_user(user_id, &mentioned);
+        }
+    }
+}
+
+/// Reject message content using custom emojis from anywhere but the server
+/// of the channel it's posted in. DMs have no server, so no custom emojis.
+async fn check_custom_emojis(state: &AppState, channel_id: Uuid, content: &str) -> AppResult<()> {
+    let ids = chat::parse_custom_emojis(content);
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let known = match find_channel(state, channel_id).await?.server_id {
+        Some(server_id) => db::emojis::count_in_server(&state.db, server_id, &ids).await?,
+        None => 0,
+    };
+    if known != ids.len() as i64 {
+        return Err(AppError::BadRequest("Unknown emoji".to_string()));
+    }
+    Ok(())
+}
+
+/// Broadcast to channel subscribers, or straight to both ends of a DM.
+async fn broadcast_message_event(
+    state: &AppState,
+    channel_id: Uuid,
+    event: &WsEvent,
+) -> AppResult<()> {
+    match db::dms::participants(&state.db, channel_id).await? {
+        Some((a, b)) => {
+            state.broadcast_to_user(&a, event);
+            state.broadcast_to_user(&b, event);
+        }
+        None => state.broadcast_to_channel(&channel_id, event),
+    }
+    Ok(())
+}
+
+/// Unfurl the first link in a freshly sent or edited message in the
+/// background, then store the preview and push the updated message. Servers
+/// can turn previews off; DMs always get them. `author` is the user or
+/// webhook the fetch counts against; over the limit, the link stays plain.
+fn spawn_unfurl(state: &AppState, message: &Message, author: Uuid) {
+    let Some(url) = embeds::first_url(&message.content) else {
+        return;
+    };
+    if !state.unfurl_limiter.check(&author) {
+        tracing::debug!("Not unfurling {} for {}: rate limited", url, author);
+        return;
+    }
+    let state = state.clone();
+    let (channel_id, message_id) = (message.channel_id, message.id);
+
+    tokio::spawn(async move {
+        let result: AppResult<()> = async {
+            let server_id = db::channels::find_by_id(&state.db, channel_id)
+                .await?
+                .and_then(|c| c.server_id);
+            if let Some(server_id) = server_id {
+                let enabled = db::servers::find_by_id(&state.db, server_id)
+                    .await?
+                    .is_some_and(|s| s.embeds_enabled);
+                if !enabled {
+                    return Ok(());
+                }
+            }
+            let Some(embed) = embeds::unfurl(&state.embed_client, &url).await else {
+                tracing::debug!("No preview for {}", url);
+                return Ok(());
+            };
+            db::embeds::create(&state.db, message_id, &embed).await?;
+
+            // The message may have been deleted while the page loaded
+            let Some(message) = db::messages::find_by_id(&state.db, channel_id, message_id)
+                .await?
+                .filter(|m| !m.is_deleted)
+            else {
+                return Ok(());
+            };
+            broadcast_message_event(&state, channel_id, &WsEvent::MessageUpdate(message)).await
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to unfurl link in message {}: {}", message_id, e);
+        }
+    });
+}
+
+/// POST /api/channels/:channel_id/ack
+/// Mark the channel read up to `message_id`.
+#[utoipa::path(
+    post,
+    path = "/api/channels/{channel_id}/ack",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+    ),
+    request_body = AckRequest,
+    responses(
+        (status = 204, description = "Read state updated"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn ack_channel(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+    Json(req): Json<AckRequest>,
+) -> AppResult<StatusCode> {
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM messages WHERE id = $1 AND channel_id = $2)",
+    )
+    .bind(req.message_id)
+    .bind(channel_id)
+    .fetch_one(&state.db)
+    .await?;
+    if !exists {
+        return Err(AppError::NotFound("Message not found".to_string()));
+    }
+
+    db::read_states::ack(&state.db, auth.user_id, channel_id, req.message_id).await?;
+    state.broadcast_to_user(
+        &auth.user_id,
+        &WsEvent::MessageAck {
+            channel_id,
+            message_id: req.message_id,
+        },
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/channels/:channel_id/typing
+/// REST counterpart of the `TypingStart` gateway frame, for clients that
+/// don't hold a WebSocket open.
+#[utoipa::path(
+    post,
+    path = "/api/channels/{channel_id}/typing",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+    ),
+    responses(
+        (status = 204, description = "Typing indicator started"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn trigger_typing(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    let channel = find_channel(&state, channel_id).await?;
+    let required = post_permission(&channel.channel_type)?;
+    check_channel_permission(&state, auth.user_id, channel_id, required).await?;
+
+    state.presence.set_typing(channel_id, auth.user_id);
+    state.broadcast_to_channel_except(
+        &channel_id,
+        &WsEvent::TypingStart {
+            channel_id,
+            user_id: auth.user_id,
+        },
+        &auth.user_id,
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MessageQuery {
+    before: Option<i64>,
+    after: Option<i64>,
+    around: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/channels/{channel_id}/messages",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+        MessageQuery,
+    ),
+    responses(
+        (status = 200, description = "Messages, newest first", body = [Message]),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn get_messages(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+    Query(params): Query<MessageQuery>,
+) -> AppResult<Json<Vec<Message>>> {
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+
+    let limit = params.limit.unwrap_or(50).min(100);
+    let messages = match (params.before, params.after, params.around) {
+        (before, None, None) => {
+            db::messages::list_for_channel(&state.db, channel_id, before, limit).await?
+        }
+        (None, Some(after), None) => {
+            db::messages::list_after(&state.db, channel_id, after, limit).await?
+        }
+        (None, None, Some(around)) => {
+            db::messages::list_around(&state.db, channel_id, around, limit).await?
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "Only one of before, after and around may be given".to_string(),
+            ))
+        }
+    };
+    Ok(Json(messages))
+}
+
+/// GET /api/channels/:channel_id/messages/:message_id
+/// Deleted messages are returned flagged, as in the history.
+#[utoipa::path(
+    get,
+    path = "/api/channels/{channel_id}/messages/{message_id}",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+        ("message_id" = i64, Path, description = "Message ID"),
+    ),
+    responses(
+        (status = 200, description = "The message", body = Message),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Message not found", body = ErrorResponse),
+    ),
+)]
+async fn get_message(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, message_id)): Path<(Uuid, i64)>,
+) -> AppResult<Json<Message>> {
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+
+    let message = db::messages::find_by_id(&state.db, channel_id, message_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+    Ok(Json(message))
+}
+
+/// Longest accepted search query (in characters).
+const MAX_SEARCH_QUERY_LENGTH: usize = 200;
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchQuery {
+    q: String,
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// GET /api/channels/:channel_id/messages/search?q=...
+#[utoipa::path(
+    get,
+    path = "/api/channels/{channel_id}/messages/search",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+        SearchQuery,
+    ),
+    responses(
+        (status = 200, description = "Matching messages, newest first", body = [Message]),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn search_messages(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+    Query(params): Query<SearchQuery>,
+) -> AppResult<Json<Vec<Message>>> {
+    let query = params.q.trim();
+    if query.is_empty() || query.chars().count() > MAX_SEARCH_QUERY_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "Search query must be 1-{} characters",
+            MAX_SEARCH_QUERY_LENGTH
+        )));
+    }
+
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+
+    let limit = params.limit.unwrap_or(25).clamp(1, 100);
+    let messages = db::messages::search(&state.db, channel_id, query, params.before, limit).await?;
+    Ok(Json(messages))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/channels/{channel_id}/messages/{message_id}",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+        ("message_id" = i64, Path, description = "Message ID"),
+    ),
+    responses(
+        (status = 204, description = "Message deleted"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Message not found", body = ErrorResponse),
+    ),
+)]
+async fn delete_message(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, message_id)): Path<(Uuid, i64)>,
+) -> AppResult<StatusCode> {
+    // 1. Fetch message to check authorship
+    let message = db::messages::find_by_id(&state.db, channel_id, message_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+
+    // 2. Authors may delete their own messages in channels they can still
+    //    see; anyone else needs MANAGE_MESSAGES there
+    let required = if message.author_id == auth.user_id {
+        Permissions::VIEW_CHANNEL
+    } else {
+        Permissions::MANAGE_MESSAGES
+    };
+    check_channel_permission(&state, auth.user_id, channel_id, required).await?;
+
+    let deleted = db::messages::delete(&state.db, message_id).await?;
+    if !deleted {
+        return Err(AppError::NotFound("Message not found".to_string()));
+    }
+
+    state.broadcast_to_channel(
+        &channel_id,
+        &WsEvent::MessageDelete {
+            channel_id,
+            message_id,
+            is_deleted: true,
+        },
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Most messages a single bulk delete may target.
+const MAX_BULK_DELETE: usize = 100;
+
+/// POST /api/channels/:channel_id/messages/bulk-delete
+#[utoipa::path(
+    post,
+    path = "/api/channels/{channel_id}/messages/bulk-delete",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+    ),
+    request_body = Vec<i64>,
+    responses(
+        (status = 204, description = "Messages deleted"),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+    ),
+)]
+async fn bulk_delete_messages(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+    Json(mut message_ids): Json<Vec<i64>>,
+) -> AppResult<StatusCode> {
+    message_ids.sort_unstable();
+    message_ids.dedup();
+    if message_ids.is_empty() || message_ids.len() > MAX_BULK_DELETE {
+        return Err(AppError::BadRequest(format!(
+            "Bulk delete takes between 1 and {} message ids",
+            MAX_BULK_DELETE
+        )));
+    }
+
+    check_channel_permission(
+        &state,
+        auth.user_id,
+        channel_id,
+        Permissions::MANAGE_MESSAGES,
+    )
+    .await?;
+
+    let in_channel = db::messages::count_in_channel(&state.db, channel_id, &message_ids).await?;
+    if in_channel != message_ids.len() as i64 {
+        return Err(AppError::BadRequest(
+            "All messages must belong to this channel".to_string(),
+        ));
+    }
+
+    let deleted = db::messages::delete_many(&state.db, channel_id, &message_ids).await?;
+    if !deleted.is_empty() {
+        state.broadcast_to_channel(
+            &channel_id,
+            &WsEvent::MessageDeleteBulk {
+                channel_id,
+                message_ids: deleted,
+            },
+        );
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch a message that hasn't been deleted, or NotFound.
+async fn find_live_message(
+    state: &AppState,
+    channel_id: Uuid,
+    message_id: i64,
+) -> AppResult<Message> {
+    db::messages::find_by_id(&state.db, channel_id, message_id)
+        .await?
+        .filter(|m| !m.is_deleted)
+        .ok_or_else(|| AppError::NotFound("Message not found".to_string()))
+}
+
+/// PATCH /api/channels/:channel_id/messages/:message_id
+#[utoipa::path(
+    patch,
+    path = "/api/channels/{channel_id}/messages/{message_id}",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+        ("message_id" = i64, Path, description = "Message ID"),
+    ),
+    request_body = EditMessageRequest,
+    responses(
+        (status = 200, description = "Message edited", body = Message),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Message not found", body = ErrorResponse),
+    ),
+)]
+async fn edit_message(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, message_id)): Path<(Uuid, i64)>,
+    Json(req): Json<EditMessageRequest>,
+) -> AppResult<Json<Message>> {
+    let content = chat::sanitize_content(&req.content);
+    chat::validate_message(&content)?;
+
+    // 1. Fetch message to check authorship
+    let message = find_live_message(&state, channel_id, message_id).await?;
+
+    // 2. Only the author may edit, unless the user can manage messages
+    if message.author_id != auth.user_id {
+        check_channel_permission(
+            &state,
+            auth.user_id,
+            channel_id,
+            Permissions::MANAGE_MESSAGES,
+        )
+        .await?;
+    }
+    check_custom_emojis(&state, channel_id, &content).await?;
+
+    let previous_url = embeds::first_url(&message.content);
+    let mut message = db::messages::update_content(&state.db, message_id, &content)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+
+    // A different first link means a different preview, if any
+    let relink = embeds::first_url(&message.content) != previous_url;
+    if relink && !message.embeds.is_empty() {
+        db::embeds::delete_for_message(&state.db, message_id).await?;
+        message.embeds.clear();
+    }
+
+    state.broadcast_to_channel(&channel_id, &WsEvent::MessageUpdate(message.clone()));
+    if relink {
+        spawn_unfurl(&state, &message, auth.user_id);
+    }
+
+    Ok(Json(message))
+}
+
+/// The permission it takes to read a message's edit history: the history of
+/// your own messages is as visible as the channel, anyone else's needs
+/// MANAGE_MESSAGES.
+fn edit_history_permission(message: &Message, user_id: Uuid) -> i64 {
+    if message.author_id == user_id {
+        Permissions::VIEW_CHANNEL
+    } else {
+        Permissions::MANAGE_MESSAGES
+    }
+}
+
+/// GET /api/channels/:channel_id/messages/:message_id/history
+/// Earlier versions of a message, oldest first. Anyone in the channel can see
+/// the history of their own messages; other people's need MANAGE_MESSAGES.
+#[utoipa::path(
+    get,
+    path = "/api/channels/{channel_id}/messages/{message_id}/history",
+    tag = "messages",
+    params(
+        ("channel_id" = Uuid, Path, description = "Channel ID"),
+        ("message_id" = i64, Path, description = "Message ID"),
+    ),
+    responses(
+        (status = 200, description = "Superseded versions, oldest first", body = [MessageEdit]),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing permission", body = ErrorResponse),
+        (status = 404, description = "Message not found", body = ErrorResponse),
+    ),
+)]
+async fn get_message_history(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, message_id)): Path<(Uuid, i64)>,
+) -> AppResult<Json<Vec<MessageEdit>>> {
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+
+    let message = find_live_message(&state, channel_id, message_id).await?;
+    let required = edit_history_permission(&message, auth.user_id);
+    if required != Permissions::VIEW_CHANNEL {
+        check_channel_permission(&state, auth.user_id, channel_id, required).await?;
+    }
+
+    let edits = db::messages::list_edits(&state.db, message_id).await?;
+    Ok(Json(edits))
+}
+
+// ─── Webhook Handlers ───────────────────────────────────────────────────────
+
+fn generate_webhook_token() -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::Rng;
+
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
+
+fn hash_webhook_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Trim and check a webhook display name and avatar URL.
+fn validate_webhook_identity(
+    name: Option<&str>,
+    avatar_url: Option<&str>,
+) -> AppResult<(Option<String>, Option<String>)> {
+    let name = name.map(|n| n.trim().to_string());
+    if let Some(name) = &name {
+        if name.is_empty() || name.chars().count() > chat::MAX_WEBHOOK_NAME_LENGTH {
+            return Err(AppError::BadRequest(format!(
+                "Webhook name must be 1-{} characters",
+                chat::MAX_WEBHOOK_NAME_LENGTH
+            )));
+        }
+    }
+
+    let avatar_url = avatar_url.map(str::trim).filter(|u| !u.is_empty());
+    if let Some(url) = avatar_url {
+        if !(url.starts_with("https://") || url.starts_with("http://"))
+            || url.len() > chat::MAX_AVATAR_URL_LENGTH
+        {
+            return Err(AppError::BadRequest(
+                "avatar_url must be an http(s) URL".to_string(),
+            ));
+        }
+    }
+
+    Ok((name, avatar_url.map(str::to_string)))
+}
+
+/// POST /api/channels/:channel_id/webhooks
+/// The returned token is shown only once.
+async fn create_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+    Json(req): Json<CreateWebhookRequest>,
+) -> AppResult<Json<CreatedWebhook>> {
+    check_channel_permission(
+        &state,
+        auth.user_id,
+        channel_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    let (name, avatar_url) = validate_webhook_identity(Some(&req.name), req.avatar_url.as_deref())?;
+    let name = name.unwrap_or_default();
+
+    let token = generate_webhook_token();
+    let webhook = db::webhooks::create(
         &state.db,
-        message_id,
+        Uuid::now_v7(),
         channel_id,
+        &name,
+        avatar_url.as_deref(),
+        &hash_webhook_token(&token),
         auth.user_id,
-        &req.content,
-        req.reply_to_id,
     )
     .await?;
 
-    // Broadcast to channel subscribers
-    state.broadcast_to_channel(&channel_id, &WsEvent::MessageCreate(message.clone()));
+    Ok(Json(CreatedWebhook { webhook, token }))
+}
 
-    Ok(Json(message))
+/// GET /api/channels/:channel_id/webhooks
+async fn list_webhooks(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+) -> AppResult<Json<Vec<Webhook>>> {
+    check_channel_permission(
+        &state,
+        auth.user_id,
+        channel_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    let webhooks = db::webhooks::list_for_channel(&state.db, channel_id).await?;
+    Ok(Json(webhooks))
 }
 
-#[derive(Deserialize)]
-struct MessageQuery {
-    before: Option<i64>,
-    limit: Option<i64>,
+/// DELETE /api/channels/:channel_id/webhooks/:webhook_id
+async fn delete_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, webhook_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    check_channel_permission(
+        &state,
+        auth.user_id,
+        channel_id,
+        Permissions::MANAGE_CHANNELS,
+    )
+    .await?;
+
+    let message_ids = db::webhooks::delete(&state.db, channel_id, webhook_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Webhook not found".to_string()))?;
+    if !message_ids.is_empty() {
+        state.broadcast_to_channel(
+            &channel_id,
+            &WsEvent::MessageDeleteBulk {
+                channel_id,
+                message_ids,
+            },
+        );
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
 
-async fn get_messages(
+/// POST /api/webhooks/:webhook_id/:token
+/// Unauthenticated: the token in the path is the credential.
+async fn execute_webhook(
+    State(state): State<AppState>,
+    Path((webhook_id, token)): Path<(Uuid, String)>,
+    Json(req): Json<ExecuteWebhookRequest>,
+) -> AppResult<Json<Message>> {
+    let webhook = db::webhooks::find_by_id(&state.db, webhook_id)
+        .await?
+        .filter(|w| w.token_hash == hash_webhook_token(&token))
+        .ok_or_else(|| AppError::NotFound("Webhook not found".to_string()))?;
+
+    // Each webhook gets its own bucket, checked once the token is known good
+    if let Err(wait) = state.message_limiter.try_acquire(&webhook.id) {
+        return Err(AppError::RateLimited(
+            Some(wait.as_secs_f64().ceil() as u64),
+        ));
+    }
+
+    let content = chat::sanitize_content(&req.content);
+    chat::validate_message(&content)?;
+    let (username, avatar_url) =
+        validate_webhook_identity(req.username.as_deref(), req.avatar_url.as_deref())?;
+    check_custom_emojis(&state, webhook.channel_id, &content).await?;
+
+    let message = db::webhooks::create_message(
+        &state.db,
+        state.snowflake.next_id(),
+        &webhook,
+        &content,
+        username.as_deref(),
+        avatar_url.as_deref(),
+    )
+    .await?;
+
+    state.broadcast_to_channel(
+        &webhook.channel_id,
+        &WsEvent::MessageCreate(message.clone()),
+    );
+    spawn_unfurl(&state, &message, webhook.id);
+
+    Ok(Json(message))
+}
+
+// ─── Pin Handlers ───────────────────────────────────────────────────────────
+
+/// GET /api/channels/:channel_id/pins
+async fn list_pins(
     State(state): State<AppState>,
+    auth: AuthUser,
     Path(channel_id): Path<Uuid>,
-    Query(params): Query<MessageQuery>,
 ) -> AppResult<Json<Vec<Message>>> {
-    let limit = params.limit.unwrap_or(50).min(100);
-    let messages =
-        db::messages::list_for_channel(&state.db, channel_id, params.before, limit).await?;
-    Ok(Json(messages))
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+
+    let pins = db::pins::list(&state.db, channel_id).await?;
+    Ok(Json(pins))
 }
 
-async fn delete_message(
+/// PUT /api/channels/:channel_id/pins/:message_id
+async fn pin_message(
     State(state): State<AppState>,
     auth: AuthUser,
     Path((channel_id, message_id)): Path<(Uuid, i64)>,
 ) -> AppResult<StatusCode> {
-    // 1. Fetch message to check authorship
-    let message_opt =
-        db::messages::list_for_channel(&state.db, channel_id, Some(message_id + 1), 1).await?;
-    let message = message_opt
-        .into_iter()
-        .find(|m| m.id == message_id)
-        .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+    check_channel_permission(
+        &state,
+        auth.user_id,
+        channel_id,
+        Permissions::MANAGE_MESSAGES,
+    )
+    .await?;
 
-    // 2. Fetch channel to get server_id for permission check
-    // Use `query` instead of `query!` to avoid offline sqlx compilation issues in this environment
-    let channel_record = sqlx::query("SELECT server_id FROM channels WHERE id = $1")
-        .bind(channel_id)
-        .fetch_optional(&state.db)
-        .await?;
+    // Make sure the message exists in this channel
+    find_live_message(&state, channel_id, message_id).await?;
 
-    let channel_server_id: Uuid = match channel_record {
-        Some(row) => sqlx::Row::try_get(&row, "server_id")?,
-        None => return Err(AppError::NotFound("Channel not found".to_string())),
-    };
+    if db::pins::count(&state.db, channel_id).await? >= chat::MAX_PINS_PER_CHANNEL {
+        return Err(AppError::BadRequest(format!(
+            "Channel already has the maximum of {} pinned messages",
+            chat::MAX_PINS_PER_CHANNEL
+        )));
+    }
 
-    // 3. Verify ownership OR MANAGE_MESSAGES permission
-    if message.author_id != auth.user_id {
-        // Not the author, check permissions
-        if let Err(e) = check_permission(
-            &state,
-            auth.user_id,
-            channel_server_id,
-            Permissions::MANAGE_MESSAGES,
-        )
-        .await
-        {
-            return Err(e); // Propagate Forbidden/Unauthorized
+    if db::pins::add(&state.db, channel_id, message_id, auth.user_id).await? {
+        state.broadcast_to_channel(&channel_id, &WsEvent::PinsUpdate { channel_id });
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/channels/:channel_id/pins/:message_id
+async fn unpin_message(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, message_id)): Path<(Uuid, i64)>,
+) -> AppResult<StatusCode> {
+    check_channel_permission(
+        &state,
+        auth.user_id,
+        channel_id,
+        Permissions::MANAGE_MESSAGES,
+    )
+    .await?;
+
+    if !db::pins::remove(&state.db, channel_id, message_id).await? {
+        return Err(AppError::NotFound("Message is not pinned".to_string()));
+    }
+    state.broadcast_to_channel(&channel_id, &WsEvent::PinsUpdate { channel_id });
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ─── Reaction Handlers ──────────────────────────────────────────────────────
+
+/// Maximum length of a unicode reaction emoji. Custom emojis are
+/// `<:name:id>` references and are checked against the server instead.
+const MAX_EMOJI_LENGTH: usize = 32;
+
+/// PUT /api/channels/:channel_id/messages/:message_id/reactions/:emoji
+async fn add_reaction(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, message_id, emoji)): Path<(Uuid, i64, String)>,
+) -> AppResult<StatusCode> {
+    let custom_emoji = chat::parse_custom_emoji(&emoji);
+    if custom_emoji.is_none() && (emoji.is_empty() || emoji.chars().count() > MAX_EMOJI_LENGTH) {
+        return Err(AppError::BadRequest("Invalid emoji".to_string()));
+    }
+
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+    // Make sure the message exists in this channel
+    find_live_message(&state, channel_id, message_id).await?;
+
+    // Custom emojis can only be used in the server they belong to
+    if let Some((name, emoji_id)) = custom_emoji {
+        let server_id = db::channels::find_by_id(&state.db, channel_id)
+            .await?
+            .and_then(|c| c.server_id);
+        let found = match server_id {
+            Some(server_id) => db::emojis::find(&state.db, server_id, emoji_id).await?,
+            None => None,
+        };
+        if found.is_none_or(|e| e.name != name) {
+            return Err(AppError::BadRequest("Unknown emoji".to_string()));
         }
     }
 
-    let deleted = db::messages::delete(&state.db, message_id).await?;
-    if !deleted {
-        return Err(AppError::NotFound("Message not found".to_string()));
+    // Enforce the per-message limit on distinct emojis
+    let existing = db::reactions::list_for_message(&state.db, message_id).await?;
+    if !existing.iter().any(|r| r.emoji == emoji) {
+        let mut distinct: Vec<&str> = existing.iter().map(|r| r.emoji.as_str()).collect();
+        distinct.sort_unstable();
+        distinct.dedup();
+        if distinct.len() >= chat::MAX_REACTIONS_PER_MESSAGE {
+            return Err(AppError::BadRequest(format!(
+                "Message already has the maximum of {} reactions",
+                chat::MAX_REACTIONS_PER_MESSAGE
+            )));
+        }
+    }
+
+    // Reacting twice with the same emoji is a no-op
+    let added = db::reactions::add(&state.db, message_id, auth.user_id, &emoji).await?;
+    if added {
+        state.broadcast_to_channel(
+            &channel_id,
+            &WsEvent::ReactionAdd {
+                channel_id,
+                message_id,
+                user_id: auth.user_id,
+                emoji,
+            },
+        );
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/channels/:channel_id/messages/:message_id/reactions/:emoji
+async fn remove_reaction(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, message_id, emoji)): Path<(Uuid, i64, String)>,
+) -> AppResult<StatusCode> {
+    check_channel_permission(&state, auth.user_id, channel_id, Permissions::VIEW_CHANNEL).await?;
+    // The message id must belong to this channel, not just any channel
+    find_live_message(&state, channel_id, message_id).await?;
+
+    let removed = db::reactions::remove(&state.db, message_id, auth.user_id, &emoji).await?;
+    if !removed {
+        return Err(AppError::NotFound("Reaction not found".to_string()));
     }
 
     state.broadcast_to_channel(
         &channel_id,
-        &WsEvent::MessageDelete {
+        &WsEvent::ReactionRemove {
             channel_id,
             message_id,
-            is_deleted: true,
+            user_id: auth.user_id,
+            emoji,
         },
     );
 
@@ -1302,127 +5760,383 @@ async fn delete_message(
 // ─── WebSocket Gateway ──────────────────────────────────────────────────────
 
 async fn ws_upgrade(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_ws(socket, state))
+    ws.max_message_size(state.config.server.ws_max_message_size)
+        .on_upgrade(move |socket| handle_ws(socket, state))
 }
 
 use futures_util::{SinkExt, StreamExt};
 
-async fn handle_ws(mut socket: WebSocket, state: AppState) {
-    // Wait for Identify message with token
-    let user_id = match socket.recv().await {
-        Some(Ok(WsMessage::Text(text))) => match serde_json::from_str::<WsEvent>(&text) {
-            Ok(WsEvent::Identify { token }) => match state.validate_token_federated(&token).await {
-                Ok((id, _username)) => id,
-                Err(_) => {
-                    let _ = socket
-                        .send(WsMessage::Close(Some(axum::extract::ws::CloseFrame {
-                            code: 1000,
-                            reason: "Invalid token".into(),
-                        })))
-                        .await;
-                    return;
-                }
-            },
-            _ => {
-                let _ = socket
-                    .send(WsMessage::Close(Some(axum::extract::ws::CloseFrame {
-                        code: 1000,
-                        reason: "Expected Identify".into(),
-                    })))
-                    .await;
-                return;
-            }
-        },
-        _ => {
-            let _ = socket
-                .send(WsMessage::Close(Some(axum::extract::ws::CloseFrame {
-                    code: 1000,
-                    reason: "No message received".into(),
-                })))
-                .await;
-            return;
+/// The first frame sent to a freshly identified client.
+fn ready_frame(user: UserPublic, session_id: Uuid, read_states: Vec<ReadState>) -> String {
+    let ready = WsEvent::Ready {
+        user,
+        session_id: session_id.to_string(),
+        read_states,
+    };
+    serde_json::to_string(&ready).unwrap_or_default()
+}
+
+/// Most recent messages sent per channel when catching a client up.
+const CATCHUP_MESSAGES_PER_CHANNEL: i64 = 50;
+
+/// `ChannelCatchup` frames for channels with unread messages: what was posted
+/// after the user's read position, newest first and capped per channel.
+/// `read_states` must only cover channels the user can view.
+async fn catchup_frames(state: &AppState, user_id: Uuid, read_states: &[ReadState]) -> Vec<String> {
+    let after: Vec<(Uuid, i64)> = read_states
+        .iter()
+        .filter(|s| s.unread_count > 0)
+        .map(|s| (s.channel_id, s.last_read_message_id.unwrap_or(0)))
+        .collect();
+    if after.is_empty() {
+        return Vec::new();
+    }
+
+    let messages = match db::messages::list_after_for_channels(
+        &state.db,
+        &after,
+        CATCHUP_MESSAGES_PER_CHANNEL,
+    )
+    .await
+    {
+        Ok(messages) => messages,
+        Err(e) => {
+            tracing::warn!("Failed to load catch-up for {}: {}", user_id, e);
+            return Vec::new();
         }
     };
+    let mut by_channel: HashMap<Uuid, Vec<Message>> = HashMap::new();
+    for message in messages {
+        by_channel
+            .entry(message.channel_id)
+            .or_default()
+            .push(message);
+    }
+
+    after
+        .iter()
+        .filter_map(|(channel_id, _)| {
+            let catchup = WsEvent::ChannelCatchup {
+                channel_id: *channel_id,
+                messages: by_channel.remove(channel_id)?,
+            };
+            Some(serde_json::to_string(&catchup).unwrap_or_default())
+        })
+        .collect()
+}
+
+/// Serialize a `PresenceSnapshot` covering `user_ids`.
+fn presence_snapshot_frame(state: &AppState, user_ids: HashSet<Uuid>) -> String {
+    let user_ids: Vec<Uuid> = user_ids.into_iter().collect();
+    let snapshot = WsEvent::PresenceSnapshot {
+        presences: state.presence.get_bulk_status(&user_ids),
+    };
+    serde_json::to_string(&snapshot).unwrap_or_default()
+}
 
-    // Create broadcast channel for this session
-    let (tx, mut rx) = broadcast::channel::<String>(256);
-    state.ws_sessions.insert(user_id, tx);
+/// Close a WebSocket that failed the handshake.
+async fn close_ws(socket: &mut WebSocket, code: u16, reason: &'static str) {
+    let _ = socket
+        .send(WsMessage::Close(Some(axum::extract::ws::CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await;
+}
 
-    // Subscribe user to all channels they have access to
+/// Subscribe a gateway session of `user_id` to every channel they can view:
+/// those of their servers that no override hides, plus their DMs. Returns
+/// the channels, and everyone sharing a server with the user for the
+/// presence snapshot.
+async fn subscribe_gateway(state: &AppState, user_id: Uuid) -> (Vec<Uuid>, HashSet<Uuid>) {
     let mut subscribed_channels = Vec::new();
+    let mut mutuals = HashSet::new();
 
     // 1. Get all servers the user is a member of
+    let mut server_channels = Vec::new();
     if let Ok(servers) = db::servers::list_for_user(&state.db, user_id).await {
         for server in servers {
+            if let Ok(member_ids) = state.server_member_ids(&server.id).await {
+                mutuals.extend(member_ids);
+            }
             // 2. Get all channels for each server
             if let Ok(channels) = db::channels::list_for_server(&state.db, server.id).await {
-                for channel in channels {
-                    subscribed_channels.push(channel.id);
-                    state
-                        .channel_subs
-                        .entry(channel.id)
-                        .or_default()
-                        .push(user_id);
+                server_channels.extend(channels);
+            }
+        }
+    }
+    // 2b. Minus those an override hides
+    match viewable_channels(state, user_id, &server_channels).await {
+        Ok(viewable) => subscribed_channels.extend(
+            server_channels
+                .iter()
+                .map(|c| c.id)
+                .filter(|id| viewable.contains(id)),
+        ),
+        Err(e) => tracing::warn!("Failed to resolve channel access for {}: {}", user_id, e),
+    }
+
+    // 3. And their DM channels
+    if let Ok(dms) = db::dms::list_for_user(&state.db, user_id).await {
+        subscribed_channels.extend(dms.into_iter().map(|dm| dm.id));
+    }
+
+    // A session this replaces may still hold subscriptions, so don't add twice
+    for channel_id in &subscribed_channels {
+        let mut subs = state.channel_subs.entry(*channel_id).or_default();
+        if !subs.contains(&user_id) {
+            subs.push(user_id);
+        }
+    }
+
+    (subscribed_channels, mutuals)
+}
+
+async fn handle_ws(mut socket: WebSocket, state: AppState) {
+    // Wait for Identify (or Resume) with a token
+    let (token, resume) = match socket.recv().await {
+        Some(Ok(WsMessage::Text(text))) => match serde_json::from_str::<WsEvent>(&text) {
+            Ok(WsEvent::Identify { token }) => (token, None),
+            Ok(WsEvent::Resume {
+                token,
+                session_id,
+                last_seq,
+            }) => (token, Some((session_id, last_seq))),
+            _ => {
+                close_ws(&mut socket, 1000, "Expected Identify").await;
+                return;
+            }
+        },
+        _ => {
+            close_ws(&mut socket, 1000, "No message received").await;
+            return;
+        }
+    };
+    // Bots are always online: they never go idle
+    let (user_id, is_bot) = match state.validate_token_federated(&token).await {
+        Ok((id, _username, token_type)) => (id, token_type.is_bot()),
+        Err(_) => {
+            close_ws(&mut socket, 1000, "Invalid token").await;
+            return;
+        }
+    };
+
+    // Pick a dropped session back up, replaying what the client missed;
+    // anything else falls through to a fresh Ready
+    if let Some((session_id, last_seq)) = resume {
+        let session = state
+            .ws_sessions
+            .get(&user_id)
+            .map(|s| s.value().clone())
+            .filter(|s| s.id == session_id);
+        if let Some((session, (generation, missed, rx))) =
+            session.and_then(|s| s.resume(last_seq).map(|resumed| (s, resumed)))
+        {
+            tracing::info!(
+                "User {} resumed session {}, replaying {} events",
+                user_id,
+                session_id,
+                missed.len()
+            );
+            // Access may have changed while the client was away
+            let (channels, _) = subscribe_gateway(&state, user_id).await;
+            for channel_id in session
+                .channels()
+                .iter()
+                .filter(|id| !channels.contains(id))
+            {
+                if let Some(mut subs) = state.channel_subs.get_mut(channel_id) {
+                    subs.retain(|&id| id != user_id);
                 }
             }
+            session.set_channels(channels);
+
+            let resumed =
+                serde_json::to_string(&WsEvent::Resumed { session_id }).unwrap_or_default();
+            let _ = socket.send(WsMessage::Text(resumed)).await;
+            for frame in missed {
+                let _ = socket.send(WsMessage::Text(frame)).await;
+            }
+            if !is_bot {
+                state.presence.touch(user_id);
+            }
+            run_ws_session(socket, state, session, generation, is_bot, rx).await;
+            return;
         }
+        tracing::debug!(
+            "Session {} of user {} can't be resumed",
+            session_id,
+            user_id
+        );
     }
 
+    // Load the user up front so Ready carries their real profile
+    let user = match db::users::find_by_id(&state.db, user_id).await {
+        Ok(Some(user)) => UserPublic::from(user),
+        result => {
+            if let Err(e) = result {
+                tracing::error!("Failed to load user {} for Ready: {}", user_id, e);
+            }
+            close_ws(&mut socket, 1011, "User not found").await;
+            return;
+        }
+    };
+
+    let (subscribed_channels, mut mutuals) = subscribe_gateway(&state, user_id).await;
+
     tracing::info!(
         "User {} connected, subscribed to {} channels",
         user_id,
         subscribed_channels.len()
     );
 
-    // Send Ready event
-    let ready = WsEvent::Ready {
-        user: UserPublic {
-            id: user_id,
-            username: String::new(), // TODO: fetch from DB
-            display_name: String::new(),
-            avatar_hash: None,
-        },
-        session_id: Uuid::now_v7().to_string(),
-    };
+    let read_states =
+        match db::read_states::list_for_channels(&state.db, user_id, &subscribed_channels).await {
+            Ok(states) => states,
+            Err(e) => {
+                tracing::warn!("Failed to load read states for {}: {}", user_id, e);
+                Vec::new()
+            }
+        };
+
+    // Create the session that buffers this user's events
+    let (session, rx) = WsSession::new(
+        user_id,
+        subscribed_channels,
+        state.config.server.ws_buffer_size,
+    );
+    let session = Arc::new(session);
+    state.ws_sessions.insert(user_id, session.clone());
+
+    // Loaded after the session exists so nothing posted in between is lost;
+    // the client may see a message both here and as a live event
+    let catchup = catchup_frames(&state, user_id, &read_states).await;
+
+    // Send Ready event, then who's online so the member list is right immediately
     let _ = socket
-        .send(WsMessage::Text(
-            serde_json::to_string(&ready).unwrap().into(),
-        ))
+        .send(WsMessage::Text(ready_frame(user, session.id, read_states)))
         .await;
+    mutuals.remove(&user_id);
+    let _ = socket
+        .send(WsMessage::Text(presence_snapshot_frame(&state, mutuals)))
+        .await;
+    // Then what was posted while the user was away
+    for frame in catchup {
+        let _ = socket.send(WsMessage::Text(frame)).await;
+    }
 
-    // Set online status
-    state.presence.set_status(user_id, PresenceStatus::Online);
+    // Restore the status the user picked, or go Online if they have none.
+    // A picked status isn't subject to automatic idling.
+    let preferred = db::users::preferred_status(&state.db, user_id)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to load preferred status for {}: {}", user_id, e);
+            None
+        });
+    let status = match preferred {
+        Some(preferred) => {
+            let custom_text = state.presence.get_custom_text(user_id);
+            state
+                .presence
+                .set_custom_status(user_id, preferred.clone(), custom_text);
+            preferred
+        }
+        None => {
+            state.presence.set_status(user_id, PresenceStatus::Online);
+            PresenceStatus::Online
+        }
+    };
+    if !is_bot {
+        state.presence.touch(user_id);
+    }
 
-    // Broadcast presence update to all mutual guilds/users (simplified: broadcast to all known channels for now)
-    // In a real app, we'd only send to mutuals. Here, we send to channels the user is in.
+    // Broadcast presence update once to every user sharing a server with us
     let presence_update = WsEvent::PresenceUpdate {
         user_id,
-        status: PresenceStatus::Online,
+        status,
+        custom_text: state.presence.get_custom_text(user_id),
     };
+    state.broadcast_to_mutuals(&user_id, &presence_update).await;
 
-    for channel_id in &subscribed_channels {
-        state.broadcast_to_channel(channel_id, &presence_update);
-    }
+    run_ws_session(socket, state, session, 0, is_bot, rx).await;
+}
 
+/// Pump a session's frames to the socket and handle client messages until
+/// the connection drops, then keep the session resumable for a while.
+async fn run_ws_session(
+    socket: WebSocket,
+    state: AppState,
+    session: Arc<WsSession>,
+    generation: u64,
+    is_bot: bool,
+    mut rx: broadcast::Receiver<String>,
+) {
+    let user_id = session.user_id;
     let (mut sender, mut receiver) = socket.split();
+    let heartbeat_timeout = Duration::from_secs(state.config.server.heartbeat_timeout_secs.max(3));
 
-    // Spawn task to forward broadcast messages to WebSocket
+    // Spawn task to forward broadcast messages to WebSocket. It also pings the
+    // client well within the timeout, so clients that never send `Heartbeat`
+    // still stay alive through their automatic pongs.
     let mut forward_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            if sender.send(WsMessage::Text(msg.into())).await.is_err() {
+        let mut ping = tokio::time::interval(heartbeat_timeout / 3);
+        ping.tick().await;
+        loop {
+            let frame = tokio::select! {
+                frame = next_session_frame(&mut rx, user_id) => match frame {
+                    Some(frame) => WsMessage::Text(frame),
+                    None => break,
+                },
+                _ = ping.tick() => WsMessage::Ping(Vec::new()),
+            };
+            if sender.send(frame).await.is_err() {
                 break;
             }
         }
     });
 
     let state_for_recv = state.clone();
+    let session_for_recv = session.clone();
     let mut receive_task = tokio::spawn(async move {
-        while let Some(Ok(msg)) = receiver.next().await {
+        // Any frame counts as a sign of life; silence past the timeout means
+        // the client is gone (e.g. behind a NAT that dropped the connection)
+        while let Ok(Some(Ok(msg))) = tokio::time::timeout(heartbeat_timeout, receiver.next()).await
+        {
             match msg {
                 WsMessage::Close(_) => break,
                 WsMessage::Text(text) => {
+                    // Heartbeats and other client messages count as activity;
+                    // automatic pongs don't
+                    if !is_bot && state_for_recv.presence.touch(user_id) {
+                        state_for_recv
+                            .broadcast_presence(user_id, PresenceStatus::Online)
+                            .await;
+                    }
+
                     // Parse incoming messages and relay WebRTC signals
                     match serde_json::from_str::<WsEvent>(&text) {
+                        Ok(WsEvent::Heartbeat { .. }) => {
+                            // Kept out of the replay buffer: a resumed client
+                            // has no use for stale acks
+                            let ack =
+                                serde_json::to_string(&WsEvent::HeartbeatAck).unwrap_or_default();
+                            session_for_recv.send_unsequenced(&ack);
+                        }
+                        Ok(WsEvent::TypingStart { channel_id, .. }) => {
+                            // Ignore the client-supplied user_id and typing in channels
+                            // this session isn't subscribed to
+                            if session_for_recv.channels().contains(&channel_id) {
+                                state_for_recv.presence.set_typing(channel_id, user_id);
+                                state_for_recv.broadcast_to_channel_except(
+                                    &channel_id,
+                                    &WsEvent::TypingStart {
+                                        channel_id,
+                                        user_id,
+                                    },
+                                    &user_id,
+                                );
+                            }
+                        }
                         Ok(event) => {
                             if let WsEvent::WebRTCSignal {
                                 to_user_id,
@@ -1454,10 +6168,28 @@ async fn handle_ws(mut socket: WebSocket, state: AppState) {
                                                     state_for_recv
                                                         .broadcast_to_user(&user_id, &answer);
                                                 }
-                                                Err(e) => tracing::error!(
-                                                    "SFU error handling offer: {}",
-                                                    e
-                                                ),
+                                                Err(e) => {
+                                                    tracing::error!(
+                                                        "SFU error handling offer: {}",
+                                                        e
+                                                    );
+                                                    // Nothing was set up for a rejected join;
+                                                    // take the user back out of the channel too
+                                                    if e.is::<crate::voice::ChannelFull>() {
+                                                        leave_voice_channel(
+                                                            &state_for_recv,
+                                                            channel_id,
+                                                            user_id,
+                                                        )
+                                                        .await;
+                                                    }
+                                                    state_for_recv.broadcast_to_user(
+                                                        &user_id,
+                                                        &voice_error_signal(
+                                                            channel_id, user_id, &e,
+                                                        ),
+                                                    );
+                                                }
                                             }
                                         }
                                     } else if signal_type == "answer" {
@@ -1513,10 +6245,13 @@ async fn handle_ws(mut socket: WebSocket, state: AppState) {
         _ = &mut receive_task => forward_task.abort(),
     }
 
-    state.ws_sessions.remove(&user_id);
-
     tracing::info!("WebSocket disconnected: {}", user_id);
 
+    // A newer connection resumed this session; it owns the cleanup now
+    if !session.detach(generation) {
+        return;
+    }
+
     // SFU Cleanup: Remove user from any active SFU channels
     let sfu = state.sfu.clone();
     for entry in sfu.channels.iter() {
@@ -1528,8 +6263,52 @@ async fn handle_ws(mut socket: WebSocket, state: AppState) {
     // so that broadcast_to_channel can still reach other subscribers.
     broadcast_voice_leave(&state, user_id).await;
 
+    // Events keep buffering while the client gets a chance to resume
+    tokio::time::sleep(Duration::from_secs(state.config.server.resume_window_secs)).await;
+    if session.still_detached(generation) {
+        end_ws_session(&state, &session).await;
+    }
+}
+
+/// The next frame for a session's connection, or `None` once the session is
+/// closed. A connection that fell behind during a burst gets a `Lagged` event
+/// in place of the frames it lost, rather than being disconnected, so the
+/// client knows to resync.
+async fn next_session_frame(rx: &mut broadcast::Receiver<String>, user_id: Uuid) -> Option<String> {
+    match rx.recv().await {
+        Ok(frame) => Some(frame),
+        Err(broadcast::error::RecvError::Lagged(missed)) => {
+            tracing::debug!("WS of user {} lagged, skipped {} frames", user_id, missed);
+            Some(serde_json::to_string(&WsEvent::Lagged { missed }).unwrap_or_default())
+        }
+        Err(broadcast::error::RecvError::Closed) => None,
+    }
+}
+
+/// Discard a session that wasn't resumed in time.
+async fn end_ws_session(state: &AppState, session: &Arc<WsSession>) {
+    let user_id = session.user_id;
+
+    // The user may have identified again, starting a new session meanwhile
+    let replacement = state
+        .ws_sessions
+        .get(&user_id)
+        .filter(|current| !Arc::ptr_eq(current.value(), session))
+        .map(|current| current.channels());
+    if let Some(kept) = replacement {
+        for channel_id in session.channels().iter().filter(|id| !kept.contains(id)) {
+            if let Some(mut subs) = state.channel_subs.get_mut(channel_id) {
+                subs.retain(|&id| id != user_id);
+            }
+        }
+        return;
+    }
+    state
+        .ws_sessions
+        .remove_if(&user_id, |_, current| Arc::ptr_eq(current, session));
+
     // Unsubscribe from channels
-    for channel_id in &subscribed_channels {
+    for channel_id in &session.channels() {
         if let Some(mut subs) = state.channel_subs.get_mut(channel_id) {
             subs.retain(|&id| id != user_id);
         }
@@ -1541,27 +6320,305 @@ async fn handle_ws(mut socket: WebSocket, state: AppState) {
     let presence_update = WsEvent::PresenceUpdate {
         user_id,
         status: PresenceStatus::Offline,
+        custom_text: None,
     };
+    state.broadcast_to_mutuals(&user_id, &presence_update).await;
+}
 
-    // We already unsubscribed, but we need to notify others.
-    // The channel_subs map still has other users.
-    // We can iterate over the channels we *were* in.
-    // However, we just cleared local `subscribed_channels` from global map.
-    // But we still have the list in `subscribed_channels` local variable!
+// ─── Presence Handlers ──────────────────────────────────────────────────────
 
-    for channel_id in &subscribed_channels {
-        state.broadcast_to_channel(channel_id, &presence_update);
+/// PATCH /api/users/@me/presence
+/// Sets the caller's status and custom status text (`null` clears the text).
+async fn update_presence(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<UpdatePresenceRequest>,
+) -> AppResult<StatusCode> {
+    let custom_text = match req.custom_text.as_deref().map(chat::sanitize_content) {
+        Some(text) if text.chars().count() > chat::MAX_CUSTOM_STATUS_LENGTH => {
+            return Err(AppError::BadRequest(format!(
+                "Custom status exceeds maximum length of {} characters",
+                chat::MAX_CUSTOM_STATUS_LENGTH
+            )));
+        }
+        Some(text) if !text.is_empty() => Some(text),
+        _ => None,
+    };
+    // An explicit status is remembered across reconnects; Online means
+    // no preference
+    if let Some(status) = &req.status {
+        let preferred = (*status != PresenceStatus::Online).then_some(status);
+        db::users::set_preferred_status(&state.db, auth.user_id, preferred).await?;
+    }
+    let status = req
+        .status
+        .unwrap_or_else(|| state.presence.get_status(auth.user_id));
+
+    state
+        .presence
+        .set_custom_status(auth.user_id, status.clone(), custom_text.clone());
+
+    let event = WsEvent::PresenceUpdate {
+        user_id: auth.user_id,
+        status,
+        custom_text,
+    };
+
+    // Let everyone sharing a server with the user know, including their own sessions
+    state.broadcast_to_mutuals(&auth.user_id, &event).await;
+    state.broadcast_to_user(&auth.user_id, &event);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ─── Key Handlers ───────────────────────────────────────────────────────────
+
+/// Most one-time pre-keys accepted in a single upload.
+const MAX_ONE_TIME_PRE_KEYS_PER_UPLOAD: usize = 100;
+/// Most one-time pre-keys stored per user.
+const MAX_ONE_TIME_PRE_KEYS: i64 = 500;
+
+/// PUT /api/users/@me/keys
+/// Publishes the caller's identity key and signed pre-key and appends a batch
+/// of one-time pre-keys. Uploading a new identity key discards old one-time keys.
+async fn upload_keys(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<UploadKeysRequest>,
+) -> AppResult<Json<PreKeyCount>> {
+    let keys_valid = crypto::is_valid_identity_key(&req.identity_key)
+        && req.signed_pre_key.len() == crypto::PUBLIC_KEY_LENGTH
+        && req.signed_pre_key_signature.len() == crypto::SIGNATURE_LENGTH
+        && req
+            .one_time_pre_keys
+            .iter()
+            .all(|k| k.len() == crypto::PUBLIC_KEY_LENGTH);
+    if !keys_valid {
+        return Err(AppError::BadRequest("Malformed key bundle".to_string()));
+    }
+    if req.one_time_pre_keys.len() > MAX_ONE_TIME_PRE_KEYS_PER_UPLOAD {
+        return Err(AppError::BadRequest(format!(
+            "At most {} one-time pre-keys per upload",
+            MAX_ONE_TIME_PRE_KEYS_PER_UPLOAD
+        )));
+    }
+    if !crypto::verify_signature(
+        &req.identity_key,
+        &req.signed_pre_key,
+        &req.signed_pre_key_signature,
+    ) {
+        return Err(AppError::BadRequest(
+            "Invalid signed pre-key signature".to_string(),
+        ));
+    }
+
+    let user = db::users::find_by_id(&state.db, auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let identity_changed = user.identity_key_public.as_deref() != Some(req.identity_key.as_slice());
+
+    // One-time keys published under a previous identity are useless now
+    let mut remaining = if identity_changed {
+        0
+    } else {
+        db::prekeys::count_one_time(&state.db, auth.user_id).await?
+    };
+    if remaining + req.one_time_pre_keys.len() as i64 > MAX_ONE_TIME_PRE_KEYS {
+        return Err(AppError::BadRequest(format!(
+            "At most {} one-time pre-keys can be stored",
+            MAX_ONE_TIME_PRE_KEYS
+        )));
+    }
+
+    if identity_changed {
+        db::prekeys::replace_identity(&state.db, auth.user_id, &req.identity_key).await?;
+    }
+
+    db::prekeys::set_signed(
+        &state.db,
+        auth.user_id,
+        &req.signed_pre_key,
+        &req.signed_pre_key_signature,
+    )
+    .await?;
+
+    if !req.one_time_pre_keys.is_empty() {
+        db::prekeys::add_one_time(&state.db, auth.user_id, &req.one_time_pre_keys).await?;
+        remaining += req.one_time_pre_keys.len() as i64;
+    }
+
+    Ok(Json(PreKeyCount {
+        one_time_pre_keys_remaining: remaining,
+    }))
+}
+
+/// PUT /api/users/@me/identity-key
+/// Registers the caller's Ed25519 identity key on its own, e.g. before any
+/// pre-keys exist. Replacing it drops the signed and one-time pre-keys, which
+/// belonged to the old identity.
+async fn set_identity_key(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(req): Json<UpdateIdentityKeyRequest>,
+) -> AppResult<StatusCode> {
+    if !crypto::is_valid_identity_key(&req.identity_key) {
+        return Err(AppError::BadRequest("Malformed identity key".to_string()));
+    }
+
+    let user = db::users::find_by_id(&state.db, auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    if user.identity_key_public.as_deref() != Some(req.identity_key.as_slice()) {
+        db::prekeys::replace_identity(&state.db, auth.user_id, &req.identity_key).await?;
     }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/users/@me/keys
+/// Lets clients check whether they need to replenish one-time pre-keys.
+async fn get_own_key_count(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<Json<PreKeyCount>> {
+    let remaining = db::prekeys::count_one_time(&state.db, auth.user_id).await?;
+    Ok(Json(PreKeyCount {
+        one_time_pre_keys_remaining: remaining,
+    }))
+}
+
+/// GET /api/users/:user_id/keys
+/// Returns the user's pre-key bundle, consuming one of their one-time pre-keys.
+async fn get_key_bundle(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<Json<PreKeyBundle>> {
+    let not_published = || AppError::NotFound("User has not published keys".to_string());
+
+    let identity_key = db::users::find_by_id(&state.db, user_id)
+        .await?
+        .and_then(|u| u.identity_key_public)
+        .ok_or_else(not_published)?;
+    let (signed_pre_key, signed_pre_key_signature) = db::prekeys::find_signed(&state.db, user_id)
+        .await?
+        .ok_or_else(not_published)?;
+    let one_time_pre_key = db::prekeys::pop_one_time(&state.db, user_id).await?;
+
+    Ok(Json(PreKeyBundle {
+        identity_key,
+        signed_pre_key,
+        signed_pre_key_signature,
+        one_time_pre_key,
+    }))
 }
 
 // ─── Voice Handlers ─────────────────────────────────────────────────────────
 
+/// One entry of a browser `RTCConfiguration.iceServers` list.
+#[derive(Debug, Serialize)]
+struct IceServer {
+    urls: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    credential: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct VoiceConfigResponse {
+    ice_servers: Vec<IceServer>,
+    /// Opus bitrate range in kbps; the SFU clamps each client into it.
+    min_bitrate: u32,
+    max_bitrate: u32,
+}
+
+/// GET /api/voice/config — the ICE servers the SFU uses, so clients relay
+/// through the same TURN server, and the bitrate range voice is held to.
+async fn voice_config(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+) -> AppResult<Json<VoiceConfigResponse>> {
+    let mut ice_servers = vec![IceServer {
+        urls: crate::voice::STUN_URLS
+            .iter()
+            .map(|url| url.to_string())
+            .collect(),
+        username: None,
+        credential: None,
+    }];
+    if let Some(turn) = &state.config.voice.turn {
+        ice_servers.push(IceServer {
+            urls: turn.urls.clone(),
+            username: Some(turn.username.clone()),
+            credential: Some(turn.credential.clone()),
+        });
+    }
+    let (min_bitrate, max_bitrate) = state.config.voice.bitrate_range();
+    Ok(Json(VoiceConfigResponse {
+        ice_servers,
+        min_bitrate,
+        max_bitrate,
+    }))
+}
+
 #[derive(Debug, Deserialize)]
 struct VoiceStateBody {
     muted: Option<bool>,
     deafened: Option<bool>,
 }
 
+/// The `error` signal sent back when the SFU can't take a user's offer.
+fn voice_error_signal(channel_id: Uuid, user_id: Uuid, error: &anyhow::Error) -> WsEvent {
+    let payload = match error.downcast_ref::<crate::voice::ChannelFull>() {
+        Some(full) => serde_json::json!({ "code": "channel_full", "message": full.to_string() }),
+        None => serde_json::json!({
+            "code": "offer_failed",
+            "message": "Could not set up the voice connection",
+        }),
+    };
+    WsEvent::WebRTCSignal {
+        from_user_id: Uuid::nil(),
+        to_user_id: user_id,
+        channel_id,
+        signal_type: "error".to_string(),
+        payload,
+    }
+}
+
+/// The user limit a new channel gets: only voice channels keep the
+/// requested one, which must leave room for at least one user.
+fn voice_user_limit(channel_type: &ChannelType, requested: Option<i32>) -> AppResult<Option<i32>> {
+    if *channel_type != ChannelType::Voice {
+        return Ok(None);
+    }
+    if matches!(requested, Some(limit) if limit < 1) {
+        return Err(AppError::BadRequest(
+            "user_limit must be at least 1".to_string(),
+        ));
+    }
+    Ok(requested)
+}
+
+/// Whether `user_id` joining `channel_id` would exceed its user limit.
+/// Users already in the channel (reconnects) never count against it.
+fn voice_channel_is_full(
+    state: &AppState,
+    channel_id: Uuid,
+    user_id: Uuid,
+    user_limit: Option<i32>,
+) -> bool {
+    let Some(limit) = user_limit else {
+        return false;
+    };
+    let others = state
+        .voice_states
+        .get(&channel_id)
+        .map(|participants| participants.iter().filter(|p| p.user_id != user_id).count())
+        .unwrap_or(0);
+    others >= limit.max(0) as usize
+}
+
 /// POST /api/voice/:channel_id/join
 async fn voice_join(
     State(state): State<AppState>,
@@ -1570,9 +6627,33 @@ async fn voice_join(
     body: Option<Json<VoiceStateBody>>,
 ) -> AppResult<Json<Vec<VoiceParticipant>>> {
     let user_id = auth.user_id;
-    let initial_muted = body.as_ref().and_then(|b| b.muted).unwrap_or(false);
+    let mut initial_muted = body.as_ref().and_then(|b| b.muted).unwrap_or(false);
     let initial_deafened = body.as_ref().and_then(|b| b.deafened).unwrap_or(false);
 
+    // Enforce the channel's user limit before touching any existing voice state
+    let (channel, channel_server_id) = find_voice_channel(&state, channel_id).await?;
+    if voice_channel_is_full(&state, channel_id, user_id, channel.user_limit) {
+        // The owner and channel managers may join a full channel
+        check_permission(
+            &state,
+            user_id,
+            channel_server_id,
+            Permissions::MANAGE_CHANNELS,
+        )
+        .await
+        .map_err(|_| AppError::BadRequest("Voice channel is full".to_string()))?;
+    }
+
+    // A server mute follows the user into every voice channel of that server
+    let server_muted = state
+        .voice_mutes
+        .get(&channel_server_id)
+        .is_some_and(|muted| muted.contains(&user_id));
+    state
+        .sfu
+        .set_server_muted(channel_id, user_id, server_muted);
+    initial_muted |= server_muted;
+
     // Remove user from any other voice channel first (one channel at a time)
     let mut old_channels = Vec::new();
     for entry in state.voice_states.iter() {
@@ -1594,6 +6675,7 @@ async fn voice_join(
             user: None,
         };
         state.broadcast_to_channel(old_ch, &leave_event);
+        sync_voice_channel(&state, *old_ch, &leave_event).await;
     }
 
     // Look up user info
@@ -1633,6 +6715,17 @@ async fn voice_join(
         user: user_public,
     };
     state.broadcast_to_channel(&channel_id, &event);
+    sync_voice_channel(&state, channel_id, &event).await;
+
+    // Tell the joining client which SFU node to negotiate with
+    state.broadcast_to_user(
+        &user_id,
+        &WsEvent::VoiceServerUpdate {
+            channel_id,
+            region: state.config.voice.region.clone(),
+            endpoint: state.config.voice.endpoint.clone(),
+        },
+    );
 
     // Return current participant list
     let participants = state
@@ -1650,8 +6743,13 @@ async fn voice_leave(
     auth: AuthUser,
     Path(channel_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let user_id = auth.user_id;
+    leave_voice_channel(&state, channel_id, auth.user_id).await;
+    Ok(StatusCode::OK)
+}
 
+/// Take a user out of a voice channel: close their SFU connection, drop
+/// them from `voice_states` and announce the leave.
+async fn leave_voice_channel(state: &AppState, channel_id: Uuid, user_id: Uuid) {
     // Clean up SFU peer connection
     state.sfu.leave_channel(channel_id, user_id).await;
 
@@ -1673,8 +6771,7 @@ async fn voice_leave(
         user: None,
     };
     state.broadcast_to_channel(&channel_id, &event);
-
-    Ok(StatusCode::OK)
+    sync_voice_channel(state, channel_id, &event).await;
 }
 
 /// PATCH /api/voice/:channel_id/state
@@ -1688,54 +6785,284 @@ async fn voice_update_state(
     let mut muted = false;
     let mut deafened = false;
 
-    if let Some(mut participants) = state.voice_states.get_mut(&channel_id) {
-        if let Some(p) = participants.iter_mut().find(|p| p.user_id == user_id) {
-            if let Some(m) = body.muted {
-                p.muted = m;
-            }
-            if let Some(d) = body.deafened {
-                p.deafened = d;
-            }
-            muted = p.muted;
-            deafened = p.deafened;
-        } else {
-            return Err(AppError::NotFound("Not in voice channel".to_string()));
-        }
-    } else {
-        return Err(AppError::NotFound("Not in voice channel".to_string()));
-    }
+    if let Some(mut participants) = state.voice_states.get_mut(&channel_id) {
+        if let Some(p) = participants.iter_mut().find(|p| p.user_id == user_id) {
+            if let Some(m) = body.muted {
+                // Clients can't lift a server-enforced mute
+                p.muted = m || state.sfu.is_server_muted(channel_id, user_id);
+            }
+            if let Some(d) = body.deafened {
+                p.deafened = d;
+            }
+            muted = p.muted;
+            deafened = p.deafened;
+        } else {
+            return Err(AppError::NotFound("Not in voice channel".to_string()));
+        }
+    } else {
+        return Err(AppError::NotFound("Not in voice channel".to_string()));
+    }
+
+    let user_public = if let Ok(Some(user)) = db::users::find_by_id(&state.db, user_id).await {
+        Some(UserPublic::from(user))
+    } else {
+        None
+    };
+
+    let event = WsEvent::VoiceStateUpdate {
+        channel_id,
+        user_id,
+        joined: true,
+        muted,
+        deafened,
+        user: user_public,
+    };
+    state.broadcast_to_channel(&channel_id, &event);
+    sync_voice_channel(&state, channel_id, &event).await;
+
+    Ok(StatusCode::OK)
+}
+
+/// GET /api/voice/:channel_id/participants
+async fn voice_participants(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+    Path(channel_id): Path<Uuid>,
+) -> Json<Vec<VoiceParticipant>> {
+    // Redis has the participants connected to every instance, not just ours
+    if let Some(store) = &state.voice_store {
+        match store.load(channel_id).await {
+            Ok(stored) => return Json(stored),
+            Err(e) => tracing::warn!("Failed to load voice state from Redis: {}", e),
+        }
+    }
+
+    let participants = state
+        .voice_states
+        .get(&channel_id)
+        .map(|v| v.value().clone())
+        .unwrap_or_default();
+    Json(participants)
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveMemberBody {
+    target_channel_id: Uuid,
+}
+
+/// POST /api/voice/:channel_id/members/:user_id/move
+async fn voice_move_member(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, user_id)): Path<(Uuid, Uuid)>,
+    Json(body): Json<MoveMemberBody>,
+) -> AppResult<StatusCode> {
+    let target_channel_id = body.target_channel_id;
+
+    let (_, channel_server_id) = find_voice_channel(&state, channel_id).await?;
+
+    check_permission(
+        &state,
+        auth.user_id,
+        channel_server_id,
+        Permissions::MANAGE_CHANNELS | Permissions::MOVE_MEMBERS,
+    )
+    .await?;
+
+    // The target must be a voice channel on the same server
+    let target = db::channels::find_by_id(&state.db, target_channel_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Target channel not found".to_string()))?;
+    if target.server_id != Some(channel_server_id) || target.channel_type != ChannelType::Voice {
+        return Err(AppError::BadRequest(
+            "Target must be a voice channel in the same server".to_string(),
+        ));
+    }
+
+    // The target's limits apply as if the member joined it; only the owner
+    // and channel managers may move someone past its user limit
+    if voice_channel_is_full(&state, target_channel_id, user_id, target.user_limit) {
+        check_permission(
+            &state,
+            auth.user_id,
+            channel_server_id,
+            Permissions::MANAGE_CHANNELS,
+        )
+        .await
+        .map_err(|_| AppError::BadRequest("Voice channel is full".to_string()))?;
+    }
+    if !state.sfu.has_room(target_channel_id, user_id) {
+        return Err(AppError::BadRequest("Voice channel is full".to_string()));
+    }
+
+    // Take the user out of the current channel
+    let participant = {
+        let not_in_channel = || AppError::NotFound("User is not in this voice channel".to_string());
+        let mut participants = state
+            .voice_states
+            .get_mut(&channel_id)
+            .ok_or_else(not_in_channel)?;
+        let index = participants
+            .iter()
+            .position(|p| p.user_id == user_id)
+            .ok_or_else(not_in_channel)?;
+        if target_channel_id == channel_id {
+            return Ok(StatusCode::OK);
+        }
+        participants.remove(index)
+    };
+    state
+        .voice_states
+        .remove_if(&channel_id, |_, p| p.is_empty());
+
+    // Drop their media from the old channel; the client reconnects to the target
+    state.sfu.leave_channel(channel_id, user_id).await;
+
+    let moved = VoiceParticipant {
+        channel_id: target_channel_id,
+        ..participant
+    };
+    {
+        let mut participants = state.voice_states.entry(target_channel_id).or_default();
+        participants.retain(|p| p.user_id != user_id);
+        participants.push(moved.clone());
+    }
+
+    // Their peer connection was dropped: have the client negotiate a new one
+    // for the target, before the leave event would take it out of voice
+    state.broadcast_to_user(
+        &user_id,
+        &WsEvent::VoiceServerUpdate {
+            channel_id: target_channel_id,
+            region: state.config.voice.region.clone(),
+            endpoint: state.config.voice.endpoint.clone(),
+        },
+    );
+    state.broadcast_to_user(
+        &user_id,
+        &WsEvent::WebRTCSignal {
+            from_user_id: Uuid::nil(),
+            to_user_id: user_id,
+            channel_id: target_channel_id,
+            signal_type: "reconnect".to_string(),
+            payload: serde_json::json!({ "from_channel_id": channel_id }),
+        },
+    );
+
+    let leave_event = WsEvent::VoiceStateUpdate {
+        channel_id,
+        user_id,
+        joined: false,
+        muted: false,
+        deafened: false,
+        user: None,
+    };
+    state.broadcast_to_channel(&channel_id, &leave_event);
+    sync_voice_channel(&state, channel_id, &leave_event).await;
+
+    let join_event = WsEvent::VoiceStateUpdate {
+        channel_id: target_channel_id,
+        user_id,
+        joined: true,
+        muted: moved.muted,
+        deafened: moved.deafened,
+        user: moved.user,
+    };
+    state.broadcast_to_channel(&target_channel_id, &join_event);
+    sync_voice_channel(&state, target_channel_id, &join_event).await;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Debug, Deserialize)]
+struct MuteMemberBody {
+    muted: bool,
+}
+
+/// POST /api/voice/:channel_id/members/:user_id/mute
+async fn voice_mute_member(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((channel_id, user_id)): Path<(Uuid, Uuid)>,
+    body: Option<Json<MuteMemberBody>>,
+) -> AppResult<StatusCode> {
+    // No body means mute; `{ "muted": false }` lifts it
+    let muted = body.map(|Json(b)| b.muted).unwrap_or(true);
+
+    let (_, channel_server_id) = find_voice_channel(&state, channel_id).await?;
 
-    let user_public = if let Ok(Some(user)) = db::users::find_by_id(&state.db, user_id).await {
-        Some(UserPublic::from(user))
-    } else {
-        None
+    check_permission(
+        &state,
+        auth.user_id,
+        channel_server_id,
+        Permissions::MUTE_MEMBERS,
+    )
+    .await?;
+
+    let (deafened, user) = {
+        let not_in_channel = || AppError::NotFound("User is not in this voice channel".to_string());
+        let mut participants = state
+            .voice_states
+            .get_mut(&channel_id)
+            .ok_or_else(not_in_channel)?;
+        let p = participants
+            .iter_mut()
+            .find(|p| p.user_id == user_id)
+            .ok_or_else(not_in_channel)?;
+        p.muted = muted;
+        (p.deafened, p.user.clone())
     };
 
+    if muted {
+        state
+            .voice_mutes
+            .entry(channel_server_id)
+            .or_default()
+            .insert(user_id);
+    } else if let Some(mut muted_users) = state.voice_mutes.get_mut(&channel_server_id) {
+        muted_users.remove(&user_id);
+    }
+    state.sfu.set_server_muted(channel_id, user_id, muted);
+
     let event = WsEvent::VoiceStateUpdate {
         channel_id,
         user_id,
         joined: true,
         muted,
         deafened,
-        user: user_public,
+        user,
     };
     state.broadcast_to_channel(&channel_id, &event);
+    sync_voice_channel(&state, channel_id, &event).await;
 
     Ok(StatusCode::OK)
 }
 
-/// GET /api/voice/:channel_id/participants
-async fn voice_participants(
-    State(state): State<AppState>,
-    _auth: AuthUser,
-    Path(channel_id): Path<Uuid>,
-) -> Json<Vec<VoiceParticipant>> {
-    let participants = state
-        .voice_states
-        .get(&channel_id)
-        .map(|v| v.value().clone())
-        .unwrap_or_default();
-    Json(participants)
+/// Mirror the participant a voice event is about to Redis and publish the
+/// event for other instances. No-op when Redis isn't configured.
+async fn sync_voice_channel(state: &AppState, channel_id: Uuid, event: &WsEvent) {
+    let Some(store) = &state.voice_store else {
+        return;
+    };
+
+    // Only that participant's entry is written: others in the channel may be
+    // connected to another instance
+    if let WsEvent::VoiceStateUpdate { user_id, .. } = event {
+        let participant = state
+            .voice_states
+            .get(&channel_id)
+            .and_then(|participants| participants.iter().find(|p| p.user_id == *user_id).cloned());
+        let result = match participant {
+            Some(participant) => store.save(&participant).await,
+            None => store.remove(channel_id, *user_id).await,
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to persist voice state to Redis: {}", e);
+        }
+    }
+    if let Err(e) = store.publish(channel_id, event).await {
+        tracing::warn!("Failed to publish voice event to Redis: {}", e);
+    }
 }
 
 /// Remove a user from all voice channels and broadcast leave events.
@@ -1766,14 +7093,849 @@ async fn broadcast_voice_leave(state: &AppState, user_id: Uuid) {
             user: None,
         };
         state.broadcast_to_channel(&channel_id, &event);
+        sync_voice_channel(state, channel_id, &event).await;
     }
 }
 
 // ─── Health Check ───────────────────────────────────────────────────────────
 
-async fn health_check() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "ok",
+/// How long each dependency gets to answer a health probe.
+const HEALTH_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// GET /health — 503 when the database (or Redis, if configured) is unreachable.
+async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
+    let database = tokio::time::timeout(
+        HEALTH_PROBE_TIMEOUT,
+        sqlx::query("SELECT 1").execute(&state.db),
+    )
+    .await
+    .is_ok_and(|r| r.is_ok());
+
+    let redis = match &state.voice_store {
+        Some(store) => Some(
+            tokio::time::timeout(HEALTH_PROBE_TIMEOUT, store.ping())
+                .await
+                .is_ok_and(|r| r.is_ok()),
+        ),
+        None => None,
+    };
+
+    let healthy = database && redis.unwrap_or(true);
+    let probe = |ok: bool| if ok { "ok" } else { "unreachable" };
+    let mut body = serde_json::json!({
+        "status": if healthy { "ok" } else { "degraded" },
         "version": env!("CARGO_PKG_VERSION"),
-    }))
+        "database": probe(database),
+    });
+    if let Some(redis) = redis {
+        body["redis"] = probe(redis).into();
+    }
+
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an `AppState` whose database pool points at an unreachable address,
+    /// so any test that accidentally hits the database fails instead of hanging.
+    fn test_state() -> AppState {
+        let config: AppConfig = toml::from_str(
+            r#"
+            [server]
+            host = "127.0.0.1"
+            port = 0
+            public_url = "http://localhost"
+
+            [database]
+            url = "postgres://antarcticom@127.0.0.1:1/antarcticom"
+            max_connections = 1
+
+            [redis]
+            url = ""
+
+            [voice]
+            max_sessions = 1
+            min_bitrate = 32
+            max_bitrate = 128
+
+            [tls]
+            cert_path = ""
+            key_path = ""
+            acme_enabled = false
+            acme_domain = ""
+
+            [auth]
+            jwt_public_key_path = ""
+            token_expiry = 60
+            allow_local_registration = true
+
+            [identity]
+            federation_enabled = false
+            auth_hub_url = ""
+
+            [logging]
+            level = "info"
+            format = "pretty"
+            "#,
+        )
+        .expect("valid test config");
+
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(100))
+            .connect_lazy(&config.database.url)
+            .expect("lazy pool");
+
+        AppState::new(db, None, config)
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_to_server_uses_warm_cache() {
+        let state = test_state();
+        let server_id = Uuid::now_v7();
+        let user_id = Uuid::now_v7();
+
+        let (session, mut rx) = WsSession::new(user_id, Vec::new(), 16);
+        state.ws_sessions.insert(user_id, Arc::new(session));
+        state.server_members.insert(server_id, vec![user_id]);
+
+        // The pool is unreachable, so delivery proves no query was issued.
+        let event = WsEvent::MemberLeave {
+            server_id,
+            user_id: Uuid::now_v7(),
+        };
+        state.broadcast_to_server(&server_id, &event).await;
+
+        let received = rx.try_recv().expect("event delivered from cache");
+        assert!(received.contains("MemberLeave"));
+    }
+
+    #[tokio::test]
+    async fn test_auth_rate_limit_rejects_eleventh_attempt() {
+        let state = test_state();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+        for _ in 0..10 {
+            assert!(check_auth_rate_limit(&state, ip).is_ok());
+        }
+        assert!(matches!(
+            check_auth_rate_limit(&state, ip),
+            Err(AppError::RateLimited(None))
+        ));
+
+        // Other clients are unaffected
+        let other: IpAddr = "203.0.113.8".parse().unwrap();
+        assert!(check_auth_rate_limit(&state, other).is_ok());
+    }
+
+    #[test]
+    fn test_client_ip_trusts_forwarded_for_only_from_proxies() {
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let inner_proxy: IpAddr = "10.0.0.3".parse().unwrap();
+        let trusted = [proxy, inner_proxy];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Forwarded-For",
+            "198.51.100.1, 203.0.113.7, 10.0.0.3".parse().unwrap(),
+        );
+
+        // The first untrusted hop from the right, not whatever the client wrote first
+        assert_eq!(
+            client_ip(proxy, &headers, &trusted),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        // Anyone else's header is ignored
+        let stranger: IpAddr = "203.0.113.50".parse().unwrap();
+        assert_eq!(client_ip(stranger, &headers, &trusted), stranger);
+        // A proxy that sent no header is the client itself
+        assert_eq!(client_ip(proxy, &HeaderMap::new(), &trusted), proxy);
+    }
+
+    #[tokio::test]
+    async fn test_remote_voice_events_update_voice_states() {
+        let state = test_state();
+        let channel_id = Uuid::now_v7();
+        let user_id = Uuid::now_v7();
+        let update = |joined: bool, muted: bool| WsEvent::VoiceStateUpdate {
+            channel_id,
+            user_id,
+            joined,
+            muted,
+            deafened: false,
+            user: None,
+        };
+
+        state.apply_remote_voice_event(&update(true, false));
+        state.apply_remote_voice_event(&update(true, true));
+        {
+            let participants = state.voice_states.get(&channel_id).unwrap();
+            assert_eq!(participants.len(), 1);
+            assert!(participants[0].muted);
+        }
+
+        state.apply_remote_voice_event(&update(false, false));
+        assert!(state.voice_states.get(&channel_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_member_cache_add_remove() {
+        let state = test_state();
+        let server_id = Uuid::now_v7();
+        let user_id = Uuid::now_v7();
+
+        // Uncached servers stay lazy
+        state.cache_member_add(server_id, user_id);
+        assert!(state.server_members.get(&server_id).is_none());
+
+        state.server_members.insert(server_id, Vec::new());
+        state.cache_member_add(server_id, user_id);
+        state.cache_member_add(server_id, user_id);
+        assert_eq!(
+            *state.server_members.get(&server_id).unwrap(),
+            vec![user_id]
+        );
+
+        state.cache_member_remove(server_id, user_id);
+        assert!(state.server_members.get(&server_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_muted_role_cannot_send_messages() {
+        let channel_id = Uuid::now_v7();
+        let base = Permissions::new(Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES);
+        let muted = ChannelOverride {
+            channel_id,
+            target_id: Uuid::now_v7(),
+            target_type: OverrideTarget::Role,
+            allow: 0,
+            deny: Permissions::SEND_MESSAGES,
+        };
+
+        assert!(channel_permits(base, &[], Permissions::SEND_MESSAGES));
+        assert!(!channel_permits(
+            base,
+            std::slice::from_ref(&muted),
+            Permissions::SEND_MESSAGES
+        ));
+        // Muted members can still read the channel
+        assert!(channel_permits(
+            base,
+            std::slice::from_ref(&muted),
+            Permissions::VIEW_CHANNEL
+        ));
+
+        // A member override beats the role's deny
+        let unmuted = ChannelOverride {
+            target_type: OverrideTarget::Member,
+            allow: Permissions::SEND_MESSAGES,
+            deny: 0,
+            ..muted.clone()
+        };
+        assert!(channel_permits(
+            base,
+            &[muted, unmuted],
+            Permissions::SEND_MESSAGES
+        ));
+    }
+
+    #[test]
+    fn test_non_members_cannot_send_messages() {
+        // Non-members resolve to no server permissions at all
+        assert!(!channel_permits(
+            Permissions::new(0),
+            &[],
+            Permissions::SEND_MESSAGES
+        ));
+    }
+
+    #[test]
+    fn test_limits_refuse_creation_once_reached() {
+        let limits = crate::config::LimitsConfig::default();
+        let max = limits.max_servers_per_user;
+        let message = || format!("You can own at most {} servers", max);
+
+        assert!(check_limit(0, max, message).is_ok());
+        assert!(check_limit(max - 1, max, message).is_ok());
+        match check_limit(max, max, message) {
+            Err(AppError::BadRequest(msg)) => assert_eq!(msg, message()),
+            other => panic!("expected the limit to apply, got {:?}", other.err()),
+        }
+        // A limit of zero turns creation off
+        assert!(check_limit(0, 0, message).is_err());
+    }
+
+    #[test]
+    fn test_message_content_is_sanitized_and_validated() {
+        assert_eq!(
+            message_content("  hi\u{7} there ", false).unwrap(),
+            "hi there"
+        );
+        // Control characters alone don't make a message
+        assert!(matches!(
+            message_content("\u{0}\u{1}", false),
+            Err(AppError::BadRequest(_))
+        ));
+        assert_eq!(message_content("\u{0}\u{1}", true).unwrap(), "");
+        let too_long = "a".repeat(chat::MAX_MESSAGE_LENGTH + 1);
+        assert!(matches!(
+            message_content(&too_long, true),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_edit_history_of_others_needs_manage_messages() {
+        let author = Uuid::now_v7();
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "channel_id": Uuid::now_v7(),
+            "author_id": author,
+            "content": "edited",
+            "created_at": chrono::Utc::now(),
+            "edited_at": chrono::Utc::now(),
+            "is_deleted": false,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            edit_history_permission(&message, author),
+            Permissions::VIEW_CHANNEL
+        );
+        assert_eq!(
+            edit_history_permission(&message, Uuid::now_v7()),
+            Permissions::MANAGE_MESSAGES
+        );
+    }
+
+    #[test]
+    fn test_ready_frame_carries_user_profile() {
+        let user_id = Uuid::now_v7();
+        let channel_id = Uuid::now_v7();
+        let session_id = Uuid::now_v7();
+        let frame = ready_frame(
+            UserPublic {
+                id: user_id,
+                username: "penguin".to_string(),
+                display_name: "Emperor Penguin".to_string(),
+                avatar_hash: Some("abc123".to_string()),
+                is_bot: false,
+            },
+            session_id,
+            vec![ReadState {
+                channel_id,
+                last_read_message_id: Some(10),
+                last_message_id: Some(12),
+                unread_count: 2,
+            }],
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&frame).unwrap();
+        assert_eq!(json["type"], "Ready");
+        assert_eq!(json["data"]["session_id"], session_id.to_string());
+        assert_eq!(json["data"]["user"]["id"], user_id.to_string());
+        assert_eq!(json["data"]["user"]["username"], "penguin");
+        assert_eq!(json["data"]["user"]["display_name"], "Emperor Penguin");
+        assert_eq!(json["data"]["user"]["avatar_hash"], "abc123");
+        assert_eq!(
+            json["data"]["read_states"][0]["channel_id"],
+            channel_id.to_string()
+        );
+        assert_eq!(json["data"]["read_states"][0]["unread_count"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_do_not_disturb_holds_back_mention_pings() {
+        let state = test_state();
+        let mut receivers = Vec::new();
+        for status in [
+            PresenceStatus::Online,
+            PresenceStatus::Idle,
+            PresenceStatus::Dnd,
+        ] {
+            let user_id = Uuid::now_v7();
+            let (session, rx) = WsSession::new(user_id, Vec::new(), 16);
+            state.ws_sessions.insert(user_id, Arc::new(session));
+            state.presence.set_status(user_id, status);
+            receivers.push((user_id, rx));
+        }
+
+        let users = receivers.iter().map(|(user_id, _)| *user_id).collect();
+        ping_mentioned(&state, Uuid::now_v7(), 42, &users);
+
+        let pinged: Vec<bool> = receivers
+            .iter_mut()
+            .map(|(_, rx)| rx.try_recv().is_ok_and(|frame| frame.contains("Mentioned")))
+            .collect();
+        assert_eq!(pinged, [true, true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_presence_snapshot_reports_current_status() {
+        let state = test_state();
+        let online = Uuid::now_v7();
+        let away = Uuid::now_v7();
+        state.presence.set_status(online, PresenceStatus::Online);
+
+        let frame = presence_snapshot_frame(&state, HashSet::from([online, away]));
+
+        let json: serde_json::Value = serde_json::from_str(&frame).unwrap();
+        assert_eq!(json["type"], "PresenceSnapshot");
+        assert_eq!(json["data"]["presences"][online.to_string()], "online");
+        assert_eq!(json["data"]["presences"][away.to_string()], "offline");
+    }
+
+    #[test]
+    fn test_webhook_identity_validation() {
+        let (name, url) =
+            validate_webhook_identity(Some("  CI  "), Some("https://ci.example/a.png")).unwrap();
+        assert_eq!(name.as_deref(), Some("CI"));
+        assert_eq!(url.as_deref(), Some("https://ci.example/a.png"));
+
+        assert!(validate_webhook_identity(Some(" "), None).is_err());
+        assert!(validate_webhook_identity(None, Some("javascript:alert(1)")).is_err());
+        assert_eq!(
+            validate_webhook_identity(None, Some("")).unwrap(),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_validate_image_checks_type_and_dimensions() {
+        let gif = |width: u16, height: u16| {
+            let mut data = b"GIF89a".to_vec();
+            data.extend_from_slice(&width.to_le_bytes());
+            data.extend_from_slice(&height.to_le_bytes());
+            data
+        };
+
+        assert!(validate_image(&gif(128, 128), "image/gif", 512).is_ok());
+        // Declared as PNG but it's a GIF
+        assert!(validate_image(&gif(128, 128), "image/png", 512).is_err());
+        assert!(validate_image(&gif(20000, 20000), "image/gif", MAX_AVATAR_DIMENSION).is_err());
+        assert!(validate_image(&gif(0, 16), "image/gif", 512).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_mode_blocks_second_post_in_cooldown() {
+        let state = test_state();
+        let (channel, other_channel, user) = (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+        let cooldown = Duration::from_secs(30);
+
+        assert!(state.check_slow_mode(channel, user, cooldown).is_ok());
+        assert!(matches!(
+            state.check_slow_mode(channel, user, cooldown),
+            Err(AppError::RateLimited(Some(30)))
+        ));
+        assert!(state.check_slow_mode(other_channel, user, cooldown).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_token_cache_sweep_evicts_expired_entries() {
+        let state = test_state();
+        let stale = Instant::now() - Duration::from_secs(TOKEN_CACHE_TTL_SECS + 1);
+        state.token_cache.insert(
+            "stale".into(),
+            (Uuid::now_v7(), "old".into(), TokenType::User, stale),
+        );
+        state.token_cache.insert(
+            "fresh".into(),
+            (Uuid::now_v7(), "new".into(), TokenType::Bot, Instant::now()),
+        );
+
+        assert_eq!(state.sweep_token_cache(), 1);
+        assert!(!state.token_cache.contains_key("stale"));
+        assert!(state.token_cache.contains_key("fresh"));
+    }
+
+    #[tokio::test]
+    async fn test_voice_channel_full_rejects_new_users() {
+        let state = test_state();
+        let channel_id = Uuid::now_v7();
+        let present = Uuid::now_v7();
+        state.voice_states.insert(
+            channel_id,
+            vec![VoiceParticipant {
+                user_id: present,
+                channel_id,
+                muted: false,
+                deafened: false,
+                user: None,
+            }],
+        );
+
+        assert!(voice_channel_is_full(
+            &state,
+            channel_id,
+            Uuid::now_v7(),
+            Some(1)
+        ));
+        // Reconnecting users are not blocked by their own slot
+        assert!(!voice_channel_is_full(&state, channel_id, present, Some(1)));
+        assert!(!voice_channel_is_full(
+            &state,
+            channel_id,
+            Uuid::now_v7(),
+            Some(2)
+        ));
+        assert!(!voice_channel_is_full(
+            &state,
+            channel_id,
+            Uuid::now_v7(),
+            None
+        ));
+    }
+
+    #[test]
+    fn test_full_sfu_channel_is_reported_over_the_signal() {
+        let channel_id = Uuid::now_v7();
+        let user_id = Uuid::now_v7();
+        let signal = |error| {
+            let event = voice_error_signal(channel_id, user_id, &error);
+            serde_json::to_value(&event).unwrap()
+        };
+
+        let full = signal(anyhow::Error::new(crate::voice::ChannelFull {
+            max_participants: 25,
+        }));
+        assert_eq!(full["type"], "WebRTCSignal");
+        assert_eq!(full["data"]["to_user_id"], user_id.to_string());
+        assert_eq!(full["data"]["signal_type"], "error");
+        assert_eq!(full["data"]["payload"]["code"], "channel_full");
+        assert!(full["data"]["payload"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("25"));
+
+        // Other failures don't leak their details to the client
+        let failed = signal(anyhow::anyhow!("DTLS setup failed at 10.0.0.3"));
+        assert_eq!(failed["data"]["payload"]["code"], "offer_failed");
+        assert!(!failed.to_string().contains("10.0.0.3"));
+    }
+
+    #[test]
+    fn test_only_voice_channels_take_a_user_limit() {
+        assert_eq!(
+            voice_user_limit(&ChannelType::Voice, Some(5)).unwrap(),
+            Some(5)
+        );
+        assert_eq!(voice_user_limit(&ChannelType::Voice, None).unwrap(), None);
+        assert!(matches!(
+            voice_user_limit(&ChannelType::Voice, Some(0)),
+            Err(AppError::BadRequest(_))
+        ));
+        // Other channel types drop the limit rather than reject it
+        assert_eq!(voice_user_limit(&ChannelType::Text, Some(5)).unwrap(), None);
+        assert_eq!(voice_user_limit(&ChannelType::Text, Some(0)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_everyone_role_is_protected() {
+        let role = |name: &str, position| Role {
+            id: Uuid::now_v7(),
+            server_id: Uuid::now_v7(),
+            name: name.to_string(),
+            permissions: 0,
+            color: 0,
+            position,
+        };
+
+        assert!(is_everyone_role(&role("@everyone", 0)));
+        assert!(!is_everyone_role(&role("@everyone", 3)));
+        assert!(!is_everyone_role(&role("Moderator", 0)));
+
+        // Only roles strictly below the caller's highest role can be managed
+        assert!(can_manage_role(Some(5), 4));
+        assert!(!can_manage_role(Some(5), 5));
+        assert!(!can_manage_role(Some(5), 6));
+        assert!(!can_manage_role(None, 0));
+        assert!(can_manage_role(Some(i32::MAX), 1000));
+    }
+
+    #[test]
+    fn test_role_edits_respect_everyone_and_rank() {
+        let role = |name: &str, position| Role {
+            id: Uuid::now_v7(),
+            server_id: Uuid::now_v7(),
+            name: name.to_string(),
+            permissions: 0,
+            color: 0,
+            position,
+        };
+        let everyone = role("@everyone", 0);
+        let moderator = role("Moderator", 3);
+
+        assert!(matches!(
+            check_role_delete(&everyone, Some(i32::MAX)),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(check_role_delete(&moderator, Some(4)).is_ok());
+        assert!(matches!(
+            check_role_delete(&moderator, Some(3)),
+            Err(AppError::Forbidden)
+        ));
+
+        // @everyone keeps its name and place, but its permissions may change
+        assert!(check_role_update(&everyone, Some(4), "@everyone", 0).is_ok());
+        assert!(matches!(
+            check_role_update(&everyone, Some(4), "Everybody", 0),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            check_role_update(&everyone, Some(4), "@everyone", 1),
+            Err(AppError::BadRequest(_))
+        ));
+
+        assert!(check_role_update(&moderator, Some(4), "Mod", 2).is_ok());
+        // Neither raising a role to the caller's rank nor posing as @everyone
+        assert!(matches!(
+            check_role_update(&moderator, Some(4), "Moderator", 4),
+            Err(AppError::Forbidden)
+        ));
+        assert!(matches!(
+            check_role_update(&moderator, Some(4), "@everyone", 2),
+            Err(AppError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn test_role_permissions_cannot_escalate() {
+        let moderator = Permissions::new(Permissions::MANAGE_SERVER | Permissions::KICK_MEMBERS);
+
+        assert!(check_grantable(moderator, Permissions::KICK_MEMBERS).is_ok());
+        assert!(check_grantable(moderator, 0).is_ok());
+        assert!(matches!(
+            check_grantable(moderator, Permissions::BAN_MEMBERS),
+            Err(AppError::Forbidden)
+        ));
+        assert!(matches!(
+            check_grantable(moderator, Permissions::ADMINISTRATOR),
+            Err(AppError::Forbidden)
+        ));
+
+        let admin = Permissions::new(Permissions::ADMINISTRATOR);
+        assert!(check_grantable(
+            admin,
+            Permissions::BAN_MEMBERS | Permissions::MANAGE_CHANNELS
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_post_permission_by_channel_type() {
+        assert_eq!(
+            post_permission(&ChannelType::Text).unwrap(),
+            Permissions::SEND_MESSAGES
+        );
+        assert_eq!(
+            post_permission(&ChannelType::Announcement).unwrap(),
+            Permissions::MANAGE_MESSAGES
+        );
+        for channel_type in [ChannelType::Voice, ChannelType::Category] {
+            assert!(matches!(
+                post_permission(&channel_type),
+                Err(AppError::BadRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_moderation_requires_higher_rank() {
+        assert!(outranks(Some(3), Some(2)));
+        assert!(outranks(Some(0), None));
+        assert!(!outranks(Some(2), Some(2)));
+        assert!(!outranks(Some(1), Some(2)));
+        assert!(!outranks(None, None));
+        // Nobody outranks the owner, not even the owner themselves
+        assert!(!outranks(Some(1000), Some(i32::MAX)));
+        assert!(!outranks(Some(i32::MAX), Some(i32::MAX)));
+    }
+
+    #[tokio::test]
+    async fn test_owner_outranks_every_member() {
+        let state = test_state();
+        let owner = Uuid::now_v7();
+        let server = Server {
+            id: Uuid::now_v7(),
+            name: "Test".to_string(),
+            icon_hash: None,
+            owner_id: owner,
+            e2ee_enabled: false,
+            created_at: chrono::Utc::now(),
+            embeds_enabled: true,
+            disabled: false,
+        };
+
+        // The owner's rank needs no role lookup
+        let owner_rank = member_rank(&state, &server, owner).await.unwrap();
+        assert!(outranks(owner_rank, Some(i32::MAX - 1)));
+        assert!(!outranks(Some(i32::MAX - 1), owner_rank));
+        assert!(matches!(
+            member_rank(&state, &server, Uuid::now_v7()).await,
+            Err(AppError::Database(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_server_cannot_be_transferred_to_its_caller() {
+        let state = test_state();
+        let user_id = Uuid::now_v7();
+        let transfer = |new_owner_id| {
+            transfer_server(
+                State(state.clone()),
+                AuthUser {
+                    user_id,
+                    is_bot: false,
+                },
+                Path(Uuid::now_v7()),
+                Json(TransferOwnershipRequest { new_owner_id }),
+            )
+        };
+
+        // Refused before looking the server up
+        assert!(matches!(
+            transfer(user_id).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            transfer(Uuid::now_v7()).await,
+            Err(AppError::Database(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_hub_keys_fall_back_to_legacy_public_key() {
+        use rsa::pkcs8::EncodePublicKey;
+
+        // A hub from before JWKS: only the PEM endpoint exists
+        let key = rsa::RsaPublicKey::new(
+            rsa::BigUint::from(0xC5A6_8E5B_3D1F_0E37u64),
+            rsa::BigUint::from(65537u32),
+        )
+        .unwrap();
+        let pem = key.to_public_key_pem(Default::default()).unwrap();
+        let hub = Router::new().route(
+            "/api/auth/public-key",
+            get(move || {
+                let pem = pem.clone();
+                async move {
+                    Json(PublicKeyResponse {
+                        public_key_pem: pem,
+                        algorithm: "RS256".to_string(),
+                    })
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hub_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, hub).await });
+
+        let mut state = test_state();
+        state.config.mode = ServerMode::Community;
+        state.config.identity.auth_hub_url = hub_url;
+
+        let jwks = state.hub_jwks("not.a.token").await.unwrap();
+        assert_eq!(jwks.keys.len(), 1);
+        let expected =
+            auth::rsa_public_jwk(&key.to_public_key_pem(Default::default()).unwrap()).unwrap();
+        assert_eq!(jwks.keys[0].common.key_id, expected.common.key_id);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_connection_gets_lagged_then_catches_up() {
+        let user_id = Uuid::now_v7();
+        let (session, mut rx) = WsSession::new(user_id, Vec::new(), 4);
+        for _ in 0..6 {
+            session.send(r#"{"type":"HeartbeatAck"}"#);
+        }
+
+        let frame = next_session_frame(&mut rx, user_id).await.unwrap();
+        assert!(matches!(
+            serde_json::from_str::<WsEvent>(&frame),
+            Ok(WsEvent::Lagged { missed: 2 })
+        ));
+        // The connection stays up and carries on from the oldest frame kept
+        for seq in 3..=6 {
+            let frame = next_session_frame(&mut rx, user_id).await.unwrap();
+            assert!(frame.starts_with(&format!(r#"{{"seq":{},"#, seq)));
+        }
+
+        session.close();
+        assert!(next_session_frame(&mut rx, user_id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_set_identity_key_validates_before_writing() {
+        let auth = || AuthUser {
+            user_id: Uuid::now_v7(),
+            is_bot: false,
+        };
+        let set = |identity_key: Vec<u8>| {
+            set_identity_key(
+                State(test_state()),
+                auth(),
+                Json(UpdateIdentityKeyRequest { identity_key }),
+            )
+        };
+
+        assert!(matches!(
+            set(vec![7; 31]).await,
+            Err(AppError::BadRequest(_))
+        ));
+        // A well-formed key gets as far as the (unreachable) database
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]).verifying_key();
+        assert!(matches!(
+            set(key.to_bytes().to_vec()).await,
+            Err(AppError::Database(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_only_custom_emoji_references_are_looked_up() {
+        let state = test_state();
+        let channel_id = Uuid::now_v7();
+        assert!(
+            check_custom_emojis(&state, channel_id, "plain <:text: here")
+                .await
+                .is_ok()
+        );
+
+        let content = format!("hi <:party:{}>", Uuid::now_v7());
+        assert!(matches!(
+            check_custom_emojis(&state, channel_id, &content).await,
+            Err(AppError::Database(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failed_logins_are_only_counted_with_lockout_on() {
+        let mut state = test_state();
+        state.config.auth.lockout.max_failures = 0;
+        assert!(record_failed_login(&state, Uuid::now_v7()).await.is_ok());
+
+        state.config.auth.lockout.max_failures = 3;
+        assert!(matches!(
+            record_failed_login(&state, Uuid::now_v7()).await,
+            Err(AppError::Database(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_health_check_reports_unreachable_database() {
+        let response = health_check(State(test_state())).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "degraded");
+        assert_eq!(json["database"], "unreachable");
+        assert!(json.get("redis").is_none());
+    }
 }
@@ -3,15 +3,48 @@ use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse,
+    RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPublicKey;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
 
-use crate::config::AuthConfig;
+use crate::config::{AuthConfig, JwtAlgorithm, LockoutConfig, PasswordPolicyConfig};
 use crate::error::{AppError, AppResult};
 
+/// Minimum and maximum username length (in characters).
+pub const MIN_USERNAME_LENGTH: usize = 3;
+pub const MAX_USERNAME_LENGTH: usize = 32;
+
+/// Usernames nobody may register, compared case-insensitively and ignoring
+/// separators. "system" owns the seeded default server.
+const RESERVED_USERNAMES: &[&str] = &[
+    "system",
+    "admin",
+    "administrator",
+    "root",
+    "moderator",
+    "staff",
+    "support",
+    "everyone",
+    "here",
+    "antarcticom",
+];
+
 /// JWT claims stored in each token.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -23,6 +56,144 @@ pub struct Claims {
     pub iat: i64,
     /// Expiry (Unix timestamp)
     pub exp: i64,
+    /// Unique token ID, used for revocation (empty for legacy tokens)
+    #[serde(default)]
+    pub jti: String,
+    /// Kind of account the token was issued to (legacy tokens are user tokens)
+    #[serde(default)]
+    pub token_type: TokenType,
+}
+
+/// Kind of account a token authenticates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    #[default]
+    User,
+    Bot,
+}
+
+impl TokenType {
+    pub fn is_bot(self) -> bool {
+        self == TokenType::Bot
+    }
+}
+
+/// Normalize a requested username (NFKC, so `ａｄｍｉｎ` becomes `admin`) and
+/// check it: 3–32 ASCII letters, digits and single `_`, `.` or `-` separators
+/// between them, and not reserved. ASCII-only rules out look-alikes from
+/// other scripts, such as a Cyrillic `а`. Returns the name to store.
+pub fn normalize_username(username: &str) -> AppResult<String> {
+    let username: String = username.trim().nfkc().collect();
+
+    let len = username.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&len) {
+        return Err(AppError::BadRequest(format!(
+            "Username must be {}-{} characters",
+            MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+        )));
+    }
+
+    let is_separator = |c: char| matches!(c, '_' | '.' | '-');
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || is_separator(c))
+    {
+        return Err(AppError::BadRequest(
+            "Username may only contain letters, digits, '_', '.' and '-'".to_string(),
+        ));
+    }
+    if username.starts_with(is_separator)
+        || username.ends_with(is_separator)
+        || username
+            .as_bytes()
+            .windows(2)
+            .any(|w| is_separator(w[0] as char) && is_separator(w[1] as char))
+    {
+        return Err(AppError::BadRequest(
+            "Username can't start or end with a separator or have two in a row".to_string(),
+        ));
+    }
+
+    let bare: String = username
+        .chars()
+        .filter(|c| !is_separator(*c))
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if RESERVED_USERNAMES.contains(&bare.as_str()) {
+        return Err(AppError::BadRequest("Username is reserved".to_string()));
+    }
+
+    Ok(username)
+}
+
+/// Check a new password against the policy, listing every unmet requirement
+/// in the error.
+pub fn check_password_policy(policy: &PasswordPolicyConfig, password: &str) -> AppResult<()> {
+    let mut unmet = Vec::new();
+    if password.chars().count() < policy.min_length {
+        unmet.push(format!("at least {} characters", policy.min_length));
+    }
+    if policy.require_mixed_case
+        && !(password.chars().any(char::is_uppercase) && password.chars().any(char::is_lowercase))
+    {
+        unmet.push("upper and lower case letters".to_string());
+    }
+    if policy.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        unmet.push("a digit".to_string());
+    }
+    if policy.min_entropy_bits > 0 && password_entropy_bits(password) < policy.min_entropy_bits {
+        unmet.push("less predictable characters (avoid repeats and sequences)".to_string());
+    }
+
+    if unmet.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!(
+            "Password needs {}",
+            unmet.join(", ")
+        )))
+    }
+}
+
+/// How long an account is locked for after `failures` failed logins in a
+/// row, or None if that's still below the limit.
+pub fn lockout_duration(lockout: &LockoutConfig, failures: i32) -> Option<chrono::Duration> {
+    if lockout.max_failures == 0 || failures < lockout.max_failures as i32 {
+        return None;
+    }
+    Some(chrono::Duration::seconds(lockout.duration_secs as i64))
+}
+
+/// Rough entropy estimate: log2 of the character pool per character, where
+/// a character repeating or continuing a sequence (`aaa`, `abc`, `321`) from
+/// the previous one adds nothing.
+fn password_entropy_bits(password: &str) -> u32 {
+    let has = |f: fn(&char) -> bool| password.chars().any(|c| f(&c));
+    let pool = [
+        (has(char::is_ascii_lowercase), 26),
+        (has(char::is_ascii_uppercase), 26),
+        (has(char::is_ascii_digit), 10),
+        (has(char::is_ascii_punctuation), 32),
+        (has(|c| c.is_whitespace()), 1),
+        (has(|c| !c.is_ascii()), 100),
+    ]
+    .iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, size)| size)
+    .sum::<u32>();
+
+    let mut effective = 0;
+    let mut prev: Option<char> = None;
+    for c in password.chars() {
+        let predictable = prev.is_some_and(|p| (c as i64 - p as i64).abs() <= 1);
+        if !predictable {
+            effective += 1;
+        }
+        prev = Some(c);
+    }
+
+    (effective as f64 * (pool.max(1) as f64).log2()) as u32
 }
 
 /// Hash a password using Argon2id.
@@ -44,71 +215,197 @@ pub fn verify_password(password: &str, hash: &str) -> AppResult<bool> {
         .is_ok())
 }
 
-/// Create a JWT token for a user (RS256 — requires private key).
+/// Get the HS256 shared secret, failing if it isn't configured.
+fn hs256_secret(config: &AuthConfig) -> AppResult<&[u8]> {
+    match config.jwt_secret.as_deref() {
+        Some(secret) if !secret.is_empty() => Ok(secret.as_bytes()),
+        _ => Err(AppError::Internal(anyhow::anyhow!(
+            "jwt_secret not configured — required for HS256 tokens"
+        ))),
+    }
+}
+
+/// Create a JWT token for a user (RS256 — requires private key; HS256 — requires secret).
 pub fn create_token(config: &AuthConfig, user_id: Uuid, username: &str) -> AppResult<String> {
-    let key_path = config.jwt_private_key_path.as_deref().ok_or_else(|| {
-        AppError::Internal(anyhow::anyhow!(
-            "jwt_private_key_path not configured — cannot sign tokens"
-        ))
-    })?;
+    issue_token(
+        config,
+        user_id,
+        username,
+        TokenType::User,
+        config.token_expiry,
+    )
+}
 
-    let pem = std::fs::read(key_path).map_err(|e| {
-        AppError::Internal(anyhow::anyhow!(
-            "Failed to read private key '{}': {}",
-            key_path,
-            e
-        ))
-    })?;
+/// Create a long-lived token for a bot account (`auth.bot_token_expiry`).
+pub fn create_bot_token(config: &AuthConfig, bot_id: Uuid, username: &str) -> AppResult<String> {
+    issue_token(
+        config,
+        bot_id,
+        username,
+        TokenType::Bot,
+        config.bot_token_expiry,
+    )
+}
+
+/// A random opaque refresh token. Only its `hash_refresh_token` is stored.
+pub fn generate_refresh_token() -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::Rng;
 
-    let encoding_key = EncodingKey::from_rsa_pem(&pem)
-        .map_err(|e| AppError::Internal(anyhow::anyhow!("Invalid RSA private key: {}", e)))?;
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
 
+pub fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn issue_token(
+    config: &AuthConfig,
+    user_id: Uuid,
+    username: &str,
+    token_type: TokenType,
+    lifetime_secs: u64,
+) -> AppResult<String> {
     let now = Utc::now().timestamp();
     let claims = Claims {
         sub: user_id.to_string(),
         username: username.to_string(),
         iat: now,
-        exp: now + config.token_expiry as i64,
+        exp: now + lifetime_secs as i64,
+        jti: Uuid::now_v7().to_string(),
+        token_type,
     };
 
-    let token = encode(&Header::new(Algorithm::RS256), &claims, &encoding_key)
+    let (header, encoding_key) = match config.algorithm {
+        JwtAlgorithm::Hs256 => (
+            Header::new(Algorithm::HS256),
+            EncodingKey::from_secret(hs256_secret(config)?),
+        ),
+        JwtAlgorithm::Rs256 => {
+            // The `kid` lets validators pick this key out of the JWKS
+            let mut header = Header::new(Algorithm::RS256);
+            header.kid = signing_jwk(config)?.common.key_id;
+            (header, rsa_encoding_key(config)?)
+        }
+    };
+
+    let token = encode(&header, &claims, &encoding_key)
         .map_err(|e| AppError::Internal(anyhow::anyhow!("Token creation failed: {}", e)))?;
 
     Ok(token)
 }
 
-/// Validate and decode a JWT token (RS256 — requires public key).
-pub fn validate_token(config: &AuthConfig, token: &str) -> AppResult<Claims> {
-    let pem = std::fs::read(&config.jwt_public_key_path).map_err(|e| {
+/// Load the RSA private key used to sign RS256 tokens.
+fn rsa_encoding_key(config: &AuthConfig) -> AppResult<EncodingKey> {
+    let active = config.active_key().and_then(|key| key.private_key_path);
+    let key_path = active.as_deref().ok_or_else(|| {
         AppError::Internal(anyhow::anyhow!(
-            "Failed to read public key '{}': {}",
-            config.jwt_public_key_path,
+            "No private key configured for the active key — cannot sign tokens"
+        ))
+    })?;
+
+    let pem = std::fs::read(key_path).map_err(|e| {
+        AppError::Internal(anyhow::anyhow!(
+            "Failed to read private key '{}': {}",
+            key_path,
             e
         ))
     })?;
 
-    let decoding_key = DecodingKey::from_rsa_pem(&pem)
-        .map_err(|e| AppError::Internal(anyhow::anyhow!("Invalid RSA public key: {}", e)))?;
+    EncodingKey::from_rsa_pem(&pem)
+        .map_err(|e| AppError::Internal(anyhow::anyhow!("Invalid RSA private key: {}", e)))
+}
 
-    let mut validation = Validation::new(Algorithm::RS256);
-    validation.validate_exp = true;
+/// Validate and decode a JWT token (RS256 — requires public key; HS256 — requires secret).
+pub fn validate_token(config: &AuthConfig, token: &str) -> AppResult<Claims> {
+    if config.algorithm == JwtAlgorithm::Hs256 {
+        let decoding_key = DecodingKey::from_secret(hs256_secret(config)?);
+        return decode_claims(token, &decoding_key, Algorithm::HS256);
+    }
 
-    let token_data =
-        decode::<Claims>(token, &decoding_key, &validation).map_err(|_| AppError::Unauthorized)?;
+    validate_token_with_jwks(&published_keys(config)?, token)
+}
 
-    Ok(token_data.claims)
+/// Validate an RS256 token against a key set, picking the key by the token's
+/// `kid`. Tokens issued before `kid`s existed are tried against every key.
+pub fn validate_token_with_jwks(jwks: &JwkSet, token: &str) -> AppResult<Claims> {
+    let candidates: Vec<&Jwk> = match token_key_id(token) {
+        Some(kid) => jwks.find(&kid).into_iter().collect(),
+        None => jwks.keys.iter().collect(),
+    };
+
+    for jwk in candidates {
+        let decoding_key = DecodingKey::from_jwk(jwk)
+            .map_err(|e| AppError::Internal(anyhow::anyhow!("Invalid JWK: {}", e)))?;
+        if let Ok(claims) = decode_claims(token, &decoding_key, Algorithm::RS256) {
+            return Ok(claims);
+        }
+    }
+    Err(AppError::Unauthorized)
+}
+
+/// The `kid` from a token's header, without validating anything.
+pub fn token_key_id(token: &str) -> Option<String> {
+    decode_header(token).ok()?.kid
+}
+
+/// The RSA public keys tokens may be signed with, active key first.
+pub fn published_keys(config: &AuthConfig) -> AppResult<JwkSet> {
+    let mut keys = config.rsa_keys();
+    keys.sort_by_key(|key| !key.active);
+    let keys = keys
+        .iter()
+        .map(|key| rsa_public_jwk(&read_pem(&key.public_key_path)?))
+        .collect::<AppResult<_>>()?;
+    Ok(JwkSet { keys })
+}
+
+fn signing_jwk(config: &AuthConfig) -> AppResult<Jwk> {
+    rsa_public_jwk(&read_public_key_pem(config)?)
 }
 
-/// Validate a token using a raw PEM public key (for Community mode with fetched key).
-pub fn validate_token_with_public_key(public_key_pem: &[u8], token: &str) -> AppResult<Claims> {
-    let decoding_key = DecodingKey::from_rsa_pem(public_key_pem)
+/// Convert an RSA public key PEM (SPKI or PKCS#1) into a JWK whose `kid` is
+/// its RFC 7638 thumbprint, so every instance derives the same id.
+pub fn rsa_public_jwk(pem: &str) -> AppResult<Jwk> {
+    let key = RsaPublicKey::from_public_key_pem(pem)
+        .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
         .map_err(|e| AppError::Internal(anyhow::anyhow!("Invalid RSA public key: {}", e)))?;
+    let n = URL_SAFE_NO_PAD.encode(key.n().to_bytes_be());
+    let e = URL_SAFE_NO_PAD.encode(key.e().to_bytes_be());
+
+    // Thumbprint input: the required members in lexicographic order, no whitespace
+    let thumbprint = Sha256::digest(format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n));
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(KeyAlgorithm::RS256),
+            key_id: Some(URL_SAFE_NO_PAD.encode(thumbprint)),
+            ..Default::default()
+        },
+        algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n,
+            e,
+        }),
+    })
+}
 
-    let mut validation = Validation::new(Algorithm::RS256);
+/// Decode and validate claims (including expiry) with the given key and algorithm.
+fn decode_claims(
+    token: &str,
+    decoding_key: &DecodingKey,
+    algorithm: Algorithm,
+) -> AppResult<Claims> {
+    let mut validation = Validation::new(algorithm);
     validation.validate_exp = true;
 
     let token_data =
-        decode::<Claims>(token, &decoding_key, &validation).map_err(|_| AppError::Unauthorized)?;
+        decode::<Claims>(token, decoding_key, &validation).map_err(|_| AppError::Unauthorized)?;
 
     Ok(token_data.claims)
 }
@@ -119,26 +416,58 @@ pub fn user_id_from_claims(claims: &Claims) -> AppResult<Uuid> {
         .map_err(|_| AppError::Internal(anyhow::anyhow!("Invalid user ID in token")))
 }
 
-/// Read the public key PEM as a string (for the public-key endpoint).
+/// Read the active key's public PEM as a string (for the public-key endpoint).
 pub fn read_public_key_pem(config: &AuthConfig) -> AppResult<String> {
-    std::fs::read_to_string(&config.jwt_public_key_path).map_err(|e| {
+    let key = config
+        .active_key()
+        .ok_or_else(|| AppError::Internal(anyhow::anyhow!("No active signing key configured")))?;
+    read_pem(&key.public_key_path)
+}
+
+fn read_pem(path: &str) -> AppResult<String> {
+    std::fs::read_to_string(path).map_err(|e| {
         AppError::Internal(anyhow::anyhow!(
             "Failed to read public key '{}': {}",
-            config.jwt_public_key_path,
+            path,
             e
         ))
     })
 }
 
-/// Auto-generate an RSA keypair using the `openssl` CLI if the key files don't exist.
-/// Called on startup in Auth Hub / Standalone modes.
+/// Auto-generate missing RSA keys using the `openssl` CLI. Called on startup
+/// in Auth Hub / Standalone modes. HS256 needs no keypair.
+///
+/// Every configured key that has a private key path is generated if absent,
+/// so rotating means adding a new active key — existing keys are never
+/// overwritten and retired public keys stay published.
 pub fn ensure_keypair(config: &AuthConfig) -> Result<()> {
-    let private_path = match config.jwt_private_key_path.as_deref() {
-        Some(p) => p,
-        None => return Ok(()), // Community mode — no private key needed
-    };
-    let public_path = &config.jwt_public_key_path;
+    if config.algorithm == JwtAlgorithm::Hs256 {
+        if hs256_secret(config).is_err() {
+            anyhow::bail!("auth.algorithm is HS256 but auth.jwt_secret is not set");
+        }
+        tracing::info!("Using HS256 tokens — skipping RSA keypair generation");
+        return Ok(());
+    }
+
+    for key in config.rsa_keys() {
+        match key.private_key_path.as_deref() {
+            Some(private_path) => generate_keypair(private_path, &key.public_key_path)?,
+            // Community mode — no private key needed
+            None if key.active => {}
+            None => {
+                if !Path::new(&key.public_key_path).exists() {
+                    anyhow::bail!("Retired public key '{}' not found", key.public_key_path);
+                }
+            }
+        }
+    }
 
+    Ok(())
+}
+
+/// Create whichever halves of a keypair don't exist yet. An existing private
+/// key is never replaced; a missing public key is derived from it.
+fn generate_keypair(private_path: &str, public_path: &str) -> Result<()> {
     // If both files exist, nothing to do
     if Path::new(private_path).exists() && Path::new(public_path).exists() {
         tracing::info!(
@@ -160,36 +489,38 @@ pub fn ensure_keypair(config: &AuthConfig) -> Result<()> {
     }
 
     // Generate private key
-    let gen_priv = std::process::Command::new("openssl")
-        .args(["genrsa", "-out", private_path, "2048"])
-        .output();
+    if !Path::new(private_path).exists() {
+        let gen_priv = std::process::Command::new("openssl")
+            .args(["genrsa", "-out", private_path, "2048"])
+            .output();
 
-    match gen_priv {
-        Ok(output) if output.status.success() => {
-            tracing::info!("Private key written to '{}'", private_path);
-        }
-        Ok(output) => {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!(
-                "openssl genrsa failed: {}\n\
-                 Please generate keys manually:\n  \
-                 openssl genrsa -out {} 2048\n  \
-                 openssl rsa -in {} -pubout -out {}",
-                stderr,
-                private_path,
-                private_path,
-                public_path
-            );
-        }
-        Err(_) => {
-            anyhow::bail!(
-                "openssl not found. Please generate RSA keys manually:\n  \
-                 openssl genrsa -out {} 2048\n  \
-                 openssl rsa -in {} -pubout -out {}",
-                private_path,
-                private_path,
-                public_path
-            );
+        match gen_priv {
+            Ok(output) if output.status.success() => {
+                tracing::info!("Private key written to '{}'", private_path);
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                anyhow::bail!(
+                    "openssl genrsa failed: {}\n\
+                     Please generate keys manually:\n  \
+                     openssl genrsa -out {} 2048\n  \
+                     openssl rsa -in {} -pubout -out {}",
+                    stderr,
+                    private_path,
+                    private_path,
+                    public_path
+                );
+            }
+            Err(_) => {
+                anyhow::bail!(
+                    "openssl not found. Please generate RSA keys manually:\n  \
+                     openssl genrsa -out {} 2048\n  \
+                     openssl rsa -in {} -pubout -out {}",
+                    private_path,
+                    private_path,
+                    public_path
+                );
+            }
         }
     }
 
@@ -206,3 +537,190 @@ pub fn ensure_keypair(config: &AuthConfig) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hs256_config() -> AuthConfig {
+        AuthConfig {
+            algorithm: JwtAlgorithm::Hs256,
+            jwt_secret: Some("test-secret".to_string()),
+            jwt_private_key_path: None,
+            jwt_public_key_path: String::new(),
+            keys: Vec::new(),
+            token_expiry: 60,
+            bot_token_expiry: 3600,
+            refresh_token_expiry: 86400,
+            allow_local_registration: true,
+            admins: Vec::new(),
+            rate_limit: Default::default(),
+            password_policy: Default::default(),
+            lockout: Default::default(),
+            token_cache_sweep_secs: 120,
+        }
+    }
+
+    #[test]
+    fn test_normalize_username() {
+        assert_eq!(normalize_username("alice.b-2").unwrap(), "alice.b-2");
+        // Fullwidth forms fold to ASCII
+        assert_eq!(normalize_username("ａｌｉｃｅ").unwrap(), "alice");
+        // A Cyrillic "а" doesn't pass for a Latin one
+        assert!(normalize_username("\u{430}lice").is_err());
+        assert!(normalize_username("al ice").is_err());
+        assert!(normalize_username("al\u{0}ice").is_err());
+        assert!(normalize_username("_alice").is_err());
+        assert!(normalize_username("alice.").is_err());
+        assert!(normalize_username("al..ice").is_err());
+        assert!(normalize_username("ab").is_err());
+    }
+
+    #[test]
+    fn test_normalize_username_rejects_reserved() {
+        assert!(normalize_username("system").is_err());
+        assert!(normalize_username("SYSTEM").is_err());
+        assert!(normalize_username("ad_min").is_err());
+        assert!(normalize_username("ｓｙｓｔｅｍ").is_err());
+        assert!(normalize_username("systematic").is_ok());
+    }
+
+    #[test]
+    fn test_password_policy_lists_unmet_requirements() {
+        let policy = PasswordPolicyConfig {
+            min_length: 10,
+            require_mixed_case: true,
+            require_digit: true,
+            min_entropy_bits: 0,
+        };
+        assert!(check_password_policy(&policy, "Correct7Horse").is_ok());
+
+        let Err(AppError::BadRequest(message)) = check_password_policy(&policy, "short") else {
+            panic!("expected a policy error");
+        };
+        assert!(message.contains("at least 10 characters"));
+        assert!(message.contains("upper and lower case"));
+        assert!(message.contains("a digit"));
+
+        // The default only asks for eight characters
+        assert!(check_password_policy(&Default::default(), "aaaaaaaa").is_ok());
+    }
+
+    #[test]
+    fn test_rotated_refresh_token_is_stored_under_a_new_hash() {
+        let token = generate_refresh_token();
+        let replacement = generate_refresh_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, replacement);
+        // The stored hash must be reproducible from the token the client sends
+        assert_eq!(hash_refresh_token(&token), hash_refresh_token(&token));
+        assert_ne!(hash_refresh_token(&token), hash_refresh_token(&replacement));
+        assert_ne!(hash_refresh_token(&token), token);
+    }
+
+    #[test]
+    fn test_failed_logins_lock_the_account_at_the_limit() {
+        let lockout = LockoutConfig {
+            max_failures: 3,
+            duration_secs: 900,
+        };
+        assert_eq!(lockout_duration(&lockout, 1), None);
+        assert_eq!(lockout_duration(&lockout, 2), None);
+        assert_eq!(
+            lockout_duration(&lockout, 3),
+            Some(chrono::Duration::minutes(15))
+        );
+        // Failures racing past the limit still lock
+        assert!(lockout_duration(&lockout, 4).is_some());
+
+        let disabled = LockoutConfig {
+            max_failures: 0,
+            ..lockout
+        };
+        assert_eq!(lockout_duration(&disabled, 100), None);
+    }
+
+    #[test]
+    fn test_password_entropy_ignores_repeats_and_sequences() {
+        let policy = PasswordPolicyConfig {
+            min_length: 0,
+            require_mixed_case: false,
+            require_digit: false,
+            min_entropy_bits: 40,
+        };
+        assert!(check_password_policy(&policy, "aaaaaaaaaaaaaaaaaaaa").is_err());
+        assert!(check_password_policy(&policy, "abcdefghijklmnopqrst").is_err());
+        assert!(check_password_policy(&policy, "98765432109876543210").is_err());
+        assert!(check_password_policy(&policy, "tq8;Vm2#pw").is_ok());
+    }
+
+    #[test]
+    fn test_hs256_roundtrip() {
+        let config = hs256_config();
+        let user_id = Uuid::now_v7();
+
+        let token = create_token(&config, user_id, "alice").unwrap();
+        let claims = validate_token(&config, &token).unwrap();
+
+        assert_eq!(user_id_from_claims(&claims).unwrap(), user_id);
+        assert_eq!(claims.username, "alice");
+        assert!(!claims.jti.is_empty());
+        assert_eq!(claims.token_type, TokenType::User);
+    }
+
+    #[test]
+    fn test_bot_token_is_long_lived_and_typed() {
+        let config = hs256_config();
+        let token = create_bot_token(&config, Uuid::now_v7(), "helper-bot").unwrap();
+        let claims = validate_token(&config, &token).unwrap();
+
+        assert!(claims.token_type.is_bot());
+        assert_eq!(claims.exp - claims.iat, config.bot_token_expiry as i64);
+
+        // Tokens minted before the claim existed are user tokens
+        let legacy: Claims = serde_json::from_value(serde_json::json!({
+            "sub": Uuid::now_v7().to_string(),
+            "username": "alice",
+            "iat": 0,
+            "exp": 1,
+        }))
+        .unwrap();
+        assert_eq!(legacy.token_type, TokenType::User);
+    }
+
+    #[test]
+    fn test_jwk_kid_is_rfc7638_thumbprint() {
+        use rsa::pkcs8::EncodePublicKey;
+        use rsa::BigUint;
+
+        // Example key from RFC 7638, section 3.1
+        let n = "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw";
+        let key = RsaPublicKey::new(
+            BigUint::from_bytes_be(&URL_SAFE_NO_PAD.decode(n).unwrap()),
+            BigUint::from(65537u32),
+        )
+        .unwrap();
+        let pem = key.to_public_key_pem(Default::default()).unwrap();
+
+        let jwk = rsa_public_jwk(&pem).unwrap();
+        assert_eq!(
+            jwk.common.key_id.as_deref(),
+            Some("NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs")
+        );
+        assert!(
+            matches!(&jwk.algorithm, AlgorithmParameters::RSA(rsa) if rsa.n == n && rsa.e == "AQAB")
+        );
+    }
+
+    #[test]
+    fn test_hs256_wrong_secret_rejected() {
+        let token = create_token(&hs256_config(), Uuid::now_v7(), "alice").unwrap();
+
+        let mut other = hs256_config();
+        other.jwt_secret = Some("other-secret".to_string());
+        assert!(matches!(
+            validate_token(&other, &token),
+            Err(AppError::Unauthorized)
+        ));
+    }
+}
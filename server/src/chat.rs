@@ -6,8 +6,9 @@
 /// and the database. It's responsible for:
 /// - Message validation and sanitization
 /// - Mention parsing (@user, @role, @channel)
+/// - Custom emoji references (<:name:id>)
 /// - Reaction management
-/// - Message search (via Meilisearch when available)
+/// - Message search (Postgres full-text, see `db::messages::search`)
 use uuid::Uuid;
 
 use crate::error::{AppError, AppResult};
@@ -18,6 +19,43 @@ pub const MAX_MESSAGE_LENGTH: usize = 4000;
 /// Maximum number of reactions per message.
 pub const MAX_REACTIONS_PER_MESSAGE: usize = 20;
 
+/// Maximum custom status length (in characters).
+pub const MAX_CUSTOM_STATUS_LENGTH: usize = 128;
+
+/// Maximum number of attachments per message.
+pub const MAX_ATTACHMENTS_PER_MESSAGE: usize = 10;
+
+/// Maximum nickname length (in characters).
+pub const MAX_NICKNAME_LENGTH: usize = 32;
+
+/// Maximum display name length (in characters).
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 32;
+
+/// Maximum channel name length (in characters).
+pub const MAX_CHANNEL_NAME_LENGTH: usize = 100;
+
+/// Maximum channel topic length (in characters).
+pub const MAX_CHANNEL_TOPIC_LENGTH: usize = 1024;
+
+/// Maximum webhook name length (in characters).
+pub const MAX_WEBHOOK_NAME_LENGTH: usize = 80;
+
+/// Maximum length of a webhook avatar URL.
+pub const MAX_AVATAR_URL_LENGTH: usize = 2048;
+
+/// Longest slow-mode cooldown a channel can have (6 hours).
+pub const MAX_SLOW_MODE_SECS: i32 = 6 * 60 * 60;
+
+/// Maximum number of pinned messages per channel.
+pub const MAX_PINS_PER_CHANNEL: i64 = 50;
+
+/// Maximum number of custom emojis per server.
+pub const MAX_EMOJIS_PER_SERVER: usize = 50;
+
+/// Minimum and maximum custom emoji name length (in characters).
+pub const MIN_EMOJI_NAME_LENGTH: usize = 2;
+pub const MAX_EMOJI_NAME_LENGTH: usize = 32;
+
 /// Validate a message before storing/sending.
 pub fn validate_message(content: &str) -> AppResult<()> {
     if content.is_empty() {
@@ -35,7 +73,8 @@ pub fn validate_message(content: &str) -> AppResult<()> {
 /// Parse mentions from message content.
 /// Returns a list of mentioned user IDs.
 ///
-/// Mention format: <@user_id> for users, <@&role_id> for roles, <#channel_id> for channels
+/// Mention format: <@user_id> for users, <@&role_id> for roles, <#channel_id> for channels,
+/// and plain @everyone / @here
 pub fn parse_mentions(content: &str) -> Vec<MentionType> {
     let mut mentions = Vec::new();
     let mut chars = content.chars().peekable();
@@ -65,6 +104,22 @@ pub fn parse_mentions(content: &str) -> Vec<MentionType> {
                     mentions.push(MentionType::Channel(uuid));
                 }
             }
+        } else if ch == '@' {
+            for (word, mention) in [
+                ("everyone", MentionType::Everyone),
+                ("here", MentionType::Here),
+            ] {
+                let mut ahead = chars.clone();
+                let matches = word.chars().all(|w| ahead.next() == Some(w))
+                    && !ahead
+                        .peek()
+                        .is_some_and(|c| c.is_alphanumeric() || *c == '_');
+                if matches {
+                    chars = ahead;
+                    mentions.push(mention);
+                    break;
+                }
+            }
         }
     }
 
@@ -76,6 +131,58 @@ pub enum MentionType {
     User(Uuid),
     Role(Uuid),
     Channel(Uuid),
+    /// Every member of the server
+    Everyone,
+    /// Members of the server who are currently online
+    Here,
+}
+
+/// Custom emoji names are 2–32 ASCII letters, digits and underscores.
+pub fn validate_emoji_name(name: &str) -> AppResult<()> {
+    if !is_valid_emoji_name(name) {
+        return Err(AppError::BadRequest(format!(
+            "Emoji names must be {}-{} letters, digits or underscores",
+            MIN_EMOJI_NAME_LENGTH, MAX_EMOJI_NAME_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+fn is_valid_emoji_name(name: &str) -> bool {
+    (MIN_EMOJI_NAME_LENGTH..=MAX_EMOJI_NAME_LENGTH).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse a single custom emoji reference, `<:name:id>`, into its name and id.
+pub fn parse_custom_emoji(s: &str) -> Option<(&str, Uuid)> {
+    let (name, id) = s.strip_prefix("<:")?.strip_suffix('>')?.split_once(':')?;
+    if !is_valid_emoji_name(name) {
+        return None;
+    }
+    Some((name, Uuid::parse_str(id).ok()?))
+}
+
+/// Ids of the custom emojis referenced in message content, in order of first use.
+pub fn parse_custom_emojis(content: &str) -> Vec<Uuid> {
+    let mut ids = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("<:") {
+        rest = &rest[start..];
+        let Some(end) = rest.find('>') else { break };
+        match parse_custom_emoji(&rest[..=end]) {
+            Some((_, id)) => {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+                rest = &rest[end + 1..];
+            }
+            // Not a reference; a later `<:` may still start one
+            None => rest = &rest[2..],
+        }
+    }
+
+    ids
 }
 
 /// Sanitize message content — strip control characters, normalize whitespace.
@@ -108,6 +215,34 @@ mod tests {
         assert!(validate_message(&long).is_err());
     }
 
+    #[test]
+    fn test_parse_mentions() {
+        let id = Uuid::now_v7();
+        let mentions = parse_mentions(&format!("hey <@{}> and @here, not @everyones", id));
+        assert_eq!(mentions, vec![MentionType::User(id), MentionType::Here]);
+        assert_eq!(parse_mentions("@everyone!"), vec![MentionType::Everyone]);
+    }
+
+    #[test]
+    fn test_parse_custom_emojis() {
+        let id = Uuid::now_v7();
+        let reference = format!("<:party_parrot:{}>", id);
+        assert_eq!(parse_custom_emoji(&reference), Some(("party_parrot", id)));
+        assert_eq!(parse_custom_emoji(&format!("<:a:{}>", id)), None);
+        assert_eq!(parse_custom_emoji("<:party:not-an-id>"), None);
+
+        let content = format!("<:x <:bad name:{id}> {reference} and again {reference}");
+        assert_eq!(parse_custom_emojis(&content), vec![id]);
+    }
+
+    #[test]
+    fn test_validate_emoji_name() {
+        assert!(validate_emoji_name("thumbs_up2").is_ok());
+        assert!(validate_emoji_name("x").is_err());
+        assert!(validate_emoji_name("no-dashes").is_err());
+        assert!(validate_emoji_name(&"a".repeat(MAX_EMOJI_NAME_LENGTH + 1)).is_err());
+    }
+
     #[test]
     fn test_sanitize_strips_control() {
         let input = "Hello\x00World\x01!";
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use serde::Deserialize;
+use std::net::IpAddr;
 use std::path::Path;
 
 // ─── Server Mode ────────────────────────────────────────────────────────────
@@ -38,6 +39,16 @@ pub struct AppConfig {
     pub auth: AuthConfig,
     pub identity: IdentityConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub uploads: UploadConfig,
+    #[serde(default)]
+    pub presence: PresenceConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub messages: MessageConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -45,12 +56,64 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub public_url: String,
+    /// Close a WebSocket that sends nothing (heartbeats included) for this long.
+    #[serde(default = "default_heartbeat_timeout_secs")]
+    pub heartbeat_timeout_secs: u64,
+    /// How long a dropped WebSocket session can be resumed before the user
+    /// goes offline and the session is discarded.
+    #[serde(default = "default_resume_window_secs")]
+    pub resume_window_secs: u64,
+    /// Snowflake worker id (0-1023); must differ between instances sharing a database.
+    #[serde(default = "default_worker_id")]
+    pub worker_id: u16,
+    /// Outgoing frames queued per WebSocket session (and kept for resume)
+    /// before a slow client starts missing events.
+    #[serde(default = "default_ws_buffer_size")]
+    pub ws_buffer_size: usize,
+    /// Largest message a client may send over the WebSocket, in bytes.
+    #[serde(default = "default_ws_max_message_size")]
+    pub ws_max_message_size: usize,
+}
+
+fn default_worker_id() -> u16 {
+    1
+}
+
+fn default_ws_buffer_size() -> usize {
+    256
+}
+
+fn default_ws_max_message_size() -> usize {
+    64 * 1024
+}
+
+fn default_heartbeat_timeout_secs() -> u64 {
+    45
+}
+
+fn default_resume_window_secs() -> u64 {
+    30
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct DatabaseConfig {
     pub url: String,
     pub max_connections: u32,
+    /// Connection attempts on startup before giving up (the database may
+    /// still be starting, e.g. under docker-compose).
+    #[serde(default = "default_connect_attempts")]
+    pub connect_attempts: u32,
+    /// Delay before the first retry; doubles with each further attempt.
+    #[serde(default = "default_connect_retry_base_ms")]
+    pub connect_retry_base_ms: u64,
+}
+
+fn default_connect_attempts() -> u32 {
+    10
+}
+
+fn default_connect_retry_base_ms() -> u64 {
+    500
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -68,26 +131,293 @@ pub struct VoiceConfig {
     /// Set via ANTARCTICOM__VOICE__PUBLIC_IP env var.
     #[serde(default)]
     pub public_ip: Option<String>,
+    /// TURN relay for clients behind symmetric NATs or strict firewalls.
+    /// Without it only STUN is offered.
+    #[serde(default)]
+    pub turn: Option<TurnConfig>,
+    /// Hard cap on SFU participants per voice channel, on top of each
+    /// channel's own user limit. Unset means no cap.
+    #[serde(default)]
+    pub max_channel_participants: Option<u32>,
+    /// Label for this SFU node, sent to clients joining voice (e.g. "eu-west").
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Public URL clients should reach this SFU node at, for multi-region
+    /// deployments where voice isn't served by the API host.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+/// Bitrates Opus can encode at, in kbps.
+const OPUS_MIN_KBPS: u32 = 6;
+const OPUS_MAX_KBPS: u32 = 510;
+
+impl VoiceConfig {
+    /// `(min_bitrate, max_bitrate)` in kbps, kept within what Opus supports
+    /// and with the minimum never above the maximum.
+    pub fn bitrate_range(&self) -> (u32, u32) {
+        let max = self.max_bitrate.clamp(OPUS_MIN_KBPS, OPUS_MAX_KBPS);
+        let min = self.min_bitrate.clamp(OPUS_MIN_KBPS, max);
+        (min, max)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TurnConfig {
+    /// e.g. ["turn:turn.example.com:3478", "turns:turn.example.com:5349"]
+    pub urls: Vec<String>,
+    pub username: String,
+    pub credential: String,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize)]
 pub struct TlsConfig {
-    pub cert_path: String,
-    pub key_path: String,
+    /// PEM certificate chain; together with `key_path` enables native HTTPS.
+    #[serde(default)]
+    pub cert_path: Option<String>,
+    /// PEM private key for `cert_path`.
+    #[serde(default)]
+    pub key_path: Option<String>,
+    /// Obtain and renew a certificate for `acme_domain` from Let's Encrypt.
     pub acme_enabled: bool,
     pub acme_domain: String,
+    /// Contact email registered with the ACME account.
+    #[serde(default)]
+    pub acme_contact: Option<String>,
+    /// Where the ACME account key and issued certificates are kept across restarts.
+    #[serde(default = "default_acme_cache_dir")]
+    pub acme_cache_dir: String,
+    /// Use the Let's Encrypt staging directory (untrusted certs, generous rate limits).
+    #[serde(default)]
+    pub acme_staging: bool,
+}
+
+fn default_acme_cache_dir() -> String {
+    "data/acme".to_string()
+}
+
+impl TlsConfig {
+    /// Certificate and key paths, if both are configured (empty counts as unset).
+    pub fn pem_paths(&self) -> Option<(&str, &str)> {
+        match (self.cert_path.as_deref(), self.key_path.as_deref()) {
+            (Some(cert), Some(key)) if !cert.is_empty() && !key.is_empty() => Some((cert, key)),
+            _ => None,
+        }
+    }
+}
+
+/// JWT signing algorithm.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+pub enum JwtAlgorithm {
+    /// RSA keypair — community servers verify with the hub's public key (default).
+    #[default]
+    #[serde(rename = "RS256")]
+    Rs256,
+    /// Shared secret — no keypair (or openssl) needed; for single-instance deployments.
+    #[serde(rename = "HS256")]
+    Hs256,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize)]
 pub struct AuthConfig {
-    /// Path to the RSA private key PEM (required for Auth Hub / Standalone).
+    /// Token signing algorithm: "RS256" (default) or "HS256".
+    #[serde(default)]
+    pub algorithm: JwtAlgorithm,
+    /// Shared secret used to sign and verify tokens (required for HS256).
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+    /// Path to the RSA private key PEM (required for Auth Hub / Standalone
+    /// unless `keys` is set).
     pub jwt_private_key_path: Option<String>,
-    /// Path to the RSA public key PEM (required for all modes).
+    /// Path to the RSA public key PEM (required for all modes with RS256).
+    #[serde(default)]
     pub jwt_public_key_path: String,
+    /// RSA keys for rotation. Replaces the single `jwt_*_key_path` pair
+    /// when non-empty; exactly one must be `active`.
+    #[serde(default)]
+    pub keys: Vec<SigningKeyConfig>,
+    /// Lifetime of access tokens in seconds. Kept short: clients renew them
+    /// with their refresh token.
+    #[serde(default = "default_token_expiry")]
     pub token_expiry: u64,
+    /// Lifetime of bot account tokens in seconds.
+    #[serde(default = "default_bot_token_expiry")]
+    pub bot_token_expiry: u64,
+    /// Lifetime of refresh tokens in seconds.
+    #[serde(default = "default_refresh_token_expiry")]
+    pub refresh_token_expiry: u64,
+    /// When false, only holders of a registration invite can sign up.
     pub allow_local_registration: bool,
+    /// Usernames of the instance admins (`users.is_admin`), synced at startup
+    /// and granted on registration: accounts missing from the list lose the flag.
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// Brute-force protection for login/register.
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Requirements for new passwords (registration and password changes).
+    #[serde(default)]
+    pub password_policy: PasswordPolicyConfig,
+    /// Per-account lockout after repeated failed logins.
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+    /// How often expired entries are swept out of the validated-token cache.
+    #[serde(default = "default_token_cache_sweep_secs")]
+    pub token_cache_sweep_secs: u64,
+}
+
+/// One RSA key in `auth.keys`. The active key signs new tokens; the others
+/// are published in the JWKS and still accepted, so a retired key's tokens
+/// stay valid until they expire and a new key can be announced early.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SigningKeyConfig {
+    /// Private key PEM. Needed for the active key; generated on startup if
+    /// missing. Retired keys only need their public half.
+    #[serde(default)]
+    pub private_key_path: Option<String>,
+    pub public_key_path: String,
+    #[serde(default)]
+    pub active: bool,
+}
+
+impl AuthConfig {
+    /// The RSA key set: `keys`, or the legacy single key pair as the active key.
+    pub fn rsa_keys(&self) -> Vec<SigningKeyConfig> {
+        if !self.keys.is_empty() {
+            return self.keys.clone();
+        }
+        vec![SigningKeyConfig {
+            private_key_path: self.jwt_private_key_path.clone(),
+            public_key_path: self.jwt_public_key_path.clone(),
+            active: true,
+        }]
+    }
+
+    /// The key new tokens are signed with.
+    pub fn active_key(&self) -> Option<SigningKeyConfig> {
+        self.rsa_keys().into_iter().find(|key| key.active)
+    }
+
+    /// Whether `username` is listed in `admins`. Usernames compare
+    /// case-insensitively, as at login.
+    pub fn is_admin(&self, username: &str) -> bool {
+        self.admins
+            .iter()
+            .any(|admin| admin.eq_ignore_ascii_case(username))
+    }
+}
+
+fn default_token_cache_sweep_secs() -> u64 {
+    120
+}
+
+fn default_token_expiry() -> u64 {
+    // One hour
+    60 * 60
+}
+
+fn default_bot_token_expiry() -> u64 {
+    // One year
+    365 * 24 * 60 * 60
+}
+
+fn default_refresh_token_expiry() -> u64 {
+    // 30 days
+    30 * 24 * 60 * 60
+}
+
+/// Per-IP limit on login/register attempts (token bucket).
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitConfig {
+    /// Attempts allowed per window (also the burst size).
+    #[serde(default = "default_rate_limit_attempts")]
+    pub attempts: u32,
+    /// Window length in seconds over which attempts refill.
+    #[serde(default = "default_rate_limit_window_secs")]
+    pub window_secs: u64,
+    /// Reverse proxies whose `X-Forwarded-For` header names the client.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+fn default_rate_limit_attempts() -> u32 {
+    10
+}
+
+fn default_rate_limit_window_secs() -> u64 {
+    60
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            attempts: default_rate_limit_attempts(),
+            window_secs: default_rate_limit_window_secs(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+/// Locks an account after consecutive failed logins, from any IP.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LockoutConfig {
+    /// Consecutive failures that lock the account; 0 disables lockout.
+    #[serde(default = "default_lockout_max_failures")]
+    pub max_failures: u32,
+    /// How long a lock lasts in seconds.
+    #[serde(default = "default_lockout_duration_secs")]
+    pub duration_secs: u64,
+}
+
+fn default_lockout_max_failures() -> u32 {
+    10
+}
+
+fn default_lockout_duration_secs() -> u64 {
+    15 * 60
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: default_lockout_max_failures(),
+            duration_secs: default_lockout_duration_secs(),
+        }
+    }
+}
+
+/// What a new password must satisfy. Existing passwords are never rechecked.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordPolicyConfig {
+    /// Minimum length in characters.
+    #[serde(default = "default_password_min_length")]
+    pub min_length: usize,
+    /// Require both an upper and a lower case letter.
+    #[serde(default)]
+    pub require_mixed_case: bool,
+    /// Require at least one digit.
+    #[serde(default)]
+    pub require_digit: bool,
+    /// Minimum estimated entropy in bits; 0 disables the check.
+    #[serde(default)]
+    pub min_entropy_bits: u32,
+}
+
+fn default_password_min_length() -> usize {
+    8
+}
+
+impl Default for PasswordPolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_length: default_password_min_length(),
+            require_mixed_case: false,
+            require_digit: false,
+            min_entropy_bits: 0,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -107,6 +437,159 @@ pub struct LoggingConfig {
     pub format: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct UploadConfig {
+    /// Maximum size of a single message attachment in bytes.
+    #[serde(default = "default_max_attachment_size")]
+    pub max_attachment_size: usize,
+}
+
+fn default_max_attachment_size() -> usize {
+    25 * 1024 * 1024 // 25 MB
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            max_attachment_size: default_max_attachment_size(),
+        }
+    }
+}
+
+/// Instance-wide anti-spam limit on sending messages, per user across all
+/// channels (token bucket). Separate from per-channel slow mode.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageConfig {
+    /// Messages allowed per window (also the burst size).
+    #[serde(default = "default_message_rate_limit")]
+    pub rate_limit: u32,
+    /// Window length in seconds over which the allowance refills.
+    #[serde(default = "default_message_rate_limit_window_secs")]
+    pub rate_limit_window_secs: u64,
+}
+
+fn default_message_rate_limit() -> u32 {
+    5
+}
+
+fn default_message_rate_limit_window_secs() -> u64 {
+    5
+}
+
+impl Default for MessageConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit: default_message_rate_limit(),
+            rate_limit_window_secs: default_message_rate_limit_window_secs(),
+        }
+    }
+}
+
+/// Caps on what a single user or server can create, against resource abuse.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LimitsConfig {
+    /// Servers a user may own.
+    #[serde(default = "default_max_servers_per_user")]
+    pub max_servers_per_user: i64,
+    /// Channels (categories included) a server may have.
+    #[serde(default = "default_max_channels_per_server")]
+    pub max_channels_per_server: i64,
+    /// Bot accounts a user may own.
+    #[serde(default = "default_max_bots_per_user")]
+    pub max_bots_per_user: i64,
+}
+
+fn default_max_servers_per_user() -> i64 {
+    100
+}
+
+fn default_max_channels_per_server() -> i64 {
+    500
+}
+
+fn default_max_bots_per_user() -> i64 {
+    10
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_servers_per_user: default_max_servers_per_user(),
+            max_channels_per_server: default_max_channels_per_server(),
+            max_bots_per_user: default_max_bots_per_user(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PresenceConfig {
+    /// Online users with no client activity for this long are shown as Idle.
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    5 * 60
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: default_idle_timeout_secs(),
+        }
+    }
+}
+
+/// Where avatars and attachments are kept.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageConfig {
+    /// Root directory for the local filesystem backend.
+    #[serde(default = "default_storage_path")]
+    pub path: String,
+    /// Store files in an S3-compatible bucket instead of on local disk.
+    #[serde(default)]
+    pub s3: Option<S3Config>,
+}
+
+fn default_storage_path() -> String {
+    "./data".to_string()
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            path: default_storage_path(),
+            s3: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct S3Config {
+    /// e.g. "https://s3.eu-central-1.amazonaws.com" or "http://minio:9000"
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Address objects as `{endpoint}/{bucket}/{key}` (MinIO and most
+    /// self-hosted stores) instead of `{bucket}.{endpoint host}/{key}`.
+    #[serde(default)]
+    pub path_style: bool,
+    /// How long presigned avatar URLs stay valid.
+    #[serde(default = "default_presign_expiry_secs")]
+    pub presign_expiry_secs: u64,
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_presign_expiry_secs() -> u64 {
+    60 * 60
+}
+
 impl AppConfig {
     /// Load configuration from `antarcticom.toml`, with environment variable overrides.
     pub fn load() -> Result<Self> {
@@ -131,6 +614,19 @@ impl AppConfig {
             .build()?;
 
         let config: AppConfig = settings.try_deserialize()?;
+        if config.server.worker_id > 0x3FF {
+            anyhow::bail!(
+                "server.worker_id must be between 0 and 1023, got {}",
+                config.server.worker_id
+            );
+        }
+        let active_keys = config.auth.keys.iter().filter(|key| key.active).count();
+        if !config.auth.keys.is_empty() && active_keys != 1 {
+            anyhow::bail!(
+                "auth.keys must mark exactly one key as active, found {}",
+                active_keys
+            );
+        }
         Ok(config)
     }
 
@@ -144,3 +640,76 @@ impl AppConfig {
         matches!(self.mode, ServerMode::Community | ServerMode::Standalone)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(min_bitrate: u32, max_bitrate: u32) -> VoiceConfig {
+        VoiceConfig {
+            max_sessions: 1,
+            min_bitrate,
+            max_bitrate,
+            public_ip: None,
+            turn: None,
+            max_channel_participants: None,
+            region: None,
+            endpoint: None,
+        }
+    }
+
+    #[test]
+    fn test_bitrate_range_stays_within_opus_limits() {
+        assert_eq!(voice(32, 128).bitrate_range(), (32, 128));
+        assert_eq!(voice(0, 1000).bitrate_range(), (6, 510));
+        // A minimum above the maximum collapses onto it
+        assert_eq!(voice(256, 64).bitrate_range(), (64, 64));
+    }
+
+    #[test]
+    fn test_legacy_key_pair_is_the_active_key() {
+        let mut auth: AuthConfig = toml::from_str(
+            r#"
+            jwt_private_key_path = "old.pem"
+            jwt_public_key_path = "old.pub"
+            token_expiry = 60
+            allow_local_registration = true
+            "#,
+        )
+        .unwrap();
+        let active = auth.active_key().unwrap();
+        assert_eq!(active.private_key_path.as_deref(), Some("old.pem"));
+        assert_eq!(active.public_key_path, "old.pub");
+
+        // An explicit key list replaces the pair
+        auth.keys = vec![
+            SigningKeyConfig {
+                private_key_path: None,
+                public_key_path: "old.pub".to_string(),
+                active: false,
+            },
+            SigningKeyConfig {
+                private_key_path: Some("new.pem".to_string()),
+                public_key_path: "new.pub".to_string(),
+                active: true,
+            },
+        ];
+        assert_eq!(auth.rsa_keys().len(), 2);
+        assert_eq!(auth.active_key().unwrap().public_key_path, "new.pub");
+    }
+
+    #[test]
+    fn test_admins_are_matched_by_username() {
+        let auth: AuthConfig = toml::from_str(
+            r#"
+            jwt_public_key_path = ""
+            token_expiry = 60
+            allow_local_registration = true
+            admins = ["Alice"]
+            "#,
+        )
+        .unwrap();
+        assert!(auth.is_admin("alice"));
+        assert!(!auth.is_admin("alice2"));
+    }
+}
@@ -15,9 +15,16 @@ use anyhow::Result;
 use ring::aead::{self, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
 use ring::rand::{SecureRandom, SystemRandom};
 use ring::signature::{self, Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
 
 // ─── Key Types ──────────────────────────────────────────────────────────────
 
+/// Length of Ed25519 and X25519 public keys.
+pub const PUBLIC_KEY_LENGTH: usize = 32;
+/// Length of an Ed25519 signature.
+pub const SIGNATURE_LENGTH: usize = 64;
+
 /// An identity key pair (Ed25519) — long-term signing key.
 pub struct IdentityKeyPair {
     key_pair: Ed25519KeyPair,
@@ -45,6 +52,13 @@ impl IdentityKeyPair {
     }
 }
 
+/// Whether `key` is a well-formed Ed25519 public key: the right length and a
+/// valid curve point.
+pub fn is_valid_identity_key(key: &[u8]) -> bool {
+    <[u8; PUBLIC_KEY_LENGTH]>::try_from(key)
+        .is_ok_and(|bytes| ed25519_dalek::VerifyingKey::from_bytes(&bytes).is_ok())
+}
+
 /// Verify an Ed25519 signature.
 pub fn verify_signature(public_key: &[u8], message: &[u8], signature_bytes: &[u8]) -> bool {
     let public_key = signature::UnparsedPublicKey::new(&signature::ED25519, public_key);
@@ -54,7 +68,7 @@ pub fn verify_signature(public_key: &[u8], message: &[u8], signature_bytes: &[u8
 // ─── Pre-Key Bundle ─────────────────────────────────────────────────────────
 
 /// A pre-key bundle published to the server for X3DH key agreement.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PreKeyBundle {
     /// Identity public key (Ed25519)
     pub identity_key: Vec<u8>,
@@ -66,6 +80,105 @@ pub struct PreKeyBundle {
     pub one_time_pre_key: Option<Vec<u8>>,
 }
 
+// ─── X3DH Key Agreement ─────────────────────────────────────────────────────
+
+/// HKDF info string binding X3DH output to this protocol.
+const X3DH_INFO: &[u8] = b"Antarcticom X3DH";
+
+/// Convert an Ed25519 identity public key to its X25519 (Montgomery) form so
+/// the same identity can sign pre-keys and take part in Diffie-Hellman.
+pub fn identity_key_to_x25519(identity_key: &[u8]) -> Result<X25519PublicKey> {
+    let bytes: [u8; PUBLIC_KEY_LENGTH] = identity_key
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Identity key must be {} bytes", PUBLIC_KEY_LENGTH))?;
+    let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+        .map_err(|e| anyhow::anyhow!("Invalid identity key: {}", e))?;
+    Ok(X25519PublicKey::from(
+        verifying_key.to_montgomery().to_bytes(),
+    ))
+}
+
+/// The X25519 secret matching `identity_key_to_x25519` for an Ed25519 signing key.
+pub fn identity_secret_to_x25519(signing_key: &ed25519_dalek::SigningKey) -> StaticSecret {
+    StaticSecret::from(signing_key.to_scalar_bytes())
+}
+
+fn x25519_public(bytes: &[u8]) -> Result<X25519PublicKey> {
+    let bytes: [u8; PUBLIC_KEY_LENGTH] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("X25519 key must be {} bytes", PUBLIC_KEY_LENGTH))?;
+    Ok(X25519PublicKey::from(bytes))
+}
+
+/// Feed the concatenated DH outputs through HKDF, prefixed with 32 0xFF bytes
+/// as in the Signal X3DH specification.
+fn x3dh_kdf(dh_outputs: &[[u8; 32]]) -> Result<[u8; 32]> {
+    let mut input = vec![0xFFu8; 32];
+    for dh in dh_outputs {
+        input.extend_from_slice(dh);
+    }
+    derive_key(&input, X3DH_INFO)
+}
+
+/// Initiator side of X3DH, run against a fetched pre-key bundle.
+///
+/// `identity` is the initiator's identity key in X25519 form and `ephemeral`
+/// a fresh key generated for this session; its public half must be sent to
+/// the responder alongside the first message.
+pub fn x3dh_initiator(
+    identity: &StaticSecret,
+    ephemeral: &StaticSecret,
+    bundle: &PreKeyBundle,
+) -> Result<[u8; 32]> {
+    if !verify_signature(
+        &bundle.identity_key,
+        &bundle.signed_pre_key,
+        &bundle.signed_pre_key_signature,
+    ) {
+        anyhow::bail!("Signed pre-key signature is invalid");
+    }
+
+    let their_identity = identity_key_to_x25519(&bundle.identity_key)?;
+    let their_signed_pre_key = x25519_public(&bundle.signed_pre_key)?;
+
+    let mut dh_outputs = vec![
+        identity.diffie_hellman(&their_signed_pre_key).to_bytes(),
+        ephemeral.diffie_hellman(&their_identity).to_bytes(),
+        ephemeral.diffie_hellman(&their_signed_pre_key).to_bytes(),
+    ];
+    if let Some(one_time_pre_key) = &bundle.one_time_pre_key {
+        let their_one_time_pre_key = x25519_public(one_time_pre_key)?;
+        dh_outputs.push(ephemeral.diffie_hellman(&their_one_time_pre_key).to_bytes());
+    }
+
+    x3dh_kdf(&dh_outputs)
+}
+
+/// Responder side of X3DH. `their_identity_key` is the initiator's Ed25519
+/// identity key and `their_ephemeral` the ephemeral public key they sent;
+/// `one_time_pre_key` is the secret for the one-time pre-key they consumed, if any.
+pub fn x3dh_responder(
+    identity: &StaticSecret,
+    signed_pre_key: &StaticSecret,
+    one_time_pre_key: Option<&StaticSecret>,
+    their_identity_key: &[u8],
+    their_ephemeral: &[u8],
+) -> Result<[u8; 32]> {
+    let their_identity = identity_key_to_x25519(their_identity_key)?;
+    let their_ephemeral = x25519_public(their_ephemeral)?;
+
+    let mut dh_outputs = vec![
+        signed_pre_key.diffie_hellman(&their_identity).to_bytes(),
+        identity.diffie_hellman(&their_ephemeral).to_bytes(),
+        signed_pre_key.diffie_hellman(&their_ephemeral).to_bytes(),
+    ];
+    if let Some(one_time_pre_key) = one_time_pre_key {
+        dh_outputs.push(one_time_pre_key.diffie_hellman(&their_ephemeral).to_bytes());
+    }
+
+    x3dh_kdf(&dh_outputs)
+}
+
 // ─── AES-256-GCM Encryption ────────────────────────────────────────────────
 
 /// Encrypt data using AES-256-GCM.
@@ -154,6 +267,18 @@ mod tests {
         assert!(decrypt_aes256gcm(&key2, &ciphertext, &nonce).is_err());
     }
 
+    #[test]
+    fn test_identity_key_validation() {
+        let identity = IdentityKeyPair::generate().unwrap();
+        assert!(is_valid_identity_key(identity.public_key()));
+
+        assert!(!is_valid_identity_key(&identity.public_key()[1..]));
+        // y = 2 isn't the encoding of any point on the curve
+        let mut off_curve = [0u8; PUBLIC_KEY_LENGTH];
+        off_curve[0] = 2;
+        assert!(!is_valid_identity_key(&off_curve));
+    }
+
     #[test]
     fn test_identity_key_sign_verify() {
         let identity = IdentityKeyPair::generate().unwrap();
@@ -165,4 +290,50 @@ mod tests {
         // Tampered message should fail
         assert!(!verify_signature(identity.public_key(), b"Tampered", &sig));
     }
+
+    #[test]
+    fn test_x3dh_roundtrip() {
+        use ed25519_dalek::Signer;
+        use rand::rngs::OsRng;
+
+        let alice_signing = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+        let bob_signing = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+        let alice_identity = identity_secret_to_x25519(&alice_signing);
+        let bob_identity = identity_secret_to_x25519(&bob_signing);
+
+        let bob_signed_pre_key = StaticSecret::random_from_rng(OsRng);
+        let bob_one_time_pre_key = StaticSecret::random_from_rng(OsRng);
+        let spk_public = X25519PublicKey::from(&bob_signed_pre_key)
+            .to_bytes()
+            .to_vec();
+        let bundle = PreKeyBundle {
+            identity_key: bob_signing.verifying_key().to_bytes().to_vec(),
+            signed_pre_key_signature: bob_signing.sign(&spk_public).to_bytes().to_vec(),
+            signed_pre_key: spk_public,
+            one_time_pre_key: Some(
+                X25519PublicKey::from(&bob_one_time_pre_key)
+                    .to_bytes()
+                    .to_vec(),
+            ),
+        };
+
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let alice_key = x3dh_initiator(&alice_identity, &ephemeral, &bundle).unwrap();
+        let bob_key = x3dh_responder(
+            &bob_identity,
+            &bob_signed_pre_key,
+            Some(&bob_one_time_pre_key),
+            alice_signing.verifying_key().as_bytes(),
+            X25519PublicKey::from(&ephemeral).as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(alice_key.len(), 32);
+        assert_eq!(alice_key, bob_key);
+
+        // A tampered signed pre-key is rejected
+        let mut forged = bundle.clone();
+        forged.signed_pre_key[0] ^= 1;
+        assert!(x3dh_initiator(&alice_identity, &ephemeral, &forged).is_err());
+    }
 }
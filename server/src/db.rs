@@ -1,4 +1,6 @@
-use anyhow::Result;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{PgPool, Pool, Postgres};
 
@@ -6,14 +8,70 @@ use crate::config::DatabaseConfig;
 
 pub type DbPool = Pool<Postgres>;
 
-/// Initialize the database connection pool.
+/// Upper bound on the wait between connection attempts.
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Placeholder for accounts without a password (webhook and bot users): a
+/// well-formed hash of random bytes nobody kept, so logging in as one fails
+/// like any wrong password. Login checks unknown accounts against it too.
+pub const NO_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$i0hbaghLUJ5y+OC/FvfPgw$PgNaRugTcXTK1EHIfdLCbN3qrRKfRnfXctYJAxWoCb4";
+
+/// Initialize the database connection pool, retrying with exponential
+/// backoff while the database is unreachable.
 pub async fn init_pool(config: &DatabaseConfig) -> Result<DbPool> {
-    let pool = PgPoolOptions::new()
-        .max_connections(config.max_connections)
-        .connect(&config.url)
-        .await?;
+    let attempts = config.connect_attempts.max(1);
+    let base = Duration::from_millis(config.connect_retry_base_ms);
+
+    let mut attempt = 1;
+    loop {
+        match PgPoolOptions::new()
+            .max_connections(config.max_connections)
+            .connect(&config.url)
+            .await
+        {
+            Ok(pool) => return Ok(pool),
+            Err(e) if attempt < attempts => {
+                let delay = connect_retry_delay(base, attempt);
+                tracing::warn!(
+                    "Database connection attempt {}/{} failed: {}; retrying in {:?}",
+                    attempt,
+                    attempts,
+                    e,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Failed to connect to the database after {} attempts",
+                        attempts
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// `ILIKE` pattern matching strings that start with `prefix` literally.
+fn like_prefix_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
 
-    Ok(pool)
+/// Wait after the `attempt`-th failed connection: `base`, doubling each time.
+fn connect_retry_delay(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_CONNECT_RETRY_DELAY)
 }
 
 /// Run embedded SQL migrations.
@@ -25,11 +83,12 @@ pub async fn run_migrations(pool: &PgPool) -> Result<()> {
 // ─── User Queries ───────────────────────────────────────────────────────────
 
 pub mod users {
+    use chrono::{DateTime, Utc};
     use sqlx::PgPool;
     use uuid::Uuid;
 
     use crate::error::AppResult;
-    use crate::models::User;
+    use crate::models::{PresenceStatus, User};
 
     pub async fn find_by_id(pool: &PgPool, id: Uuid) -> AppResult<Option<User>> {
         let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
@@ -48,17 +107,94 @@ pub mod users {
         Ok(user)
     }
 
+    /// Users ordered by ID, optionally filtered by username/display name prefix.
+    pub async fn list_paginated(
+        pool: &PgPool,
+        after: Option<Uuid>,
+        query: Option<&str>,
+        limit: i64,
+    ) -> AppResult<Vec<User>> {
+        let pattern = query.map(super::like_prefix_pattern);
+        let users = sqlx::query_as::<_, User>(
+            r#"
+            SELECT * FROM users
+            WHERE ($1::UUID IS NULL OR id > $1)
+              AND ($2::TEXT IS NULL OR username ILIKE $2 OR display_name ILIKE $2)
+            ORDER BY id
+            LIMIT $3
+            "#,
+        )
+        .bind(after)
+        .bind(pattern)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+        Ok(users)
+    }
+
+    /// Count a failed login. Returns the failures in a row so far.
+    pub async fn record_failed_login(pool: &PgPool, id: Uuid) -> AppResult<i32> {
+        let failures = sqlx::query_scalar::<_, i32>(
+            "UPDATE users SET failed_logins = failed_logins + 1 WHERE id = $1 RETURNING failed_logins",
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+        Ok(failures)
+    }
+
+    /// Lock an account until `until`, starting its failure count over.
+    pub async fn lock(pool: &PgPool, id: Uuid, until: DateTime<Utc>) -> AppResult<()> {
+        sqlx::query("UPDATE users SET failed_logins = 0, locked_until = $2 WHERE id = $1")
+            .bind(id)
+            .bind(until)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn reset_failed_logins(pool: &PgPool, id: Uuid) -> AppResult<()> {
+        sqlx::query("UPDATE users SET failed_logins = 0, locked_until = NULL WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Make exactly the accounts with the given usernames instance admins,
+    /// revoking the flag from everyone else. Returns how many were granted
+    /// and revoked.
+    pub async fn sync_admins(pool: &PgPool, usernames: &[String]) -> AppResult<(u64, u64)> {
+        let usernames: Vec<String> = usernames.iter().map(|u| u.to_lowercase()).collect();
+        let mut tx = pool.begin().await?;
+        let revoked = sqlx::query(
+            "UPDATE users SET is_admin = FALSE WHERE is_admin AND NOT LOWER(username) = ANY($1)",
+        )
+        .bind(&usernames)
+        .execute(&mut *tx)
+        .await?;
+        let granted = sqlx::query(
+            "UPDATE users SET is_admin = TRUE WHERE NOT is_admin AND LOWER(username) = ANY($1)",
+        )
+        .bind(&usernames)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok((granted.rows_affected(), revoked.rows_affected()))
+    }
+
     pub async fn create(
         pool: &PgPool,
         id: Uuid,
         username: &str,
         display_name: &str,
         password_hash: &str,
+        is_admin: bool,
     ) -> AppResult<User> {
         let user = sqlx::query_as::<_, User>(
             r#"
-            INSERT INTO users (id, username, display_name, password_hash, created_at, last_seen)
-            VALUES ($1, $2, $3, $4, NOW(), NOW())
+            INSERT INTO users (id, username, display_name, password_hash, is_admin, created_at, last_seen)
+            VALUES ($1, $2, $3, $4, $5, NOW(), NOW())
             RETURNING *
             "#,
         )
@@ -66,6 +202,7 @@ pub mod users {
         .bind(username)
         .bind(display_name)
         .bind(password_hash)
+        .bind(is_admin)
         .fetch_one(pool)
         .await?;
         Ok(user)
@@ -88,10 +225,131 @@ pub mod users {
         Ok(())
     }
 
+    /// The status the user chose to appear as, if they picked one.
+    pub async fn preferred_status(pool: &PgPool, id: Uuid) -> AppResult<Option<PresenceStatus>> {
+        let status = sqlx::query_scalar::<_, Option<PresenceStatus>>(
+            "SELECT preferred_status FROM users WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(status.flatten())
+    }
+
+    /// `None` clears the preference, so the user shows as online again.
+    pub async fn set_preferred_status(
+        pool: &PgPool,
+        id: Uuid,
+        status: Option<&PresenceStatus>,
+    ) -> AppResult<()> {
+        sqlx::query("UPDATE users SET preferred_status = $2 WHERE id = $1")
+            .bind(id)
+            .bind(status)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_display_name(
+        pool: &PgPool,
+        id: Uuid,
+        display_name: &str,
+    ) -> AppResult<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "UPDATE users SET display_name = $2 WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .bind(display_name)
+        .fetch_optional(pool)
+        .await?;
+        Ok(user)
+    }
+
+    /// Store a new password hash and invalidate every token issued before now.
+    pub async fn update_password_hash(pool: &PgPool, id: Uuid, hash: &str) -> AppResult<()> {
+        sqlx::query(
+            "UPDATE users SET password_hash = $2, tokens_valid_after = NOW() WHERE id = $1",
+        )
+        .bind(id)
+        .bind(hash)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Cut-off before which the user's tokens are no longer accepted, if any.
+    pub async fn tokens_valid_after(pool: &PgPool, id: Uuid) -> AppResult<Option<DateTime<Utc>>> {
+        let cutoff = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+            "SELECT tokens_valid_after FROM users WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(cutoff.flatten())
+    }
+
+    /// Delete a user, the bots they own and everything that can't outlive
+    /// them, in one transaction. `transfers` hands each owned server to a new
+    /// owner first. Messages are removed rather than tombstoned since
+    /// tombstones still reference the author; DM channels go too, as they
+    /// can't exist with one participant.
+    pub async fn delete_account(
+        pool: &PgPool,
+        id: Uuid,
+        transfers: &[(Uuid, Uuid)],
+        deleted_servers: &[Uuid],
+    ) -> AppResult<()> {
+        let mut tx = pool.begin().await?;
+
+        for (server_id, new_owner_id) in transfers {
+            sqlx::query("UPDATE servers SET owner_id = $2 WHERE id = $1")
+                .bind(server_id)
+                .bind(new_owner_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        // Channels, messages and memberships cascade
+        sqlx::query("DELETE FROM servers WHERE id = ANY($1)")
+            .bind(deleted_servers)
+            .execute(&mut *tx)
+            .await?;
+
+        let mut accounts: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM bots WHERE owner_id = $1")
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+        accounts.push(id);
+
+        sqlx::query(
+            "DELETE FROM channels WHERE id IN (SELECT channel_id FROM dm_channels WHERE user_a = ANY($1) OR user_b = ANY($1))",
+        )
+        .bind(&accounts)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM messages WHERE author_id = ANY($1)")
+            .bind(&accounts)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM invites WHERE creator_id = ANY($1)")
+            .bind(&accounts)
+            .execute(&mut *tx)
+            .await?;
+        // Memberships, roles, bans, keys and the rest cascade from here. The
+        // bots go in the same statement, before their owner link cascades away
+        sqlx::query("DELETE FROM users WHERE id = ANY($1)")
+            .bind(&accounts)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
     /// Upsert a user from federated auth hub data.
     /// Used by community servers to create or update local user records
-    /// so that FK constraints (messages, members) work correctly.
-    #[allow(dead_code)]
+    /// so that FK constraints (messages, members) work correctly. The hub's
+    /// display name only seeds new records: after that it's the user's to
+    /// change here.
     pub async fn upsert_federated(
         pool: &PgPool,
         id: Uuid,
@@ -105,7 +363,6 @@ pub mod users {
             VALUES ($1, $2, $3, $4, '__federated__', NOW(), NOW())
             ON CONFLICT (id) DO UPDATE
               SET username = $2,
-                  display_name = $3,
                   avatar_hash = COALESCE($4, users.avatar_hash),
                   last_seen = NOW()
             "#,
@@ -120,6 +377,237 @@ pub mod users {
     }
 }
 
+// ─── Revoked Token Queries ──────────────────────────────────────────────────
+
+pub mod revoked_tokens {
+    use chrono::{DateTime, Utc};
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+
+    /// Blacklist a token ID until its natural expiry.
+    pub async fn revoke(
+        pool: &PgPool,
+        jti: &str,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO revoked_tokens (jti, user_id, expires_at, revoked_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (jti) DO NOTHING
+            "#,
+        )
+        .bind(jti)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+
+        // Tokens past their expiry are rejected anyway, so their entries can go
+        sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < NOW()")
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn is_revoked(pool: &PgPool, jti: &str) -> AppResult<bool> {
+        let revoked = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM revoked_tokens WHERE jti = $1)",
+        )
+        .bind(jti)
+        .fetch_one(pool)
+        .await?;
+        Ok(revoked)
+    }
+}
+
+// ─── Refresh Token Queries ──────────────────────────────────────────────────
+
+pub mod refresh_tokens {
+    use chrono::{DateTime, Utc};
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+
+    pub async fn create(
+        pool: &PgPool,
+        token_hash: &str,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<()> {
+        sqlx::query(
+            "INSERT INTO refresh_tokens (token_hash, user_id, expires_at) VALUES ($1, $2, $3)",
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+
+        // Expired tokens can't be used anymore, so their rows can go
+        sqlx::query("DELETE FROM refresh_tokens WHERE expires_at < NOW()")
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Use up a refresh token, replacing it with `new_hash`. Returns the
+    /// owner, or `None` if the token is unknown, already used or expired.
+    pub async fn rotate(
+        pool: &PgPool,
+        token_hash: &str,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<Option<Uuid>> {
+        let mut tx = pool.begin().await?;
+
+        let used = sqlx::query_as::<_, (Uuid, DateTime<Utc>)>(
+            "DELETE FROM refresh_tokens WHERE token_hash = $1 RETURNING user_id, expires_at",
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(user_id) = live_owner(used, Utc::now()) else {
+            tx.commit().await?;
+            return Ok(None);
+        };
+
+        sqlx::query(
+            "INSERT INTO refresh_tokens (token_hash, user_id, expires_at) VALUES ($1, $2, $3)",
+        )
+        .bind(new_hash)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(user_id))
+    }
+
+    /// The owner of a token row just used up, unless it had expired. A
+    /// token that was already used has no row left.
+    pub(super) fn live_owner(
+        used: Option<(Uuid, DateTime<Utc>)>,
+        now: DateTime<Utc>,
+    ) -> Option<Uuid> {
+        used.filter(|(_, expires_at)| *expires_at > now)
+            .map(|(user_id, _)| user_id)
+    }
+
+    pub async fn delete(pool: &PgPool, token_hash: &str, user_id: Uuid) -> AppResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2")
+                .bind(token_hash)
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete_for_user(pool: &PgPool, user_id: Uuid) -> AppResult<()> {
+        sqlx::query("DELETE FROM refresh_tokens WHERE user_id = $1")
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+}
+
+// ─── Bot Queries ────────────────────────────────────────────────────────────
+
+pub mod bots {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use super::NO_PASSWORD_HASH;
+    use crate::error::AppResult;
+    use crate::models::User;
+
+    /// Create a bot account owned by `owner_id`, unless they already own
+    /// `max_bots`. Bots have no password; they authenticate with the token
+    /// minted when they are created. Returns `None` if the owner is at the cap.
+    pub async fn create(
+        pool: &PgPool,
+        id: Uuid,
+        owner_id: Uuid,
+        username: &str,
+        display_name: &str,
+        max_bots: i64,
+    ) -> AppResult<Option<User>> {
+        let mut tx = pool.begin().await?;
+        // Serializes concurrent creations by the same owner for the count below
+        sqlx::query("SELECT 1 FROM users WHERE id = $1 FOR UPDATE")
+            .bind(owner_id)
+            .execute(&mut *tx)
+            .await?;
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            INSERT INTO users (id, username, display_name, password_hash, is_bot, created_at, last_seen)
+            VALUES ($1, $2, $3, $4, TRUE, NOW(), NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(username)
+        .bind(display_name)
+        .bind(NO_PASSWORD_HASH)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO bots (id, owner_id, created_at)
+            SELECT $1, $2, NOW()
+            WHERE (SELECT COUNT(*) FROM bots WHERE owner_id = $2) < $3
+            "#,
+        )
+        .bind(id)
+        .bind(owner_id)
+        .bind(max_bots)
+        .execute(&mut *tx)
+        .await?;
+        if inserted.rows_affected() == 0 {
+            return Ok(None);
+        }
+        tx.commit().await?;
+
+        Ok(Some(user))
+    }
+
+    /// The bot accounts `owner_id` owns.
+    pub async fn list_owned(pool: &PgPool, owner_id: Uuid) -> AppResult<Vec<User>> {
+        let bots = sqlx::query_as::<_, User>(
+            "SELECT u.* FROM users u JOIN bots b ON b.id = u.id WHERE b.owner_id = $1",
+        )
+        .bind(owner_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(bots)
+    }
+
+    /// Invalidate every token of bot `id`, if `owner_id` owns it. Returns
+    /// the bot, or `None` if it isn't theirs.
+    pub async fn revoke_tokens(pool: &PgPool, id: Uuid, owner_id: Uuid) -> AppResult<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users SET tokens_valid_after = NOW()
+            WHERE id = $1 AND id IN (SELECT id FROM bots WHERE owner_id = $2)
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(owner_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(user)
+    }
+}
+
 // ─── Server Queries ─────────────────────────────────────────────────────────
 
 pub mod servers {
@@ -175,18 +663,62 @@ pub mod servers {
         Ok(servers)
     }
 
-    /// List all servers (used for auto-joining new users).
-    pub async fn list_all(pool: &PgPool) -> AppResult<Vec<Server>> {
-        let servers = sqlx::query_as::<_, Server>("SELECT * FROM servers ORDER BY name")
-            .fetch_all(pool)
-            .await?;
-        Ok(servers)
-    }
+    /// Delete a server if `owner_id` owns it. Channels (and with them
+    /// messages and webhooks), members, roles, bans, invites and emojis
+    /// cascade; the synthetic users its webhooks posted as are removed too.
+    pub async fn delete(pool: &PgPool, id: Uuid, owner_id: Uuid) -> AppResult<bool> {
+        let mut tx = pool.begin().await?;
 
-    /// List all members for a specific server (used for broadcasting events).
-    pub async fn list_members(
-        pool: &PgPool,
-        server_id: Uuid,
+        let webhook_users = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT w.user_id FROM webhooks w
+            JOIN channels c ON c.id = w.channel_id
+            WHERE c.server_id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let result = sqlx::query("DELETE FROM servers WHERE id = $1 AND owner_id = $2")
+            .bind(id)
+            .bind(owner_id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+        sqlx::query("DELETE FROM users WHERE id = ANY($1)")
+            .bind(&webhook_users)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn count_owned(pool: &PgPool, owner_id: Uuid) -> AppResult<i64> {
+        let count =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM servers WHERE owner_id = $1")
+                .bind(owner_id)
+                .fetch_one(pool)
+                .await?;
+        Ok(count)
+    }
+
+    /// List all servers (used for auto-joining new users).
+    pub async fn list_all(pool: &PgPool) -> AppResult<Vec<Server>> {
+        let servers = sqlx::query_as::<_, Server>("SELECT * FROM servers ORDER BY name")
+            .fetch_all(pool)
+            .await?;
+        Ok(servers)
+    }
+
+    /// List all members for a specific server (used for broadcasting events).
+    pub async fn list_members(
+        pool: &PgPool,
+        server_id: Uuid,
     ) -> AppResult<Vec<crate::models::Member>> {
         let members = sqlx::query_as::<_, crate::models::Member>(
             r#"
@@ -199,18 +731,242 @@ pub mod servers {
         Ok(members)
     }
 
-    /// Transfer ownership of a server to a new user.
+    /// Update mutable server fields. `None` leaves the current value in place.
+    pub async fn update(
+        pool: &PgPool,
+        id: Uuid,
+        name: Option<&str>,
+        embeds_enabled: Option<bool>,
+    ) -> AppResult<Option<Server>> {
+        let server = sqlx::query_as::<_, Server>(
+            r#"
+            UPDATE servers SET
+                name = COALESCE($2, name),
+                embeds_enabled = COALESCE($3, embeds_enabled)
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(name)
+        .bind(embeds_enabled)
+        .fetch_optional(pool)
+        .await?;
+        Ok(server)
+    }
+
+    pub async fn set_disabled(
+        pool: &PgPool,
+        id: Uuid,
+        disabled: bool,
+    ) -> AppResult<Option<Server>> {
+        let server = sqlx::query_as::<_, Server>(
+            "UPDATE servers SET disabled = $2 WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .bind(disabled)
+        .fetch_optional(pool)
+        .await?;
+        Ok(server)
+    }
+
+    /// Transfer ownership of a server to a new user, provided it's still owned
+    /// by `current_owner_id`. Returns `None` if it isn't (or no longer exists).
     pub async fn transfer_ownership(
         pool: &PgPool,
         server_id: Uuid,
+        current_owner_id: Uuid,
         new_owner_id: Uuid,
-    ) -> AppResult<()> {
-        sqlx::query("UPDATE servers SET owner_id = $2 WHERE id = $1")
-            .bind(server_id)
-            .bind(new_owner_id)
+    ) -> AppResult<Option<Server>> {
+        let server = sqlx::query_as::<_, Server>(
+            r#"
+            UPDATE servers SET owner_id = $3
+            WHERE id = $1 AND owner_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(server_id)
+        .bind(current_owner_id)
+        .bind(new_owner_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(server)
+    }
+}
+
+// ─── Invite Queries ─────────────────────────────────────────────────────────
+
+pub mod invites {
+    use chrono::{DateTime, Utc};
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::Invite;
+
+    pub async fn create(
+        pool: &PgPool,
+        code: &str,
+        server_id: Uuid,
+        creator_id: Uuid,
+        max_uses: Option<i32>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> AppResult<Invite> {
+        let invite = sqlx::query_as::<_, Invite>(
+            r#"
+            INSERT INTO invites (code, server_id, creator_id, max_uses, uses, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, 0, $5, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(code)
+        .bind(server_id)
+        .bind(creator_id)
+        .bind(max_uses)
+        .bind(expires_at)
+        .fetch_one(pool)
+        .await?;
+        Ok(invite)
+    }
+
+    pub async fn find_by_code(pool: &PgPool, code: &str) -> AppResult<Option<Invite>> {
+        let invite = sqlx::query_as::<_, Invite>("SELECT * FROM invites WHERE code = $1")
+            .bind(code)
+            .fetch_optional(pool)
+            .await?;
+        Ok(invite)
+    }
+
+    pub async fn list_for_server(pool: &PgPool, server_id: Uuid) -> AppResult<Vec<Invite>> {
+        let invites = sqlx::query_as::<_, Invite>(
+            "SELECT * FROM invites WHERE server_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(server_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(invites)
+    }
+
+    /// Atomically consume one use of an invite.
+    /// Returns `None` if the invite has already reached `max_uses`.
+    pub async fn increment_uses(pool: &PgPool, code: &str) -> AppResult<Option<Invite>> {
+        let invite = sqlx::query_as::<_, Invite>(
+            r#"
+            UPDATE invites SET uses = uses + 1
+            WHERE code = $1 AND (max_uses IS NULL OR uses < max_uses)
+            RETURNING *
+            "#,
+        )
+        .bind(code)
+        .fetch_optional(pool)
+        .await?;
+        Ok(invite)
+    }
+
+    pub async fn delete(pool: &PgPool, code: &str) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM invites WHERE code = $1")
+            .bind(code)
             .execute(pool)
             .await?;
-        Ok(())
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+// ─── Registration Invite Queries ────────────────────────────────────────────
+
+pub mod registration_invites {
+    use chrono::{DateTime, Utc};
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::{RegistrationInvite, User};
+
+    pub async fn create(
+        pool: &PgPool,
+        code: &str,
+        creator_id: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> AppResult<RegistrationInvite> {
+        let invite = sqlx::query_as::<_, RegistrationInvite>(
+            r#"
+            INSERT INTO registration_invites (code, creator_id, expires_at, created_at)
+            VALUES ($1, $2, $3, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(code)
+        .bind(creator_id)
+        .bind(expires_at)
+        .fetch_one(pool)
+        .await?;
+        Ok(invite)
+    }
+
+    /// All invites, newest first.
+    pub async fn list(pool: &PgPool) -> AppResult<Vec<RegistrationInvite>> {
+        let invites = sqlx::query_as::<_, RegistrationInvite>(
+            "SELECT * FROM registration_invites ORDER BY created_at DESC",
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(invites)
+    }
+
+    /// Create an account and use up the invite it registered with, in one
+    /// transaction. Returns None, creating nothing, if the invite doesn't
+    /// exist, was already used or has expired.
+    pub async fn redeem(
+        pool: &PgPool,
+        code: &str,
+        id: Uuid,
+        username: &str,
+        display_name: &str,
+        password_hash: &str,
+        is_admin: bool,
+    ) -> AppResult<Option<User>> {
+        let mut tx = pool.begin().await?;
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            INSERT INTO users (id, username, display_name, password_hash, is_admin, created_at, last_seen)
+            VALUES ($1, $2, $3, $4, $5, NOW(), NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(username)
+        .bind(display_name)
+        .bind(password_hash)
+        .bind(is_admin)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let consumed = sqlx::query(
+            r#"
+            UPDATE registration_invites SET used_at = NOW(), used_by = $2
+            WHERE code = $1 AND used_at IS NULL
+              AND (expires_at IS NULL OR expires_at > NOW())
+            "#,
+        )
+        .bind(code)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        if consumed.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+
+        tx.commit().await?;
+        Ok(Some(user))
+    }
+
+    pub async fn delete(pool: &PgPool, code: &str) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM registration_invites WHERE code = $1")
+            .bind(code)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
     }
 }
 
@@ -223,6 +979,7 @@ pub mod channels {
     use crate::error::AppResult;
     use crate::models::{Channel, ChannelType};
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn create(
         pool: &PgPool,
         id: Uuid,
@@ -231,11 +988,12 @@ pub mod channels {
         channel_type: &ChannelType,
         position: i32,
         category_id: Option<Uuid>,
+        user_limit: Option<i32>,
     ) -> AppResult<Channel> {
         let channel = sqlx::query_as::<_, Channel>(
             r#"
-            INSERT INTO channels (id, server_id, name, channel_type, position, category_id)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            INSERT INTO channels (id, server_id, name, channel_type, position, category_id, user_limit)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
             RETURNING *
             "#,
         )
@@ -245,11 +1003,20 @@ pub mod channels {
         .bind(channel_type)
         .bind(position)
         .bind(category_id)
+        .bind(user_limit)
         .fetch_one(pool)
         .await?;
         Ok(channel)
     }
 
+    pub async fn find_by_id(pool: &PgPool, id: Uuid) -> AppResult<Option<Channel>> {
+        let channel = sqlx::query_as::<_, Channel>("SELECT * FROM channels WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(channel)
+    }
+
     pub async fn list_for_server(pool: &PgPool, server_id: Uuid) -> AppResult<Vec<Channel>> {
         let channels = sqlx::query_as::<_, Channel>(
             "SELECT * FROM channels WHERE server_id = $1 ORDER BY position",
@@ -260,6 +1027,68 @@ pub mod channels {
         Ok(channels)
     }
 
+    pub async fn count_for_server(pool: &PgPool, server_id: Uuid) -> AppResult<i64> {
+        let count =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM channels WHERE server_id = $1")
+                .bind(server_id)
+                .fetch_one(pool)
+                .await?;
+        Ok(count)
+    }
+
+    /// Rename a channel and/or change its topic or slow mode. `None` leaves a
+    /// field as is; an empty topic clears it.
+    pub async fn update(
+        pool: &PgPool,
+        server_id: Uuid,
+        id: Uuid,
+        name: Option<&str>,
+        topic: Option<&str>,
+        slow_mode_secs: Option<i32>,
+    ) -> AppResult<Option<Channel>> {
+        let channel = sqlx::query_as::<_, Channel>(
+            r#"
+            UPDATE channels
+            SET name = COALESCE($3, name),
+                topic = CASE WHEN $4::TEXT IS NULL THEN topic ELSE NULLIF($4, '') END,
+                slow_mode_secs = COALESCE($5, slow_mode_secs)
+            WHERE id = $1 AND server_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(server_id)
+        .bind(name)
+        .bind(topic)
+        .bind(slow_mode_secs)
+        .fetch_optional(pool)
+        .await?;
+        Ok(channel)
+    }
+
+    /// Set each channel's position to its index in `ordered_ids`, atomically.
+    pub async fn update_positions(
+        pool: &PgPool,
+        server_id: Uuid,
+        ordered_ids: &[Uuid],
+    ) -> AppResult<Vec<Channel>> {
+        let mut tx = pool.begin().await?;
+        let mut channels = Vec::with_capacity(ordered_ids.len());
+        for (position, id) in ordered_ids.iter().enumerate() {
+            let channel = sqlx::query_as::<_, Channel>(
+                "UPDATE channels SET position = $3 WHERE id = $1 AND server_id = $2 RETURNING *",
+            )
+            .bind(id)
+            .bind(server_id)
+            .bind(position as i32)
+            .fetch_one(&mut *tx)
+            .await?;
+            channels.push(channel);
+        }
+        tx.commit().await?;
+        Ok(channels)
+    }
+
     pub async fn delete(pool: &PgPool, id: Uuid) -> AppResult<bool> {
         let result = sqlx::query("DELETE FROM channels WHERE id = $1")
             .bind(id)
@@ -269,15 +1098,170 @@ pub mod channels {
     }
 }
 
+// ─── Direct Message Queries ─────────────────────────────────────────────────
+
+pub mod dms {
+    use sqlx::postgres::PgRow;
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::{ChannelType, DmChannel};
+
+    /// Columns selected for the other participant, see `dm_from_row`.
+    const DM_COLUMNS: &str = r#"
+        SELECT d.channel_id, d.created_at, u.id AS user_id, u.username, u.display_name, u.avatar_hash, u.is_bot
+        FROM dm_channels d
+        JOIN users u ON u.id = CASE WHEN d.user_a = $1 THEN d.user_b ELSE d.user_a END
+    "#;
+
+    fn dm_from_row(row: &PgRow) -> DmChannel {
+        use crate::models::UserPublic;
+        use sqlx::Row;
+
+        DmChannel {
+            id: row.get("channel_id"),
+            recipient: UserPublic {
+                id: row.get("user_id"),
+                username: row.get("username"),
+                display_name: row.get("display_name"),
+                avatar_hash: row.get("avatar_hash"),
+                is_bot: row.get("is_bot"),
+            },
+            created_at: row.get("created_at"),
+        }
+    }
+
+    /// Return the DM channel between two users, creating it on first use.
+    pub async fn find_or_create(
+        pool: &PgPool,
+        user_id: Uuid,
+        other_id: Uuid,
+    ) -> AppResult<DmChannel> {
+        let (a, b) = if user_id < other_id {
+            (user_id, other_id)
+        } else {
+            (other_id, user_id)
+        };
+
+        let mut tx = pool.begin().await?;
+        let channel_id = Uuid::now_v7();
+        sqlx::query(
+            "INSERT INTO channels (id, server_id, name, channel_type, position) VALUES ($1, NULL, 'dm', $2, 0)",
+        )
+        .bind(channel_id)
+        .bind(ChannelType::Text)
+        .execute(&mut *tx)
+        .await?;
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO dm_channels (channel_id, user_a, user_b, created_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (user_a, user_b) DO NOTHING
+            "#,
+        )
+        .bind(channel_id)
+        .bind(a)
+        .bind(b)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        if inserted {
+            tx.commit().await?;
+        } else {
+            // The pair already has a channel; drop the one we just made
+            tx.rollback().await?;
+        }
+
+        let row = sqlx::query(&format!(
+            "{} WHERE d.user_a = $2 AND d.user_b = $3",
+            DM_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(a)
+        .bind(b)
+        .fetch_one(pool)
+        .await?;
+        Ok(dm_from_row(&row))
+    }
+
+    pub async fn list_for_user(pool: &PgPool, user_id: Uuid) -> AppResult<Vec<DmChannel>> {
+        let rows = sqlx::query(&format!(
+            "{} WHERE d.user_a = $1 OR d.user_b = $1 ORDER BY d.created_at DESC",
+            DM_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(rows.iter().map(dm_from_row).collect())
+    }
+
+    /// The two participants of a DM channel, or `None` if it isn't one.
+    pub async fn participants(pool: &PgPool, channel_id: Uuid) -> AppResult<Option<(Uuid, Uuid)>> {
+        let pair = sqlx::query_as::<_, (Uuid, Uuid)>(
+            "SELECT user_a, user_b FROM dm_channels WHERE channel_id = $1",
+        )
+        .bind(channel_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(pair)
+    }
+}
+
 // ─── Message Queries ────────────────────────────────────────────────────────
 
 pub mod messages {
+    use std::collections::HashMap;
+
+    use chrono::{DateTime, Utc};
+    use sqlx::postgres::PgRow;
     use sqlx::PgPool;
     use uuid::Uuid;
 
     use crate::error::AppResult;
-    use crate::models::Message;
+    use crate::models::{Message, MessageEdit};
+
+    /// Build a `Message` from a row of `messages` joined with its author.
+    pub(super) fn message_from_row(row: &PgRow) -> Message {
+        use crate::models::UserPublic;
+        use sqlx::Row;
+
+        Message {
+            id: row.get("id"),
+            channel_id: row.get("channel_id"),
+            author_id: row.get("author_id"),
+            content: row.get("content"),
+            nonce: row.get("nonce"),
+            created_at: row.get("created_at"),
+            edited_at: row.get("edited_at"),
+            reply_to_id: row.get("reply_to_id"),
+            is_deleted: row.try_get("is_deleted").unwrap_or(false),
+            author: Some(UserPublic {
+                id: row.get("author_id"),
+                username: row.get("username"),
+                // Webhook messages may carry their own display name
+                display_name: row
+                    .try_get::<Option<String>, _>("webhook_username")
+                    .ok()
+                    .flatten()
+                    .unwrap_or_else(|| row.get("display_name")),
+                avatar_hash: row.get("avatar_hash"),
+                is_bot: row.get("is_bot"),
+            }),
+            attachments: Vec::new(),
+            webhook_id: row.try_get("webhook_id").unwrap_or(None),
+            avatar_url: row.try_get("webhook_avatar_url").unwrap_or(None),
+            mentions: Vec::new(),
+            mention_everyone: row.try_get("mention_everyone").unwrap_or(false),
+            referenced_message: None,
+            embeds: Vec::new(),
+        }
+    }
 
+    /// Insert a message along with who it mentions and whether it pinged
+    /// everyone, in one transaction.
+    #[allow(clippy::too_many_arguments)]
     pub async fn create(
         pool: &PgPool,
         id: i64,
@@ -285,11 +1269,14 @@ pub mod messages {
         author_id: Uuid,
         content: &str,
         reply_to_id: Option<i64>,
+        mentions: &[Uuid],
+        mention_everyone: bool,
     ) -> AppResult<Message> {
-        let message = sqlx::query_as::<_, Message>(
+        let mut tx = pool.begin().await?;
+        let mut message = sqlx::query_as::<_, Message>(
             r#"
-            INSERT INTO messages (id, channel_id, author_id, content, created_at, reply_to_id)
-            VALUES ($1, $2, $3, $4, NOW(), $5)
+            INSERT INTO messages (id, channel_id, author_id, content, created_at, reply_to_id, mention_everyone)
+            VALUES ($1, $2, $3, $4, NOW(), $5, $6)
             RETURNING *
             "#,
         )
@@ -298,15 +1285,29 @@ pub mod messages {
         .bind(author_id)
         .bind(content)
         .bind(reply_to_id)
-        .fetch_one(pool)
+        .bind(mention_everyone)
+        .fetch_one(&mut *tx)
         .await?;
+        if !mentions.is_empty() {
+            sqlx::query(
+                r#"
+                INSERT INTO message_mentions (message_id, user_id)
+                SELECT $1, UNNEST($2::uuid[])
+                ON CONFLICT DO NOTHING
+                "#,
+            )
+            .bind(id)
+            .bind(mentions)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        message.mentions = mentions.to_vec();
 
         // Fetch author details
-        let author = super::users::find_by_id(pool, author_id)
+        message.author = super::users::find_by_id(pool, author_id)
             .await?
             .map(|u| u.into());
-        let mut message = message;
-        message.author = author;
 
         Ok(message)
     }
@@ -319,7 +1320,7 @@ pub mod messages {
     ) -> AppResult<Vec<Message>> {
         let query_str = if before.is_some() {
             r#"
-            SELECT m.*, u.username, u.display_name, u.avatar_hash
+            SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot
             FROM messages m
             JOIN users u ON m.author_id = u.id
             WHERE m.channel_id = $1 AND m.id < $2
@@ -328,7 +1329,7 @@ pub mod messages {
             "#
         } else {
             r#"
-            SELECT m.*, u.username, u.display_name, u.avatar_hash
+            SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot
             FROM messages m
             JOIN users u ON m.author_id = u.id
             WHERE m.channel_id = $1
@@ -352,385 +1353,1845 @@ pub mod messages {
                 .await?
         };
 
-        let messages = rows
-            .into_iter()
-            .map(|row| {
-                use crate::models::UserPublic;
-                use sqlx::Row;
-
-                let msg = Message {
-                    id: row.get("id"),
-                    channel_id: row.get("channel_id"),
-                    author_id: row.get("author_id"),
-                    content: row.get("content"),
-                    nonce: row.get("nonce"),
-                    created_at: row.get("created_at"),
-                    edited_at: row.get("edited_at"),
-                    reply_to_id: row.get("reply_to_id"),
-                    is_deleted: row.try_get("is_deleted").unwrap_or(false),
-                    author: Some(UserPublic {
-                        id: row.get("author_id"),
-                        username: row.get("username"),
-                        display_name: row.get("display_name"),
-                        avatar_hash: row.get("avatar_hash"),
-                    }),
-                };
-                msg
-            })
-            .collect();
+        let mut messages: Vec<Message> = rows.iter().map(message_from_row).collect();
+
+        super::attachments::populate(pool, &mut messages).await?;
+        super::mentions::populate(pool, &mut messages).await?;
+        super::embeds::populate(pool, &mut messages).await?;
+        populate_references(pool, &mut messages).await?;
 
         Ok(messages)
     }
 
-    #[allow(dead_code)]
-    pub async fn update_content(
+    /// Live messages posted after a read position, for several channels in
+    /// one query: `after` pairs a channel with the last message id read there.
+    /// Newest first within each channel, at most `limit_per_channel` each.
+    pub async fn list_after_for_channels(
         pool: &PgPool,
-        id: i64,
-        content: &str,
-    ) -> AppResult<Option<Message>> {
+        after: &[(Uuid, i64)],
+        limit_per_channel: i64,
+    ) -> AppResult<Vec<Message>> {
+        let (channel_ids, after_ids): (Vec<Uuid>, Vec<i64>) = after.iter().copied().unzip();
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM (
+                SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot,
+                       ROW_NUMBER() OVER (PARTITION BY m.channel_id ORDER BY m.id DESC) AS rank
+                FROM UNNEST($1::uuid[], $2::bigint[]) AS r(channel_id, after_id)
+                JOIN messages m ON m.channel_id = r.channel_id AND m.id > r.after_id
+                JOIN users u ON m.author_id = u.id
+                WHERE NOT m.is_deleted
+            ) ranked
+            WHERE rank <= $3
+            ORDER BY channel_id, id DESC
+            "#,
+        )
+        .bind(&channel_ids)
+        .bind(&after_ids)
+        .bind(limit_per_channel)
+        .fetch_all(pool)
+        .await?;
+
+        let mut messages: Vec<Message> = rows.iter().map(message_from_row).collect();
+
+        super::attachments::populate(pool, &mut messages).await?;
+        super::mentions::populate(pool, &mut messages).await?;
+        super::embeds::populate(pool, &mut messages).await?;
+        populate_references(pool, &mut messages).await?;
+
+        Ok(messages)
+    }
+
+    /// Attach the message each reply points at. Only one level deep: the
+    /// referenced messages carry no references (or attachments) of their own.
+    pub async fn populate_references(pool: &PgPool, messages: &mut [Message]) -> AppResult<()> {
+        let mut ids: Vec<i64> = messages.iter().filter_map(|m| m.reply_to_id).collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        ids.sort_unstable();
+        ids.dedup();
+
+        let rows = sqlx::query(
+            r#"
+            SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot
+            FROM messages m
+            JOIN users u ON m.author_id = u.id
+            WHERE m.id = ANY($1)
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(pool)
+        .await?;
+        let referenced: HashMap<i64, Message> = rows
+            .iter()
+            .map(message_from_row)
+            .map(|m| (m.id, m))
+            .collect();
+        attach_references(messages, &referenced);
+        Ok(())
+    }
+
+    /// Point each reply in `messages` at its message in `referenced`. A reply
+    /// can only point into its own channel.
+    pub(super) fn attach_references(messages: &mut [Message], referenced: &HashMap<i64, Message>) {
+        for message in messages.iter_mut() {
+            message.referenced_message = message
+                .reply_to_id
+                .and_then(|id| referenced.get(&id))
+                .filter(|r| r.channel_id == message.channel_id)
+                .map(|r| Box::new(r.clone()));
+        }
+    }
+
+    /// Fetch a single message in a channel, including soft-deleted ones.
+    pub async fn find_by_id(
+        pool: &PgPool,
+        channel_id: Uuid,
+        id: i64,
+    ) -> AppResult<Option<Message>> {
+        let row = sqlx::query(
+            r#"
+            SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot
+            FROM messages m
+            JOIN users u ON m.author_id = u.id
+            WHERE m.channel_id = $1 AND m.id = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+        let mut messages: Vec<Message> = row.iter().map(message_from_row).collect();
+        super::attachments::populate(pool, &mut messages).await?;
+        super::mentions::populate(pool, &mut messages).await?;
+        super::embeds::populate(pool, &mut messages).await?;
+        populate_references(pool, &mut messages).await?;
+
+        Ok(messages.pop())
+    }
+
+    /// Messages newer than `after`, returned newest first like `list_for_channel`.
+    pub async fn list_after(
+        pool: &PgPool,
+        channel_id: Uuid,
+        after: i64,
+        limit: i64,
+    ) -> AppResult<Vec<Message>> {
+        // Take the oldest `limit` messages after the anchor, then flip the order
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM (
+                SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot
+                FROM messages m
+                JOIN users u ON m.author_id = u.id
+                WHERE m.channel_id = $1 AND m.id > $2
+                ORDER BY m.id ASC
+                LIMIT $3
+            ) page
+            ORDER BY id DESC
+            "#,
+        )
+        .bind(channel_id)
+        .bind(after)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        let mut messages: Vec<Message> = rows.iter().map(message_from_row).collect();
+        super::attachments::populate(pool, &mut messages).await?;
+        super::mentions::populate(pool, &mut messages).await?;
+        super::embeds::populate(pool, &mut messages).await?;
+        populate_references(pool, &mut messages).await?;
+
+        Ok(messages)
+    }
+
+    /// Up to `limit` messages centred on `around` (which is included if it
+    /// exists), newest first.
+    pub async fn list_around(
+        pool: &PgPool,
+        channel_id: Uuid,
+        around: i64,
+        limit: i64,
+    ) -> AppResult<Vec<Message>> {
+        let newer = limit / 2;
+        let mut messages = list_after(pool, channel_id, around, newer).await?;
+        messages.extend(list_for_channel(pool, channel_id, Some(around + 1), limit - newer).await?);
+        Ok(messages)
+    }
+
+    /// Full-text search over a channel's messages, newest first.
+    pub async fn search(
+        pool: &PgPool,
+        channel_id: Uuid,
+        query: &str,
+        before: Option<i64>,
+        limit: i64,
+    ) -> AppResult<Vec<Message>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot
+            FROM messages m
+            JOIN users u ON m.author_id = u.id
+            WHERE m.channel_id = $1
+              AND m.is_deleted = FALSE
+              AND m.content_tsv @@ plainto_tsquery('simple', $2)
+              AND ($3::BIGINT IS NULL OR m.id < $3)
+            ORDER BY m.id DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(channel_id)
+        .bind(query)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        let mut messages: Vec<Message> = rows.iter().map(message_from_row).collect();
+        super::attachments::populate(pool, &mut messages).await?;
+        super::mentions::populate(pool, &mut messages).await?;
+        super::embeds::populate(pool, &mut messages).await?;
+        populate_references(pool, &mut messages).await?;
+
+        Ok(messages)
+    }
+
+    /// Replace a message's content, keeping the old version in `message_edits`.
+    pub async fn update_content(
+        pool: &PgPool,
+        id: i64,
+        content: &str,
+    ) -> AppResult<Option<Message>> {
+        let mut tx = pool.begin().await?;
+
+        // Lock the row so concurrent edits can't both archive the same
+        // version, and each one's history entry is the content it replaced
+        let locked = sqlx::query(
+            r#"
+            SELECT id FROM messages
+            WHERE id = $1 AND is_deleted = FALSE
+            FOR UPDATE
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if locked.is_none() {
+            return Ok(None);
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO message_edits (message_id, content, created_at)
+            SELECT id, content, COALESCE(edited_at, created_at) FROM messages
+            WHERE id = $1 AND is_deleted = FALSE
+            "#,
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
         let message = sqlx::query_as::<_, Message>(
             r#"
             UPDATE messages SET content = $2, edited_at = NOW()
-            WHERE id = $1
+            WHERE id = $1 AND is_deleted = FALSE
             RETURNING *
             "#,
         )
         .bind(id)
         .bind(content)
-        .fetch_optional(pool)
+        .fetch_optional(&mut *tx)
         .await?;
-        Ok(message)
+
+        tx.commit().await?;
+
+        let Some(mut message) = message else {
+            return Ok(None);
+        };
+
+        // Fetch author details
+        message.author = super::users::find_by_id(pool, message.author_id)
+            .await?
+            .map(|u| u.into());
+        message.attachments = super::attachments::list_for_messages(pool, &[message.id]).await?;
+        message.embeds = super::embeds::list_for_message(pool, message.id).await?;
+
+        Ok(Some(message))
+    }
+
+    /// Earlier versions of a message, oldest first.
+    pub async fn list_edits(pool: &PgPool, id: i64) -> AppResult<Vec<MessageEdit>> {
+        let edits = sqlx::query_as::<_, MessageEdit>(
+            "SELECT content, created_at, replaced_at FROM message_edits WHERE message_id = $1 ORDER BY id",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+        Ok(edits)
     }
 
+    /// Soft-delete: the row stays as a tombstone (blank content, `is_deleted`)
+    /// so replies keep pointing at it. Its edit history goes with the content.
     pub async fn delete(pool: &PgPool, id: i64) -> AppResult<bool> {
         let result =
             sqlx::query("UPDATE messages SET is_deleted = TRUE, content = '' WHERE id = $1")
                 .bind(id)
                 .execute(pool)
                 .await?;
+        sqlx::query("DELETE FROM message_edits WHERE message_id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
         Ok(result.rows_affected() > 0)
     }
-}
 
-// ─── Member Queries ─────────────────────────────────────────────────────────
+    /// Permanently remove messages created before `cutoff`. Replies to them
+    /// keep their row with `reply_to_id` nulled. Reserved for retention jobs;
+    /// user-facing deletion goes through `delete`.
+    #[allow(dead_code)]
+    pub async fn purge(pool: &PgPool, cutoff: DateTime<Utc>) -> AppResult<u64> {
+        let result = sqlx::query("DELETE FROM messages WHERE created_at < $1")
+            .bind(cutoff)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
 
-// ─── Member Queries ─────────────────────────────────────────────────────────
+    /// How many of `ids` are messages in `channel_id`.
+    pub async fn count_in_channel(pool: &PgPool, channel_id: Uuid, ids: &[i64]) -> AppResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM messages WHERE channel_id = $1 AND id = ANY($2)",
+        )
+        .bind(channel_id)
+        .bind(ids)
+        .fetch_one(pool)
+        .await?;
+        Ok(count)
+    }
 
-pub mod members {
+    /// Soft-delete several messages of a channel at once. Returns the ids
+    /// that were actually deleted (already-deleted messages are skipped).
+    pub async fn delete_many(pool: &PgPool, channel_id: Uuid, ids: &[i64]) -> AppResult<Vec<i64>> {
+        let deleted = sqlx::query_scalar::<_, i64>(
+            r#"
+            UPDATE messages SET is_deleted = TRUE, content = ''
+            WHERE channel_id = $1 AND id = ANY($2) AND is_deleted = FALSE
+            RETURNING id
+            "#,
+        )
+        .bind(channel_id)
+        .bind(ids)
+        .fetch_all(pool)
+        .await?;
+        sqlx::query("DELETE FROM message_edits WHERE message_id = ANY($1)")
+            .bind(&deleted)
+            .execute(pool)
+            .await?;
+        Ok(deleted)
+    }
+}
+
+// ─── Attachment Queries ─────────────────────────────────────────────────────
+
+pub mod attachments {
     use sqlx::PgPool;
     use uuid::Uuid;
 
     use crate::error::AppResult;
-    use crate::models::{Member, Permissions};
+    use crate::models::{Attachment, Message};
 
-    pub async fn add(pool: &PgPool, user_id: Uuid, server_id: Uuid) -> AppResult<Member> {
-        // We initialize with empty roles list
-        let member = sqlx::query_as::<_, Member>(
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        pool: &PgPool,
+        id: Uuid,
+        channel_id: Uuid,
+        uploader_id: Uuid,
+        filename: &str,
+        content_type: &str,
+        size: i64,
+        hash: &str,
+    ) -> AppResult<Attachment> {
+        let attachment = sqlx::query_as::<_, Attachment>(
             r#"
-            INSERT INTO members (user_id, server_id, joined_at)
-            VALUES ($1, $2, NOW())
-            ON CONFLICT (user_id, server_id) DO UPDATE SET joined_at = members.joined_at
-            RETURNING *, ARRAY[]::uuid[] as roles
+            INSERT INTO attachments (id, channel_id, uploader_id, filename, content_type, size, hash, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+            RETURNING *
             "#,
         )
-        .bind(user_id)
-        .bind(server_id)
+        .bind(id)
+        .bind(channel_id)
+        .bind(uploader_id)
+        .bind(filename)
+        .bind(content_type)
+        .bind(size)
+        .bind(hash)
+        .fetch_one(pool)
+        .await?;
+        Ok(attachment)
+    }
+
+    /// Any attachment in the channel with this content hash.
+    pub async fn find_by_hash(
+        pool: &PgPool,
+        channel_id: Uuid,
+        hash: &str,
+    ) -> AppResult<Option<Attachment>> {
+        let attachment = sqlx::query_as::<_, Attachment>(
+            "SELECT * FROM attachments WHERE channel_id = $1 AND hash = $2 LIMIT 1",
+        )
+        .bind(channel_id)
+        .bind(hash)
+        .fetch_optional(pool)
+        .await?;
+        Ok(attachment)
+    }
+
+    /// How many of `ids` were uploaded by `uploader_id` to `channel_id` and
+    /// are not yet part of a message.
+    pub async fn count_pending(
+        pool: &PgPool,
+        channel_id: Uuid,
+        uploader_id: Uuid,
+        ids: &[Uuid],
+    ) -> AppResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM attachments
+            WHERE id = ANY($1) AND channel_id = $2 AND uploader_id = $3 AND message_id IS NULL
+            "#,
+        )
+        .bind(ids)
+        .bind(channel_id)
+        .bind(uploader_id)
         .fetch_one(pool)
         .await?;
-        Ok(member)
+        Ok(count)
+    }
+
+    /// Link pending uploads to a sent message.
+    pub async fn attach_to_message(
+        pool: &PgPool,
+        message_id: i64,
+        channel_id: Uuid,
+        uploader_id: Uuid,
+        ids: &[Uuid],
+    ) -> AppResult<Vec<Attachment>> {
+        let attachments = sqlx::query_as::<_, Attachment>(
+            r#"
+            UPDATE attachments SET message_id = $1
+            WHERE id = ANY($2) AND channel_id = $3 AND uploader_id = $4 AND message_id IS NULL
+            RETURNING *
+            "#,
+        )
+        .bind(message_id)
+        .bind(ids)
+        .bind(channel_id)
+        .bind(uploader_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(attachments)
+    }
+
+    pub async fn list_for_messages(
+        pool: &PgPool,
+        message_ids: &[i64],
+    ) -> AppResult<Vec<Attachment>> {
+        let attachments = sqlx::query_as::<_, Attachment>(
+            "SELECT * FROM attachments WHERE message_id = ANY($1) ORDER BY created_at",
+        )
+        .bind(message_ids)
+        .fetch_all(pool)
+        .await?;
+        Ok(attachments)
+    }
+
+    /// Fill in `attachments` for a page of messages with a single query.
+    pub async fn populate(pool: &PgPool, messages: &mut [Message]) -> AppResult<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+        for attachment in list_for_messages(pool, &ids).await? {
+            if let Some(message) = messages
+                .iter_mut()
+                .find(|m| Some(m.id) == attachment.message_id)
+            {
+                message.attachments.push(attachment);
+            }
+        }
+        Ok(())
+    }
+}
+
+// ─── Mention Queries ────────────────────────────────────────────────────────
+
+pub mod mentions {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::Message;
+
+    /// Fill in `mentions` for a page of messages.
+    pub async fn populate(pool: &PgPool, messages: &mut [Message]) -> AppResult<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+        let rows = sqlx::query_as::<_, (i64, Uuid)>(
+            "SELECT message_id, user_id FROM message_mentions WHERE message_id = ANY($1)",
+        )
+        .bind(&ids)
+        .fetch_all(pool)
+        .await?;
+        for (message_id, user_id) in rows {
+            if let Some(message) = messages.iter_mut().find(|m| m.id == message_id) {
+                message.mentions.push(user_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+// ─── Embed Queries ──────────────────────────────────────────────────────────
+
+pub mod embeds {
+    use sqlx::PgPool;
+
+    use crate::error::AppResult;
+    use crate::models::{Embed, Message};
+
+    /// Store the preview for a message, replacing any earlier one.
+    pub async fn create(pool: &PgPool, message_id: i64, embed: &Embed) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO message_embeds (message_id, url, title, description, image_url)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (message_id) DO UPDATE SET
+                url = EXCLUDED.url,
+                title = EXCLUDED.title,
+                description = EXCLUDED.description,
+                image_url = EXCLUDED.image_url,
+                created_at = NOW()
+            "#,
+        )
+        .bind(message_id)
+        .bind(&embed.url)
+        .bind(&embed.title)
+        .bind(&embed.description)
+        .bind(&embed.image_url)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_for_message(pool: &PgPool, message_id: i64) -> AppResult<()> {
+        sqlx::query("DELETE FROM message_embeds WHERE message_id = $1")
+            .bind(message_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_for_message(pool: &PgPool, message_id: i64) -> AppResult<Vec<Embed>> {
+        let embeds = sqlx::query_as::<_, Embed>(
+            "SELECT url, title, description, image_url FROM message_embeds WHERE message_id = $1",
+        )
+        .bind(message_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(embeds)
+    }
+
+    /// Fill in `embeds` for a page of messages.
+    pub async fn populate(pool: &PgPool, messages: &mut [Message]) -> AppResult<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+        let rows =
+            sqlx::query_as::<_, (i64, String, Option<String>, Option<String>, Option<String>)>(
+                r#"
+            SELECT message_id, url, title, description, image_url
+            FROM message_embeds WHERE message_id = ANY($1)
+            "#,
+            )
+            .bind(&ids)
+            .fetch_all(pool)
+            .await?;
+        for (message_id, url, title, description, image_url) in rows {
+            if let Some(message) = messages.iter_mut().find(|m| m.id == message_id) {
+                message.embeds.push(Embed {
+                    url,
+                    title,
+                    description,
+                    image_url,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// ─── Reaction Queries ───────────────────────────────────────────────────────
+
+pub mod reactions {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::Reaction;
+
+    /// Add a reaction. Returns `false` if the user already reacted with this emoji.
+    pub async fn add(
+        pool: &PgPool,
+        message_id: i64,
+        user_id: Uuid,
+        emoji: &str,
+    ) -> AppResult<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO reactions (message_id, user_id, emoji, created_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (message_id, user_id, emoji) DO NOTHING
+            "#,
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(emoji)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove(
+        pool: &PgPool,
+        message_id: i64,
+        user_id: Uuid,
+        emoji: &str,
+    ) -> AppResult<bool> {
+        let result = sqlx::query(
+            "DELETE FROM reactions WHERE message_id = $1 AND user_id = $2 AND emoji = $3",
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(emoji)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_for_message(pool: &PgPool, message_id: i64) -> AppResult<Vec<Reaction>> {
+        let reactions = sqlx::query_as::<_, Reaction>(
+            "SELECT * FROM reactions WHERE message_id = $1 ORDER BY created_at",
+        )
+        .bind(message_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(reactions)
+    }
+}
+
+// ─── Pin Queries ────────────────────────────────────────────────────────────
+
+pub mod pins {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::Message;
+
+    /// Pin a message. Returns `false` if it was already pinned.
+    pub async fn add(
+        pool: &PgPool,
+        channel_id: Uuid,
+        message_id: i64,
+        pinned_by: Uuid,
+    ) -> AppResult<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO pinned_messages (channel_id, message_id, pinned_by, pinned_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (channel_id, message_id) DO NOTHING
+            "#,
+        )
+        .bind(channel_id)
+        .bind(message_id)
+        .bind(pinned_by)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove(pool: &PgPool, channel_id: Uuid, message_id: i64) -> AppResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM pinned_messages WHERE channel_id = $1 AND message_id = $2")
+                .bind(channel_id)
+                .bind(message_id)
+                .execute(pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn count(pool: &PgPool, channel_id: Uuid) -> AppResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pinned_messages WHERE channel_id = $1",
+        )
+        .bind(channel_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// Pinned messages of a channel, most recently pinned first.
+    pub async fn list(pool: &PgPool, channel_id: Uuid) -> AppResult<Vec<Message>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot
+            FROM pinned_messages p
+            JOIN messages m ON p.message_id = m.id
+            JOIN users u ON m.author_id = u.id
+            WHERE p.channel_id = $1
+            ORDER BY p.pinned_at DESC
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_all(pool)
+        .await?;
+
+        let mut messages: Vec<Message> =
+            rows.iter().map(super::messages::message_from_row).collect();
+        super::attachments::populate(pool, &mut messages).await?;
+        super::mentions::populate(pool, &mut messages).await?;
+        super::embeds::populate(pool, &mut messages).await?;
+        super::messages::populate_references(pool, &mut messages).await?;
+
+        Ok(messages)
+    }
+}
+
+// ─── Member Queries ─────────────────────────────────────────────────────────
+
+pub mod members {
+    use sqlx::postgres::PgRow;
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::{Member, Permissions};
+
+    /// Build a `Member` from a row produced by the member/user/roles join below.
+    fn member_from_row(row: &PgRow) -> Member {
+        use crate::models::UserPublic;
+        use sqlx::Row;
+
+        Member {
+            user_id: row.get("user_id"),
+            server_id: row.get("server_id"),
+            nickname: row.get("nickname"),
+            joined_at: row.get("joined_at"),
+            roles: row.get("roles"),
+            user: Some(UserPublic {
+                id: row.get("user_id"),
+                username: row.get("username"),
+                display_name: row.get("display_name"),
+                avatar_hash: row.get("avatar_hash"),
+                is_bot: row.get("is_bot"),
+            }),
+            status: None,
+        }
+    }
+
+    pub async fn add(pool: &PgPool, user_id: Uuid, server_id: Uuid) -> AppResult<Member> {
+        sqlx::query(
+            r#"
+            INSERT INTO members (user_id, server_id, joined_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (user_id, server_id) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(server_id)
+        .execute(pool)
+        .await?;
+
+        // Re-read so rejoining members keep their roles and the user is populated
+        let member = find(pool, user_id, server_id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+        Ok(member)
+    }
+
+    pub async fn update_nickname(
+        pool: &PgPool,
+        user_id: Uuid,
+        server_id: Uuid,
+        nickname: Option<&str>,
+    ) -> AppResult<Option<Member>> {
+        let result =
+            sqlx::query("UPDATE members SET nickname = $3 WHERE user_id = $1 AND server_id = $2")
+                .bind(user_id)
+                .bind(server_id)
+                .bind(nickname)
+                .execute(pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        find(pool, user_id, server_id).await
+    }
+
+    pub async fn remove(pool: &PgPool, user_id: Uuid, server_id: Uuid) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM members WHERE user_id = $1 AND server_id = $2")
+            .bind(user_id)
+            .bind(server_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find(pool: &PgPool, user_id: Uuid, server_id: Uuid) -> AppResult<Option<Member>> {
+        let row = sqlx::query(
+            r#"
+            SELECT m.*, 
+                   u.username, u.display_name, u.avatar_hash, u.is_bot,
+                   COALESCE(array_agg(mr.role_id) FILTER (WHERE mr.role_id IS NOT NULL), '{}') as roles
+            FROM members m
+            JOIN users u ON m.user_id = u.id
+            LEFT JOIN member_roles mr ON m.user_id = mr.user_id AND m.server_id = mr.server_id
+            WHERE m.user_id = $1 AND m.server_id = $2
+            GROUP BY m.user_id, m.server_id, u.id
+            "#,
+        )
+        .bind(user_id)
+        .bind(server_id)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.as_ref().map(member_from_row))
+    }
+
+    /// The longest-standing human member of a server outside `leaving`, who
+    /// inherits it when its owner deletes their account.
+    pub async fn find_heir(
+        pool: &PgPool,
+        server_id: Uuid,
+        leaving: &[Uuid],
+    ) -> AppResult<Option<Uuid>> {
+        let heir = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT m.user_id FROM members m
+            JOIN users u ON m.user_id = u.id
+            WHERE m.server_id = $1 AND m.user_id <> ALL($2) AND NOT u.is_bot
+            ORDER BY m.joined_at, m.user_id
+            LIMIT 1
+            "#,
+        )
+        .bind(server_id)
+        .bind(leaving)
+        .fetch_optional(pool)
+        .await?;
+        Ok(heir)
+    }
+
+    /// A page of members ordered by user id, starting after `after`. `query`
+    /// matches a prefix of the username, display name or nickname.
+    pub async fn list_paginated(
+        pool: &PgPool,
+        server_id: Uuid,
+        after: Option<Uuid>,
+        query: Option<&str>,
+        limit: i64,
+    ) -> AppResult<Vec<Member>> {
+        let pattern = query.map(super::like_prefix_pattern);
+        let rows = sqlx::query(
+            r#"
+            SELECT m.*,
+                   u.username, u.display_name, u.avatar_hash, u.is_bot,
+                   COALESCE(array_agg(mr.role_id) FILTER (WHERE mr.role_id IS NOT NULL), '{}') as roles
+            FROM members m
+            JOIN users u ON m.user_id = u.id
+            LEFT JOIN member_roles mr ON m.user_id = mr.user_id AND m.server_id = mr.server_id
+            WHERE m.server_id = $1
+              AND ($2::UUID IS NULL OR m.user_id > $2)
+              AND ($3::TEXT IS NULL
+                   OR u.username ILIKE $3
+                   OR u.display_name ILIKE $3
+                   OR m.nickname ILIKE $3)
+            GROUP BY m.user_id, m.server_id, u.id
+            ORDER BY m.user_id
+            LIMIT $4
+            "#,
+        )
+        .bind(server_id)
+        .bind(after)
+        .bind(pattern)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.iter().map(member_from_row).collect())
+    }
+
+    pub async fn add_role(
+        pool: &PgPool,
+        user_id: Uuid,
+        server_id: Uuid,
+        role_id: Uuid,
+    ) -> AppResult<()> {
+        sqlx::query(
+            "INSERT INTO member_roles (user_id, server_id, role_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        )
+        .bind(user_id)
+        .bind(server_id)
+        .bind(role_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_role(
+        pool: &PgPool,
+        user_id: Uuid,
+        server_id: Uuid,
+        role_id: Uuid,
+    ) -> AppResult<()> {
+        sqlx::query(
+            "DELETE FROM member_roles WHERE user_id = $1 AND server_id = $2 AND role_id = $3",
+        )
+        .bind(user_id)
+        .bind(server_id)
+        .bind(role_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Position of the highest role assigned to a member, if they have any.
+    pub async fn highest_role_position(
+        pool: &PgPool,
+        user_id: Uuid,
+        server_id: Uuid,
+    ) -> AppResult<Option<i32>> {
+        let position = sqlx::query_scalar::<_, Option<i32>>(
+            r#"
+            SELECT MAX(r.position)
+            FROM member_roles mr
+            JOIN roles r ON r.id = mr.role_id
+            WHERE mr.user_id = $1 AND mr.server_id = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(server_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(position)
+    }
+
+    pub async fn get_permissions(
+        pool: &PgPool,
+        user_id: Uuid,
+        server_id: Uuid,
+    ) -> AppResult<Permissions> {
+        // 1. Check if owner; nobody, not even the owner, has any permission
+        //    in a disabled server
+        let server = sqlx::query_as::<_, (Uuid, bool)>(
+            "SELECT owner_id, disabled FROM servers WHERE id = $1",
+        )
+        .bind(server_id)
+        .fetch_optional(pool)
+        .await?;
+
+        if let Some((owner_id, disabled)) = server {
+            if disabled {
+                return Ok(Permissions::new(0));
+            }
+            if owner_id == user_id {
+                return Ok(Permissions::new(Permissions::ADMINISTRATOR));
+            }
+        }
+
+        // 2. Aggregate permissions from the member's roles plus @everyone,
+        //    which applies to every member without being assigned. Members
+        //    can always view channels unless a channel override says otherwise.
+        let permissions = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT CASE
+                WHEN EXISTS (SELECT 1 FROM members m WHERE m.user_id = $1 AND m.server_id = $2)
+                THEN $3 | COALESCE((
+                    SELECT BIT_OR(r.permissions)
+                    FROM roles r
+                    WHERE r.server_id = $2
+                      AND (
+                          r.name = '@everyone'
+                          OR r.id IN (
+                              SELECT mr.role_id FROM member_roles mr
+                              WHERE mr.user_id = $1 AND mr.server_id = $2
+                          )
+                      )
+                ), 0)
+                ELSE 0
+            END
+            "#,
+        )
+        .bind(user_id)
+        .bind(server_id)
+        .bind(Permissions::VIEW_CHANNEL)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Permissions::new(permissions))
+    }
+}
+
+// ─── Role Queries ───────────────────────────────────────────────────────────
+
+pub mod roles {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::Role;
+
+    pub async fn create(
+        pool: &PgPool,
+        server_id: Uuid,
+        name: &str,
+        permissions: i64,
+        color: i32,
+        position: i32,
+    ) -> AppResult<Role> {
+        let id = Uuid::now_v7();
+        let role = sqlx::query_as::<_, Role>(
+            r#"
+            INSERT INTO roles (id, server_id, name, permissions, color, position)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(server_id)
+        .bind(name)
+        .bind(permissions)
+        .bind(color)
+        .bind(position)
+        .fetch_one(pool)
+        .await?;
+        Ok(role)
+    }
+
+    pub async fn list_for_server(pool: &PgPool, server_id: Uuid) -> AppResult<Vec<Role>> {
+        let roles = sqlx::query_as::<_, Role>(
+            "SELECT * FROM roles WHERE server_id = $1 ORDER BY position DESC",
+        )
+        .bind(server_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(roles)
+    }
+
+    pub async fn delete(pool: &PgPool, id: Uuid) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM roles WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn update(
+        pool: &PgPool,
+        role_id: Uuid,
+        server_id: Uuid,
+        name: &str,
+        permissions: i64,
+        color: i32,
+        position: i32,
+    ) -> AppResult<Option<Role>> {
+        let role = sqlx::query_as::<_, Role>(
+            r#"
+            UPDATE roles
+            SET name = $3, permissions = $4, color = $5, position = $6
+            WHERE id = $1 AND server_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(role_id)
+        .bind(server_id)
+        .bind(name)
+        .bind(permissions)
+        .bind(color)
+        .bind(position)
+        .fetch_optional(pool)
+        .await?;
+        Ok(role)
+    }
+}
+
+// ─── Channel Override Queries ───────────────────────────────────────────────
+
+pub mod overrides {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::{ChannelOverride, OverrideTarget};
+
+    pub async fn upsert(
+        pool: &PgPool,
+        channel_id: Uuid,
+        target_id: Uuid,
+        target_type: OverrideTarget,
+        allow: i64,
+        deny: i64,
+    ) -> AppResult<ChannelOverride> {
+        let o = sqlx::query_as::<_, ChannelOverride>(
+            r#"
+            INSERT INTO channel_overrides (channel_id, target_id, target_type, allow, deny)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (channel_id, target_id)
+            DO UPDATE SET target_type = EXCLUDED.target_type,
+                          allow = EXCLUDED.allow,
+                          deny = EXCLUDED.deny
+            RETURNING *
+            "#,
+        )
+        .bind(channel_id)
+        .bind(target_id)
+        .bind(target_type)
+        .bind(allow)
+        .bind(deny)
+        .fetch_one(pool)
+        .await?;
+        Ok(o)
+    }
+
+    pub async fn delete(pool: &PgPool, channel_id: Uuid, target_id: Uuid) -> AppResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM channel_overrides WHERE channel_id = $1 AND target_id = $2")
+                .bind(channel_id)
+                .bind(target_id)
+                .execute(pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Drop every override pointing at a role or user, e.g. when a role is deleted.
+    pub async fn delete_for_target(pool: &PgPool, target_id: Uuid) -> AppResult<()> {
+        sqlx::query("DELETE FROM channel_overrides WHERE target_id = $1")
+            .bind(target_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Overrides on a channel that apply to a member: their own, their roles'
+    /// and the server's @everyone role.
+    pub async fn list_for_member(
+        pool: &PgPool,
+        channel_id: Uuid,
+        server_id: Uuid,
+        user_id: Uuid,
+    ) -> AppResult<Vec<ChannelOverride>> {
+        let overrides = sqlx::query_as::<_, ChannelOverride>(
+            r#"
+            SELECT o.* FROM channel_overrides o
+            WHERE o.channel_id = $1
+              AND (
+                  (o.target_type = 'member' AND o.target_id = $3)
+                  OR (o.target_type = 'role' AND o.target_id IN (
+                      SELECT mr.role_id FROM member_roles mr
+                      WHERE mr.user_id = $3 AND mr.server_id = $2
+                      UNION
+                      SELECT r.id FROM roles r
+                      WHERE r.server_id = $2 AND r.name = '@everyone'
+                  ))
+              )
+            "#,
+        )
+        .bind(channel_id)
+        .bind(server_id)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(overrides)
+    }
+
+    /// `list_for_member` for many channels (of any servers) in one query.
+    pub async fn list_for_member_in_channels(
+        pool: &PgPool,
+        channel_ids: &[Uuid],
+        user_id: Uuid,
+    ) -> AppResult<Vec<ChannelOverride>> {
+        let overrides = sqlx::query_as::<_, ChannelOverride>(
+            r#"
+            SELECT o.* FROM channel_overrides o
+            JOIN channels c ON c.id = o.channel_id
+            WHERE o.channel_id = ANY($1)
+              AND (
+                  (o.target_type = 'member' AND o.target_id = $2)
+                  OR (o.target_type = 'role' AND o.target_id IN (
+                      SELECT mr.role_id FROM member_roles mr
+                      WHERE mr.user_id = $2 AND mr.server_id = c.server_id
+                      UNION
+                      SELECT r.id FROM roles r
+                      WHERE r.server_id = c.server_id AND r.name = '@everyone'
+                  ))
+              )
+            "#,
+        )
+        .bind(channel_ids)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(overrides)
+    }
+}
+
+// ─── Custom Emoji Queries ───────────────────────────────────────────────────
+
+pub mod emojis {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::CustomEmoji;
+
+    pub async fn create(
+        pool: &PgPool,
+        id: Uuid,
+        server_id: Uuid,
+        name: &str,
+        animated: bool,
+        creator_id: Uuid,
+    ) -> AppResult<CustomEmoji> {
+        let emoji = sqlx::query_as::<_, CustomEmoji>(
+            r#"
+            INSERT INTO custom_emojis (id, server_id, name, animated, creator_id, created_at)
+            VALUES ($1, $2, $3, $4, $5, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(server_id)
+        .bind(name)
+        .bind(animated)
+        .bind(creator_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(emoji)
+    }
+
+    /// How many of `ids` are emojis of the server.
+    pub async fn count_in_server(pool: &PgPool, server_id: Uuid, ids: &[Uuid]) -> AppResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM custom_emojis WHERE server_id = $1 AND id = ANY($2)",
+        )
+        .bind(server_id)
+        .bind(ids)
+        .fetch_one(pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// A server's emojis, oldest first.
+    pub async fn list_for_server(pool: &PgPool, server_id: Uuid) -> AppResult<Vec<CustomEmoji>> {
+        let emojis = sqlx::query_as::<_, CustomEmoji>(
+            "SELECT * FROM custom_emojis WHERE server_id = $1 ORDER BY created_at, id",
+        )
+        .bind(server_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(emojis)
+    }
+
+    pub async fn find(
+        pool: &PgPool,
+        server_id: Uuid,
+        emoji_id: Uuid,
+    ) -> AppResult<Option<CustomEmoji>> {
+        let emoji = sqlx::query_as::<_, CustomEmoji>(
+            "SELECT * FROM custom_emojis WHERE server_id = $1 AND id = $2",
+        )
+        .bind(server_id)
+        .bind(emoji_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(emoji)
+    }
+
+    pub async fn delete(pool: &PgPool, server_id: Uuid, emoji_id: Uuid) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM custom_emojis WHERE server_id = $1 AND id = $2")
+            .bind(server_id)
+            .bind(emoji_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+// ─── Ban Queries ────────────────────────────────────────────────────────────
+
+pub mod bans {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::Ban;
+
+    pub async fn create(
+        pool: &PgPool,
+        server_id: Uuid,
+        user_id: Uuid,
+        reason: Option<&str>,
+    ) -> AppResult<Ban> {
+        let ban = sqlx::query_as::<_, Ban>(
+            r#"
+            INSERT INTO bans (server_id, user_id, reason, banned_at)
+            VALUES ($1, $2, $3, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(server_id)
+        .bind(user_id)
+        .bind(reason)
+        .fetch_one(pool)
+        .await?;
+        Ok(ban)
+    }
+
+    pub async fn find(pool: &PgPool, server_id: Uuid, user_id: Uuid) -> AppResult<Option<Ban>> {
+        let ban =
+            sqlx::query_as::<_, Ban>("SELECT * FROM bans WHERE server_id = $1 AND user_id = $2")
+                .bind(server_id)
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?;
+        Ok(ban)
+    }
+
+    pub async fn delete(pool: &PgPool, server_id: Uuid, user_id: Uuid) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM bans WHERE server_id = $1 AND user_id = $2")
+            .bind(server_id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// All bans for a server, newest first, with the banned user populated.
+    pub async fn list_for_server(pool: &PgPool, server_id: Uuid) -> AppResult<Vec<Ban>> {
+        use crate::models::UserPublic;
+        use sqlx::Row;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT b.*, u.username, u.display_name, u.avatar_hash, u.is_bot
+            FROM bans b
+            JOIN users u ON b.user_id = u.id
+            WHERE b.server_id = $1
+            ORDER BY b.banned_at DESC
+            "#,
+        )
+        .bind(server_id)
+        .fetch_all(pool)
+        .await?;
+
+        let bans = rows
+            .iter()
+            .map(|row| Ban {
+                server_id: row.get("server_id"),
+                user_id: row.get("user_id"),
+                reason: row.get("reason"),
+                banned_at: row.get("banned_at"),
+                user: Some(UserPublic {
+                    id: row.get("user_id"),
+                    username: row.get("username"),
+                    display_name: row.get("display_name"),
+                    avatar_hash: row.get("avatar_hash"),
+                    is_bot: row.get("is_bot"),
+                }),
+            })
+            .collect();
+        Ok(bans)
+    }
+}
+
+// ─── Pre-Key Queries ────────────────────────────────────────────────────────
+
+pub mod prekeys {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+
+    /// Replace the user's signed pre-key.
+    pub async fn set_signed(
+        pool: &PgPool,
+        user_id: Uuid,
+        public_key: &[u8],
+        signature: &[u8],
+    ) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO signed_prekeys (user_id, public_key, signature, updated_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (user_id) DO UPDATE
+              SET public_key = $2,
+                  signature = $3,
+                  updated_at = NOW()
+            "#,
+        )
+        .bind(user_id)
+        .bind(public_key)
+        .bind(signature)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the signed pre-key and its signature.
+    pub async fn find_signed(
+        pool: &PgPool,
+        user_id: Uuid,
+    ) -> AppResult<Option<(Vec<u8>, Vec<u8>)>> {
+        let signed = sqlx::query_as::<_, (Vec<u8>, Vec<u8>)>(
+            "SELECT public_key, signature FROM signed_prekeys WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(signed)
     }
 
-    pub async fn remove(pool: &PgPool, user_id: Uuid, server_id: Uuid) -> AppResult<bool> {
-        let result = sqlx::query("DELETE FROM members WHERE user_id = $1 AND server_id = $2")
+    pub async fn add_one_time(pool: &PgPool, user_id: Uuid, keys: &[Vec<u8>]) -> AppResult<()> {
+        sqlx::query("INSERT INTO prekeys (user_id, public_key) SELECT $1, UNNEST($2::bytea[])")
             .bind(user_id)
-            .bind(server_id)
+            .bind(keys)
             .execute(pool)
             .await?;
-        Ok(result.rows_affected() > 0)
+        Ok(())
     }
 
-    pub async fn find(pool: &PgPool, user_id: Uuid, server_id: Uuid) -> AppResult<Option<Member>> {
-        let rows = sqlx::query(
+    /// Remove and return the user's oldest one-time pre-key. Each key is
+    /// handed out at most once, even under concurrent fetches.
+    pub async fn pop_one_time(pool: &PgPool, user_id: Uuid) -> AppResult<Option<Vec<u8>>> {
+        let key = sqlx::query_scalar::<_, Vec<u8>>(
             r#"
-            SELECT m.*, 
-                   u.username, u.display_name, u.avatar_hash,
-                   COALESCE(array_agg(mr.role_id) FILTER (WHERE mr.role_id IS NOT NULL), '{}') as roles
-            FROM members m
-            JOIN users u ON m.user_id = u.id
-            LEFT JOIN member_roles mr ON m.user_id = mr.user_id AND m.server_id = mr.server_id
-            WHERE m.user_id = $1 AND m.server_id = $2
-            GROUP BY m.user_id, m.server_id, u.id
+            DELETE FROM prekeys
+            WHERE id = (
+                SELECT id FROM prekeys
+                WHERE user_id = $1
+                ORDER BY id
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING public_key
             "#,
         )
         .bind(user_id)
-        .bind(server_id)
         .fetch_optional(pool)
         .await?;
-
-        let member = rows.map(|row| {
-            use crate::models::UserPublic;
-            use sqlx::Row;
-
-            Member {
-                user_id: row.get("user_id"),
-                server_id: row.get("server_id"),
-                nickname: row.get("nickname"),
-                joined_at: row.get("joined_at"),
-                roles: row.get("roles"),
-                user: Some(UserPublic {
-                    id: row.get("user_id"),
-                    username: row.get("username"),
-                    display_name: row.get("display_name"),
-                    avatar_hash: row.get("avatar_hash"),
-                }),
-                status: None,
-            }
-        });
-        Ok(member)
+        Ok(key)
     }
 
-    pub async fn list_for_server(pool: &PgPool, server_id: Uuid) -> AppResult<Vec<Member>> {
-        let rows = sqlx::query(
-            r#"
-            SELECT m.*, 
-                   u.username, u.display_name, u.avatar_hash,
-                   COALESCE(array_agg(mr.role_id) FILTER (WHERE mr.role_id IS NOT NULL), '{}') as roles
-            FROM members m
-            JOIN users u ON m.user_id = u.id
-            LEFT JOIN member_roles mr ON m.user_id = mr.user_id AND m.server_id = mr.server_id
-            WHERE m.server_id = $1
-            GROUP BY m.user_id, m.server_id, u.id
-            ORDER BY m.joined_at
-            "#,
-        )
-        .bind(server_id)
-        .fetch_all(pool)
-        .await?;
-
-        let members = rows
-            .into_iter()
-            .map(|row| {
-                use crate::models::UserPublic;
-                use sqlx::Row;
-
-                Member {
-                    user_id: row.get("user_id"),
-                    server_id: row.get("server_id"),
-                    nickname: row.get("nickname"),
-                    joined_at: row.get("joined_at"),
-                    roles: row.get("roles"),
-                    user: Some(UserPublic {
-                        id: row.get("user_id"),
-                        username: row.get("username"),
-                        display_name: row.get("display_name"),
-                        avatar_hash: row.get("avatar_hash"),
-                    }),
-                    status: None,
-                }
-            })
-            .collect();
-        Ok(members)
+    pub async fn count_one_time(pool: &PgPool, user_id: Uuid) -> AppResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM prekeys WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?;
+        Ok(count)
     }
 
-    pub async fn add_role(
+    /// Switch a user to a new identity key, dropping every pre-key signed
+    /// under the old one, in one transaction.
+    pub async fn replace_identity(
         pool: &PgPool,
         user_id: Uuid,
-        server_id: Uuid,
-        role_id: Uuid,
+        identity_key: &[u8],
     ) -> AppResult<()> {
-        sqlx::query(
-            "INSERT INTO member_roles (user_id, server_id, role_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
-        )
-        .bind(user_id)
-        .bind(server_id)
-        .bind(role_id)
-        .execute(pool)
-        .await?;
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM prekeys WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM signed_prekeys WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE users SET identity_key_public = $2 WHERE id = $1")
+            .bind(user_id)
+            .bind(identity_key)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
         Ok(())
     }
+}
 
-    pub async fn remove_role(
+// ─── Audit Log Queries ──────────────────────────────────────────────────────
+
+pub mod audit {
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use crate::error::AppResult;
+    use crate::models::{AuditAction, AuditLogEntry};
+
+    pub async fn insert(
         pool: &PgPool,
-        user_id: Uuid,
         server_id: Uuid,
-        role_id: Uuid,
+        actor_id: Uuid,
+        action: AuditAction,
+        target_id: Option<Uuid>,
+        role_id: Option<Uuid>,
+        reason: Option<&str>,
     ) -> AppResult<()> {
         sqlx::query(
-            "DELETE FROM member_roles WHERE user_id = $1 AND server_id = $2 AND role_id = $3",
+            r#"
+            INSERT INTO audit_log (server_id, actor_id, action, target_id, role_id, reason, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW())
+            "#,
         )
-        .bind(user_id)
         .bind(server_id)
+        .bind(actor_id)
+        .bind(action)
+        .bind(target_id)
         .bind(role_id)
+        .bind(reason)
         .execute(pool)
         .await?;
         Ok(())
     }
 
-    pub async fn get_permissions(
+    /// Newest entries first; `before` pages back through older ones.
+    pub async fn list_for_server(
         pool: &PgPool,
-        user_id: Uuid,
         server_id: Uuid,
-    ) -> AppResult<Permissions> {
-        // 1. Check if owner
-        let server_owner =
-            sqlx::query_scalar::<_, Uuid>("SELECT owner_id FROM servers WHERE id = $1")
-                .bind(server_id)
-                .fetch_optional(pool)
-                .await?;
-
-        if let Some(owner_id) = server_owner {
-            if owner_id == user_id {
-                return Ok(Permissions::new(Permissions::ADMINISTRATOR));
-            }
-        }
-
-        // 2. Aggregate permissions from roles
-        let permissions = sqlx::query_scalar::<_, i64>(
+        before: Option<i64>,
+        limit: i64,
+    ) -> AppResult<Vec<AuditLogEntry>> {
+        let entries = sqlx::query_as::<_, AuditLogEntry>(
             r#"
-            SELECT COALESCE(BIT_OR(r.permissions), 0)
-            FROM member_roles mr
-            JOIN roles r ON mr.role_id = r.id
-            WHERE mr.user_id = $1 AND mr.server_id = $2
+            SELECT * FROM audit_log
+            WHERE server_id = $1 AND ($2::BIGINT IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3
             "#,
         )
-        .bind(user_id)
         .bind(server_id)
-        .fetch_one(pool)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(pool)
         .await?;
-
-        Ok(Permissions::new(permissions))
+        Ok(entries)
     }
 }
 
-// ─── Role Queries ───────────────────────────────────────────────────────────
+// ─── Webhook Queries ────────────────────────────────────────────────────────
 
-pub mod roles {
+pub mod webhooks {
     use sqlx::PgPool;
     use uuid::Uuid;
 
+    use super::NO_PASSWORD_HASH;
     use crate::error::AppResult;
-    use crate::models::Role;
+    use crate::models::{Message, Webhook};
 
+    /// Create a webhook together with the synthetic user that authors its messages.
     pub async fn create(
         pool: &PgPool,
-        server_id: Uuid,
+        id: Uuid,
+        channel_id: Uuid,
         name: &str,
-        permissions: i64,
-        color: i32,
-        position: i32,
-    ) -> AppResult<Role> {
-        let id = Uuid::now_v7();
-        let role = sqlx::query_as::<_, Role>(
+        avatar_url: Option<&str>,
+        token_hash: &str,
+        created_by: Uuid,
+    ) -> AppResult<Webhook> {
+        let user_id = Uuid::now_v7();
+        // Usernames are capped at 32 characters; the id tail keeps them unique
+        let username = format!("webhook-{}", &id.simple().to_string()[8..]);
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(
             r#"
-            INSERT INTO roles (id, server_id, name, permissions, color, position)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            INSERT INTO users (id, username, display_name, password_hash, created_at, last_seen)
+            VALUES ($1, $2, $3, $4, NOW(), NOW())
+            "#,
+        )
+        .bind(user_id)
+        .bind(&username)
+        .bind(name)
+        .bind(NO_PASSWORD_HASH)
+        .execute(&mut *tx)
+        .await?;
+
+        let webhook = sqlx::query_as::<_, Webhook>(
+            r#"
+            INSERT INTO webhooks (id, channel_id, user_id, name, avatar_url, token_hash, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
             RETURNING *
             "#,
         )
         .bind(id)
-        .bind(server_id)
+        .bind(channel_id)
+        .bind(user_id)
         .bind(name)
-        .bind(permissions)
-        .bind(color)
-        .bind(position)
-        .fetch_one(pool)
+        .bind(avatar_url)
+        .bind(token_hash)
+        .bind(created_by)
+        .fetch_one(&mut *tx)
         .await?;
-        Ok(role)
+        tx.commit().await?;
+
+        Ok(webhook)
     }
 
-    pub async fn list_for_server(pool: &PgPool, server_id: Uuid) -> AppResult<Vec<Role>> {
-        let roles = sqlx::query_as::<_, Role>(
-            "SELECT * FROM roles WHERE server_id = $1 ORDER BY position DESC",
+    pub async fn find_by_id(pool: &PgPool, id: Uuid) -> AppResult<Option<Webhook>> {
+        let webhook = sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(webhook)
+    }
+
+    pub async fn list_for_channel(pool: &PgPool, channel_id: Uuid) -> AppResult<Vec<Webhook>> {
+        let webhooks = sqlx::query_as::<_, Webhook>(
+            "SELECT * FROM webhooks WHERE channel_id = $1 ORDER BY created_at",
         )
-        .bind(server_id)
+        .bind(channel_id)
         .fetch_all(pool)
         .await?;
-        Ok(roles)
+        Ok(webhooks)
     }
 
-    pub async fn delete(pool: &PgPool, id: Uuid) -> AppResult<bool> {
-        let result = sqlx::query("DELETE FROM roles WHERE id = $1")
-            .bind(id)
-            .execute(pool)
+    /// Delete a webhook with its synthetic user and, since they can't outlive
+    /// their author, its messages. Returns the deleted message ids, or `None`
+    /// if the channel has no such webhook.
+    pub async fn delete(pool: &PgPool, channel_id: Uuid, id: Uuid) -> AppResult<Option<Vec<i64>>> {
+        let mut tx = pool.begin().await?;
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            "DELETE FROM webhooks WHERE id = $1 AND channel_id = $2 RETURNING user_id",
+        )
+        .bind(id)
+        .bind(channel_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        let message_ids =
+            sqlx::query_scalar::<_, i64>("DELETE FROM messages WHERE author_id = $1 RETURNING id")
+                .bind(user_id)
+                .fetch_all(&mut *tx)
+                .await?;
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
             .await?;
-        Ok(result.rows_affected() > 0)
+        tx.commit().await?;
+
+        Ok(Some(message_ids))
     }
 
-    pub async fn update(
+    /// Post a message as the webhook, with optional per-message name and avatar.
+    pub async fn create_message(
         pool: &PgPool,
-        role_id: Uuid,
-        server_id: Uuid,
-        name: &str,
-        permissions: i64,
-        color: i32,
-        position: i32,
-    ) -> AppResult<Option<Role>> {
-        let role = sqlx::query_as::<_, Role>(
+        id: i64,
+        webhook: &Webhook,
+        content: &str,
+        username: Option<&str>,
+        avatar_url: Option<&str>,
+    ) -> AppResult<Message> {
+        sqlx::query(
             r#"
-            UPDATE roles
-            SET name = $3, permissions = $4, color = $5, position = $6
-            WHERE id = $1 AND server_id = $2
-            RETURNING *
+            INSERT INTO messages (id, channel_id, author_id, content, created_at,
+                                  webhook_id, webhook_username, webhook_avatar_url)
+            VALUES ($1, $2, $3, $4, NOW(), $5, $6, $7)
             "#,
         )
-        .bind(role_id)
-        .bind(server_id)
-        .bind(name)
-        .bind(permissions)
-        .bind(color)
-        .bind(position)
-        .fetch_optional(pool)
+        .bind(id)
+        .bind(webhook.channel_id)
+        .bind(webhook.user_id)
+        .bind(content)
+        .bind(webhook.id)
+        .bind(username)
+        .bind(avatar_url.or(webhook.avatar_url.as_deref()))
+        .execute(pool)
         .await?;
-        Ok(role)
+
+        let row = sqlx::query(
+            r#"
+            SELECT m.*, u.username, u.display_name, u.avatar_hash, u.is_bot
+            FROM messages m
+            JOIN users u ON m.author_id = u.id
+            WHERE m.id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+        Ok(super::messages::message_from_row(&row))
     }
 }
 
-// ─── Ban Queries ────────────────────────────────────────────────────────────
+// ─── Read State Queries ─────────────────────────────────────────────────────
 
-pub mod bans {
+pub mod read_states {
     use sqlx::PgPool;
     use uuid::Uuid;
 
     use crate::error::AppResult;
-    use crate::models::Ban;
+    use crate::models::ReadState;
 
-    pub async fn create(
+    /// Unread counts stop here; clients show it as "99+".
+    pub const MAX_UNREAD_COUNT: i64 = 100;
+
+    /// Mark `channel_id` read up to `message_id`. Acks never move backwards.
+    pub async fn ack(
         pool: &PgPool,
-        server_id: Uuid,
         user_id: Uuid,
-        reason: Option<&str>,
-    ) -> AppResult<Ban> {
-        let ban = sqlx::query_as::<_, Ban>(
+        channel_id: Uuid,
+        message_id: i64,
+    ) -> AppResult<()> {
+        sqlx::query(
             r#"
-            INSERT INTO bans (server_id, user_id, reason, banned_at)
+            INSERT INTO read_states (user_id, channel_id, last_read_message_id, updated_at)
             VALUES ($1, $2, $3, NOW())
-            RETURNING *
+            ON CONFLICT (user_id, channel_id) DO UPDATE
+            SET last_read_message_id = GREATEST(read_states.last_read_message_id, EXCLUDED.last_read_message_id),
+                updated_at = NOW()
             "#,
         )
-        .bind(server_id)
         .bind(user_id)
-        .bind(reason)
-        .fetch_one(pool)
+        .bind(channel_id)
+        .bind(message_id)
+        .execute(pool)
         .await?;
-        Ok(ban)
+        Ok(())
     }
 
-    pub async fn find(pool: &PgPool, server_id: Uuid, user_id: Uuid) -> AppResult<Option<Ban>> {
-        let ban =
-            sqlx::query_as::<_, Ban>("SELECT * FROM bans WHERE server_id = $1 AND user_id = $2")
-                .bind(server_id)
-                .bind(user_id)
-                .fetch_optional(pool)
-                .await?;
-        Ok(ban)
+    /// Read state of each of `channel_ids` for `user_id`, including channels
+    /// the user has never acknowledged.
+    pub async fn list_for_channels(
+        pool: &PgPool,
+        user_id: Uuid,
+        channel_ids: &[Uuid],
+    ) -> AppResult<Vec<ReadState>> {
+        let states = sqlx::query_as::<_, ReadState>(
+            r#"
+            SELECT c.id AS channel_id,
+                   r.last_read_message_id,
+                   (SELECT MAX(m.id) FROM messages m WHERE m.channel_id = c.id) AS last_message_id,
+                   (SELECT COUNT(*) FROM (
+                        SELECT 1 FROM messages m
+                        WHERE m.channel_id = c.id
+                          AND m.id > COALESCE(r.last_read_message_id, 0)
+                          AND m.author_id <> $1
+                          AND NOT m.is_deleted
+                        LIMIT $3
+                   ) unread) AS unread_count
+            FROM UNNEST($2::uuid[]) AS c(id)
+            LEFT JOIN read_states r ON r.channel_id = c.id AND r.user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(channel_ids)
+        .bind(MAX_UNREAD_COUNT)
+        .fetch_all(pool)
+        .await?;
+        Ok(states)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_retry_delay_backs_off_exponentially() {
+        let base = Duration::from_millis(500);
+        assert_eq!(connect_retry_delay(base, 1), Duration::from_millis(500));
+        assert_eq!(connect_retry_delay(base, 2), Duration::from_secs(1));
+        assert_eq!(connect_retry_delay(base, 4), Duration::from_secs(4));
+        assert_eq!(connect_retry_delay(base, 7), MAX_CONNECT_RETRY_DELAY);
+        assert_eq!(connect_retry_delay(base, 40), MAX_CONNECT_RETRY_DELAY);
     }
 
-    pub async fn delete(pool: &PgPool, server_id: Uuid, user_id: Uuid) -> AppResult<bool> {
-        let result = sqlx::query("DELETE FROM bans WHERE server_id = $1 AND user_id = $2")
-            .bind(server_id)
-            .bind(user_id)
-            .execute(pool)
-            .await?;
-        Ok(result.rows_affected() > 0)
+    #[test]
+    fn test_like_prefix_pattern_escapes_wildcards() {
+        assert_eq!(like_prefix_pattern("pen"), "pen%");
+        assert_eq!(like_prefix_pattern("50%_off"), "50\\%\\_off%");
+        assert_eq!(like_prefix_pattern("a\\b"), "a\\\\b%");
+    }
+
+    #[test]
+    fn test_refresh_tokens_rotate_once_and_only_while_live() {
+        let now = chrono::Utc::now();
+        let user_id = uuid::Uuid::now_v7();
+        let live = Some((user_id, now + chrono::Duration::days(1)));
+        assert_eq!(refresh_tokens::live_owner(live, now), Some(user_id));
+
+        // Reusing a rotated token finds no row
+        assert_eq!(refresh_tokens::live_owner(None, now), None);
+        let expired = Some((user_id, now - chrono::Duration::seconds(1)));
+        assert_eq!(refresh_tokens::live_owner(expired, now), None);
+    }
+
+    #[test]
+    fn test_replies_get_the_message_they_point_at() {
+        let message = |id, channel_id, reply_to_id| crate::models::Message {
+            id,
+            channel_id,
+            author_id: uuid::Uuid::now_v7(),
+            content: format!("message {}", id),
+            nonce: None,
+            created_at: chrono::Utc::now(),
+            edited_at: None,
+            reply_to_id,
+            is_deleted: false,
+            author: None,
+            attachments: Vec::new(),
+            webhook_id: None,
+            avatar_url: None,
+            mentions: Vec::new(),
+            mention_everyone: false,
+            referenced_message: None,
+            embeds: Vec::new(),
+        };
+        let channel = uuid::Uuid::now_v7();
+        let elsewhere = uuid::Uuid::now_v7();
+        let referenced: std::collections::HashMap<_, _> = [
+            (1, message(1, channel, None)),
+            (2, message(2, elsewhere, None)),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut messages = vec![
+            message(10, channel, Some(1)),
+            message(11, channel, Some(2)),
+            message(12, channel, Some(99)),
+            message(13, channel, None),
+        ];
+        messages::attach_references(&mut messages, &referenced);
+        assert_eq!(
+            messages[0].referenced_message.as_ref().map(|r| r.id),
+            Some(1)
+        );
+        // Not into another channel, and not to a message that's gone
+        assert!(messages[1].referenced_message.is_none());
+        assert!(messages[2].referenced_message.is_none());
+        assert!(messages[3].referenced_message.is_none());
+    }
+
+    #[test]
+    fn test_no_password_hash_rejects_passwords() {
+        // A malformed placeholder would make logins error instead of fail
+        assert!(!crate::auth::verify_password("", NO_PASSWORD_HASH).unwrap());
+        assert!(!crate::auth::verify_password("hunter2", NO_PASSWORD_HASH).unwrap());
     }
 }
@@ -0,0 +1,439 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{header, redirect, Url};
+
+use crate::models::Embed;
+
+/// Give up on a page that takes longer than this to fetch.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Only this much of a page is read; Open Graph tags live in the `<head>`.
+const MAX_BODY_BYTES: usize = 512 * 1024;
+const MAX_REDIRECTS: usize = 3;
+/// Longest stored title / description (in characters).
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 1024;
+
+/// HTTP client for fetching link previews. Separate from `AppState::http_client`
+/// because it refuses to connect to anything but public addresses: names
+/// resolve through `PublicResolver`, and redirects are checked hop by hop.
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent("AntarcticomBot/1.0 (link preview)")
+        // A proxy would resolve the target itself, bypassing the resolver
+        .no_proxy()
+        .dns_resolver(Arc::new(PublicResolver))
+        .redirect(redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.stop()
+            } else if !is_fetchable(attempt.url()) {
+                attempt.error("redirect to a non-public address")
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()
+        .unwrap_or_default()
+}
+
+/// Resolves names like the system resolver, minus any non-public address.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// The first http(s) link in a message. Links wrapped in `<...>` are skipped
+/// so a sender can opt out of the preview.
+pub fn first_url(content: &str) -> Option<Url> {
+    content.split_whitespace().find_map(|word| {
+        if word.starts_with('<') {
+            return None;
+        }
+        let start = word.find("https://").or_else(|| word.find("http://"))?;
+        let candidate = word[start..].trim_end_matches(['.', ',', '!', '?', ')', '>', '"', '\'']);
+        Url::parse(candidate).ok().filter(is_fetchable)
+    })
+}
+
+/// An http(s) URL whose host isn't a literal non-public IP. Hostnames are
+/// checked when they resolve.
+fn is_fetchable(url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    // IPv6 hosts come bracketed
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => !host.eq_ignore_ascii_case("localhost"),
+    }
+}
+
+/// Whether an address is globally routable, i.e. not loopback, private,
+/// link-local, shared (CGNAT), reserved or otherwise special-purpose.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Shared address space (carrier-grade NAT)
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking
+        || (a == 198 && (18..20).contains(&b))
+        // Reserved
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(v4);
+    }
+    let segments = ip.segments();
+    // NAT64 addresses embed an IPv4 address in their last 32 bits
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., hi, lo] = segments;
+        return is_public_ipv4(Ipv4Addr::from(((hi as u32) << 16) | lo as u32));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local (fc00::/7)
+        || (segments[0] & 0xfe00) == 0xfc00
+        // Link-local (fe80::/10)
+        || (segments[0] & 0xffc0) == 0xfe80
+        // Documentation (2001:db8::/32)
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+/// Fetch `url` and build a preview from its Open Graph tags. `None` when the
+/// page can't be fetched, isn't HTML or has nothing worth showing.
+pub async fn unfurl(client: &reqwest::Client, url: &Url) -> Option<Embed> {
+    if !is_fetchable(url) {
+        return None;
+    }
+    let mut response = client.get(url.clone()).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().starts_with("text/html"));
+    if !is_html {
+        return None;
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.ok()? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_BODY_BYTES {
+            body.truncate(MAX_BODY_BYTES);
+            break;
+        }
+    }
+
+    // Relative image links resolve against where the page actually came from
+    parse_embed(&String::from_utf8_lossy(&body), url, response.url())
+}
+
+/// Build a preview for `url` from a page's Open Graph (or Twitter card) tags,
+/// falling back to `<title>` and the plain description meta tag.
+fn parse_embed(html: &str, url: &Url, base: &Url) -> Option<Embed> {
+    let mut title = None;
+    let mut description = None;
+    let mut image = None;
+    let mut fallback_description = None;
+
+    for attrs in meta_tags(html) {
+        let key = attrs
+            .iter()
+            .find(|(name, _)| name == "property" || name == "name")
+            .map(|(_, value)| value.to_ascii_lowercase());
+        let content = attrs
+            .iter()
+            .find(|(name, _)| name == "content")
+            .map(|(_, value)| value.clone());
+        let (Some(key), Some(content)) = (key, content) else {
+            continue;
+        };
+        match key.as_str() {
+            "og:title" => title = Some(content),
+            "twitter:title" => {
+                title.get_or_insert(content);
+            }
+            "og:description" => description = Some(content),
+            "twitter:description" => {
+                description.get_or_insert(content);
+            }
+            "description" => fallback_description = Some(content),
+            "og:image" | "og:image:url" | "og:image:secure_url" => image = Some(content),
+            "twitter:image" => {
+                image.get_or_insert(content);
+            }
+            _ => {}
+        }
+    }
+
+    let title = title
+        .or_else(|| title_tag(html))
+        .and_then(|t| clean_text(&t, MAX_TITLE_LENGTH));
+    let description = description
+        .or(fallback_description)
+        .and_then(|d| clean_text(&d, MAX_DESCRIPTION_LENGTH));
+    if title.is_none() && description.is_none() {
+        return None;
+    }
+    let image_url = image
+        .and_then(|src| base.join(decode_entities(src.trim()).as_str()).ok())
+        .filter(|src| matches!(src.scheme(), "http" | "https"))
+        .map(String::from);
+
+    Some(Embed {
+        url: url.to_string(),
+        title,
+        description,
+        image_url,
+    })
+}
+
+/// The attributes (lowercased names, raw values) of every `<meta>` tag.
+fn meta_tags(html: &str) -> Vec<Vec<(String, String)>> {
+    // ASCII lowercasing keeps byte offsets, so positions carry over to `html`
+    let lower = html.to_ascii_lowercase();
+    let mut tags = Vec::new();
+    let mut rest = 0;
+    while let Some(found) = lower[rest..].find("<meta") {
+        let start = rest + found + "<meta".len();
+        let Some(len) = html[start..].find('>') else {
+            break;
+        };
+        tags.push(parse_attributes(&html[start..start + len]));
+        rest = start + len;
+    }
+    tags
+}
+
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut chars = tag.char_indices().peekable();
+    loop {
+        while chars
+            .next_if(|(_, c)| c.is_whitespace() || *c == '/')
+            .is_some()
+        {}
+        let Some(&(name_start, _)) = chars.peek() else {
+            break;
+        };
+        let mut name_end = tag.len();
+        while let Some(&(i, c)) = chars.peek() {
+            if c == '=' || c.is_whitespace() {
+                name_end = i;
+                break;
+            }
+            chars.next();
+        }
+        let name = tag[name_start..name_end].to_ascii_lowercase();
+
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        if chars.next_if(|(_, c)| *c == '=').is_none() {
+            attrs.push((name, String::new()));
+            continue;
+        }
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        let value = match chars.next() {
+            Some((i, quote @ ('"' | '\''))) => {
+                let start = i + 1;
+                let end = tag[start..].find(quote).map_or(tag.len(), |n| start + n);
+                while chars.next_if(|(j, _)| *j <= end).is_some() {}
+                &tag[start..end]
+            }
+            Some((start, _)) => {
+                let mut end = tag.len();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_whitespace() {
+                        end = j;
+                        break;
+                    }
+                    chars.next();
+                }
+                &tag[start..end]
+            }
+            None => "",
+        };
+        attrs.push((name, value.to_string()));
+    }
+    attrs
+}
+
+fn title_tag(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    Some(html[start..end].to_string())
+}
+
+/// Decode entities, collapse whitespace and cap the length. `None` if nothing
+/// is left.
+fn clean_text(raw: &str, max_chars: usize) -> Option<String> {
+    let text = decode_entities(raw)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    if text.chars().count() <= max_chars {
+        return Some(text);
+    }
+    let mut truncated: String = text.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    Some(truncated)
+}
+
+/// Decode the named entities pages commonly use plus numeric references.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                _ => {
+                    let code = match entity.strip_prefix('#')? {
+                        hex if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16),
+                        dec => dec.parse(),
+                    };
+                    char::from_u32(code.ok()?)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_url() {
+        assert_eq!(
+            first_url("look: https://example.com/a?b=1, neat").map(String::from),
+            Some("https://example.com/a?b=1".to_string())
+        );
+        assert_eq!(
+            first_url("(see https://example.com/x)").map(String::from),
+            Some("https://example.com/x".to_string())
+        );
+        // Suppressed, non-http and internal links don't unfurl
+        assert!(first_url("<https://example.com> ftp://example.com").is_none());
+        assert!(first_url("http://127.0.0.1/admin http://localhost:8080").is_none());
+        assert!(first_url("no links here").is_none());
+    }
+
+    #[test]
+    fn test_only_public_addresses_are_fetchable() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{} is not public", ip);
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+
+    #[test]
+    fn test_parse_open_graph_tags() {
+        let html = r#"<html><head>
+            <title>Fallback title</title>
+            <meta property="og:title" content="Penguins &amp; Ice">
+            <META name='description' content='Plain description'>
+            <meta property="og:description" content="All about   penguins.">
+            <meta property="og:image" content="/img/penguin.png" />
+        </head></html>"#;
+        let url = Url::parse("https://example.com/post").unwrap();
+        let base = Url::parse("https://www.example.com/post").unwrap();
+
+        let embed = parse_embed(html, &url, &base).unwrap();
+        assert_eq!(embed.url, "https://example.com/post");
+        assert_eq!(embed.title.as_deref(), Some("Penguins & Ice"));
+        assert_eq!(embed.description.as_deref(), Some("All about penguins."));
+        assert_eq!(
+            embed.image_url.as_deref(),
+            Some("https://www.example.com/img/penguin.png")
+        );
+
+        let plain = parse_embed("<title>Just &#8220;a&#x201D; title</title>", &url, &url).unwrap();
+        assert_eq!(plain.title.as_deref(), Some("Just \u{201c}a\u{201d} title"));
+        assert!(plain.description.is_none() && plain.image_url.is_none());
+
+        assert!(parse_embed("<p>nothing</p>", &url, &url).is_none());
+    }
+}
@@ -1,8 +1,9 @@
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use serde_json::json;
+use serde::Serialize;
 use thiserror::Error;
+use utoipa::ToSchema;
 
 /// Application-wide error type.
 #[derive(Debug, Error)]
@@ -22,9 +23,12 @@ pub enum AppError {
     #[error("Conflict: {0}")]
     Conflict(String),
 
-    #[allow(dead_code)]
+    #[error("Gone: {0}")]
+    Gone(String),
+
+    /// Too many requests; optionally how many seconds until the client may retry.
     #[error("Rate limited")]
-    RateLimited,
+    RateLimited(Option<u64>),
 
     #[error("Internal server error")]
     Internal(#[from] anyhow::Error),
@@ -33,6 +37,8 @@ pub enum AppError {
     Database(#[from] sqlx::Error),
 }
 
+/// Internal and database errors are logged here, inside the handler's request
+/// span, so the log line carries the request id echoed to the client.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, message) = match &self {
@@ -41,7 +47,8 @@ impl IntoResponse for AppError {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
             AppError::Conflict(msg) => (StatusCode::CONFLICT, msg.clone()),
-            AppError::RateLimited => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            AppError::Gone(msg) => (StatusCode::GONE, msg.clone()),
+            AppError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
             AppError::Internal(e) => {
                 tracing::error!("Internal error: {:?}", e);
                 (
@@ -58,15 +65,45 @@ impl IntoResponse for AppError {
             }
         };
 
-        let body = json!({
-            "error": {
-                "code": status.as_u16(),
-                "message": message,
-            }
-        });
+        let retry_after = match self {
+            AppError::RateLimited(retry_after) => retry_after,
+            _ => None,
+        };
+        let body = ErrorResponse {
+            error: ErrorDetail {
+                code: status.as_u16(),
+                message,
+                retry_after,
+            },
+        };
+
+        if let Some(retry_after) = retry_after {
+            return (
+                status,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                Json(body),
+            )
+                .into_response();
+        }
 
         (status, Json(body)).into_response()
     }
 }
 
 pub type AppResult<T> = Result<T, AppError>;
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorDetail {
+    /// HTTP status code
+    pub code: u16,
+    pub message: String,
+    /// Seconds until a rate-limited request may be retried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
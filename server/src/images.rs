@@ -0,0 +1,168 @@
+//! Uploaded images (avatars, emojis): header-level inspection and thumbnails.
+//!
+//! `content_type` and `dimensions` only look at the bytes needed to identify
+//! the format and read the canvas size; nothing is decoded, so a
+//! decompression bomb costs us nothing to reject. Only images that passed
+//! those checks should be handed to `thumbnails`.
+
+use std::io::Cursor;
+
+use image::imageops::FilterType;
+use image::ImageFormat;
+
+/// Content type of a PNG, JPEG, GIF or WebP image, from its magic bytes.
+pub fn content_type(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// `(width, height)` in pixels as declared in the image header, or `None` if
+/// the header is missing or malformed.
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    match content_type(data)? {
+        "image/png" => png_dimensions(data),
+        "image/jpeg" => jpeg_dimensions(data),
+        "image/gif" => Some((
+            u16_le(data.get(6..8)?) as u32,
+            u16_le(data.get(8..10)?) as u32,
+        )),
+        _ => webp_dimensions(data),
+    }
+}
+
+/// Square PNG thumbnails of an image, one per entry of `sizes`, cropped to
+/// fill. Decoding is CPU-bound: call this from a blocking task.
+pub fn thumbnails(data: &[u8], sizes: &[u32]) -> anyhow::Result<Vec<(u32, Vec<u8>)>> {
+    let image = image::load_from_memory(data)?;
+    sizes
+        .iter()
+        .map(|&size| {
+            let mut png = Cursor::new(Vec::new());
+            image
+                .resize_to_fill(size, size, FilterType::Lanczos3)
+                .write_to(&mut png, ImageFormat::Png)?;
+            Ok((size, png.into_inner()))
+        })
+        .collect()
+}
+
+fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    // The IHDR chunk always comes first
+    if data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    Some((u32_be(data.get(16..20)?), u32_be(data.get(20..24)?)))
+}
+
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    loop {
+        // Markers may be padded with any number of 0xFF bytes
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        while *data.get(pos)? == 0xFF {
+            pos += 1;
+        }
+        let marker = *data.get(pos)?;
+        pos += 1;
+
+        // Standalone markers carry no length
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            continue;
+        }
+        let length = u16_be(data.get(pos..pos + 2)?) as usize;
+        // Start of frame, except DHT (C4), JPG (C8) and DAC (CC)
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            let height = u16_be(data.get(pos + 3..pos + 5)?) as u32;
+            let width = u16_be(data.get(pos + 5..pos + 7)?) as u32;
+            return Some((width, height));
+        }
+        if length < 2 {
+            return None;
+        }
+        pos += length;
+    }
+}
+
+fn webp_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    match data.get(12..16)? {
+        // Lossy: a VP8 key frame header after the 3-byte frame tag
+        b"VP8 " => {
+            if data.get(23..26)? != [0x9D, 0x01, 0x2A] {
+                return None;
+            }
+            Some((
+                (u16_le(data.get(26..28)?) & 0x3FFF) as u32,
+                (u16_le(data.get(28..30)?) & 0x3FFF) as u32,
+            ))
+        }
+        // Lossless: 14-bit width and height (minus one) after the signature
+        b"VP8L" => {
+            if *data.get(20)? != 0x2F {
+                return None;
+            }
+            let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        // Extended: 24-bit canvas width and height (minus one)
+        b"VP8X" => Some((u24_le(data.get(24..27)?) + 1, u24_le(data.get(27..30)?) + 1)),
+        _ => None,
+    }
+}
+
+fn u16_le(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn u16_be(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn u24_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+fn u32_be(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_header_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&20000u32.to_be_bytes());
+        assert_eq!(dimensions(&png), Some((640, 20000)));
+
+        let gif = b"GIF89a\x80\x00\x40\x00";
+        assert_eq!(dimensions(gif), Some((128, 64)));
+
+        // SOI, an APP0 segment to skip, then a baseline SOF0
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01,
+            0x00, 0x02, 0x00,
+        ];
+        assert_eq!(dimensions(&jpeg), Some((512, 256)));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0\0\0\0\0".to_vec();
+        webp.extend_from_slice(&[0xFF, 0x0F, 0x00, 0x63, 0x00, 0x00]);
+        assert_eq!(dimensions(&webp), Some((4096, 100)));
+    }
+
+    #[test]
+    fn test_rejects_truncated_and_unknown_images() {
+        assert_eq!(dimensions(b"\x89PNG\r\n\x1a\n"), None);
+        assert_eq!(dimensions(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00]), None);
+        assert_eq!(dimensions(b"<svg></svg>"), None);
+        assert_eq!(content_type(b"<svg></svg>"), None);
+    }
+}
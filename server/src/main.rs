@@ -1,5 +1,8 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
 use sqlx::PgPool;
+use std::net::SocketAddr;
+use std::time::Duration;
 use tracing_subscriber::{fmt, EnvFilter};
 use uuid::Uuid;
 
@@ -9,13 +12,23 @@ mod chat;
 mod config;
 mod crypto;
 mod db;
+mod embeds;
 mod error;
+mod images;
 mod models;
+mod openapi;
 mod presence;
+mod ratelimit;
+mod session;
+mod storage;
 mod voice;
+mod voice_state;
+
+use axum_server::tls_rustls::RustlsConfig;
+use rustls_acme::{axum::AxumAcceptor, caches::DirCache, AcmeConfig};
 
 use crate::config::AppConfig;
-use crate::models::ChannelType;
+use crate::models::{ChannelType, Permissions};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -59,6 +72,17 @@ async fn main() -> Result<()> {
     db::run_migrations(&db_pool).await?;
     tracing::info!("Migrations complete");
 
+    // Instance admins are exactly the accounts listed in the config
+    let (granted, revoked) = db::users::sync_admins(&db_pool, &config.auth.admins).await?;
+    if granted > 0 || revoked > 0 {
+        tracing::info!(
+            "Instance admins: {} of {} configured account(s) granted, {} revoked",
+            granted,
+            config.auth.admins.len(),
+            revoked
+        );
+    }
+
     // Seed default server for standalone and community modes
     match config.mode {
         config::ServerMode::Standalone | config::ServerMode::Community => {
@@ -85,24 +109,117 @@ async fn main() -> Result<()> {
     // Build application state
     let state = api::AppState::new(db_pool, redis_client, config.clone());
 
+    // Purge expired typing indicators in the background
+    tokio::spawn(state.presence.clone().cleanup_loop());
+
+    // Evict expired token cache entries in the background
+    tokio::spawn(state.clone().token_cache_sweep_loop(Duration::from_secs(
+        config.auth.token_cache_sweep_secs.max(1),
+    )));
+
     // Voice server (SFU) is now integrated into the AppState and handled via WebSockets.
 
+    // Kept to close peer connections once the HTTP server has stopped
+    let sfu = state.sfu.clone();
+
     // Build HTTP + WebSocket router
     let app = api::build_router(state);
 
     // Bind and serve
     let addr = format!("{}:{}", config.server.host, config.server.port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    tracing::info!("API server listening on {}", addr);
+    // Connect info is needed for per-IP rate limiting
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    // Both ring (ours) and reqwest's backend may be linked; pick one explicitly
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    if config.tls.acme_enabled {
+        let acceptor = acme_acceptor(&config.tls)?;
+        let socket_addr = resolve_bind_addr(&addr).await?;
+
+        tracing::info!(
+            "API server listening on {} (TLS via ACME for {})",
+            socket_addr,
+            config.tls.acme_domain
+        );
+        axum_server::bind(socket_addr)
+            .acceptor(acceptor)
+            .handle(tls_shutdown_handle())
+            .serve(app)
+            .await?;
+    } else if let Some((cert_path, key_path)) = config.tls.pem_paths() {
+        let tls = RustlsConfig::from_pem_file(cert_path, key_path)
+            .await
+            .with_context(|| {
+                format!("Failed to load TLS certificate '{}' / key '{}'", cert_path, key_path)
+            })?;
+        let socket_addr = resolve_bind_addr(&addr).await?;
+
+        tracing::info!("API server listening on {} (TLS)", socket_addr);
+        axum_server::bind_rustls(socket_addr, tls)
+            .handle(tls_shutdown_handle())
+            .serve(app)
+            .await?;
+    } else {
+        let listener = tokio::net::TcpListener::bind(&addr).await?;
+        tracing::info!("API server listening on {}", addr);
+
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_signal())
+            .await?;
+    }
+
+    let closed = sfu.shutdown().await;
+    tracing::info!("Closed {} voice connection(s)", closed);
 
     tracing::info!("Antarcticom server stopped gracefully");
     Ok(())
 }
 
+async fn resolve_bind_addr(addr: &str) -> Result<SocketAddr> {
+    tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .with_context(|| format!("Could not resolve bind address {}", addr))
+}
+
+/// Handle for `axum_server` that starts a graceful shutdown on CTRL+C.
+fn tls_shutdown_handle() -> axum_server::Handle {
+    let handle = axum_server::Handle::new();
+    let handle_c = handle.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        handle_c.graceful_shutdown(None);
+    });
+    handle
+}
+
+/// Start ACME provisioning and renewal for `tls.acme_domain` in the background,
+/// returning the acceptor that serves the (cached or freshly issued) certificate.
+fn acme_acceptor(tls: &config::TlsConfig) -> Result<AxumAcceptor> {
+    if tls.acme_domain.is_empty() {
+        anyhow::bail!("tls.acme_enabled requires tls.acme_domain to be set");
+    }
+
+    let mut state = AcmeConfig::new([tls.acme_domain.clone()])
+        .contact(tls.acme_contact.iter().map(|email| format!("mailto:{}", email)))
+        .cache(DirCache::new(tls.acme_cache_dir.clone()))
+        .directory_lets_encrypt(!tls.acme_staging)
+        .state();
+    let acceptor = state.axum_acceptor(state.default_rustls_config());
+
+    tokio::spawn(async move {
+        while let Some(event) = state.next().await {
+            match event {
+                Ok(ok) => tracing::info!("ACME: {:?}", ok),
+                Err(err) => tracing::error!("ACME error: {:?}", err),
+            }
+        }
+    });
+
+    Ok(acceptor)
+}
+
 async fn shutdown_signal() {
     tokio::signal::ctrl_c()
         .await
@@ -121,9 +238,9 @@ async fn seed_default_server(pool: &PgPool) -> Result<()> {
     tracing::info!("No servers found — seeding default Antarcticom server");
 
     // Use a deterministic UUID so the seed is idempotent
-    let server_id = Uuid::parse_str("00000000-0000-7000-8000-000000000001")?;
+    let server_id = api::DEFAULT_SERVER_ID;
     // System owner — no real user owns the default server
-    let system_owner_id = Uuid::parse_str("00000000-0000-7000-8000-000000000000")?;
+    let system_owner_id = api::SYSTEM_USER_ID;
 
     // Ensure system user exists
     if db::users::find_by_id(pool, system_owner_id)
@@ -137,6 +254,7 @@ async fn seed_default_server(pool: &PgPool) -> Result<()> {
             "system",
             "System",
             "$argon2id$v=19$m=19456,t=2,p=1$wc8tCg$Ew", // Dummy hash
+            false,
         )
         .await?;
     }
@@ -153,6 +271,7 @@ async fn seed_default_server(pool: &PgPool) -> Result<()> {
         &ChannelType::Text,
         0,
         None,
+        None,
     )
     .await?;
 
@@ -165,6 +284,18 @@ async fn seed_default_server(pool: &PgPool) -> Result<()> {
         &ChannelType::Voice,
         1,
         None,
+        None,
+    )
+    .await?;
+
+    // Members get SEND_MESSAGES through @everyone, as in create_server
+    db::roles::create(
+        pool,
+        server_id,
+        "@everyone",
+        Permissions::SEND_MESSAGES,
+        0,
+        0,
     )
     .await?;
 
@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 // ─── Users ──────────────────────────────────────────────────────────────────
@@ -15,33 +16,90 @@ pub struct User {
     pub identity_key_public: Option<Vec<u8>>,
     pub created_at: DateTime<Utc>,
     pub last_seen: DateTime<Utc>,
+    /// Bot account: authenticates with a bot token, never a password
+    pub is_bot: bool,
+    /// Instance administrator, as opposed to a per-server role
+    pub is_admin: bool,
+    /// Failed logins since the last success or lock
+    pub failed_logins: i32,
+    /// Logins are refused until then after too many failed attempts
+    pub locked_until: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateUserRequest {
     pub username: String,
     pub password: String,
     pub display_name: Option<String>,
+    /// Registration invite; required when local registration is closed
+    #[serde(default)]
+    pub invite_code: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangePasswordRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+/// Body of `POST /api/auth/refresh`, and optionally of logout.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeleteAccountRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AuthResponse {
     pub token: String,
+    /// Exchanges for a new access token at `POST /api/auth/refresh`. Not
+    /// issued to bots, whose tokens are long-lived.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
     pub user: UserPublic,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct UserPublic {
     pub id: Uuid,
     pub username: String,
     pub display_name: String,
     pub avatar_hash: Option<String>,
+    #[serde(default)]
+    pub is_bot: bool,
+}
+
+/// The caller's own profile: the public fields plus private account details.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CurrentUser {
+    #[serde(flatten)]
+    pub user: UserPublic,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateUserRequest {
+    pub display_name: Option<String>,
+}
+
+impl From<User> for CurrentUser {
+    fn from(user: User) -> Self {
+        let created_at = user.created_at;
+        Self {
+            user: user.into(),
+            created_at,
+        }
+    }
 }
 
 impl From<User> for UserPublic {
@@ -51,13 +109,42 @@ impl From<User> for UserPublic {
             username: user.username,
             display_name: user.display_name,
             avatar_hash: user.avatar_hash,
+            is_bot: user.is_bot,
         }
     }
 }
 
+/// A user as seen by instance admins.
+#[derive(Debug, Serialize)]
+pub struct InstanceUser {
+    #[serde(flatten)]
+    pub user: UserPublic,
+    pub is_admin: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl From<User> for InstanceUser {
+    fn from(user: User) -> Self {
+        let (is_admin, created_at, last_seen) = (user.is_admin, user.created_at, user.last_seen);
+        Self {
+            user: user.into(),
+            is_admin,
+            created_at,
+            last_seen,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateBotRequest {
+    pub username: String,
+    pub display_name: Option<String>,
+}
+
 // ─── Servers ────────────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Server {
     pub id: Uuid,
     pub name: String,
@@ -65,6 +152,10 @@ pub struct Server {
     pub owner_id: Uuid,
     pub e2ee_enabled: bool,
     pub created_at: DateTime<Utc>,
+    /// Whether links posted in the server get previews
+    pub embeds_enabled: bool,
+    /// Disabled by an instance admin: nobody has any permissions in it
+    pub disabled: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,6 +164,8 @@ pub struct ServerPublic {
     pub name: String,
     pub icon_hash: Option<String>,
     pub owner_id: Uuid,
+    #[serde(default)]
+    pub disabled: bool,
 }
 
 impl From<Server> for ServerPublic {
@@ -82,50 +175,164 @@ impl From<Server> for ServerPublic {
             name: server.name,
             icon_hash: server.icon_hash,
             owner_id: server.owner_id,
+            disabled: server.disabled,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateServerRequest {
     pub name: String,
     pub e2ee_enabled: Option<bool>,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateServerRequest {
+    pub name: Option<String>,
+    pub embeds_enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TransferOwnershipRequest {
+    pub new_owner_id: Uuid,
+}
+
+// ─── Invites ────────────────────────────────────────────────────────────────
+
+/// A one-time code that lets someone register on an invite-only instance.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RegistrationInvite {
+    pub code: String,
+    pub creator_id: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub used_by: Option<Uuid>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRegistrationInviteRequest {
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Invite {
+    pub code: String,
+    pub server_id: Uuid,
+    pub creator_id: Uuid,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteRequest {
+    pub max_uses: Option<i32>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
 // ─── Channels ───────────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type, PartialEq, ToSchema)]
 #[sqlx(type_name = "channel_type", rename_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum ChannelType {
     Text,
     Voice,
     Announcement,
+    /// Groups other channels via their `category_id`.
+    Category,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Channel {
     pub id: Uuid,
-    pub server_id: Uuid,
+    /// `None` for DM channels
+    pub server_id: Option<Uuid>,
     pub name: String,
     pub channel_type: ChannelType,
     pub position: i32,
     pub category_id: Option<Uuid>,
+    /// Maximum concurrent voice participants (voice channels only)
+    pub user_limit: Option<i32>,
+    pub topic: Option<String>,
+    /// Seconds members must wait between messages (0 = off)
+    #[serde(default)]
+    pub slow_mode_secs: i32,
     #[sqlx(skip)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub voice_participants: Option<Vec<VoiceParticipant>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateChannelRequest {
     pub name: String,
     pub channel_type: ChannelType,
     pub category_id: Option<Uuid>,
+    pub user_limit: Option<i32>,
 }
 
-// ─── Messages ───────────────────────────────────────────────────────────────
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateChannelRequest {
+    pub name: Option<String>,
+    /// An empty topic clears it.
+    pub topic: Option<String>,
+    /// 0 turns slow mode off.
+    pub slow_mode_secs: Option<i32>,
+}
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReorderChannelsRequest {
+    /// Channel ids in their new order; each channel's position becomes its index.
+    pub channel_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "override_target", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OverrideTarget {
+    Role,
+    Member,
+}
+
+/// Per-channel permission adjustment for a role or a single member.
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ChannelOverride {
+    pub channel_id: Uuid,
+    pub target_id: Uuid,
+    pub target_type: OverrideTarget,
+    pub allow: i64,
+    pub deny: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetChannelOverrideRequest {
+    pub target_type: OverrideTarget,
+    #[serde(default)]
+    pub allow: i64,
+    #[serde(default)]
+    pub deny: i64,
+}
+
+// ─── Direct Messages ────────────────────────────────────────────────────────
+
+/// A 1:1 channel as seen by one participant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DmChannel {
+    pub id: Uuid,
+    pub recipient: UserPublic,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDmRequest {
+    pub recipient_id: Uuid,
+}
+
+// ─── Messages ───────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Message {
     pub id: i64, // Snowflake ID
     pub channel_id: Uuid,
@@ -139,27 +346,143 @@ pub struct Message {
     pub is_deleted: bool,
     #[sqlx(skip)]
     pub author: Option<UserPublic>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// Set when the message was posted through a webhook
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<Uuid>,
+    /// Avatar URL a webhook supplied for this message
+    #[sqlx(rename = "webhook_avatar_url", default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    /// Users explicitly mentioned (and members of the channel's server)
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub mentions: Vec<Uuid>,
+    /// Whether an @everyone / @here mention went out
+    #[sqlx(default)]
+    #[serde(default)]
+    pub mention_everyone: bool,
+    /// The message `reply_to_id` points at, without its own reference
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referenced_message: Option<Box<Message>>,
+    /// Link previews, filled in shortly after the message is sent
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub embeds: Vec<Embed>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Open Graph preview of a link in a message.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Embed {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SendMessageRequest {
     pub content: String,
     #[allow(dead_code)]
     pub nonce: Option<String>,
     pub reply_to_id: Option<i64>,
+    /// Ids of attachments previously uploaded to this channel
+    #[serde(default)]
+    pub attachments: Vec<Uuid>,
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub channel_id: Uuid,
+    pub uploader_id: Uuid,
+    pub message_id: Option<i64>,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct EditMessageRequest {
     pub content: String,
 }
 
-// ─── Members ────────────────────────────────────────────────────────────────
+/// A superseded version of an edited message.
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct MessageEdit {
+    pub content: String,
+    /// When this version was posted, or the edit that produced it
+    pub created_at: DateTime<Utc>,
+    /// When the next edit replaced it
+    pub replaced_at: DateTime<Utc>,
+}
 
-// ─── Members ────────────────────────────────────────────────────────────────
+// ─── Read States ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ReadState {
+    pub channel_id: Uuid,
+    /// Newest message the user has acknowledged, if any
+    pub last_read_message_id: Option<i64>,
+    /// Newest message in the channel, if any
+    pub last_message_id: Option<i64>,
+    /// Messages by others after the ack, capped at `db::read_states::MAX_UNREAD_COUNT`
+    pub unread_count: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AckRequest {
+    pub message_id: i64,
+}
+
+// ─── Webhooks ───────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub channel_id: Uuid,
+    /// Synthetic user the webhook's messages are authored by
+    pub user_id: Uuid,
+    pub name: String,
+    pub avatar_url: Option<String>,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Returned once on creation; the token can't be retrieved later.
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub name: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExecuteWebhookRequest {
+    pub content: String,
+    /// Overrides the webhook's name for this message
+    pub username: Option<String>,
+    /// Overrides the webhook's avatar for this message
+    pub avatar_url: Option<String>,
+}
+
+// ─── Members ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Member {
     pub user_id: Uuid,
     pub server_id: Uuid,
@@ -173,9 +496,15 @@ pub struct Member {
     pub status: Option<PresenceStatus>,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateMemberRequest {
+    /// `null` clears the nickname
+    pub nickname: Option<String>,
+}
+
 // ─── Roles ──────────────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Role {
     pub id: Uuid,
     pub server_id: Uuid,
@@ -198,12 +527,17 @@ impl Permissions {
     pub const SEND_MESSAGES: i64 = 1 << 4; // 16
     pub const ADMINISTRATOR: i64 = 1 << 5; // 32
     pub const MANAGE_MESSAGES: i64 = 1 << 6; // 64
+    pub const MUTE_MEMBERS: i64 = 1 << 7; // 128
+    pub const MOVE_MEMBERS: i64 = 1 << 8; // 256
+    /// Granted to every member server-wide; only channel overrides take it away.
+    pub const VIEW_CHANNEL: i64 = 1 << 9; // 512
+    /// Notify the whole server with @everyone / @here.
+    pub const MENTION_EVERYONE: i64 = 1 << 10; // 1024
 
     pub fn new(bits: i64) -> Self {
         Self(bits)
     }
 
-    #[allow(dead_code)]
     pub fn bits(&self) -> i64 {
         self.0
     }
@@ -221,12 +555,38 @@ impl Permissions {
     pub fn remove(&mut self, permission: i64) {
         self.0 &= !permission;
     }
+
+    /// Apply a channel's overrides: role denies/allows (combined), then the
+    /// member's own override. Administrators are unaffected.
+    pub fn with_overrides(self, overrides: &[ChannelOverride]) -> Self {
+        if self.0 & Self::ADMINISTRATOR != 0 {
+            return self;
+        }
+
+        let (mut allow, mut deny) = (0, 0);
+        for o in overrides
+            .iter()
+            .filter(|o| o.target_type == OverrideTarget::Role)
+        {
+            allow |= o.allow;
+            deny |= o.deny;
+        }
+        let mut bits = (self.0 & !deny) | allow;
+
+        for o in overrides
+            .iter()
+            .filter(|o| o.target_type == OverrideTarget::Member)
+        {
+            bits = (bits & !o.deny) | o.allow;
+        }
+        Self(bits)
+    }
 }
 
 // ─── Voice ──────────────────────────────────────────────────────────────────
 
 /// Lightweight voice participant for signaling (no DB backing).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct VoiceParticipant {
     pub user_id: Uuid,
     pub channel_id: Uuid,
@@ -237,7 +597,7 @@ pub struct VoiceParticipant {
 
 // ─── Bans ───────────────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Ban {
     pub server_id: Uuid,
     pub user_id: Uuid,
@@ -247,9 +607,51 @@ pub struct Ban {
     pub user: Option<UserPublic>,
 }
 
+// ─── Custom Emojis ──────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct CustomEmoji {
+    pub id: Uuid,
+    pub server_id: Uuid,
+    pub name: String,
+    /// GIF emojis may be animated; clients only play them when this is set
+    pub animated: bool,
+    pub creator_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ─── Audit Log ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "audit_action", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    MemberKick,
+    MemberBan,
+    MemberUnban,
+    ChannelDelete,
+    RoleCreate,
+    RoleDelete,
+    MemberRoleAdd,
+    MemberRoleRemove,
+    EmojiCreate,
+    EmojiDelete,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub server_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub action: AuditAction,
+    pub target_id: Option<Uuid>,
+    pub role_id: Option<Uuid>,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
 // ─── Reactions ──────────────────────────────────────────────────────────────
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Reaction {
     pub message_id: i64,
@@ -258,39 +660,84 @@ pub struct Reaction {
     pub created_at: DateTime<Utc>,
 }
 
+// ─── E2EE Keys ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct UploadKeysRequest {
+    pub identity_key: Vec<u8>,
+    pub signed_pre_key: Vec<u8>,
+    pub signed_pre_key_signature: Vec<u8>,
+    #[serde(default)]
+    pub one_time_pre_keys: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateIdentityKeyRequest {
+    /// Ed25519 public key
+    pub identity_key: Vec<u8>,
+}
+
+/// How many one-time pre-keys the server still holds for the caller.
+#[derive(Debug, Serialize)]
+pub struct PreKeyCount {
+    pub one_time_pre_keys_remaining: i64,
+}
+
 // ─── Snowflake ID Generator ─────────────────────────────────────────────────
 
 /// Discord-style Snowflake ID generator.
 /// Layout: [42 bits timestamp][10 bits worker][12 bits sequence]
 pub struct SnowflakeGenerator {
     worker_id: u16,
-    sequence: std::sync::atomic::AtomicU16,
+    /// (millisecond of the last id, sequence number within it)
+    state: std::sync::Mutex<(u64, u16)>,
     epoch: u64, // Custom epoch (ms since Unix epoch)
 }
 
 impl SnowflakeGenerator {
+    /// Ids per millisecond per worker (12-bit sequence).
+    const MAX_SEQUENCE: u16 = 0xFFF;
+
     /// Create a new generator with a custom epoch.
     /// Antarcticom epoch: 2025-01-01T00:00:00Z
     pub fn new(worker_id: u16) -> Self {
         Self {
             worker_id: worker_id & 0x3FF, // 10 bits
-            sequence: std::sync::atomic::AtomicU16::new(0),
+            state: std::sync::Mutex::new((0, 0)),
             epoch: 1_735_689_600_000, // 2025-01-01 00:00:00 UTC in ms
         }
     }
 
-    pub fn next_id(&self) -> i64 {
-        let now = std::time::SystemTime::now()
+    fn now_ms() -> u64 {
+        std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
-            .as_millis() as u64;
+            .as_millis() as u64
+    }
 
-        let timestamp = now - self.epoch;
-        let seq = self
-            .sequence
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-            & 0xFFF;
+    pub fn next_id(&self) -> i64 {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (last_ms, sequence) = *state;
+
+        // Never go back in time, even if the wall clock does
+        let mut now = Self::now_ms().max(last_ms);
+        let seq = if now == last_ms {
+            if sequence == Self::MAX_SEQUENCE {
+                // This millisecond is used up; wait for the next one
+                while now <= last_ms {
+                    std::hint::spin_loop();
+                    now = Self::now_ms();
+                }
+                0
+            } else {
+                sequence + 1
+            }
+        } else {
+            0
+        };
+        *state = (now, seq);
 
+        let timestamp = now - self.epoch;
         ((timestamp as i64) << 22) | ((self.worker_id as i64) << 12) | (seq as i64)
     }
 }
@@ -307,11 +754,37 @@ pub enum WsEvent {
     Heartbeat {
         seq: u64,
     },
+    /// Sent instead of `Identify` to pick a dropped session back up; the
+    /// server replays every frame after `last_seq`, or answers with a fresh
+    /// `Ready` if it can't.
+    Resume {
+        token: String,
+        session_id: Uuid,
+        last_seq: u64,
+    },
 
     // Server → Client
     Ready {
         user: UserPublic,
         session_id: String,
+        /// Read position and unread count for every channel the user can see
+        #[serde(default)]
+        read_states: Vec<ReadState>,
+    },
+    /// The session was resumed; missed frames follow.
+    Resumed {
+        session_id: Uuid,
+    },
+    /// Messages posted in a channel since the user last read it, sent after
+    /// `Ready` (newest first, only the most recent few per channel).
+    ChannelCatchup {
+        channel_id: Uuid,
+        messages: Vec<Message>,
+    },
+    /// The connection fell behind and `missed` events were dropped; the
+    /// client should refetch what it's showing.
+    Lagged {
+        missed: u64,
     },
     HeartbeatAck,
 
@@ -323,6 +796,23 @@ pub enum WsEvent {
         message_id: i64,
         is_deleted: bool,
     },
+    MessageDeleteBulk {
+        channel_id: Uuid,
+        message_ids: Vec<i64>,
+    },
+    PinsUpdate {
+        channel_id: Uuid,
+    },
+    /// The user was mentioned in a message (sent only to them)
+    Mentioned {
+        channel_id: Uuid,
+        message_id: i64,
+    },
+    /// The user marked a channel read (sent to their own sessions)
+    MessageAck {
+        channel_id: Uuid,
+        message_id: i64,
+    },
 
     // Reactions
     ReactionAdd {
@@ -342,6 +832,11 @@ pub enum WsEvent {
     PresenceUpdate {
         user_id: Uuid,
         status: PresenceStatus,
+        custom_text: Option<String>,
+    },
+    /// Sent right after `Ready`: the status of everyone sharing a server with the user.
+    PresenceSnapshot {
+        presences: std::collections::HashMap<Uuid, PresenceStatus>,
     },
     TypingStart {
         channel_id: Uuid,
@@ -357,13 +852,27 @@ pub enum WsEvent {
         deafened: bool,
         user: Option<UserPublic>,
     },
+    VoiceSpeaking {
+        channel_id: Uuid,
+        user_id: Uuid,
+        speaking: bool,
+    },
+    /// Sent to a user joining voice: which SFU node serves the channel, for
+    /// deployments running more than one.
+    VoiceServerUpdate {
+        channel_id: Uuid,
+        region: Option<String>,
+        endpoint: Option<String>,
+    },
 
     // WebRTC signaling relay (peer-to-peer audio)
     WebRTCSignal {
         from_user_id: Uuid,
         to_user_id: Uuid,
         channel_id: Uuid,
-        signal_type: String, // "offer", "answer", "ice"
+        // "offer", "answer", "ice"; the SFU also sends "error", and
+        // "reconnect" when a moderator moved the user to `channel_id`
+        signal_type: String,
         payload: serde_json::Value,
     },
 
@@ -372,7 +881,15 @@ pub enum WsEvent {
     ServerUpdate {
         server: ServerPublic,
     },
+    ServerDelete {
+        server_id: Uuid,
+    },
     ChannelCreate(Channel),
+    ChannelUpdate(Channel),
+    ChannelDelete {
+        server_id: Uuid,
+        channel_id: Uuid,
+    },
     MemberJoin {
         server_id: Uuid,
         user: UserPublic,
@@ -385,12 +902,39 @@ pub enum WsEvent {
         server_id: Uuid,
         member: Member,
     },
+    RoleCreate(Role),
+    RoleUpdate(Role),
+    RoleDelete {
+        server_id: Uuid,
+        role_id: Uuid,
+    },
+    /// Only the IDs: the reason is for `GET .../bans`, which needs BAN_MEMBERS
+    BanAdd {
+        server_id: Uuid,
+        user_id: Uuid,
+    },
+    BanRemove {
+        server_id: Uuid,
+        user_id: Uuid,
+    },
+    /// A server's custom emojis changed; carries the full new list.
+    EmojisUpdate {
+        server_id: Uuid,
+        emojis: Vec<CustomEmoji>,
+    },
     UserUpdate {
         user: UserPublic,
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize)]
+pub struct UpdatePresenceRequest {
+    pub status: Option<PresenceStatus>,
+    pub custom_text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "presence_status", rename_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum PresenceStatus {
     Online,
@@ -398,3 +942,16 @@ pub enum PresenceStatus {
     Dnd,
     Offline,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snowflake_ids_are_unique() {
+        let generator = SnowflakeGenerator::new(7);
+        let ids: std::collections::HashSet<i64> =
+            (0..10_000).map(|_| generator.next_id()).collect();
+        assert_eq!(ids.len(), 10_000);
+    }
+}
@@ -0,0 +1,163 @@
+use axum::Json;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::error::{ErrorDetail, ErrorResponse};
+use crate::models::*;
+
+/// OpenAPI description of the core REST API, served at `GET /api/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Antarcticom API"),
+    paths(
+        crate::api::register,
+        crate::api::login,
+        crate::api::logout,
+        crate::api::refresh_session,
+        crate::api::change_password,
+        crate::api::delete_account,
+        crate::api::get_current_user,
+        crate::api::update_current_user,
+        crate::api::get_user,
+        crate::api::create_bot,
+        crate::api::reset_bot_token,
+        crate::api::create_server,
+        crate::api::list_servers,
+        crate::api::get_server,
+        crate::api::update_server,
+        crate::api::delete_server,
+        crate::api::transfer_server,
+        crate::api::join_server,
+        crate::api::leave_server,
+        crate::api::create_channel,
+        crate::api::list_channels,
+        crate::api::reorder_channels,
+        crate::api::update_channel,
+        crate::api::delete_channel,
+        crate::api::send_message,
+        crate::api::get_messages,
+        crate::api::get_message,
+        crate::api::search_messages,
+        crate::api::edit_message,
+        crate::api::get_message_history,
+        crate::api::delete_message,
+        crate::api::bulk_delete_messages,
+        crate::api::ack_channel,
+        crate::api::trigger_typing,
+        crate::api::list_roles,
+        crate::api::create_role,
+        crate::api::update_role,
+        crate::api::delete_role,
+        crate::api::assign_role,
+        crate::api::remove_role,
+        crate::api::list_members,
+        crate::api::get_member,
+        crate::api::update_member,
+        crate::api::kick_member,
+        crate::api::list_bans,
+        crate::api::ban_member,
+        crate::api::unban_member,
+    ),
+    components(schemas(
+        ErrorResponse,
+        ErrorDetail,
+        UserPublic,
+        CurrentUser,
+        AuthResponse,
+        CreateUserRequest,
+        LoginRequest,
+        ChangePasswordRequest,
+        RefreshTokenRequest,
+        DeleteAccountRequest,
+        UpdateUserRequest,
+        CreateBotRequest,
+        Server,
+        CreateServerRequest,
+        UpdateServerRequest,
+        TransferOwnershipRequest,
+        ChannelType,
+        Channel,
+        CreateChannelRequest,
+        UpdateChannelRequest,
+        ReorderChannelsRequest,
+        Message,
+        Attachment,
+        Embed,
+        SendMessageRequest,
+        EditMessageRequest,
+        MessageEdit,
+        AckRequest,
+        Member,
+        PresenceStatus,
+        UpdateMemberRequest,
+        Role,
+        Ban,
+        VoiceParticipant,
+        crate::api::CreateRoleRequest,
+        crate::api::CreateBanRequest,
+    )),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+    tags(
+        (name = "auth", description = "Registration and sessions"),
+        (name = "users", description = "User accounts"),
+        (name = "servers", description = "Servers"),
+        (name = "channels", description = "Server channels"),
+        (name = "messages", description = "Channel messages"),
+        (name = "roles", description = "Roles and role assignment"),
+        (name = "members", description = "Server members and bans"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the JWT bearer scheme that the global `security` refers to.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// GET /api/openapi.json
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_covers_core_endpoints() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = spec["paths"].as_object().unwrap();
+
+        for path in [
+            "/api/auth/login",
+            "/api/servers/{server_id}",
+            "/api/servers/{server_id}/channels",
+            "/api/channels/{channel_id}/messages",
+            "/api/servers/{server_id}/roles/{role_id}",
+            "/api/servers/{server_id}/members",
+        ] {
+            assert!(paths.contains_key(path), "missing {}", path);
+        }
+        assert!(spec["components"]["schemas"]["ErrorResponse"].is_object());
+        assert!(spec["components"]["securitySchemes"]["bearer"].is_object());
+        // Logging in can't require a token
+        assert_eq!(
+            spec["paths"]["/api/auth/login"]["post"]["security"],
+            serde_json::json!([{}])
+        );
+    }
+}
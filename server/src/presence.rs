@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::Instant;
 
 use dashmap::DashMap;
 use uuid::Uuid;
@@ -11,42 +14,131 @@ use crate::models::PresenceStatus;
 /// In production, this is backed by Redis pub/sub for horizontal scaling.
 /// This in-memory implementation works for single-instance and self-hosted deployments.
 pub struct PresenceManager {
-    /// user_id → current status
-    statuses: Arc<DashMap<Uuid, PresenceStatus>>,
+    /// user_id → (current status, custom status text)
+    statuses: Arc<DashMap<Uuid, (PresenceStatus, Option<String>)>>,
+    /// user_id → (last client activity, whether Idle was set by `mark_idle`)
+    activity: Arc<DashMap<Uuid, (Instant, bool)>>,
     /// channel_id → set of currently-typing user_ids
-    #[allow(dead_code)]
     typing: Arc<DashMap<Uuid, HashMap<Uuid, tokio::time::Instant>>>,
+    /// How long an Online user may go without activity before turning Idle.
+    idle_after: Duration,
 }
 
+/// How often `idle_loop` looks for inactive users.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
 impl PresenceManager {
-    pub fn new() -> Self {
+    pub fn new(idle_after: Duration) -> Self {
         Self {
             statuses: Arc::new(DashMap::new()),
+            activity: Arc::new(DashMap::new()),
             typing: Arc::new(DashMap::new()),
+            idle_after,
         }
     }
 
-    /// Set a user's presence status.
+    /// Set a user's presence status, keeping any custom status text.
     pub fn set_status(&self, user_id: Uuid, status: PresenceStatus) {
-        self.statuses.insert(user_id, status);
+        self.statuses
+            .entry(user_id)
+            .and_modify(|(s, _)| *s = status.clone())
+            .or_insert((status, None));
+    }
+
+    /// Set a user's presence status together with their custom status text.
+    pub fn set_custom_status(
+        &self,
+        user_id: Uuid,
+        status: PresenceStatus,
+        custom_text: Option<String>,
+    ) {
+        self.statuses.insert(user_id, (status, custom_text));
+        // A status the user picked is theirs; activity must not undo it
+        if let Some(mut entry) = self.activity.get_mut(&user_id) {
+            entry.1 = false;
+        }
     }
 
     /// Get a user's current presence status.
     pub fn get_status(&self, user_id: Uuid) -> PresenceStatus {
         self.statuses
             .get(&user_id)
-            .map(|s| s.clone())
+            .map(|s| s.0.clone())
             .unwrap_or(PresenceStatus::Offline)
     }
 
+    /// Get a user's custom status text, if any.
+    pub fn get_custom_text(&self, user_id: Uuid) -> Option<String> {
+        self.statuses.get(&user_id).and_then(|s| s.1.clone())
+    }
+
     /// Mark a user as offline (called on disconnect).
+    /// The custom status text is kept so it is restored on reconnect.
     pub fn set_offline(&self, user_id: &Uuid) {
-        self.statuses.insert(*user_id, PresenceStatus::Offline);
+        self.activity.remove(user_id);
+        self.set_status(*user_id, PresenceStatus::Offline);
+    }
+
+    /// Record client activity (a heartbeat or message). Returns true if this
+    /// brought the user back from automatic Idle to Online.
+    pub fn touch(&self, user_id: Uuid) -> bool {
+        let was_auto_idle = {
+            let mut entry = self
+                .activity
+                .entry(user_id)
+                .or_insert((Instant::now(), false));
+            entry.0 = Instant::now();
+            std::mem::take(&mut entry.1)
+        };
+
+        if was_auto_idle && self.get_status(user_id) == PresenceStatus::Idle {
+            self.set_status(user_id, PresenceStatus::Online);
+            return true;
+        }
+        false
+    }
+
+    /// Move Online users without recent activity to Idle and return them.
+    /// Users who chose Idle, DND or Offline themselves are left alone.
+    pub fn mark_idle(&self) -> Vec<Uuid> {
+        let now = Instant::now();
+        let inactive: Vec<Uuid> = self
+            .activity
+            .iter()
+            .filter(|e| !e.1 && now.duration_since(e.0) >= self.idle_after)
+            .map(|e| *e.key())
+            .collect();
+
+        let mut idled = Vec::new();
+        for user_id in inactive {
+            if self.get_status(user_id) != PresenceStatus::Online {
+                continue;
+            }
+            self.set_status(user_id, PresenceStatus::Idle);
+            if let Some(mut entry) = self.activity.get_mut(&user_id) {
+                entry.1 = true;
+            }
+            idled.push(user_id);
+        }
+        idled
+    }
+
+    /// Periodically run `mark_idle`, handing each newly idle user to `on_idle`.
+    pub async fn idle_loop<F>(self: Arc<Self>, on_idle: F)
+    where
+        F: Fn(Uuid),
+    {
+        let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            for user_id in self.mark_idle() {
+                on_idle(user_id);
+            }
+        }
     }
 
     /// Mark a user as typing in a channel.
     /// Typing indicators expire after 8 seconds.
-    #[allow(dead_code)]
     pub fn set_typing(&self, channel_id: Uuid, user_id: Uuid) {
         self.typing
             .entry(channel_id)
@@ -74,18 +166,76 @@ impl PresenceManager {
             .collect()
     }
 
+    /// Drop expired typing indicators and channels left with none.
+    /// Returns how many channel entries were removed.
+    pub fn purge_typing(&self) -> usize {
+        let cutoff = tokio::time::Instant::now() - std::time::Duration::from_secs(8);
+        for mut entry in self.typing.iter_mut() {
+            entry.retain(|_, instant| *instant > cutoff);
+        }
+        // Remove empty channel entries
+        let before = self.typing.len();
+        self.typing.retain(|_, v| !v.is_empty());
+        before.saturating_sub(self.typing.len())
+    }
+
     /// Run periodic cleanup of expired typing indicators.
-    #[allow(dead_code)]
     pub async fn cleanup_loop(self: Arc<Self>) {
+        tracing::info!("Presence cleanup loop started");
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(10));
         loop {
             interval.tick().await;
-            let cutoff = tokio::time::Instant::now() - std::time::Duration::from_secs(8);
-            for mut entry in self.typing.iter_mut() {
-                entry.retain(|_, instant| *instant > cutoff);
+            let removed = self.purge_typing();
+            if removed > 0 {
+                tracing::debug!("Purged typing state for {} channel(s)", removed);
             }
-            // Remove empty channel entries
-            self.typing.retain(|_, v| !v.is_empty());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_purge_typing_drops_expired_channels() {
+        let presence = PresenceManager::new(Duration::ZERO);
+        let stale_channel = Uuid::now_v7();
+        let live_channel = Uuid::now_v7();
+        presence.typing.insert(
+            stale_channel,
+            HashMap::from([(Uuid::now_v7(), Instant::now() - Duration::from_secs(9))]),
+        );
+        presence.set_typing(live_channel, Uuid::now_v7());
+
+        assert_eq!(presence.purge_typing(), 1);
+        assert!(!presence.typing.contains_key(&stale_channel));
+        assert_eq!(presence.get_typing(&live_channel).len(), 1);
+    }
+
+    #[test]
+    fn test_idle_transition_and_wake() {
+        let presence = PresenceManager::new(Duration::ZERO);
+        let user_id = Uuid::now_v7();
+        presence.set_status(user_id, PresenceStatus::Online);
+        presence.touch(user_id);
+
+        assert_eq!(presence.mark_idle(), vec![user_id]);
+        assert_eq!(presence.get_status(user_id), PresenceStatus::Idle);
+
+        assert!(presence.touch(user_id));
+        assert_eq!(presence.get_status(user_id), PresenceStatus::Online);
+    }
+
+    #[test]
+    fn test_idle_leaves_explicit_status_alone() {
+        let presence = PresenceManager::new(Duration::ZERO);
+        let user_id = Uuid::now_v7();
+        presence.set_custom_status(user_id, PresenceStatus::Dnd, None);
+        presence.touch(user_id);
+
+        assert!(presence.mark_idle().is_empty());
+        assert!(!presence.touch(user_id));
+        assert_eq!(presence.get_status(user_id), PresenceStatus::Dnd);
+    }
+}
@@ -0,0 +1,112 @@
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+/// Number of tracked keys above which idle (fully refilled) buckets are pruned.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Token-bucket rate limiter keyed by client (IP address, user ID, ...).
+///
+/// Each key gets `capacity` tokens which refill continuously over `window`,
+/// so bursts are allowed up to the capacity and then decay back to the
+/// sustained rate.
+pub struct RateLimiter<K: Eq + Hash> {
+    capacity: f64,
+    refill_per_sec: f64,
+    /// key → (available tokens, last refill)
+    buckets: DashMap<K, (f64, Instant)>,
+}
+
+impl<K: Eq + Hash + Clone> RateLimiter<K> {
+    /// Allow `capacity` requests per `window` for each key.
+    pub fn new(capacity: u32, window: Duration) -> Self {
+        let capacity = capacity.max(1) as f64;
+        Self {
+            capacity,
+            refill_per_sec: capacity / window.as_secs_f64().max(f64::EPSILON),
+            buckets: DashMap::new(),
+        }
+    }
+
+    /// Consume one token for `key`. Returns `false` if the key is rate limited.
+    pub fn check(&self, key: &K) -> bool {
+        self.try_acquire(key).is_ok()
+    }
+
+    /// Consume one token for `key`, or return how long until one is available.
+    pub fn try_acquire(&self, key: &K) -> Result<(), Duration> {
+        if self.buckets.len() > PRUNE_THRESHOLD {
+            self.prune();
+        }
+
+        let now = Instant::now();
+        let mut bucket = self
+            .buckets
+            .entry(key.clone())
+            .or_insert((self.capacity, now));
+
+        let (tokens, last) = *bucket;
+        let tokens = (tokens + now.duration_since(last).as_secs_f64() * self.refill_per_sec)
+            .min(self.capacity);
+
+        if tokens >= 1.0 {
+            *bucket = (tokens - 1.0, now);
+            Ok(())
+        } else {
+            *bucket = (tokens, now);
+            Err(Duration::from_secs_f64(
+                (1.0 - tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+
+    /// Drop buckets that have fully refilled — they behave the same as absent ones.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.buckets.retain(|_, (tokens, last)| {
+            *tokens + now.duration_since(*last).as_secs_f64() * self.refill_per_sec < self.capacity
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_then_reject() {
+        let limiter = RateLimiter::new(3, Duration::from_secs(60));
+        assert!(limiter.check(&"a"));
+        assert!(limiter.check(&"a"));
+        assert!(limiter.check(&"a"));
+        assert!(!limiter.check(&"a"));
+    }
+
+    #[test]
+    fn test_keys_are_independent() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        assert!(limiter.check(&"a"));
+        assert!(!limiter.check(&"a"));
+        assert!(limiter.check(&"b"));
+    }
+
+    #[test]
+    fn test_try_acquire_reports_wait() {
+        let limiter = RateLimiter::new(5, Duration::from_secs(5));
+        for _ in 0..5 {
+            assert!(limiter.try_acquire(&"a").is_ok());
+        }
+        let wait = limiter.try_acquire(&"a").unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_tokens_refill() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(20));
+        assert!(limiter.check(&"a"));
+        assert!(!limiter.check(&"a"));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check(&"a"));
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// A client's gateway session. It outlives the WebSocket connection for a
+/// short window so a reconnecting client can resume without missing events.
+pub struct WsSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// Frames queued for a slow connection, and kept for replay on resume.
+    buffer_size: usize,
+    inner: Mutex<SessionState>,
+}
+
+struct SessionState {
+    /// Feeds the attached connection; `None` once the session is closed.
+    tx: Option<broadcast::Sender<String>>,
+    /// Sequence number of the most recent frame.
+    seq: u64,
+    /// The most recent frames with their sequence numbers, oldest first.
+    replay: VecDeque<(u64, String)>,
+    /// Bumped on every resume so a superseded connection can't detach the session.
+    generation: u64,
+    /// Whether the last connection went away without a resume yet.
+    detached: bool,
+    /// Channels subscribed to when the session was created or last resumed.
+    channels: Vec<Uuid>,
+}
+
+impl WsSession {
+    /// Create an attached session (generation 0) and the receiver for its
+    /// frames, buffering up to `buffer_size` of them.
+    pub fn new(
+        user_id: Uuid,
+        channels: Vec<Uuid>,
+        buffer_size: usize,
+    ) -> (Self, broadcast::Receiver<String>) {
+        let buffer_size = buffer_size.max(1);
+        let (tx, rx) = broadcast::channel(buffer_size);
+        let session = Self {
+            id: Uuid::now_v7(),
+            user_id,
+            buffer_size,
+            inner: Mutex::new(SessionState {
+                tx: Some(tx),
+                seq: 0,
+                replay: VecDeque::with_capacity(buffer_size),
+                generation: 0,
+                detached: false,
+                channels,
+            }),
+        };
+        (session, rx)
+    }
+
+    /// Stamp a serialized event with the next sequence number, keep it for
+    /// replay and deliver it to the attached connection, if any.
+    pub fn send(&self, json: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(tx) = inner.tx.clone() else {
+            return;
+        };
+        inner.seq += 1;
+        let seq = inner.seq;
+        let frame = with_seq(json, seq);
+
+        if inner.replay.len() == self.buffer_size {
+            inner.replay.pop_front();
+        }
+        inner.replay.push_back((seq, frame.clone()));
+        let _ = tx.send(frame);
+    }
+
+    /// Deliver a serialized event to the attached connection only, without a
+    /// sequence number. For replies that mean nothing after a reconnect.
+    pub fn send_unsequenced(&self, json: &str) {
+        if let Some(tx) = &self.inner.lock().unwrap().tx {
+            let _ = tx.send(json.to_string());
+        }
+    }
+
+    /// Reattach a connection that has seen everything up to `last_seq`.
+    /// Returns the new generation, the missed frames and a receiver for
+    /// everything after them, or `None` if missed frames were already dropped.
+    pub fn resume(&self, last_seq: u64) -> Option<(u64, Vec<String>, broadcast::Receiver<String>)> {
+        let mut inner = self.inner.lock().unwrap();
+        let tx = inner.tx.clone()?;
+        if last_seq > inner.seq {
+            return None;
+        }
+        let oldest = inner.replay.front().map_or(inner.seq + 1, |(seq, _)| *seq);
+        if last_seq + 1 < oldest {
+            return None;
+        }
+
+        let missed = inner
+            .replay
+            .iter()
+            .filter(|(seq, _)| *seq > last_seq)
+            .map(|(_, frame)| frame.clone())
+            .collect();
+        inner.generation += 1;
+        inner.detached = false;
+        // Subscribing under the lock means no frame falls between the replay and the receiver
+        Some((inner.generation, missed, tx.subscribe()))
+    }
+
+    /// End the session for good, disconnecting its connection.
+    pub fn close(&self) {
+        self.inner.lock().unwrap().tx = None;
+    }
+
+    /// Mark the session detached when the connection of `generation` closes.
+    /// Returns false if a newer connection has taken the session over.
+    pub fn detach(&self, generation: u64) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return false;
+        }
+        inner.detached = true;
+        true
+    }
+
+    /// Whether the session is still waiting for a resume since `generation` detached.
+    pub fn still_detached(&self, generation: u64) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.generation == generation && inner.detached
+    }
+
+    /// Whether a connection is attached right now, rather than the session
+    /// waiting out its resume window (or closed).
+    pub fn is_attached(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.tx.is_some() && !inner.detached
+    }
+
+    pub fn channels(&self) -> Vec<Uuid> {
+        self.inner.lock().unwrap().channels.clone()
+    }
+
+    /// Replace the subscribed channels, e.g. with a fresh list on resume.
+    pub fn set_channels(&self, channels: Vec<Uuid>) {
+        self.inner.lock().unwrap().channels = channels;
+    }
+}
+
+/// Add a `seq` field to a serialized JSON object.
+fn with_seq(json: &str, seq: u64) -> String {
+    match json.strip_prefix('{') {
+        Some("}") => format!("{{\"seq\":{}}}", seq),
+        Some(rest) => format!("{{\"seq\":{},{}", seq, rest),
+        None => json.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUFFER_SIZE: usize = 256;
+
+    fn seq_of(frame: &str) -> u64 {
+        let value: serde_json::Value = serde_json::from_str(frame).unwrap();
+        value["seq"].as_u64().unwrap()
+    }
+
+    #[test]
+    fn test_frames_are_sequenced() {
+        let (session, mut rx) = WsSession::new(Uuid::now_v7(), Vec::new(), BUFFER_SIZE);
+        session.send(r#"{"type":"HeartbeatAck"}"#);
+        session.send(r#"{"type":"TypingStart","data":{}}"#);
+
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first, r#"{"seq":1,"type":"HeartbeatAck"}"#);
+        assert_eq!(seq_of(&rx.try_recv().unwrap()), 2);
+        assert_eq!(with_seq("{}", 7), r#"{"seq":7}"#);
+    }
+
+    #[test]
+    fn test_resume_replays_missed_frames() {
+        let (session, rx) = WsSession::new(Uuid::now_v7(), Vec::new(), BUFFER_SIZE);
+        drop(rx);
+        for _ in 0..5 {
+            session.send(r#"{"type":"HeartbeatAck"}"#);
+        }
+        assert!(session.detach(0));
+        assert!(session.still_detached(0));
+
+        let (generation, missed, mut rx) = session.resume(3).expect("resumable");
+        assert_eq!(generation, 1);
+        assert_eq!(missed.iter().map(|f| seq_of(f)).collect::<Vec<_>>(), [4, 5]);
+        assert!(!session.still_detached(0));
+        // The superseded connection can no longer detach the session
+        assert!(!session.detach(0));
+
+        session.send(r#"{"type":"HeartbeatAck"}"#);
+        assert_eq!(seq_of(&rx.try_recv().unwrap()), 6);
+
+        // Up to date, and claims from the future
+        assert!(session.resume(6).unwrap().1.is_empty());
+        assert!(session.resume(7).is_none());
+    }
+
+    #[test]
+    fn test_resume_fails_once_frames_are_dropped() {
+        let (session, _rx) = WsSession::new(Uuid::now_v7(), Vec::new(), BUFFER_SIZE);
+        for _ in 0..BUFFER_SIZE + 10 {
+            session.send(r#"{"type":"HeartbeatAck"}"#);
+        }
+
+        assert!(session.resume(5).is_none());
+        let (_, missed, _) = session.resume(10).expect("oldest frame still buffered");
+        assert_eq!(missed.len(), BUFFER_SIZE);
+    }
+
+    #[test]
+    fn test_slow_connection_lags_instead_of_closing() {
+        let (session, mut rx) = WsSession::new(Uuid::now_v7(), Vec::new(), 4);
+        for _ in 0..6 {
+            session.send(r#"{"type":"HeartbeatAck"}"#);
+        }
+
+        assert!(matches!(
+            rx.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(2))
+        ));
+        // The receiver picks up again at the oldest frame still queued
+        assert_eq!(seq_of(&rx.try_recv().unwrap()), 3);
+    }
+
+    #[test]
+    fn test_unsequenced_frames_are_not_replayed() {
+        let (session, mut rx) = WsSession::new(Uuid::now_v7(), Vec::new(), BUFFER_SIZE);
+        session.send(r#"{"type":"TypingStart","data":{}}"#);
+        session.send_unsequenced(r#"{"type":"HeartbeatAck"}"#);
+        session.send(r#"{"type":"TypingStart","data":{}}"#);
+
+        assert_eq!(seq_of(&rx.try_recv().unwrap()), 1);
+        assert_eq!(rx.try_recv().unwrap(), r#"{"type":"HeartbeatAck"}"#);
+        assert_eq!(seq_of(&rx.try_recv().unwrap()), 2);
+
+        assert!(session.detach(0));
+        assert!(!session.is_attached());
+        let (_, missed, _) = session.resume(0).expect("resumable");
+        assert_eq!(missed.iter().map(|f| seq_of(f)).collect::<Vec<_>>(), [1, 2]);
+        assert!(session.is_attached());
+    }
+
+    #[test]
+    fn test_closed_session_disconnects_and_cannot_resume() {
+        let (session, mut rx) = WsSession::new(Uuid::now_v7(), Vec::new(), BUFFER_SIZE);
+        session.close();
+
+        assert!(matches!(
+            rx.try_recv(),
+            Err(broadcast::error::TryRecvError::Closed)
+        ));
+        assert!(session.resume(0).is_none());
+    }
+}
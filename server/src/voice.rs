@@ -1,25 +1,84 @@
 use anyhow::Result;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 use webrtc::api::interceptor_registry::register_default_interceptors;
 use webrtc::api::media_engine::MediaEngine;
 use webrtc::api::APIBuilder;
+use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::interceptor::registry::Registry;
 use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
-use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::rtp::extension::audio_level_extension::AudioLevelExtension;
+use webrtc::rtp::header::Header;
+use webrtc::rtp_transceiver::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpHeaderExtensionCapability, RTPCodecType,
+};
 use webrtc::rtp_transceiver::RTCPFeedback;
 use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 use webrtc::track::track_local::TrackLocalWriter;
 use webrtc::track::track_remote::TrackRemote;
+use webrtc::util::Unmarshal;
+
+use crate::config::VoiceConfig;
 
 /// Type alias for a function that sends a WebSocket message to a specific user.
 /// The SFU uses this to push server-initiated offers to clients.
 pub type WsSenderFn = Arc<dyn Fn(Uuid, serde_json::Value) + Send + Sync>;
 
+/// Type alias for a function notified when a user starts or stops speaking.
+/// Signature: fn(channel_id, user_id, speaking)
+pub type SpeakingFn = Arc<dyn Fn(Uuid, Uuid, bool) + Send + Sync>;
+
+/// Type alias for a function notified when a user's peer connection dies on
+/// its own (ICE failure, closed tab) rather than through `leave_channel`.
+/// Signature: fn(channel_id, user_id)
+pub type PeerLostFn = Arc<dyn Fn(Uuid, Uuid) + Send + Sync>;
+
+/// Loudest RFC 6464 audio level, in -dBov, that still counts as silence.
+/// Levels run from 0 (loudest) to 127 (silent).
+const SPEAKING_MAX_AUDIO_LEVEL: u8 = 50;
+/// How long a user must stay below the speaking level before they count as no longer speaking.
+const SPEAKING_SILENCE_TIMEOUT: Duration = Duration::from_millis(300);
+/// How often the silence watchdog checks each speaking user.
+const SPEAKING_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many times adding a track to a subscriber's PC is tried before that
+/// track is skipped for them.
+const ADD_TRACK_ATTEMPTS: u32 = 3;
+/// Wait before the first retry of a failed `add_track`; doubles each time.
+const ADD_TRACK_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Public STUN servers always offered to peers.
+pub const STUN_URLS: &[&str] = &[
+    "stun:stun.l.google.com:19302",
+    "stun:stun1.l.google.com:19302",
+];
+
+/// Returned by `handle_offer` when a channel already has the configured
+/// maximum number of SFU participants.
+#[derive(Debug)]
+pub struct ChannelFull {
+    pub max_participants: usize,
+}
+
+impl std::fmt::Display for ChannelFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Voice channel is at its limit of {} participants",
+            self.max_participants
+        )
+    }
+}
+
+impl std::error::Error for ChannelFull {}
+
 /// Represents a user connected to the SFU.
 pub struct SfuUser {
     pub user_id: Uuid,
@@ -29,6 +88,11 @@ pub struct SfuUser {
     pub published_track: Arc<RwLock<Option<Arc<TrackLocalStaticRTP>>>>,
     /// Keep track of senders we added to this user's PC, so we can remove them
     pub senders: Arc<DashMap<Uuid, Arc<webrtc::rtp_transceiver::rtp_sender::RTCRtpSender>>>,
+    /// Users whose track couldn't be added to this PC even after retrying.
+    /// They aren't tried again until they rejoin.
+    pub skipped_tracks: Arc<DashSet<Uuid>>,
+    /// Opus bitrate (bps) negotiated with this user, advertised in every SDP we send them.
+    pub bitrate: u32,
 }
 
 /// Represents a voice channel in the SFU.
@@ -44,12 +108,39 @@ pub struct SfuServer {
     /// Callback to send WebSocket events to users.
     /// Signature: fn(target_user_id, event_json)
     ws_sender: RwLock<Option<WsSenderFn>>,
+    /// Callback for voice activity changes, routed to the channel's subscribers.
+    speaking_handler: RwLock<Option<SpeakingFn>>,
+    /// Callback for peer connections that failed or closed underneath us.
+    peer_lost_handler: RwLock<Option<PeerLostFn>>,
+    /// (channel_id, user_id) pairs whose audio is not forwarded
+    /// (moderator-enforced mute). Mutes are per server, so they're kept per
+    /// channel rather than following the user everywhere.
+    server_muted: Arc<DashSet<(Uuid, Uuid)>>,
+    /// STUN, plus TURN when configured, for every peer connection.
+    ice_servers: Vec<RTCIceServer>,
+    /// Configured Opus bitrate bounds in bits per second.
+    min_bitrate: u32,
+    max_bitrate: u32,
+    /// Hard cap on peer connections per channel, whatever the channel's own user limit.
+    max_participants: Option<usize>,
 }
 
 impl SfuServer {
-    pub fn new(public_ip: Option<String>) -> Result<Self> {
+    pub fn new(config: &VoiceConfig) -> Result<Self> {
+        let public_ip = config.public_ip.clone();
+        let turn = config.turn.clone();
+        let (min_kbps, max_kbps) = config.bitrate_range();
+
         let mut m = MediaEngine::default();
         m.register_default_codecs()?;
+        // Clients tag each audio packet with its level, which speaking detection reads
+        m.register_header_extension(
+            RTCRtpHeaderExtensionCapability {
+                uri: webrtc::sdp::extmap::AUDIO_LEVEL_URI.to_string(),
+            },
+            RTPCodecType::Audio,
+            None,
+        )?;
 
         let mut registry = Registry::new();
         registry = register_default_interceptors(registry, &mut m)?;
@@ -81,13 +172,43 @@ impl SfuServer {
             .with_setting_engine(se)
             .build();
 
+        let mut ice_servers = vec![RTCIceServer {
+            urls: STUN_URLS.iter().map(|url| url.to_string()).collect(),
+            ..Default::default()
+        }];
+        if let Some(turn) = turn {
+            tracing::info!("SFU configured with TURN relay: {:?}", turn.urls);
+            ice_servers.push(RTCIceServer {
+                urls: turn.urls,
+                username: turn.username,
+                credential: turn.credential,
+                ..Default::default()
+            });
+        }
+
         Ok(Self {
             channels: Arc::new(DashMap::new()),
             api,
             ws_sender: RwLock::new(None),
+            speaking_handler: RwLock::new(None),
+            peer_lost_handler: RwLock::new(None),
+            server_muted: Arc::new(DashSet::new()),
+            ice_servers,
+            min_bitrate: min_kbps * 1000,
+            max_bitrate: max_kbps * 1000,
+            max_participants: config.max_channel_participants.map(|max| max as usize),
         })
     }
 
+    /// The bitrate a client offering `offer_sdp` should send at: what it
+    /// asks for, clamped into the configured range (the maximum if it
+    /// doesn't say).
+    fn negotiate_bitrate(&self, offer_sdp: &str) -> u32 {
+        opus_max_average_bitrate(offer_sdp)
+            .unwrap_or(self.max_bitrate)
+            .clamp(self.min_bitrate, self.max_bitrate)
+    }
+
     /// Set the WebSocket sender callback. Called once during server startup
     /// after the AppState is fully constructed.
     pub async fn set_ws_sender(&self, sender: WsSenderFn) {
@@ -95,6 +216,34 @@ impl SfuServer {
         *ws = Some(sender);
     }
 
+    /// Set the voice activity callback. Called once during server startup
+    /// after the AppState is fully constructed.
+    pub async fn set_speaking_handler(&self, handler: SpeakingFn) {
+        let mut speaking = self.speaking_handler.write().await;
+        *speaking = Some(handler);
+    }
+
+    /// Set the lost-peer callback. Called once during server startup
+    /// after the AppState is fully constructed.
+    pub async fn set_peer_lost_handler(&self, handler: PeerLostFn) {
+        let mut peer_lost = self.peer_lost_handler.write().await;
+        *peer_lost = Some(handler);
+    }
+
+    /// Enforce (or lift) a server-side mute in one channel. While muted, the
+    /// user's RTP is dropped instead of being forwarded to the rest of it.
+    pub fn set_server_muted(&self, channel_id: Uuid, user_id: Uuid, muted: bool) {
+        if muted {
+            self.server_muted.insert((channel_id, user_id));
+        } else {
+            self.server_muted.remove(&(channel_id, user_id));
+        }
+    }
+
+    pub fn is_server_muted(&self, channel_id: Uuid, user_id: Uuid) -> bool {
+        self.server_muted.contains(&(channel_id, user_id))
+    }
+
     /// Handle an offer from a client. Creates the peer connection, subscribes
     /// to existing tracks, creates an answer, and then renegotiates with all
     /// existing users so they receive this new user's track.
@@ -104,58 +253,52 @@ impl SfuServer {
         user_id: Uuid,
         offer_sdp: String,
     ) -> Result<String> {
-        use webrtc::ice_transport::ice_server::RTCIceServer;
-
         let config = RTCConfiguration {
-            ice_servers: vec![RTCIceServer {
-                urls: vec![
-                    "stun:stun.l.google.com:19302".to_string(),
-                    "stun:stun1.l.google.com:19302".to_string(),
-                ],
-                ..Default::default()
-            }],
+            ice_servers: self.ice_servers.clone(),
             ..Default::default()
         };
         let pc = Arc::new(self.api.new_peer_connection(config).await?);
-
-        let channel = self
-            .channels
-            .entry(channel_id)
-            .or_insert_with(|| {
-                Arc::new(SfuChannel {
-                    channel_id,
-                    users: Arc::new(DashMap::new()),
-                })
-            })
-            .value()
-            .clone();
-
-        // If the user already has a connection (reconnect), close the old PC.
-        if let Some(old_user) = channel.users.get(&user_id) {
-            let old_pc = old_user.peer_connection.clone();
-            drop(old_user);
-            let _ = old_pc.close().await;
-            channel.users.remove(&user_id);
-            tracing::info!("Reconnect: cleaned up old PC for user {}", user_id);
-        }
+        let bitrate = self.negotiate_bitrate(&offer_sdp);
 
         let user = Arc::new(SfuUser {
             user_id,
             peer_connection: pc.clone(),
             published_track: Arc::new(RwLock::new(None)),
             senders: Arc::new(DashMap::new()),
+            skipped_tracks: Arc::new(DashSet::new()),
+            bitrate,
         });
 
-        channel.users.insert(user_id, user.clone());
+        let (channel, old_user) = match self.claim_slot(channel_id, &user) {
+            Ok(claimed) => claimed,
+            Err(full) => {
+                let _ = pc.close().await;
+                self.channels
+                    .remove_if(&channel_id, |_, ch| ch.users.is_empty());
+                return Err(full.into());
+            }
+        };
+
+        // If the user already had a connection (reconnect), close the old PC.
+        // It was replaced first so its Closed state isn't mistaken for a lost peer.
+        if let Some(old_user) = old_user {
+            let _ = old_user.peer_connection.close().await;
+            tracing::info!("Reconnect: cleaned up old PC for user {}", user_id);
+        }
 
         // Set up on_track handler: when this user's audio arrives, write it
         // to their published_track so other users can receive it.
         let published_track_c = user.published_track.clone();
         let user_id_c = user_id;
+        let channel_id_c = channel_id;
+        let speaking_handler_c = self.speaking_handler.read().await.clone();
+        let server_muted_c = self.server_muted.clone();
 
         pc.on_track(Box::new(
             move |track: Arc<TrackRemote>, receiver, _transceiver| {
                 let published_track_inner = published_track_c.clone();
+                let speaking_handler = speaking_handler_c.clone();
+                let server_muted = server_muted_c.clone();
 
                 Box::pin(async move {
                     let track_id = track.id();
@@ -168,7 +311,7 @@ impl SfuServer {
                         track.kind()
                     );
 
-                    if track.kind() != webrtc::rtp_transceiver::rtp_codec::RTPCodecType::Audio {
+                    if track.kind() != RTPCodecType::Audio {
                         tracing::info!(
                             "Ignoring non-audio track {} from user {}",
                             track_id,
@@ -180,13 +323,13 @@ impl SfuServer {
                     // Always create a new local track with explicit audio/opus capability.
                     // This avoids the webrtc-rs bug where it puts opus into m=video sections.
                     //
-                    // Maximum fidelity: stereo 510kbps CBR with FEC and RTCP feedback.
-                    // Clean, crispy audio — no downscaling.
+                    // Stereo CBR with FEC and RTCP feedback, at the bitrate negotiated
+                    // with the publisher.
                     let audio_capability = RTCRtpCodecCapability {
                         mime_type: "audio/opus".to_string(),
                         clock_rate: 48000,
                         channels: 2,
-                        sdp_fmtp_line: "minptime=10;useinbandfec=1;stereo=1;sprop-stereo=1;maxaveragebitrate=510000;maxplaybackrate=48000;sprop-maxcapturerate=48000;cbr=1;usedtx=0;ptime=10".to_string(),
+                        sdp_fmtp_line: format!("minptime=10;useinbandfec=1;stereo=1;sprop-stereo=1;maxaveragebitrate={};maxplaybackrate=48000;sprop-maxcapturerate=48000;cbr=1;usedtx=0;ptime=10", bitrate),
                         rtcp_feedback: vec![
                             RTCPFeedback {
                                 typ: "transport-cc".to_string(),
@@ -212,6 +355,15 @@ impl SfuServer {
 
                     tracing::info!("Published track created for user {}", user_id_c);
 
+                    // The audio level extension's negotiated ID, if the client offered it
+                    let audio_level_id = receiver
+                        .get_parameters()
+                        .await
+                        .header_extensions
+                        .iter()
+                        .find(|ext| ext.uri == webrtc::sdp::extmap::AUDIO_LEVEL_URI)
+                        .and_then(|ext| u8::try_from(ext.id).ok());
+
                     // Spawn an RTCP reader to process receiver reports and NACK.
                     // Without this, the WebRTC stack cannot do packet loss recovery.
                     // Note: read_rtcp() is on RTCRtpReceiver, not TrackRemote.
@@ -231,11 +383,57 @@ impl SfuServer {
                         }
                     });
 
+                    // Voice activity is derived from the audio level each packet
+                    // carries: clients keep sending during silence (no DTX), so a
+                    // packet only counts when it's louder than SPEAKING_MAX_AUDIO_LEVEL.
+                    // Clients that didn't negotiate the extension fall back to packet
+                    // flow. Speaking stops after SPEAKING_SILENCE_TIMEOUT without a
+                    // voiced packet, which doubles as the debounce for short pauses.
+                    let last_packet = Arc::new(std::sync::Mutex::new(Instant::now()));
+                    let speaking = Arc::new(AtomicBool::new(false));
+                    let track_done = Arc::new(AtomicBool::new(false));
+
+                    if let Some(handler) = speaking_handler.clone() {
+                        let last_packet_w = last_packet.clone();
+                        let speaking_w = speaking.clone();
+                        let track_done_w = track_done.clone();
+                        tokio::spawn(async move {
+                            let mut interval = tokio::time::interval(SPEAKING_POLL_INTERVAL);
+                            while !track_done_w.load(Ordering::Relaxed) {
+                                interval.tick().await;
+                                let silent_for = last_packet_w.lock().unwrap().elapsed();
+                                if silent_for >= SPEAKING_SILENCE_TIMEOUT
+                                    && speaking_w.swap(false, Ordering::Relaxed)
+                                {
+                                    handler(channel_id_c, user_id_c, false);
+                                }
+                            }
+                            // Don't leave the user stuck as speaking when the track ends
+                            if speaking_w.swap(false, Ordering::Relaxed) {
+                                handler(channel_id_c, user_id_c, false);
+                            }
+                        });
+                    }
+
                     // Forward RTP packets from the remote track to the local track.
                     // This loop runs until the PC is closed.
                     loop {
                         match track.read_rtp().await {
                             Ok((rtp_packet, _attributes)) => {
+                                // Server-muted users are silenced here, not by trusting the client
+                                if server_muted.contains(&(channel_id_c, user_id_c)) {
+                                    continue;
+                                }
+
+                                if is_voiced(&rtp_packet.header, audio_level_id) {
+                                    *last_packet.lock().unwrap() = Instant::now();
+                                    if !speaking.swap(true, Ordering::Relaxed) {
+                                        if let Some(ref handler) = speaking_handler {
+                                            handler(channel_id_c, user_id_c, true);
+                                        }
+                                    }
+                                }
+
                                 if let Err(e) = local_track.write_rtp(&rtp_packet).await {
                                     // write_rtp can fail if no senders are subscribed yet — that's OK
                                     if e.to_string().contains("ErrRTPSenderSendAlreadyCalled") {
@@ -255,10 +453,55 @@ impl SfuServer {
                             }
                         }
                     }
+                    track_done.store(true, Ordering::Relaxed);
                 })
             },
         ));
 
+        // If the connection dies while this PC is still the user's current one,
+        // nobody called leave_channel: report it so the user is cleaned up.
+        // A Weak avoids the PC keeping itself alive through its own handler.
+        let peer_lost_c = self.peer_lost_handler.read().await.clone();
+        let channels_state = self.channels.clone();
+        let pc_weak = Arc::downgrade(&pc);
+        pc.on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
+            let peer_lost = peer_lost_c.clone();
+            let channels = channels_state.clone();
+            let pc_weak = pc_weak.clone();
+            Box::pin(async move {
+                if !matches!(
+                    s,
+                    RTCPeerConnectionState::Failed
+                        | RTCPeerConnectionState::Disconnected
+                        | RTCPeerConnectionState::Closed
+                ) {
+                    return;
+                }
+                let Some(pc) = pc_weak.upgrade() else {
+                    return;
+                };
+                let is_current = channels
+                    .get(&channel_id)
+                    .and_then(|ch| {
+                        ch.users
+                            .get(&user_id)
+                            .map(|u| Arc::ptr_eq(&u.peer_connection, &pc))
+                    })
+                    .unwrap_or(false);
+                if is_current {
+                    tracing::info!(
+                        "Peer connection of user {} in channel {} is {}; removing them",
+                        user_id,
+                        channel_id,
+                        s
+                    );
+                    if let Some(handler) = peer_lost {
+                        handler(channel_id, user_id);
+                    }
+                }
+            })
+        }));
+
         let ws_sender_ice = self.ws_sender.read().await.clone();
         let user_id_ice = user_id;
         let channel_id_ice = channel_id;
@@ -294,24 +537,21 @@ impl SfuServer {
             },
         ));
 
-        // Step 1: Set remote description (the client's offer)
-        pc.set_remote_description(RTCSessionDescription::offer(offer_sdp)?)
-            .await?;
-
-        // Step 2: Create answer for the client's initial stream
-        let answer = pc.create_answer(None).await?;
-        pc.set_local_description(answer).await?;
-
-        // Trickle ICE: Don't wait for gathering, send current local description immediately
-        let local_desc = pc
-            .local_description()
-            .await
-            .ok_or_else(|| anyhow::anyhow!("No local description available"))?;
+        // Steps 1 and 2: apply the client's offer and answer it. If that
+        // fails, the user must not be left in the channel half set up.
+        let local_desc = match Self::answer_offer(&pc, offer_sdp).await {
+            Ok(local_desc) => local_desc,
+            Err(e) => {
+                Self::discard_user(&self.channels, channel_id, &user).await;
+                return Err(e);
+            }
+        };
 
         tracing::info!(
-            "SFU answer ready for user {} ({} bytes)",
+            "SFU answer ready for user {} ({} bytes, {} bps)",
             user_id,
-            local_desc.sdp.len()
+            local_desc.sdp.len(),
+            bitrate
         );
 
         // Step 3: Schedule renegotiation for all OTHER existing users so they
@@ -322,49 +562,43 @@ impl SfuServer {
         let ws_sender_ref = self.ws_sender.read().await.clone();
         let published_track_for_renego = user.published_track.clone();
         let user_c = user.clone();
+        let peer_lost = self.peer_lost_handler.read().await.clone();
 
         tokio::spawn(async move {
             // Give the client a tiny bit of time to process the initial answer
             // before we bombard them with a renegotiation offer for existing tracks.
             tokio::time::sleep(std::time::Duration::from_millis(500)).await;
 
-            // Subscribe this new user to all existing users' tracks
+            // Subscribe this new user to all existing users' tracks. The users
+            // are collected first so retries don't hold the channel map locked.
             let mut subscribed_count = 0u32;
-            for other_entry in channel_c.users.iter() {
-                let other_user = other_entry.value();
-                if other_user.user_id == user_id {
-                    continue;
-                }
-                let other_track = other_user.published_track.read().await;
-                if let Some(track) = &*other_track {
-                    match user_c.peer_connection.add_track(track.clone()).await {
-                        Ok(sender) => {
-                            user_c.senders.insert(other_user.user_id, sender);
-                            subscribed_count += 1;
-                            tracing::info!(
-                                "Added existing user {}'s track to new user {}'s PC",
-                                other_user.user_id,
-                                user_id
-                            );
-                        }
-                        Err(e) => {
-                            tracing::error!(
-                                "Error adding existing track to user {}: {}",
-                                user_id,
-                                e
-                            );
-                        }
+            for other_user in Self::other_users(&channel_c, user_id) {
+                let other_track = other_user.published_track.read().await.clone();
+                if let Some(track) = other_track {
+                    if Self::subscribe(&user_c, other_user.user_id, &track).await {
+                        subscribed_count += 1;
                     }
                 }
             }
 
             // Immediately send an offer to the new user if they subscribed to existing tracks
             if subscribed_count > 0 {
-                if let Err(e) = Self::create_and_send_offer(&user_c, channel_id, &ws_sender_ref).await {
-                    tracing::error!("Failed to send initial renegotiation offer to user {}: {}", user_id, e);
-                } else {
-                    tracing::info!("Sent renegotiation offer to new user {} with {} existing tracks", user_id, subscribed_count);
+                if let Err(e) =
+                    Self::create_and_send_offer(&user_c, channel_id, &ws_sender_ref).await
+                {
+                    tracing::error!(
+                        "Failed to send initial renegotiation offer to user {}: {}",
+                        user_id,
+                        e
+                    );
+                    Self::drop_user(&sfu_self, &peer_lost, channel_id, &user_c).await;
+                    return;
                 }
+                tracing::info!(
+                    "Sent renegotiation offer to new user {} with {} existing tracks",
+                    user_id,
+                    subscribed_count
+                );
             }
 
             // Wait for the new user's track to be published (up to ~10 seconds normally, we just loop)
@@ -394,32 +628,11 @@ impl SfuServer {
             );
 
             // Add this track to all other users' peer connections and renegotiate
-            for other_entry in channel_c.users.iter() {
-                let other_user = other_entry.value().clone();
-                if other_user.user_id == user_id {
+            for other_user in Self::other_users(&channel_c, user_id) {
+                if !Self::subscribe(&other_user, user_id, &track).await {
                     continue;
                 }
 
-                // Add the new track to the other user's PC
-                match other_user.peer_connection.add_track(track.clone()).await {
-                    Ok(sender) => {
-                        other_user.senders.insert(user_id, sender);
-                        tracing::info!(
-                            "Added user {}'s track to user {}'s PC",
-                            user_id,
-                            other_user.user_id
-                        );
-                    }
-                    Err(e) => {
-                        tracing::error!(
-                            "Failed to add track to user {}'s PC: {}",
-                            other_user.user_id,
-                            e
-                        );
-                        continue;
-                    }
-                }
-
                 // Create a new offer from the other user's PC (server-initiated renegotiation)
                 match Self::create_and_send_offer(&other_user, channel_id, &ws_sender_ref).await {
                     Ok(()) => {
@@ -431,12 +644,187 @@ impl SfuServer {
                             other_user.user_id,
                             e
                         );
+                        Self::drop_user(&sfu_self, &peer_lost, channel_id, &other_user).await;
                     }
                 }
             }
         });
 
-        Ok(local_desc.sdp)
+        // The answer's maxaveragebitrate caps what the client's encoder sends us
+        Ok(with_opus_bitrate(&local_desc.sdp, bitrate))
+    }
+
+    /// Put `user` into the channel, replacing their previous connection if
+    /// they are reconnecting. The cap is checked while the channel entry is
+    /// locked, so concurrent joins can't both take the last slot; a rejected
+    /// join leaves the channel untouched.
+    fn claim_slot(
+        &self,
+        channel_id: Uuid,
+        user: &Arc<SfuUser>,
+    ) -> std::result::Result<(Arc<SfuChannel>, Option<Arc<SfuUser>>), ChannelFull> {
+        let entry = self.channels.entry(channel_id).or_insert_with(|| {
+            Arc::new(SfuChannel {
+                channel_id,
+                users: Arc::new(DashMap::new()),
+            })
+        });
+        let channel = entry.value().clone();
+
+        // Reconnects keep their slot
+        if let Some(max_participants) = self.max_participants {
+            if !channel.users.contains_key(&user.user_id) && channel.users.len() >= max_participants
+            {
+                return Err(ChannelFull { max_participants });
+            }
+        }
+
+        let old_user = channel.users.insert(user.user_id, user.clone());
+        Ok((channel, old_user))
+    }
+
+    /// Whether `user_id` could join `channel_id` without exceeding the
+    /// participant cap. Only a pre-check: `claim_slot` is what enforces it.
+    pub fn has_room(&self, channel_id: Uuid, user_id: Uuid) -> bool {
+        let Some(max_participants) = self.max_participants else {
+            return true;
+        };
+        self.channels.get(&channel_id).is_none_or(|channel| {
+            channel.users.contains_key(&user_id) || channel.users.len() < max_participants
+        })
+    }
+
+    /// Apply a client's offer to their PC and create the answer.
+    async fn answer_offer(
+        pc: &RTCPeerConnection,
+        offer_sdp: String,
+    ) -> Result<RTCSessionDescription> {
+        // Step 1: Set remote description (the client's offer)
+        pc.set_remote_description(RTCSessionDescription::offer(offer_sdp)?)
+            .await?;
+
+        // Step 2: Create answer for the client's initial stream
+        let answer = pc.create_answer(None).await?;
+        pc.set_local_description(answer).await?;
+
+        // Trickle ICE: Don't wait for gathering, send current local description immediately
+        pc.local_description()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("No local description available"))
+    }
+
+    /// Undo a join that failed part-way. The user is only removed if `user`
+    /// is still their current connection, then the PC is closed. Returns
+    /// whether they were removed.
+    async fn discard_user(
+        channels: &DashMap<Uuid, Arc<SfuChannel>>,
+        channel_id: Uuid,
+        user: &SfuUser,
+    ) -> bool {
+        let removed = channels
+            .get(&channel_id)
+            .map(|ch| ch.value().clone())
+            .is_some_and(|channel| {
+                channel
+                    .users
+                    .remove_if(&user.user_id, |_, current| {
+                        Arc::ptr_eq(&current.peer_connection, &user.peer_connection)
+                    })
+                    .is_some()
+            });
+        channels.remove_if(&channel_id, |_, ch| ch.users.is_empty());
+        let _ = user.peer_connection.close().await;
+        removed
+    }
+
+    /// Drop a user whose connection can no longer be renegotiated: without
+    /// the new offer their view of the channel is wrong from here on. The
+    /// peer-lost handler takes them out of the voice channel, as if their
+    /// connection had died.
+    async fn drop_user(
+        channels: &DashMap<Uuid, Arc<SfuChannel>>,
+        peer_lost: &Option<PeerLostFn>,
+        channel_id: Uuid,
+        user: &SfuUser,
+    ) {
+        if !Self::discard_user(channels, channel_id, user).await {
+            return;
+        }
+        tracing::warn!(
+            "Dropped user {} from channel {} after a failed renegotiation",
+            user.user_id,
+            channel_id
+        );
+        if let Some(handler) = peer_lost {
+            handler(channel_id, user.user_id);
+        }
+    }
+
+    /// Everyone in the channel except `user_id`.
+    fn other_users(channel: &SfuChannel, user_id: Uuid) -> Vec<Arc<SfuUser>> {
+        channel
+            .users
+            .iter()
+            .filter(|entry| *entry.key() != user_id)
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    /// Add `publisher_id`'s track to `subscriber`'s PC, retrying failures with
+    /// backoff. When every attempt fails the track is skipped for this
+    /// subscriber, so later renegotiations don't keep tripping over it.
+    /// Returns whether the track was added.
+    async fn subscribe(
+        subscriber: &SfuUser,
+        publisher_id: Uuid,
+        track: &Arc<TrackLocalStaticRTP>,
+    ) -> bool {
+        if subscriber.skipped_tracks.contains(&publisher_id) {
+            return false;
+        }
+
+        let mut delay = ADD_TRACK_RETRY_DELAY;
+        for attempt in 1..=ADD_TRACK_ATTEMPTS {
+            // A subscriber who left while we were waiting can't be retried
+            if subscriber.peer_connection.connection_state() == RTCPeerConnectionState::Closed {
+                return false;
+            }
+
+            match subscriber.peer_connection.add_track(track.clone()).await {
+                Ok(sender) => {
+                    subscriber.senders.insert(publisher_id, sender);
+                    tracing::info!(
+                        "Added user {}'s track to user {}'s PC",
+                        publisher_id,
+                        subscriber.user_id
+                    );
+                    return true;
+                }
+                Err(e) if attempt < ADD_TRACK_ATTEMPTS => {
+                    tracing::debug!(
+                        "Adding user {}'s track to user {}'s PC failed (attempt {}/{}): {}",
+                        publisher_id,
+                        subscriber.user_id,
+                        attempt,
+                        ADD_TRACK_ATTEMPTS,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Skipping user {}'s track for user {} after {} failed attempts: {}",
+                        publisher_id,
+                        subscriber.user_id,
+                        ADD_TRACK_ATTEMPTS,
+                        e
+                    );
+                    subscriber.skipped_tracks.insert(publisher_id);
+                }
+            }
+        }
+        false
     }
 
     /// Create an offer from a user's PC and send it to them via WebSocket.
@@ -464,7 +852,7 @@ impl SfuServer {
                     "to_user_id": user.user_id.to_string(),
                     "channel_id": channel_id.to_string(),
                     "signal_type": "offer",
-                    "payload": local_desc.sdp,
+                    "payload": with_opus_bitrate(&local_desc.sdp, user.bitrate),
                 }
             });
             sender(user.user_id, event);
@@ -558,6 +946,8 @@ impl SfuServer {
     /// Remove a user from a voice channel. Cleans up their PC and removes
     /// their track from all other users' connections (triggering renegotiation).
     pub async fn leave_channel(&self, channel_id: Uuid, user_id: Uuid) {
+        self.server_muted.remove(&(channel_id, user_id));
+
         let channel = match self.channels.get(&channel_id) {
             Some(ch) => ch.value().clone(),
             None => return,
@@ -583,19 +973,19 @@ impl SfuServer {
                 );
 
                 // We need to renegotiate with remaining users to remove the track.
-                for other_entry in channel.users.iter() {
-                    let other_user = other_entry.value();
+                let ws_sender = self.ws_sender.read().await.clone();
+                let peer_lost = self.peer_lost_handler.read().await.clone();
+                for other_user in Self::other_users(&channel, user_id) {
+                    other_user.skipped_tracks.remove(&user_id);
                     if let Some((_, sender)) = other_user.senders.remove(&user_id) {
                         let _ = other_user.peer_connection.remove_track(&sender).await;
                         // Trigger renegotiation so the client knows the track is gone
-                        if let Err(e) = Self::create_and_send_offer(
-                            &other_user,
-                            channel_id,
-                            &self.ws_sender.read().await.clone(),
-                        )
-                        .await
+                        if let Err(e) =
+                            Self::create_and_send_offer(&other_user, channel_id, &ws_sender).await
                         {
                             tracing::error!("Failed to reneg after remove: {}", e);
+                            Self::drop_user(&self.channels, &peer_lost, channel_id, &other_user)
+                                .await;
                         }
                     }
                 }
@@ -609,4 +999,184 @@ impl SfuServer {
             tracing::info!("Removed empty SFU channel {}", channel_id);
         }
     }
+
+    /// Close every peer connection on server shutdown. Returns how many
+    /// connections were closed.
+    pub async fn shutdown(&self) -> usize {
+        // Collect first: leave_channel mutates the maps being iterated
+        let members: Vec<(Uuid, Uuid)> = self
+            .channels
+            .iter()
+            .flat_map(|channel| {
+                let channel_id = *channel.key();
+                channel
+                    .users
+                    .iter()
+                    .map(|user| (channel_id, *user.key()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        for &(channel_id, user_id) in &members {
+            self.leave_channel(channel_id, user_id).await;
+        }
+        members.len()
+    }
+}
+
+/// Whether a packet's RFC 6464 audio level, sent under extension
+/// `audio_level_id`, is loud enough to count as speech. Packets without one
+/// count as silence. If the extension wasn't negotiated there is no level to
+/// read, so every packet counts and speaking follows packet flow instead.
+fn is_voiced(header: &Header, audio_level_id: Option<u8>) -> bool {
+    let Some(id) = audio_level_id else {
+        return true;
+    };
+    header
+        .get_extension(id)
+        .and_then(|mut raw| AudioLevelExtension::unmarshal(&mut raw).ok())
+        .is_some_and(|ext| ext.level <= SPEAKING_MAX_AUDIO_LEVEL)
+}
+
+/// Payload types of the Opus codec in an SDP.
+fn opus_payload_types(sdp: &str) -> Vec<&str> {
+    sdp.lines()
+        .filter_map(|line| {
+            let (pt, codec) = line.strip_prefix("a=rtpmap:")?.split_once(' ')?;
+            codec
+                .to_ascii_lowercase()
+                .starts_with("opus/")
+                .then_some(pt)
+        })
+        .collect()
+}
+
+/// The payload type and parameters of an Opus `a=fmtp` line.
+fn opus_fmtp<'a>(line: &'a str, opus_pts: &[&str]) -> Option<(&'a str, &'a str)> {
+    let (pt, params) = line.strip_prefix("a=fmtp:")?.split_once(' ')?;
+    opus_pts.contains(&pt).then_some((pt, params))
+}
+
+/// The `maxaveragebitrate` (bps) an SDP asks for on Opus, if any.
+fn opus_max_average_bitrate(sdp: &str) -> Option<u32> {
+    let opus_pts = opus_payload_types(sdp);
+    sdp.lines()
+        .filter_map(|line| opus_fmtp(line, &opus_pts))
+        .flat_map(|(_, params)| params.split(';'))
+        .find_map(|param| param.trim().strip_prefix("maxaveragebitrate="))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Set `maxaveragebitrate` on every Opus `a=fmtp` line of an SDP, adding
+/// the line after the `a=rtpmap` of any Opus payload type that has none.
+fn with_opus_bitrate(sdp: &str, bitrate: u32) -> String {
+    let opus_pts = opus_payload_types(sdp);
+    let with_fmtp: Vec<&str> = sdp
+        .lines()
+        .filter_map(|line| opus_fmtp(line, &opus_pts))
+        .map(|(pt, _)| pt)
+        .collect();
+    let mut out = String::with_capacity(sdp.len() + 32);
+    for line in sdp.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let rtpmap_pt = content
+            .strip_prefix("a=rtpmap:")
+            .and_then(|rest| rest.split_once(' '))
+            .map(|(pt, _)| pt)
+            .filter(|pt| opus_pts.contains(pt) && !with_fmtp.contains(pt));
+        if let Some(pt) = rtpmap_pt {
+            let ending = if ending.is_empty() { "\r\n" } else { ending };
+            out.push_str(content);
+            out.push_str(ending);
+            out.push_str(&format!("a=fmtp:{} maxaveragebitrate={}", pt, bitrate));
+            out.push_str(ending);
+            continue;
+        }
+        match opus_fmtp(content, &opus_pts) {
+            Some((pt, params)) => {
+                let mut params: Vec<&str> = params
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty() && !p.starts_with("maxaveragebitrate="))
+                    .collect();
+                let bitrate = format!("maxaveragebitrate={}", bitrate);
+                params.push(&bitrate);
+                out.push_str(&format!("a=fmtp:{} {}", pt, params.join(";")));
+            }
+            None => out.push_str(content),
+        }
+        out.push_str(ending);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speech_is_judged_by_audio_level() {
+        let header_with_level = |level: u8| Header {
+            extension: true,
+            extension_profile: webrtc::rtp::header::EXTENSION_PROFILE_ONE_BYTE,
+            extensions: vec![webrtc::rtp::header::Extension {
+                id: 1,
+                payload: vec![level].into(),
+            }],
+            ..Default::default()
+        };
+
+        assert!(is_voiced(&header_with_level(30), Some(1)));
+        // Background noise, and the level under an ID that wasn't negotiated
+        assert!(!is_voiced(&header_with_level(90), Some(1)));
+        assert!(!is_voiced(&header_with_level(30), Some(2)));
+        assert!(!is_voiced(&Header::default(), Some(1)));
+    }
+
+    #[test]
+    fn test_speech_follows_packet_flow_without_audio_level() {
+        assert!(is_voiced(&Header::default(), None));
+    }
+
+    const OFFER: &str = "v=0\r\n\
+        m=audio 9 UDP/TLS/RTP/SAVPF 111 0\r\n\
+        a=rtpmap:111 opus/48000/2\r\n\
+        a=fmtp:111 minptime=10;useinbandfec=1;maxaveragebitrate=510000\r\n\
+        a=rtpmap:0 PCMU/8000\r\n\
+        a=fmtp:0 maxaveragebitrate=1\r\n";
+
+    #[test]
+    fn test_reads_requested_opus_bitrate() {
+        assert_eq!(opus_max_average_bitrate(OFFER), Some(510_000));
+        assert_eq!(
+            opus_max_average_bitrate("a=rtpmap:96 OPUS/48000/2\r\na=fmtp:96 useinbandfec=1\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rewrites_only_opus_fmtp_lines() {
+        let sdp = with_opus_bitrate(OFFER, 64_000);
+        assert!(sdp.contains("a=fmtp:111 minptime=10;useinbandfec=1;maxaveragebitrate=64000\r\n"));
+        // Other codecs are left alone
+        assert!(sdp.contains("a=fmtp:0 maxaveragebitrate=1\r\n"));
+        assert_eq!(opus_max_average_bitrate(&sdp), Some(64_000));
+        assert_eq!(sdp.lines().count(), OFFER.lines().count());
+    }
+
+    #[test]
+    fn test_adds_missing_opus_fmtp_line() {
+        let offer =
+            "m=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=rtpmap:111 opus/48000/2\r\na=sendrecv\r\n";
+        let sdp = with_opus_bitrate(offer, 64_000);
+        assert_eq!(
+            sdp,
+            "m=audio 9 UDP/TLS/RTP/SAVPF 111\r\n\
+             a=rtpmap:111 opus/48000/2\r\n\
+             a=fmtp:111 maxaveragebitrate=64000\r\n\
+             a=sendrecv\r\n"
+        );
+        assert_eq!(opus_max_average_bitrate(&sdp), Some(64_000));
+    }
 }
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::models::{VoiceParticipant, WsEvent};
+
+/// Redis key prefix for per-channel participant hashes (field = user_id).
+const KEY_PREFIX: &str = "antarcticom:voice:";
+/// Pub/sub channel used to fan voice events out to other instances.
+const EVENTS_CHANNEL: &str = "antarcticom:voice_events";
+/// How long a participant entry counts without being refreshed. Each instance
+/// refreshes its own participants well within this, so the entries of an
+/// instance that crashed drop out on their own.
+pub const PARTICIPANT_TTL: Duration = Duration::from_secs(60);
+
+/// A participant as stored in Redis, with the time its entry goes stale.
+#[derive(Serialize, Deserialize)]
+struct StoredParticipant {
+    participant: VoiceParticipant,
+    /// Unix timestamp (seconds)
+    expires_at: i64,
+}
+
+/// Envelope for voice events published to other instances.
+#[derive(Serialize, Deserialize)]
+struct VoiceEventEnvelope {
+    /// Instance that published the event (so it can skip its own messages).
+    origin: Uuid,
+    channel_id: Uuid,
+    event: WsEvent,
+}
+
+/// Redis-backed voice participant storage.
+///
+/// Each instance writes only the entries of participants connected to it, so
+/// every instance sharing the same Redis sees the whole channel.
+pub struct VoiceStateStore {
+    client: redis::Client,
+    conn: OnceCell<MultiplexedConnection>,
+    instance_id: Uuid,
+}
+
+impl VoiceStateStore {
+    pub fn new(client: redis::Client) -> Self {
+        Self {
+            client,
+            conn: OnceCell::new(),
+            instance_id: Uuid::now_v7(),
+        }
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| self.client.get_multiplexed_tokio_connection())
+            .await?;
+        Ok(conn.clone())
+    }
+
+    fn key(channel_id: Uuid) -> String {
+        format!("{}{}", KEY_PREFIX, channel_id)
+    }
+
+    /// Store (or refresh) one participant's entry for another `PARTICIPANT_TTL`.
+    pub async fn save(&self, participant: &VoiceParticipant) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let key = Self::key(participant.channel_id);
+        let stored = StoredParticipant {
+            participant: participant.clone(),
+            expires_at: chrono::Utc::now().timestamp() + PARTICIPANT_TTL.as_secs() as i64,
+        };
+        conn.hset::<_, _, _, ()>(
+            &key,
+            participant.user_id.to_string(),
+            serde_json::to_string(&stored)?,
+        )
+        .await?;
+        // The hash itself goes once nobody refreshes anything in it
+        conn.expire::<_, ()>(&key, PARTICIPANT_TTL.as_secs() as i64)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove one participant's entry.
+    pub async fn remove(&self, channel_id: Uuid, user_id: Uuid) -> Result<()> {
+        let mut conn = self.connection().await?;
+        conn.hdel::<_, _, ()>(Self::key(channel_id), user_id.to_string())
+            .await?;
+        Ok(())
+    }
+
+    /// Load a channel's live participants, dropping entries that went stale.
+    pub async fn load(&self, channel_id: Uuid) -> Result<Vec<VoiceParticipant>> {
+        let mut conn = self.connection().await?;
+        let key = Self::key(channel_id);
+        let entries: HashMap<String, String> = conn.hgetall(&key).await?;
+
+        let now = chrono::Utc::now().timestamp();
+        let mut participants = Vec::new();
+        let mut stale = Vec::new();
+        for (user_id, json) in entries {
+            match serde_json::from_str::<StoredParticipant>(&json) {
+                Ok(stored) if stored.expires_at > now => participants.push(stored.participant),
+                _ => stale.push(user_id),
+            }
+        }
+        if !stale.is_empty() {
+            conn.hdel::<_, _, ()>(&key, stale).await?;
+        }
+        Ok(participants)
+    }
+
+    /// Check that Redis answers.
+    pub async fn ping(&self) -> Result<()> {
+        let mut conn = self.connection().await?;
+        redis::cmd("PING").query_async::<_, ()>(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Publish a voice event for other instances to rebroadcast.
+    pub async fn publish(&self, channel_id: Uuid, event: &WsEvent) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let envelope = VoiceEventEnvelope {
+            origin: self.instance_id,
+            channel_id,
+            event: event.clone(),
+        };
+        conn.publish::<_, _, ()>(EVENTS_CHANNEL, serde_json::to_string(&envelope)?)
+            .await?;
+        Ok(())
+    }
+
+    /// Listen for voice events published by other instances and hand each one
+    /// to `on_event`. Returns when the subscription connection drops.
+    pub async fn subscribe<F>(&self, on_event: F) -> Result<()>
+    where
+        F: Fn(Uuid, WsEvent),
+    {
+        let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(EVENTS_CHANNEL).await?;
+
+        let mut messages = pubsub.on_message();
+        while let Some(msg) = messages.next().await {
+            let payload: String = match msg.get_payload() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::warn!("Invalid voice event payload: {}", e);
+                    continue;
+                }
+            };
+            match serde_json::from_str::<VoiceEventEnvelope>(&payload) {
+                Ok(envelope) if envelope.origin != self.instance_id => {
+                    on_event(envelope.channel_id, envelope.event);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to parse voice event: {}", e),
+            }
+        }
+        Ok(())
+    }
+}